@@ -0,0 +1,27 @@
+//! Feeds arbitrary bytes through decoding, resizing and encoding. Run with
+//! `cargo +nightly fuzz run resize_bytes`.
+
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use resize::{settings::Operation, ResizeOptions};
+
+fuzz_target!(|data: &[u8]| {
+    // The first few bytes pick the operation and size; the rest is the image.
+    if data.len() < 3 {
+        return;
+    }
+    let operation = match data[0] % 3 {
+        0 => Operation::Shrink,
+        1 => Operation::Enlarge,
+        _ => Operation::Fit,
+    };
+    let size = u16::from_le_bytes([data[1], data[2]]) as u32 + 1;
+
+    let options = ResizeOptions::builder()
+        .operation(operation)
+        .size(size)
+        .build()
+        .expect("a single positive size is valid");
+    let _ = resize::resize_bytes(&data[3..], &options);
+});
@@ -0,0 +1,92 @@
+//! Cleaning up soft alpha edges after resizing, and trading alpha for a color key.
+
+use image::{Rgb, RgbImage, RgbaImage};
+use serde::Serialize;
+
+/// Alpha below `low` becomes fully transparent, and alpha above `high`, if given, fully opaque.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct AlphaThreshold {
+    pub low: u8,
+    pub high: Option<u8>,
+}
+
+impl AlphaThreshold {
+    /// Parses a threshold such as `16`, or `16:240` to make nearly opaque pixels opaque too.
+    pub fn parse(s: &str) -> Result<AlphaThreshold, String> {
+        let invalid = || format!("'{}' is not an alpha threshold such as 16 or 16:240", s);
+        let (low, high) = match s.split_once(':') {
+            Some((low, high)) => (low, Some(high)),
+            None => (s, None),
+        };
+        let low: u8 = low.trim().parse().map_err(|_| invalid())?;
+        let high = match high {
+            Some(high) => Some(high.trim().parse::<u8>().map_err(|_| invalid())?),
+            None => None,
+        };
+
+        if high.is_some_and(|high| high <= low) {
+            return Err(format!("the high alpha threshold must exceed {}", low));
+        }
+        Ok(AlphaThreshold { low, high })
+    }
+
+    /// Snaps the alpha of each pixel in `image` to 0 or 255 where it crosses a threshold. An
+    /// opaque image is left as it is.
+    pub fn apply(self, image: &mut RgbaImage) {
+        for pixel in image.pixels_mut() {
+            let alpha = &mut pixel.0[3];
+            if *alpha < self.low {
+                *alpha = 0;
+            } else if self.high.is_some_and(|high| *alpha > high) {
+                *alpha = 255;
+            }
+        }
+    }
+}
+
+/// `image` without its alpha, each pixel less than half opaque becoming `key`, for tools that
+/// only understand color-keyed transparency.
+pub fn color_key(image: &RgbaImage, key: [u8; 3]) -> RgbImage {
+    RgbImage::from_fn(image.width(), image.height(), |x, y| {
+        let [r, g, b, alpha] = image.get_pixel(x, y).0;
+        match alpha {
+            0..=127 => Rgb(key),
+            _ => Rgb([r, g, b]),
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{color_key, AlphaThreshold};
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn parse_thresholds() {
+        assert_eq!(
+            AlphaThreshold::parse("16"),
+            Ok(AlphaThreshold {
+                low: 16,
+                high: None
+            })
+        );
+        assert_eq!(
+            AlphaThreshold::parse("16:240"),
+            Ok(AlphaThreshold {
+                low: 16,
+                high: Some(240)
+            })
+        );
+        assert!(AlphaThreshold::parse("256").is_err());
+        assert!(AlphaThreshold::parse("200:100").is_err());
+        assert!(AlphaThreshold::parse("16:").is_err());
+    }
+
+    #[test]
+    fn transparency_becomes_the_key() {
+        let image = RgbaImage::from_fn(3, 1, |x, _| Rgba([10, 20, 30, [0, 127, 200][x as usize]]));
+        let keyed = color_key(&image, [255, 0, 255]);
+        let pixels: Vec<_> = keyed.pixels().map(|pixel| pixel.0).collect();
+        assert_eq!(pixels, [[255, 0, 255], [255, 0, 255], [10, 20, 30]]);
+    }
+}
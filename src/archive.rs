@@ -0,0 +1,116 @@
+//! Tar streams, for pipelines that would rather not touch the filesystem.
+
+use std::{
+    io::{self, Read, Write},
+    path::Path,
+    sync::Mutex,
+    time::{SystemTime, UNIX_EPOCH},
+};
+
+use glob::Pattern;
+use image::ImageFormat;
+use tar::{Archive, Builder, EntryType, Header};
+
+use resize::{raw, settings::Settings, Job};
+
+/// Reads every regular file in a tar stream into a job, skipping members matching `ignore`.
+pub fn read_jobs(
+    reader: impl Read,
+    settings: &Settings,
+    ignore: &[Pattern],
+) -> io::Result<Vec<Job>> {
+    let mut jobs = Vec::new();
+
+    for entry in Archive::new(reader).entries()? {
+        let mut entry = entry?;
+        if entry.header().entry_type() != EntryType::Regular {
+            continue;
+        }
+
+        let source = entry.path()?.to_string_lossy().into_owned();
+        if ignore.iter().any(|pattern| pattern.matches(&source)) {
+            continue;
+        }
+
+        let mut data = Vec::with_capacity(entry.size() as usize);
+        entry.read_to_end(&mut data)?;
+        jobs.push(Job {
+            data: Some(data),
+            ..Job::new(&source, settings)
+        });
+    }
+
+    Ok(jobs)
+}
+
+/// Whether a member looks like something this tool can decode, going by its name.
+pub fn is_image(path: &Path) -> bool {
+    raw::is_raw(path) || ImageFormat::from_path(path).is_ok()
+}
+
+/// A tar stream of outputs, appended to in the order they are committed.
+pub struct TarWriter<W: Write> {
+    builder: Mutex<Builder<W>>,
+}
+
+impl<W: Write> TarWriter<W> {
+    pub fn new(writer: W) -> TarWriter<W> {
+        TarWriter {
+            builder: Mutex::new(Builder::new(writer)),
+        }
+    }
+
+    pub fn append(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        let mut header = Header::new_gnu();
+        header.set_size(bytes.len() as u64);
+        header.set_mode(0o644);
+        header.set_mtime(
+            SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |elapsed| elapsed.as_secs()),
+        );
+        self.builder
+            .lock()
+            .unwrap()
+            .append_data(&mut header, path, bytes)
+    }
+
+    /// Writes the end-of-archive marker.
+    pub fn finish(self) -> io::Result<W> {
+        self.builder.into_inner().unwrap().into_inner()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_jobs, TarWriter};
+    use glob::Pattern;
+    use resize::{
+        quality::Quality,
+        settings::{Operation, Settings},
+    };
+    use std::path::Path;
+
+    #[test]
+    fn round_trip() {
+        let writer = TarWriter::new(Vec::new());
+        writer.append(Path::new("a/one.png"), b"one").unwrap();
+        writer.append(Path::new("two.txt"), b"two").unwrap();
+        writer.append(Path::new("skip.png"), b"three").unwrap();
+        let tar = writer.finish().unwrap();
+
+        let settings = Settings {
+            operation: Operation::Shrink,
+            sizes: vec![100],
+            format: None,
+            quality: Quality::default(),
+        };
+        let ignore = [Pattern::new("skip*").unwrap()];
+        let jobs = read_jobs(&tar[..], &settings, &ignore).unwrap();
+
+        assert_eq!(jobs.len(), 2);
+        assert_eq!(jobs[0].source, "a/one.png");
+        assert_eq!(jobs[0].data.as_deref(), Some(&b"one"[..]));
+        assert_eq!(jobs[1].source, "two.txt");
+    }
+}
@@ -0,0 +1,272 @@
+//! Placing images on a fixed canvas without scaling them, for `--canvas`.
+
+use image::{imageops, DynamicImage, GenericImageView, Rgba, RgbaImage};
+use serde::Serialize;
+
+/// Where an image sits on a canvas larger or smaller than it.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Anchor {
+    TopLeft,
+    Top,
+    TopRight,
+    Left,
+    Center,
+    Right,
+    BottomLeft,
+    Bottom,
+    BottomRight,
+}
+
+impl Anchor {
+    pub const NAMES: &'static [&'static str] = &[
+        "top-left",
+        "top",
+        "top-right",
+        "left",
+        "center",
+        "right",
+        "bottom-left",
+        "bottom",
+        "bottom-right",
+    ];
+
+    pub fn from_name(name: &str) -> Option<Anchor> {
+        match name {
+            "top-left" => Some(Anchor::TopLeft),
+            "top" => Some(Anchor::Top),
+            "top-right" => Some(Anchor::TopRight),
+            "left" => Some(Anchor::Left),
+            "center" => Some(Anchor::Center),
+            "right" => Some(Anchor::Right),
+            "bottom-left" => Some(Anchor::BottomLeft),
+            "bottom" => Some(Anchor::Bottom),
+            "bottom-right" => Some(Anchor::BottomRight),
+            _ => None,
+        }
+    }
+
+    /// Where along each axis the image goes, as 0 for the start, 1 for the middle and 2 for
+    /// the end.
+    fn thirds(self) -> (i64, i64) {
+        match self {
+            Anchor::TopLeft => (0, 0),
+            Anchor::Top => (1, 0),
+            Anchor::TopRight => (2, 0),
+            Anchor::Left => (0, 1),
+            Anchor::Center => (1, 1),
+            Anchor::Right => (2, 1),
+            Anchor::BottomLeft => (0, 2),
+            Anchor::Bottom => (1, 2),
+            Anchor::BottomRight => (2, 2),
+        }
+    }
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Canvas {
+    pub width: u32,
+    pub height: u32,
+    /// Red, green, blue and alpha.
+    pub color: [u8; 4],
+    pub anchor: Anchor,
+}
+
+/// What to fill the space around a padded image with.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Fill {
+    /// Red, green, blue and alpha.
+    Color([u8; 4]),
+    /// The average of the image's four corners, as for a logo on a solid field, or white if
+    /// they differ too much for there to be one.
+    Corners,
+}
+
+impl From<[u8; 4]> for Fill {
+    fn from(color: [u8; 4]) -> Fill {
+        Fill::Color(color)
+    }
+}
+
+impl Fill {
+    /// Parses `auto` for the corners' color, or else a color as [`parse_color`] does.
+    pub fn parse(s: &str) -> Result<Fill, String> {
+        match s {
+            "auto" => Ok(Fill::Corners),
+            _ => parse_color(s).map(Fill::Color),
+        }
+    }
+
+    /// The color to fill around `image` with.
+    pub fn color(self, image: &DynamicImage) -> [u8; 4] {
+        match self {
+            Fill::Color(color) => color,
+            Fill::Corners => corner_color(image).unwrap_or([255, 255, 255, 255]),
+        }
+    }
+}
+
+/// How far apart, in any channel, the corners of an image may be for them to still be taken
+/// as one background.
+const CORNER_SPREAD: u8 = 48;
+
+/// The average color of `image`'s four corners, unless they disagree.
+fn corner_color(image: &DynamicImage) -> Option<[u8; 4]> {
+    let (width, height) = image.dimensions();
+    let corners = [
+        (0, 0),
+        (width - 1, 0),
+        (0, height - 1),
+        (width - 1, height - 1),
+    ]
+    .map(|(x, y)| image.get_pixel(x, y).0);
+
+    let mut color = [0; 4];
+    for (channel, average) in color.iter_mut().enumerate() {
+        let values = corners.map(|corner| corner[channel]);
+        let (least, most) = (values.iter().min()?, values.iter().max()?);
+        if most - least > CORNER_SPREAD {
+            return None;
+        }
+        let sum: u32 = values.iter().map(|&value| u32::from(value)).sum();
+        *average = ((sum + 2) / 4) as u8;
+    }
+    Some(color)
+}
+
+/// Parses canvas dimensions such as `1000x800`.
+pub fn parse_dimensions(s: &str) -> Result<(u32, u32), String> {
+    let invalid = || format!("'{}' is not a size such as 1000x800", s);
+    let (width, height) = s.split_once('x').ok_or_else(invalid)?;
+    let width: u32 = width.trim().parse().map_err(|_| invalid())?;
+    let height: u32 = height.trim().parse().map_err(|_| invalid())?;
+
+    if width == 0 || height == 0 {
+        return Err(invalid());
+    }
+    Ok((width, height))
+}
+
+/// Parses a color by name, or as `#rgb`, `#rrggbb` or `#rrggbbaa`.
+pub fn parse_color(s: &str) -> Result<[u8; 4], String> {
+    let invalid = || {
+        format!(
+            "'{}' is not a color such as white, black, transparent or #rrggbb",
+            s
+        )
+    };
+    match s {
+        "white" => return Ok([255, 255, 255, 255]),
+        "black" => return Ok([0, 0, 0, 255]),
+        "gray" | "grey" => return Ok([128, 128, 128, 255]),
+        "magenta" => return Ok([255, 0, 255, 255]),
+        "transparent" => return Ok([0, 0, 0, 0]),
+        _ => (),
+    }
+
+    let hex = s.strip_prefix('#').ok_or_else(invalid)?;
+    if !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return Err(invalid());
+    }
+    let digit = |index: usize| u8::from_str_radix(&hex[index..index + 1], 16).unwrap();
+    let byte = |index: usize| u8::from_str_radix(&hex[index..index + 2], 16).unwrap();
+    match hex.len() {
+        3 => Ok([digit(0) * 17, digit(1) * 17, digit(2) * 17, 255]),
+        6 => Ok([byte(0), byte(2), byte(4), 255]),
+        8 => Ok([byte(0), byte(2), byte(4), byte(6)]),
+        _ => Err(invalid()),
+    }
+}
+
+/// Draws `image` unscaled on `canvas` at its anchor. An image larger than the canvas is cut
+/// down to the part that falls within it.
+pub fn place(image: &DynamicImage, canvas: &Canvas) -> RgbaImage {
+    let mut placed = RgbaImage::from_pixel(canvas.width, canvas.height, Rgba(canvas.color));
+    let (width, height) = image.dimensions();
+    let (across, down) = canvas.anchor.thirds();
+    let x = (i64::from(canvas.width) - i64::from(width)) * across / 2;
+    let y = (i64::from(canvas.height) - i64::from(height)) * down / 2;
+
+    // Whatever hangs off the top or left is cropped away, and `overlay` clips the rest.
+    let (left, top) = ((-x).max(0) as u32, (-y).max(0) as u32);
+    let visible = image
+        .crop_imm(
+            left,
+            top,
+            width.saturating_sub(left),
+            height.saturating_sub(top),
+        )
+        .to_rgba();
+    imageops::overlay(&mut placed, &visible, x.max(0) as u32, y.max(0) as u32);
+    placed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_color, parse_dimensions, place, Anchor, Canvas, Fill};
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    fn canvas(anchor: Anchor) -> Canvas {
+        Canvas {
+            width: 10,
+            height: 6,
+            color: [255, 255, 255, 255],
+            anchor,
+        }
+    }
+
+    #[test]
+    fn parses_sizes_and_colors() {
+        assert_eq!(parse_dimensions("1000x800"), Ok((1000, 800)));
+        assert!(parse_dimensions("1000").is_err());
+        assert!(parse_dimensions("0x800").is_err());
+        assert_eq!(parse_color("#f80"), Ok([255, 136, 0, 255]));
+        assert_eq!(parse_color("#10204080"), Ok([16, 32, 64, 128]));
+        assert_eq!(parse_color("transparent"), Ok([0, 0, 0, 0]));
+        assert!(parse_color("#12345").is_err());
+        assert!(parse_color("#ggg").is_err());
+    }
+
+    #[test]
+    fn places_at_anchor() {
+        let red = Rgba([255, 0, 0, 255]);
+        let image = DynamicImage::ImageRgba8(RgbaImage::from_pixel(4, 2, red));
+
+        let centered = place(&image, &canvas(Anchor::Center));
+        assert_eq!(centered.dimensions(), (10, 6));
+        assert_eq!(*centered.get_pixel(3, 2), red);
+        assert_eq!(*centered.get_pixel(6, 3), red);
+        assert_eq!(*centered.get_pixel(2, 2), Rgba([255, 255, 255, 255]));
+        assert_eq!(*centered.get_pixel(7, 3), Rgba([255, 255, 255, 255]));
+
+        let corner = place(&image, &canvas(Anchor::BottomRight));
+        assert_eq!(*corner.get_pixel(9, 5), red);
+        assert_eq!(*corner.get_pixel(5, 3), Rgba([255, 255, 255, 255]));
+    }
+
+    #[test]
+    fn larger_images_are_cut_down() {
+        let image = RgbaImage::from_fn(14, 6, |x, _| Rgba([x as u8, 0, 0, 255]));
+        let placed = place(&DynamicImage::ImageRgba8(image), &canvas(Anchor::Center));
+        assert_eq!(placed.get_pixel(0, 0)[0], 2);
+        assert_eq!(placed.get_pixel(9, 5)[0], 11);
+    }
+
+    #[test]
+    fn fills_with_matching_corners() {
+        let logo = RgbaImage::from_fn(10, 10, |x, y| match (x, y) {
+            (0, 0) => Rgba([20, 40, 60, 255]),
+            (4..=5, 4..=5) => Rgba([255, 0, 0, 255]),
+            _ => Rgba([24, 40, 60, 255]),
+        });
+        let logo = DynamicImage::ImageRgba8(logo);
+        assert_eq!(Fill::parse("auto"), Ok(Fill::Corners));
+        assert_eq!(Fill::Corners.color(&logo), [23, 40, 60, 255]);
+
+        let photo = RgbaImage::from_fn(10, 10, |x, _| Rgba([(x * 25) as u8, 0, 0, 255]));
+        let photo = DynamicImage::ImageRgba8(photo);
+        assert_eq!(Fill::Corners.color(&photo), [255, 255, 255, 255]);
+        assert_eq!(Fill::from([0, 0, 0, 255]).color(&photo), [0, 0, 0, 255]);
+    }
+}
@@ -0,0 +1,73 @@
+//! Naming outputs by when their source was captured.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Seek},
+};
+
+use exif::{In, Reader, Tag, Value};
+
+/// The EXIF capture time of the image at `path` as a file stem, e.g. `2024-06-01_142305`.
+pub fn read_stem(path: &str) -> Option<String> {
+    read_stem_from(&mut BufReader::new(File::open(path).ok()?))
+}
+
+/// Like `read_stem`, for an encoded image.
+pub fn read_stem_from<R: BufRead + Seek>(reader: &mut R) -> Option<String> {
+    let exif = Reader::new().read_from_container(reader).ok()?;
+    let field = exif.get_field(Tag::DateTimeOriginal, In::PRIMARY)?;
+    match &field.value {
+        Value::Ascii(values) => stem(&String::from_utf8_lossy(values.first()?)),
+        _ => None,
+    }
+}
+
+/// Converts an EXIF timestamp, `YYYY:MM:DD HH:MM:SS`, into a file stem.
+fn stem(datetime: &str) -> Option<String> {
+    let datetime = datetime.trim_end_matches('\0').trim();
+    let (date, time) = datetime.split_once(' ')?;
+    let date: Vec<_> = date.split(':').collect();
+    let time: Vec<_> = time.split(':').collect();
+
+    let widths = [4, 2, 2, 2, 2, 2];
+    let fields: Vec<&str> = date.iter().chain(&time).copied().collect();
+    let valid = fields.len() == widths.len()
+        && fields.iter().zip(&widths).all(|(field, &width)| {
+            field.len() == width && field.bytes().all(|b| b.is_ascii_digit())
+        });
+
+    // Cameras without a clock set write zeros, which make for a useless name.
+    if !valid || fields[0] == "0000" {
+        return None;
+    }
+
+    Some(format!(
+        "{}-{}-{}_{}{}{}",
+        fields[0], fields[1], fields[2], fields[3], fields[4], fields[5]
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::stem;
+
+    #[test]
+    fn stem_from_timestamp() {
+        assert_eq!(
+            stem("2024:06:01 14:23:05").as_deref(),
+            Some("2024-06-01_142305")
+        );
+        assert_eq!(
+            stem("2024:06:01 14:23:05\0").as_deref(),
+            Some("2024-06-01_142305")
+        );
+    }
+
+    #[test]
+    fn invalid_timestamps() {
+        assert_eq!(stem("0000:00:00 00:00:00"), None);
+        assert_eq!(stem("2024-06-01 14:23:05"), None);
+        assert_eq!(stem("2024:06:01"), None);
+        assert_eq!(stem("    :  :     :  :  "), None);
+    }
+}
@@ -0,0 +1,642 @@
+//! Converting images between color spaces by their embedded ICC profiles, for `--color-space`.
+//!
+//! Only matrix-and-curve RGB profiles are understood, which covers those of cameras, phones and
+//! the common working spaces. Anything else, such as a CMYK or lookup-table profile, is left
+//! alone.
+
+use image::{DynamicImage, ImageFormat};
+use serde::Serialize;
+
+use crate::dpi;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum ColorSpace {
+    Srgb,
+    DisplayP3,
+}
+
+impl ColorSpace {
+    pub const NAMES: &'static [&'static str] = &["srgb", "display-p3"];
+
+    pub fn from_name(name: &str) -> Option<ColorSpace> {
+        match name {
+            "srgb" => Some(ColorSpace::Srgb),
+            "display-p3" => Some(ColorSpace::DisplayP3),
+            _ => None,
+        }
+    }
+
+    /// The XYZ of each primary, adapted to D50 as ICC profiles have them, one per column.
+    fn primaries(self) -> Matrix {
+        match self {
+            ColorSpace::Srgb => [
+                [0.436_075, 0.385_065, 0.143_080],
+                [0.222_504, 0.716_879, 0.060_617],
+                [0.013_932, 0.097_105, 0.714_173],
+            ],
+            ColorSpace::DisplayP3 => [
+                [0.515_121, 0.291_977, 0.157_104],
+                [0.241_196, 0.692_245, 0.066_574],
+                [-0.001_053, 0.041_885, 0.784_073],
+            ],
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            ColorSpace::Srgb => "sRGB",
+            ColorSpace::DisplayP3 => "Display P3",
+        }
+    }
+
+    /// The profile outputs in this space are tagged with. Untagged images are taken to be
+    /// sRGB, so sRGB outputs need none.
+    pub fn profile(self) -> Option<Vec<u8>> {
+        match self {
+            ColorSpace::Srgb => None,
+            ColorSpace::DisplayP3 => Some(write_profile(self)),
+        }
+    }
+}
+
+/// Rows of columns.
+type Matrix = [[f64; 3]; 3];
+
+/// Both spaces share the sRGB transfer curve: ICC's parametric curve 3 with these parameters.
+const SRGB_CURVE: [f64; 5] = [2.4, 1.0 / 1.055, 0.055 / 1.055, 1.0 / 12.92, 0.04045];
+
+/// The D50 white point of ICC's connection space.
+const D50: [f64; 3] = [0.9642, 1.0, 0.8249];
+
+/// The Bradford adaptation from D65 to D50 that both spaces' primaries went through.
+const D65_TO_D50: Matrix = [
+    [1.047_811, 0.022_887, -0.050_127],
+    [0.029_542, 0.990_484, -0.017_049],
+    [-0.009_234, 0.015_044, 0.752_132],
+];
+
+/// A tone response curve, taking encoded values from 0 to 1 to linear light.
+#[derive(Clone, Debug, PartialEq)]
+enum Curve {
+    Gamma(f64),
+    /// Samples evenly spaced across the encoded range.
+    Table(Vec<f64>),
+    /// One of ICC's parametric functions, by number, with its parameters.
+    Parametric(u16, Vec<f64>),
+}
+
+impl Curve {
+    fn linear(&self, x: f64) -> f64 {
+        match self {
+            Curve::Gamma(gamma) => x.powf(*gamma),
+            Curve::Table(table) => {
+                let at = x * (table.len() - 1) as f64;
+                let below = at.floor() as usize;
+                let above = (below + 1).min(table.len() - 1);
+                let fraction = at - below as f64;
+                table[below] * (1.0 - fraction) + table[above] * fraction
+            }
+            Curve::Parametric(function, p) => {
+                let power = |x: f64| x.max(0.0).powf(p[0]);
+                match function {
+                    0 => power(x),
+                    1 if x >= -p[2] / p[1] => power(p[1] * x + p[2]),
+                    1 => 0.0,
+                    2 if x >= -p[2] / p[1] => power(p[1] * x + p[2]) + p[3],
+                    2 => p[3],
+                    3 if x >= p[4] => power(p[1] * x + p[2]),
+                    3 => p[3] * x,
+                    4 if x >= p[4] => power(p[1] * x + p[2]) + p[5],
+                    _ => p[3] * x + p[6],
+                }
+            }
+        }
+    }
+
+    /// Encodes linear light with the sRGB curve, the inverse of `linear` for `SRGB_CURVE`.
+    fn srgb_encoded(linear: f64) -> f64 {
+        if linear <= 0.003_130_8 {
+            linear * 12.92
+        } else {
+            1.055 * linear.powf(1.0 / 2.4) - 0.055
+        }
+    }
+}
+
+/// What an RGB profile says of its space.
+#[derive(Clone, Debug)]
+struct Profile {
+    primaries: Matrix,
+    curves: [Curve; 3],
+}
+
+impl Profile {
+    fn of(space: ColorSpace) -> Profile {
+        let curve = Curve::Parametric(3, SRGB_CURVE.to_vec());
+        Profile {
+            primaries: space.primaries(),
+            curves: [curve.clone(), curve.clone(), curve],
+        }
+    }
+
+    /// Reads the primaries and curves of a matrix-and-curve RGB profile.
+    fn parse(icc: &[u8]) -> Option<Profile> {
+        if icc.get(16..20)? != b"RGB " || icc.get(36..40)? != b"acsp" {
+            return None;
+        }
+        let tag = |signature: &[u8]| -> Option<&[u8]> {
+            let count = read_u32(icc, 128)? as usize;
+            (0..count).find_map(|index| {
+                let entry = 132 + index * 12;
+                if icc.get(entry..entry + 4)? != signature {
+                    return None;
+                }
+                let offset = read_u32(icc, entry + 4)? as usize;
+                let size = read_u32(icc, entry + 8)? as usize;
+                icc.get(offset..offset.checked_add(size)?)
+            })
+        };
+
+        let mut primaries = [[0.0; 3]; 3];
+        for (column, signature) in [b"rXYZ", b"gXYZ", b"bXYZ"].iter().enumerate() {
+            let xyz = parse_xyz(tag(&signature[..])?)?;
+            for row in 0..3 {
+                primaries[row][column] = xyz[row];
+            }
+        }
+        Some(Profile {
+            primaries,
+            curves: [
+                parse_curve(tag(b"rTRC")?)?,
+                parse_curve(tag(b"gTRC")?)?,
+                parse_curve(tag(b"bTRC")?)?,
+            ],
+        })
+    }
+
+    /// Whether this is, to within rounding, the profile of `space`.
+    fn is(&self, space: ColorSpace) -> bool {
+        let target = space.primaries();
+        let primaries_match = (0..3).all(|row| {
+            (0..3).all(|column| (self.primaries[row][column] - target[row][column]).abs() < 0.002)
+        });
+        let srgb = Profile::of(space).curves[0].clone();
+        let curves_match = self.curves.iter().all(|curve| {
+            (0..=16).all(|step| {
+                let x = f64::from(step) / 16.0;
+                (curve.linear(x) - srgb.linear(x)).abs() < 0.002
+            })
+        });
+        primaries_match && curves_match
+    }
+}
+
+fn read_u32(bytes: &[u8], at: usize) -> Option<u32> {
+    let bytes = bytes.get(at..at + 4)?;
+    Some(u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]))
+}
+
+fn read_fixed(bytes: &[u8], at: usize) -> Option<f64> {
+    Some(f64::from(read_u32(bytes, at)? as i32) / 65536.0)
+}
+
+fn parse_xyz(tag: &[u8]) -> Option<[f64; 3]> {
+    if tag.get(..4)? != b"XYZ " {
+        return None;
+    }
+    Some([
+        read_fixed(tag, 8)?,
+        read_fixed(tag, 12)?,
+        read_fixed(tag, 16)?,
+    ])
+}
+
+fn parse_curve(tag: &[u8]) -> Option<Curve> {
+    let read_u16 = |at: usize| {
+        let bytes = tag.get(at..at + 2)?;
+        Some(u16::from_be_bytes([bytes[0], bytes[1]]))
+    };
+    match tag.get(..4)? {
+        b"curv" => match read_u32(tag, 8)? {
+            0 => Some(Curve::Gamma(1.0)),
+            1 => Some(Curve::Gamma(f64::from(read_u16(12)?) / 256.0)),
+            count => (0..count as usize)
+                .map(|index| Some(f64::from(read_u16(12 + index * 2)?) / 65535.0))
+                .collect::<Option<_>>()
+                .map(Curve::Table),
+        },
+        b"para" => {
+            let function = read_u16(8)?;
+            let count = [1, 3, 4, 5, 7].get(usize::from(function))?;
+            (0..*count)
+                .map(|index| read_fixed(tag, 12 + index * 4))
+                .collect::<Option<_>>()
+                .map(|parameters| Curve::Parametric(function, parameters))
+        }
+        _ => None,
+    }
+}
+
+/// The ICC profile embedded in an encoded JPEG or PNG, if there is one.
+pub fn embedded_profile(bytes: &[u8]) -> Option<Vec<u8>> {
+    if bytes.starts_with(&[0xFF, 0xD8]) {
+        jpeg_profile(bytes)
+    } else if bytes.starts_with(b"\x89PNG\r\n\x1a\n") {
+        png_profile(bytes)
+    } else {
+        None
+    }
+}
+
+/// Gathers the `APP2` segments a JPEG's profile is split across, in order.
+fn jpeg_profile(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut chunks = Vec::new();
+    let mut at = 2;
+    while let Some(&[0xFF, marker, high, low]) = bytes.get(at..at + 4) {
+        // Segments with data all come before the scan begins.
+        if marker == 0xDA || marker == 0xD9 {
+            break;
+        }
+        let length = usize::from(u16::from_be_bytes([high, low]));
+        let data = bytes.get(at + 4..at + 2 + length)?;
+        if marker == 0xE2 && data.starts_with(b"ICC_PROFILE\0") && data.len() > 14 {
+            chunks.push((data[12], &data[14..]));
+        }
+        at += 2 + length;
+    }
+    if chunks.is_empty() {
+        return None;
+    }
+    chunks.sort_by_key(|&(sequence, _)| sequence);
+    Some(
+        chunks
+            .into_iter()
+            .flat_map(|(_, chunk)| chunk)
+            .copied()
+            .collect(),
+    )
+}
+
+/// Inflates a PNG's `iCCP` chunk.
+fn png_profile(bytes: &[u8]) -> Option<Vec<u8>> {
+    let mut at = 8;
+    while let Some(length) = read_u32(bytes, at) {
+        let kind = bytes.get(at + 4..at + 8)?;
+        let data = bytes.get(at + 8..at + 8 + length as usize)?;
+        match kind {
+            b"iCCP" => {
+                let name_end = data.iter().position(|&byte| byte == 0)?;
+                let compressed = data.get(name_end + 2..)?;
+                return miniz_oxide::inflate::decompress_to_vec_zlib(compressed).ok();
+            }
+            b"IDAT" => return None,
+            _ => at += 12 + length as usize,
+        }
+    }
+    None
+}
+
+/// Converts `image` to `target` from the space its profile `icc` describes, or from sRGB if it
+/// has none. Nothing needs converting, and `None` comes back, for grayscale images, images
+/// already in `target`, and profiles that can't be read.
+pub fn convert(
+    image: &DynamicImage,
+    icc: Option<&[u8]>,
+    target: ColorSpace,
+) -> Option<DynamicImage> {
+    // Gray is the same in both spaces, which share a white point and curve.
+    if !image.color().has_color() {
+        return None;
+    }
+    let source = match icc {
+        Some(icc) => Profile::parse(icc)?,
+        None => Profile::of(ColorSpace::Srgb),
+    };
+    if source.is(target) {
+        return None;
+    }
+    let conversion = Conversion::new(&source, target);
+
+    Some(match image {
+        DynamicImage::ImageRgb8(buffer) => {
+            let mut buffer = buffer.clone();
+            conversion.apply(&mut buffer, 3);
+            DynamicImage::ImageRgb8(buffer)
+        }
+        DynamicImage::ImageRgb16(buffer) => {
+            let mut buffer = buffer.clone();
+            conversion.apply(&mut buffer, 3);
+            DynamicImage::ImageRgb16(buffer)
+        }
+        DynamicImage::ImageRgba16(buffer) => {
+            let mut buffer = buffer.clone();
+            conversion.apply(&mut buffer, 4);
+            DynamicImage::ImageRgba16(buffer)
+        }
+        _ => {
+            let mut buffer = image.to_rgba();
+            conversion.apply(&mut buffer, 4);
+            DynamicImage::ImageRgba8(buffer)
+        }
+    })
+}
+
+/// A conversion between spaces, by way of linear light in ICC's XYZ.
+struct Conversion {
+    curves: [Curve; 3],
+    /// From linear source RGB to linear target RGB.
+    matrix: Matrix,
+    /// The sRGB curve, indexed by linear light in steps of 1/65535, as 16-bit values.
+    encode: Vec<u16>,
+}
+
+impl Conversion {
+    fn new(source: &Profile, target: ColorSpace) -> Conversion {
+        let encode = (0..=u16::MAX)
+            .map(|step| {
+                let encoded = Curve::srgb_encoded(f64::from(step) / 65535.0);
+                (encoded * 65535.0).round() as u16
+            })
+            .collect();
+        Conversion {
+            curves: source.curves.clone(),
+            matrix: multiply(&invert(&target.primaries()), &source.primaries),
+            encode,
+        }
+    }
+
+    /// Converts the color channels of pixels of `channels` samples each, leaving any alpha.
+    fn apply<S: Sample>(&self, samples: &mut [S], channels: usize) {
+        let max = S::MAX;
+        let linear: Vec<Vec<f64>> = self
+            .curves
+            .iter()
+            .map(|curve| {
+                (0..=max)
+                    .map(|value| curve.linear(f64::from(value) / f64::from(max)))
+                    .collect()
+            })
+            .collect();
+
+        for pixel in samples.chunks_exact_mut(channels) {
+            let rgb = [0, 1, 2].map(|channel| linear[channel][usize::from(pixel[channel].widen())]);
+            for (channel, row) in self.matrix.iter().enumerate() {
+                let value = row[0] * rgb[0] + row[1] * rgb[1] + row[2] * rgb[2];
+                let step = (value.clamp(0.0, 1.0) * 65535.0).round() as usize;
+                pixel[channel] = S::narrow(self.encode[step]);
+            }
+        }
+    }
+}
+
+/// An 8- or 16-bit sample.
+trait Sample: Copy {
+    const MAX: u16;
+    fn widen(self) -> u16;
+    /// From a 16-bit value, rounding.
+    fn narrow(value: u16) -> Self;
+}
+
+impl Sample for u8 {
+    const MAX: u16 = 255;
+    fn widen(self) -> u16 {
+        u16::from(self)
+    }
+    fn narrow(value: u16) -> u8 {
+        ((u32::from(value) * 255 + 32767) / 65535) as u8
+    }
+}
+
+impl Sample for u16 {
+    const MAX: u16 = u16::MAX;
+    fn widen(self) -> u16 {
+        self
+    }
+    fn narrow(value: u16) -> u16 {
+        value
+    }
+}
+
+fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut product = [[0.0; 3]; 3];
+    for (row, products) in product.iter_mut().enumerate() {
+        for (column, value) in products.iter_mut().enumerate() {
+            *value = (0..3).map(|k| a[row][k] * b[k][column]).sum();
+        }
+    }
+    product
+}
+
+fn invert(m: &Matrix) -> Matrix {
+    let cofactor = |row: usize, column: usize| {
+        let (r1, r2) = ((row + 1) % 3, (row + 2) % 3);
+        let (c1, c2) = ((column + 1) % 3, (column + 2) % 3);
+        m[r1][c1] * m[r2][c2] - m[r1][c2] * m[r2][c1]
+    };
+    let determinant = (0..3)
+        .map(|column| m[0][column] * cofactor(0, column))
+        .sum::<f64>();
+    let mut inverse = [[0.0; 3]; 3];
+    for (row, values) in inverse.iter_mut().enumerate() {
+        for (column, value) in values.iter_mut().enumerate() {
+            *value = cofactor(column, row) / determinant;
+        }
+    }
+    inverse
+}
+
+/// Tags an encoded JPEG or PNG as being in `space`. Other formats are left as they are.
+pub fn tag(bytes: &mut Vec<u8>, format: ImageFormat, space: ColorSpace) {
+    let profile = match space.profile() {
+        Some(profile) => profile,
+        None => return,
+    };
+    match format {
+        ImageFormat::Jpeg => tag_jpeg(bytes, &profile),
+        ImageFormat::Png => tag_png(bytes, &profile, space),
+        _ => (),
+    }
+}
+
+/// Adds the profile in an `APP2` segment, after the JFIF header if there is one. Generated
+/// profiles are small enough to need only the one segment.
+fn tag_jpeg(bytes: &mut Vec<u8>, profile: &[u8]) {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return;
+    }
+    let at = match bytes.get(2..6) {
+        Some(&[0xFF, 0xE0, high, low]) => 4 + usize::from(u16::from_be_bytes([high, low])),
+        _ => 2,
+    };
+
+    let mut segment = vec![0xFF, 0xE2];
+    segment.extend_from_slice(&(2 + 14 + profile.len() as u16).to_be_bytes());
+    segment.extend_from_slice(b"ICC_PROFILE\0");
+    // The first of one chunk.
+    segment.extend_from_slice(&[1, 1]);
+    segment.extend_from_slice(profile);
+    bytes.splice(at..at, segment);
+}
+
+/// Adds an `iCCP` chunk after the header.
+fn tag_png(bytes: &mut Vec<u8>, profile: &[u8], space: ColorSpace) {
+    let mut data = space.name().as_bytes().to_vec();
+    // The name's terminator, then deflate as the compression method.
+    data.extend_from_slice(&[0, 0]);
+    data.extend_from_slice(&miniz_oxide::deflate::compress_to_vec_zlib(profile, 9));
+    dpi::insert_png_chunk(bytes, b"iCCP", &data);
+}
+
+/// A version 4 display profile for `space`, with its primaries and the sRGB curve.
+fn write_profile(space: ColorSpace) -> Vec<u8> {
+    let fixed = |value: f64| ((value * 65536.0).round() as i32).to_be_bytes();
+    let xyz = |values: [f64; 3]| {
+        let mut tag = b"XYZ \0\0\0\0".to_vec();
+        for value in &values {
+            tag.extend_from_slice(&fixed(*value));
+        }
+        tag
+    };
+    let text = |text: &str| {
+        let utf16: Vec<u8> = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+        let mut tag = b"mluc\0\0\0\0".to_vec();
+        // One record, of 12 bytes, in US English, its text starting after the record.
+        tag.extend_from_slice(&1u32.to_be_bytes());
+        tag.extend_from_slice(&12u32.to_be_bytes());
+        tag.extend_from_slice(b"enUS");
+        tag.extend_from_slice(&(utf16.len() as u32).to_be_bytes());
+        tag.extend_from_slice(&28u32.to_be_bytes());
+        tag.extend_from_slice(&utf16);
+        tag
+    };
+
+    let primaries = space.primaries();
+    let primary = |column: usize| xyz([0, 1, 2].map(|row| primaries[row][column]));
+    let mut curve = b"para\0\0\0\0\0\x03\0\0".to_vec();
+    for parameter in &SRGB_CURVE {
+        curve.extend_from_slice(&fixed(*parameter));
+    }
+    let mut adaptation = b"sf32\0\0\0\0".to_vec();
+    for value in D65_TO_D50.iter().flatten() {
+        adaptation.extend_from_slice(&fixed(*value));
+    }
+
+    // The curve is shared by all three channels, so its data appears once.
+    let tags: [(&[u8; 4], usize); 10] = [
+        (b"desc", 0),
+        (b"cprt", 1),
+        (b"wtpt", 2),
+        (b"rXYZ", 3),
+        (b"gXYZ", 4),
+        (b"bXYZ", 5),
+        (b"rTRC", 6),
+        (b"gTRC", 6),
+        (b"bTRC", 6),
+        (b"chad", 7),
+    ];
+    let data = [
+        text(space.name()),
+        text("No copyright, use freely"),
+        xyz(D50),
+        primary(0),
+        primary(1),
+        primary(2),
+        curve,
+        adaptation,
+    ];
+
+    let mut offsets = Vec::new();
+    let mut body = Vec::new();
+    let start = 128 + 4 + tags.len() * 12;
+    for tag in &data {
+        offsets.push(start + body.len());
+        body.extend_from_slice(tag);
+        // Every tag starts on a four-byte boundary.
+        body.resize(body.len().div_ceil(4) * 4, 0);
+    }
+
+    let mut profile = vec![0; 128];
+    let size = (start + body.len()) as u32;
+    profile[0..4].copy_from_slice(&size.to_be_bytes());
+    profile[8..12].copy_from_slice(&[4, 0x30, 0, 0]);
+    profile[12..16].copy_from_slice(b"mntr");
+    profile[16..20].copy_from_slice(b"RGB ");
+    profile[20..24].copy_from_slice(b"XYZ ");
+    // Created at the start of 2024.
+    profile[24..26].copy_from_slice(&2024u16.to_be_bytes());
+    profile[27] = 1;
+    profile[29] = 1;
+    profile[36..40].copy_from_slice(b"acsp");
+    for (index, value) in D50.iter().enumerate() {
+        profile[68 + index * 4..72 + index * 4].copy_from_slice(&fixed(*value));
+    }
+
+    profile.extend_from_slice(&(tags.len() as u32).to_be_bytes());
+    for (signature, index) in &tags {
+        profile.extend_from_slice(&signature[..]);
+        profile.extend_from_slice(&(offsets[*index] as u32).to_be_bytes());
+        profile.extend_from_slice(&(data[*index].len() as u32).to_be_bytes());
+    }
+    profile.extend_from_slice(&body);
+    profile
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert, embedded_profile, tag, ColorSpace, Profile};
+    use image::{DynamicImage, ImageFormat, Rgb, RgbImage};
+
+    fn pixel(image: &DynamicImage) -> [u8; 3] {
+        let rgb = image.to_rgb();
+        rgb.get_pixel(0, 0).0
+    }
+
+    fn solid(color: [u8; 3]) -> DynamicImage {
+        DynamicImage::ImageRgb8(RgbImage::from_pixel(2, 2, Rgb(color)))
+    }
+
+    #[test]
+    fn generated_profiles_read_back() {
+        let icc = ColorSpace::DisplayP3.profile().unwrap();
+        let profile = Profile::parse(&icc).unwrap();
+        assert!(profile.is(ColorSpace::DisplayP3));
+        assert!(!profile.is(ColorSpace::Srgb));
+        assert!(ColorSpace::Srgb.profile().is_none());
+    }
+
+    #[test]
+    fn converts_between_spaces() {
+        // sRGB's red is well inside Display P3, so it is less saturated there.
+        let red = convert(&solid([255, 0, 0]), None, ColorSpace::DisplayP3).unwrap();
+        assert_eq!(pixel(&red), [234, 51, 35]);
+
+        let p3 = ColorSpace::DisplayP3.profile().unwrap();
+        let back = convert(&red, Some(&p3), ColorSpace::Srgb).unwrap();
+        assert_eq!(pixel(&back), [255, 0, 0]);
+
+        // Grays are the same in both, and sRGB needs no converting to sRGB.
+        let gray = convert(&solid([128, 128, 128]), Some(&p3), ColorSpace::Srgb).unwrap();
+        assert_eq!(pixel(&gray), [128, 128, 128]);
+        assert!(convert(&solid([200, 100, 50]), None, ColorSpace::Srgb).is_none());
+        assert!(convert(&red, Some(&p3), ColorSpace::DisplayP3).is_none());
+    }
+
+    #[test]
+    fn unreadable_profiles_are_left_alone() {
+        assert!(convert(&solid([200, 100, 50]), Some(b"junk"), ColorSpace::Srgb).is_none());
+    }
+
+    #[test]
+    fn tags_round_trip() {
+        for &format in &[ImageFormat::Jpeg, ImageFormat::Png] {
+            let mut bytes = Vec::new();
+            solid([10, 20, 30]).write_to(&mut bytes, format).unwrap();
+            assert!(embedded_profile(&bytes).is_none());
+
+            tag(&mut bytes, format, ColorSpace::DisplayP3);
+            assert_eq!(embedded_profile(&bytes), ColorSpace::DisplayP3.profile());
+            assert!(image::load_from_memory(&bytes).is_ok(), "{:?}", format);
+        }
+    }
+}
@@ -0,0 +1,50 @@
+//! Before-and-after images, for judging a resize by eye.
+
+use image::{
+    imageops::{self, FilterType},
+    DynamicImage, GenericImageView, Rgba, RgbaImage,
+};
+
+/// Width of the divider between the two halves.
+const DIVIDER_WIDTH: u32 = 4;
+
+/// Magenta, which photos rarely contain, so the divider stands out.
+const DIVIDER_COLOR: Rgba<u8> = Rgba([255, 0, 255, 255]);
+
+/// The source, scaled to the output's dimensions, to the left of the output.
+///
+/// `output` should be decoded from what was written, so compression artifacts show. The
+/// source is scaled with the triangle filter, which adds none of its own.
+pub fn side_by_side(source: &DynamicImage, output: &DynamicImage) -> RgbaImage {
+    let (width, height) = output.dimensions();
+    let reference = imageops::resize(source, width, height, FilterType::Triangle);
+
+    let mut canvas = RgbaImage::from_pixel(width * 2 + DIVIDER_WIDTH, height, DIVIDER_COLOR);
+    imageops::replace(&mut canvas, &reference, 0, 0);
+    imageops::replace(&mut canvas, &output.to_rgba(), width + DIVIDER_WIDTH, 0);
+    canvas
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{side_by_side, DIVIDER_COLOR, DIVIDER_WIDTH};
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    #[test]
+    fn source_left_of_output() {
+        let source = RgbaImage::from_pixel(200, 100, Rgba([255, 255, 255, 255]));
+        let output = RgbaImage::from_pixel(50, 25, Rgba([0, 0, 0, 255]));
+        let canvas = side_by_side(
+            &DynamicImage::ImageRgba8(source),
+            &DynamicImage::ImageRgba8(output),
+        );
+
+        assert_eq!(canvas.dimensions(), (100 + DIVIDER_WIDTH, 25));
+        assert_eq!(canvas.get_pixel(0, 0), &Rgba([255, 255, 255, 255]));
+        assert_eq!(canvas.get_pixel(50, 12), &DIVIDER_COLOR);
+        assert_eq!(
+            canvas.get_pixel(50 + DIVIDER_WIDTH, 24),
+            &Rgba([0, 0, 0, 255])
+        );
+    }
+}
@@ -0,0 +1,310 @@
+//! Cropping to an aspect ratio or to visible content, or into a grid of pieces.
+
+use image::{DynamicImage, GenericImageView};
+use serde::Serialize;
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct Aspect {
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Aspect {
+    pub const SQUARE: Aspect = Aspect {
+        width: 1,
+        height: 1,
+    };
+
+    /// Parses a ratio such as `16:9`.
+    pub fn parse(s: &str) -> Result<Aspect, String> {
+        let invalid = || format!("'{}' is not an aspect ratio such as 16:9", s);
+        let (width, height) = s.split_once(':').ok_or_else(invalid)?;
+        let width: u32 = width.trim().parse().map_err(|_| invalid())?;
+        let height: u32 = height.trim().parse().map_err(|_| invalid())?;
+
+        if width == 0 || height == 0 {
+            return Err(invalid());
+        }
+        Ok(Aspect { width, height })
+    }
+
+    /// Whether a `width` by `height` image is within `tolerance` of this aspect, as a fraction
+    /// of it, e.g. 0.02 for 2%.
+    pub fn matches(self, width: u32, height: u32, tolerance: f64) -> bool {
+        let actual = width as f64 / height as f64;
+        let wanted = self.width as f64 / self.height as f64;
+        (actual / wanted - 1.0).abs() <= tolerance
+    }
+}
+
+/// What `--max-aspect` does with images more elongated than it allows.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AspectAction {
+    /// Center-crops them to the limit.
+    Crop,
+    /// Leaves them out.
+    Skip,
+}
+
+impl AspectAction {
+    pub const NAMES: &'static [&'static str] = &["crop", "skip"];
+
+    pub fn from_name(name: &str) -> Option<AspectAction> {
+        match name {
+            "crop" => Some(AspectAction::Crop),
+            "skip" => Some(AspectAction::Skip),
+            _ => None,
+        }
+    }
+}
+
+/// How elongated an image may be, as its long edge over its short one, and what to do with
+/// those beyond it.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+pub struct MaxAspect {
+    pub ratio: f64,
+    pub action: AspectAction,
+}
+
+/// A region of an image, as `(x, y, width, height)`.
+pub type Rect = (u32, u32, u32, u32);
+
+/// A grid to cut images into, as `--split` does.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Grid {
+    pub columns: u32,
+    pub rows: u32,
+    /// How far each piece reaches into its neighbors, in pixels, for print bleed.
+    pub overlap: u32,
+}
+
+impl Grid {
+    /// Parses a grid of columns by rows, such as `3x2`, without overlap.
+    pub fn parse(s: &str) -> Result<Grid, String> {
+        let invalid = || format!("'{}' is not a grid such as 3x2", s);
+        let (columns, rows) = s.split_once('x').ok_or_else(invalid)?;
+        let columns: u32 = columns.trim().parse().map_err(|_| invalid())?;
+        let rows: u32 = rows.trim().parse().map_err(|_| invalid())?;
+
+        if columns == 0 || rows == 0 {
+            return Err(invalid());
+        }
+        Ok(Grid {
+            columns,
+            rows,
+            overlap: 0,
+        })
+    }
+
+    /// Each piece of a `width` by `height` image, row by row, as `(row, column, rect)`. Pixels
+    /// that don't divide evenly go one apiece to the first rows and columns.
+    pub fn pieces(self, width: u32, height: u32) -> Vec<(u32, u32, Rect)> {
+        let columns = spans(width, self.columns, self.overlap);
+        let rows = spans(height, self.rows, self.overlap);
+        let mut pieces = Vec::with_capacity(columns.len() * rows.len());
+        for (row, &(y, height)) in (0..).zip(&rows) {
+            for (column, &(x, width)) in (0..).zip(&columns) {
+                pieces.push((row, column, (x, y, width, height)));
+            }
+        }
+        pieces
+    }
+}
+
+/// Splits `length` into `count` spans, as `(start, length)`, each reaching `overlap` further
+/// into its neighbors.
+fn spans(length: u32, count: u32, overlap: u32) -> Vec<(u32, u32)> {
+    let (base, remainder) = (length / count, length % count);
+    let mut start = 0;
+    (0..count)
+        .map(|index| {
+            let end = start + base + u32::from(index < remainder);
+            let span = (start.saturating_sub(overlap), (end + overlap).min(length));
+            start = end;
+            (span.0, span.1 - span.0)
+        })
+        .collect()
+}
+
+/// The largest region of a `width` by `height` image with the given aspect, centered, as
+/// `(x, y, width, height)`.
+pub fn center_rect(width: u32, height: u32, aspect: Aspect) -> (u32, u32, u32, u32) {
+    let (a, b) = (aspect.width as u64, aspect.height as u64);
+    let (w, h) = (width as u64, height as u64);
+
+    let (cw, ch) = if w * b > h * a {
+        // Too wide: keep the full height and trim the sides.
+        (((h * a) / b).max(1) as u32, height)
+    } else {
+        // Too tall: keep the full width and trim the top and bottom.
+        (width, ((w * b) / a).max(1) as u32)
+    };
+
+    ((width - cw) / 2, (height - ch) / 2, cw, ch)
+}
+
+/// The largest region of a `width` by `height` image, centered, whose long edge is no more than
+/// `ratio` times its short one, or `None` if the whole image already is.
+pub fn clamp_rect(width: u32, height: u32, ratio: f64) -> Option<Rect> {
+    let (long, short) = (width.max(height), width.min(height));
+    if f64::from(long) <= f64::from(short) * ratio {
+        return None;
+    }
+    // The ratio to a thousandth is as close as whole pixels get for all but huge images.
+    let long = (ratio * 1000.0).round() as u32;
+    let aspect = match width >= height {
+        true => Aspect {
+            width: long,
+            height: 1000,
+        },
+        false => Aspect {
+            width: 1000,
+            height: long,
+        },
+    };
+    Some(center_rect(width, height, aspect))
+}
+
+/// The dimensions of the smallest canvas with the given aspect that holds a `width` by `height`
+/// image, as `--pad-aspect` pads to.
+pub fn padded_dimensions(width: u32, height: u32, aspect: Aspect) -> (u32, u32) {
+    let (a, b) = (aspect.width as u64, aspect.height as u64);
+    let (w, h) = (width as u64, height as u64);
+
+    if w * b > h * a {
+        // Too wide: keep the full width and add to the top and bottom.
+        (width, (w * b).div_ceil(a).min(u32::MAX as u64) as u32)
+    } else {
+        // Too tall: keep the full height and add to the sides.
+        ((h * a).div_ceil(b).min(u32::MAX as u64) as u32, height)
+    }
+}
+
+/// The smallest region of `image` holding every pixel that isn't fully transparent, as
+/// `(x, y, width, height)`, or `None` if there are no such pixels. Images without alpha are
+/// visible throughout.
+pub fn visible_rect(image: &DynamicImage) -> Option<(u32, u32, u32, u32)> {
+    let (width, height) = image.dimensions();
+    if !image.color().has_alpha() {
+        return Some((0, 0, width, height));
+    }
+
+    let visible = |x, y| image.get_pixel(x, y)[3] != 0;
+    let row = |y| (0..width).any(|x| visible(x, y));
+    let top = (0..height).find(|&y| row(y))?;
+    let bottom = (top..height).rev().find(|&y| row(y))?;
+    let column = |x| (top..=bottom).any(|y| visible(x, y));
+    let left = (0..width).find(|&x| column(x))?;
+    let right = (left..width).rev().find(|&x| column(x))?;
+
+    Some((left, top, right - left + 1, bottom - top + 1))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{center_rect, clamp_rect, padded_dimensions, visible_rect, Aspect, Grid};
+    use image::{DynamicImage, Rgba, RgbaImage};
+
+    #[test]
+    fn parse_aspect() {
+        assert_eq!(
+            Aspect::parse("16:9"),
+            Ok(Aspect {
+                width: 16,
+                height: 9
+            })
+        );
+        assert!(Aspect::parse("16x9").is_err());
+        assert!(Aspect::parse("0:1").is_err());
+        assert!(Aspect::parse("4:").is_err());
+    }
+
+    #[test]
+    fn aspect_within_tolerance() {
+        let aspect = Aspect::parse("16:9").unwrap();
+        assert!(aspect.matches(1920, 1080, 0.0));
+        assert!(aspect.matches(1920, 1100, 0.02));
+        assert!(!aspect.matches(1920, 1200, 0.02));
+        assert!(!aspect.matches(1920, 1100, 0.0));
+    }
+
+    #[test]
+    fn portrait_to_16_9_trims_top_and_bottom() {
+        let aspect = Aspect::parse("16:9").unwrap();
+        assert_eq!(center_rect(3000, 4000, aspect), (0, 1156, 3000, 1687));
+    }
+
+    #[test]
+    fn landscape_to_4_5_trims_sides() {
+        let aspect = Aspect::parse("4:5").unwrap();
+        assert_eq!(center_rect(6000, 4000, aspect), (1400, 0, 3200, 4000));
+    }
+
+    #[test]
+    fn matching_aspect_is_untouched() {
+        assert_eq!(
+            center_rect(1920, 1080, Aspect::parse("16:9").unwrap()),
+            (0, 0, 1920, 1080)
+        );
+        assert_eq!(center_rect(300, 200, Aspect::SQUARE), (50, 0, 200, 200));
+    }
+
+    #[test]
+    fn pads_to_aspect() {
+        assert_eq!(padded_dimensions(300, 200, Aspect::SQUARE), (300, 300));
+        assert_eq!(padded_dimensions(200, 300, Aspect::SQUARE), (300, 300));
+        let aspect = Aspect::parse("16:9").unwrap();
+        assert_eq!(padded_dimensions(1000, 1000, aspect), (1778, 1000));
+        assert_eq!(padded_dimensions(1920, 1000, aspect), (1920, 1080));
+        assert_eq!(padded_dimensions(1920, 1080, aspect), (1920, 1080));
+    }
+
+    #[test]
+    fn trims_transparent_edges() {
+        let mut sprite = RgbaImage::new(10, 8);
+        sprite.put_pixel(2, 3, Rgba([0, 0, 0, 1]));
+        sprite.put_pixel(6, 5, Rgba([255, 0, 0, 255]));
+        let sprite = DynamicImage::ImageRgba8(sprite);
+        assert_eq!(visible_rect(&sprite), Some((2, 3, 5, 3)));
+
+        let empty = DynamicImage::ImageRgba8(RgbaImage::new(10, 8));
+        assert_eq!(visible_rect(&empty), None);
+        let opaque = DynamicImage::new_rgb8(10, 8);
+        assert_eq!(visible_rect(&opaque), Some((0, 0, 10, 8)));
+    }
+
+    #[test]
+    fn splits_into_grid() {
+        let grid = Grid::parse("3x2").unwrap();
+        assert_eq!((grid.columns, grid.rows), (3, 2));
+        assert!(Grid::parse("3").is_err());
+        assert!(Grid::parse("0x2").is_err());
+
+        // Ten columns of pixels go four, three and three; five rows go three and two.
+        let pieces = grid.pieces(10, 5);
+        assert_eq!(pieces.len(), 6);
+        assert_eq!(pieces[0], (0, 0, (0, 0, 4, 3)));
+        assert_eq!(pieces[2], (0, 2, (7, 0, 3, 3)));
+        assert_eq!(pieces[4], (1, 1, (4, 3, 3, 2)));
+    }
+
+    #[test]
+    fn overlapping_pieces_stay_within_the_image() {
+        let grid = Grid {
+            overlap: 2,
+            ..Grid::parse("2x1").unwrap()
+        };
+        assert_eq!(
+            grid.pieces(10, 4),
+            vec![(0, 0, (0, 0, 7, 4)), (0, 1, (3, 0, 7, 4))]
+        );
+    }
+
+    #[test]
+    fn clamps_elongated_images() {
+        assert_eq!(clamp_rect(3000, 1000, 3.0), None);
+        assert_eq!(clamp_rect(4000, 1000, 3.0), Some((500, 0, 3000, 1000)));
+        assert_eq!(clamp_rect(1000, 5000, 2.5), Some((0, 1250, 1000, 2500)));
+    }
+}
@@ -0,0 +1,86 @@
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// Parses a calendar date of the form `YYYY-MM-DD` as midnight UTC.
+pub fn parse_date(s: &str) -> Result<SystemTime, String> {
+    let invalid = || format!("invalid date '{}' (expected YYYY-MM-DD)", s);
+
+    let mut parts = s.splitn(3, '-');
+    let mut next = |len: usize| {
+        parts
+            .next()
+            .filter(|part| part.len() == len && part.bytes().all(|b| b.is_ascii_digit()))
+            .and_then(|part| part.parse::<u32>().ok())
+            .ok_or_else(invalid)
+    };
+
+    let year = next(4)?;
+    let month = next(2)?;
+    let day = next(2)?;
+
+    if month == 0 || month > 12 || day == 0 || day > days_in_month(year, month) {
+        return Err(invalid());
+    }
+
+    let days = days_from_civil(year as i64, month as i64, day as i64);
+    let offset = Duration::from_secs(days.unsigned_abs() * 86_400);
+    if days < 0 {
+        Ok(UNIX_EPOCH - offset)
+    } else {
+        Ok(UNIX_EPOCH + offset)
+    }
+}
+
+fn days_in_month(year: u32, month: u32) -> u32 {
+    match month {
+        2 if is_leap_year(year) => 29,
+        2 => 28,
+        4 | 6 | 9 | 11 => 30,
+        _ => 31,
+    }
+}
+
+fn is_leap_year(year: u32) -> bool {
+    year.is_multiple_of(4) && (!year.is_multiple_of(100) || year.is_multiple_of(400))
+}
+
+/// Days since 1970-01-01 for a proleptic Gregorian date.
+///
+/// See Howard Hinnant's `days_from_civil` for the derivation.
+fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (month + if month > 2 { -3 } else { 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+#[cfg(test)]
+mod tests {
+    use super::parse_date;
+    use std::time::{Duration, UNIX_EPOCH};
+
+    #[test]
+    fn epoch() {
+        assert_eq!(parse_date("1970-01-01"), Ok(UNIX_EPOCH));
+    }
+
+    #[test]
+    fn new_year_2024() {
+        let expected = UNIX_EPOCH + Duration::from_secs(1_704_067_200);
+        assert_eq!(parse_date("2024-01-01"), Ok(expected));
+    }
+
+    #[test]
+    fn leap_day() {
+        assert!(parse_date("2024-02-29").is_ok());
+        assert!(parse_date("2023-02-29").is_err());
+    }
+
+    #[test]
+    fn malformed() {
+        assert!(parse_date("2024-1-01").is_err());
+        assert!(parse_date("2024-13-01").is_err());
+        assert!(parse_date("yesterday").is_err());
+    }
+}
@@ -0,0 +1,155 @@
+//! Converting images between 8 and 16 bits per channel, for `--bit-depth`.
+
+use std::borrow::Cow;
+
+use image::{
+    buffer::ConvertBuffer, ColorType, DynamicImage, GenericImageView, ImageBuffer, ImageFormat,
+    Rgba,
+};
+use serde::Serialize;
+
+pub type Rgba16Image = ImageBuffer<Rgba<u16>, Vec<u16>>;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub enum BitDepth {
+    #[serde(rename = "8")]
+    Eight,
+    #[serde(rename = "16")]
+    Sixteen,
+}
+
+impl BitDepth {
+    pub const NAMES: &'static [&'static str] = &["8", "16"];
+
+    pub fn from_name(name: &str) -> Option<BitDepth> {
+        match name {
+            "8" => Some(BitDepth::Eight),
+            "16" => Some(BitDepth::Sixteen),
+            _ => None,
+        }
+    }
+
+    fn of(color: ColorType) -> BitDepth {
+        match color.bits_per_pixel() / u16::from(color.channel_count()) {
+            16 => BitDepth::Sixteen,
+            _ => BitDepth::Eight,
+        }
+    }
+}
+
+/// Whether `format` can be written with 16 bits per channel; every other is always 8.
+pub fn holds_sixteen(format: ImageFormat) -> bool {
+    matches!(format, ImageFormat::Png | ImageFormat::Tiff)
+}
+
+/// `image` at `depth`, keeping its channels, or as it is if it's there already.
+pub fn convert(image: &DynamicImage, depth: BitDepth) -> Cow<'_, DynamicImage> {
+    if BitDepth::of(image.color()) == depth {
+        return Cow::Borrowed(image);
+    }
+    Cow::Owned(match depth {
+        BitDepth::Eight => narrow_image(image),
+        BitDepth::Sixteen => DynamicImage::ImageRgba16(rgba16(image)),
+    })
+}
+
+/// A 16-bit `image` narrowed to 8 bits. `image`'s own conversions keep only the low byte of
+/// each channel for some layouts, so the raw data is narrowed instead.
+fn narrow_image(image: &DynamicImage) -> DynamicImage {
+    let (width, height) = image.dimensions();
+    let narrowed = narrow(&image.to_bytes(), image.color()).and_then(|(data, color)| {
+        Some(match color {
+            ColorType::L8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, data)?),
+            ColorType::La8 => {
+                DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, data)?)
+            }
+            ColorType::Rgb8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, data)?),
+            _ => DynamicImage::ImageRgba8(ImageBuffer::from_raw(width, height, data)?),
+        })
+    });
+    narrowed.unwrap_or_else(|| DynamicImage::ImageRgba8(image.to_rgba()))
+}
+
+/// `image` as RGBA with 16 bits per channel, widening 8-bit channels so white stays white.
+pub fn rgba16(image: &DynamicImage) -> Rgba16Image {
+    match image {
+        DynamicImage::ImageRgba16(buffer) => buffer.clone(),
+        DynamicImage::ImageRgb16(buffer) => buffer.convert(),
+        DynamicImage::ImageLumaA16(buffer) => buffer.convert(),
+        DynamicImage::ImageLuma16(buffer) => buffer.convert(),
+        _ => {
+            let rgba = image.to_rgba();
+            let (width, height) = image.dimensions();
+            ImageBuffer::from_fn(width, height, |x, y| {
+                let [r, g, b, a] = rgba.get_pixel(x, y).0;
+                Rgba([r, g, b, a].map(|channel| u16::from(channel) * 257))
+            })
+        }
+    }
+}
+
+/// Raw 16-bit pixel data narrowed to 8 bits, with its new color type, for formats that can't
+/// hold 16. Other data is left alone.
+pub fn narrow(data: &[u8], color: ColorType) -> Option<(Vec<u8>, ColorType)> {
+    let narrowed = match color {
+        ColorType::L16 => ColorType::L8,
+        ColorType::La16 => ColorType::La8,
+        ColorType::Rgb16 => ColorType::Rgb8,
+        ColorType::Rgba16 => ColorType::Rgba8,
+        _ => return None,
+    };
+    let data = data
+        .chunks_exact(2)
+        .map(|word| ((u32::from(u16::from_ne_bytes([word[0], word[1]])) + 128) / 257) as u8)
+        .collect();
+    Some((data, narrowed))
+}
+
+/// Raw 16-bit pixel data in the big-endian order PNG stores it in, which `image`'s encoder
+/// leaves to its caller. Other data is left alone.
+pub fn big_endian(data: &[u8], color: ColorType) -> Cow<'_, [u8]> {
+    if BitDepth::of(color) == BitDepth::Eight || cfg!(target_endian = "big") {
+        return Cow::Borrowed(data);
+    }
+    Cow::Owned(
+        data.chunks_exact(2)
+            .flat_map(|word| [word[1], word[0]])
+            .collect(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{convert, narrow, BitDepth};
+    use image::{ColorType, DynamicImage, ImageBuffer, Rgb};
+
+    #[test]
+    fn converts_both_ways() {
+        let deep = DynamicImage::ImageRgb16(ImageBuffer::from_pixel(2, 2, Rgb([65535, 32896, 0])));
+        let shallow = convert(&deep, BitDepth::Eight);
+        assert_eq!(shallow.color(), ColorType::Rgb8);
+        assert_eq!(shallow.to_rgb().get_pixel(0, 0).0, [255, 128, 0]);
+
+        let widened = convert(&shallow, BitDepth::Sixteen);
+        assert_eq!(widened.color(), ColorType::Rgba16);
+        assert_eq!(
+            widened.as_rgba16().unwrap().get_pixel(1, 1).0,
+            [65535, 32896, 0, 65535]
+        );
+        assert_eq!(
+            convert(&widened, BitDepth::Sixteen).color(),
+            ColorType::Rgba16
+        );
+    }
+
+    #[test]
+    fn narrows_raw_data() {
+        let data: Vec<u8> = [0u16, 257, 32896, 65535]
+            .iter()
+            .flat_map(|word| word.to_ne_bytes().to_vec())
+            .collect();
+        let (narrowed, color) = narrow(&data, ColorType::Rgba16).unwrap();
+        assert_eq!((narrowed, color), (vec![0, 1, 128, 255], ColorType::Rgba8));
+        assert!(narrow(&[0, 1, 2], ColorType::Rgb8).is_none());
+    }
+}
@@ -0,0 +1,128 @@
+//! Stamping print resolution into encoded images.
+
+use image::ImageFormat;
+
+const METERS_PER_INCH: f64 = 0.0254;
+
+/// Records `dpi` as the resolution of an encoded JPEG or PNG. Other formats are left as they
+/// are.
+pub fn stamp(bytes: &mut Vec<u8>, format: ImageFormat, dpi: u16) {
+    match format {
+        ImageFormat::Jpeg => stamp_jpeg(bytes, dpi),
+        ImageFormat::Png => stamp_png(bytes, dpi),
+        _ => (),
+    }
+}
+
+/// Sets the density in the JFIF header, adding one if the encoder wrote none.
+fn stamp_jpeg(bytes: &mut Vec<u8>, dpi: u16) {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return;
+    }
+    let [high, low] = dpi.to_be_bytes();
+    // Units of dots per inch, then the horizontal and vertical densities.
+    let density = [1, high, low, high, low];
+
+    if bytes.get(2..11) == Some(&b"\xFF\xE0\x00\x10JFIF\x00"[..]) {
+        bytes[13..18].copy_from_slice(&density);
+    } else {
+        let mut header = b"\xFF\xE0\x00\x10JFIF\x00\x01\x01".to_vec();
+        header.extend_from_slice(&density);
+        header.extend_from_slice(&[0, 0]);
+        bytes.splice(2..2, header);
+    }
+}
+
+/// Adds a `pHYs` chunk after the header, which PNG measures in pixels per meter.
+fn stamp_png(bytes: &mut Vec<u8>, dpi: u16) {
+    let per_meter = (f64::from(dpi) / METERS_PER_INCH).round() as u32;
+    let mut data = per_meter.to_be_bytes().to_vec();
+    data.extend_from_slice(&per_meter.to_be_bytes());
+    data.push(1);
+    insert_png_chunk(bytes, b"pHYs", &data);
+}
+
+/// Adds a chunk of `kind` holding `data` to an encoded PNG, right after its header. Anything
+/// without a header is left as it is.
+pub(crate) fn insert_png_chunk(bytes: &mut Vec<u8>, kind: &[u8; 4], data: &[u8]) {
+    // The signature, then the IHDR chunk's length, type, 13 bytes of data and CRC.
+    const IHDR_END: usize = 8 + 4 + 4 + 13 + 4;
+    if bytes.get(12..16) != Some(&b"IHDR"[..]) || bytes.len() < IHDR_END {
+        return;
+    }
+
+    let mut chunk = kind.to_vec();
+    chunk.extend_from_slice(data);
+    let crc = crc32(&chunk);
+
+    let mut inserted = (data.len() as u32).to_be_bytes().to_vec();
+    inserted.extend_from_slice(&chunk);
+    inserted.extend_from_slice(&crc.to_be_bytes());
+    bytes.splice(IHDR_END..IHDR_END, inserted);
+}
+
+pub(crate) fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for &byte in bytes {
+        crc ^= u32::from(byte);
+        for _ in 0..8 {
+            crc = if crc & 1 == 1 {
+                (crc >> 1) ^ 0xEDB8_8320
+            } else {
+                crc >> 1
+            };
+        }
+    }
+    !crc
+}
+
+/// The pixels `inches` span at `dpi`, if at least one.
+pub fn pixels(inches: f64, dpi: u16) -> Option<u32> {
+    let pixels = (inches * f64::from(dpi)).round();
+    (1.0..=f64::from(u32::MAX))
+        .contains(&pixels)
+        .then_some(pixels as u32)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{crc32, pixels, stamp};
+    use image::{ImageFormat, RgbImage};
+
+    fn encoded(format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image::DynamicImage::ImageRgb8(RgbImage::new(4, 3))
+            .write_to(&mut bytes, format)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn png_crc() {
+        assert_eq!(crc32(b"IEND"), 0xAE42_6082);
+    }
+
+    #[test]
+    fn stamps_jpeg_density() {
+        let mut bytes = encoded(ImageFormat::Jpeg);
+        stamp(&mut bytes, ImageFormat::Jpeg, 300);
+        assert_eq!(bytes[13..18], [1, 1, 44, 1, 44]);
+        assert!(image::load_from_memory(&bytes).is_ok());
+    }
+
+    #[test]
+    fn stamps_png_physical_size() {
+        let mut bytes = encoded(ImageFormat::Png);
+        stamp(&mut bytes, ImageFormat::Png, 300);
+        // 300 dpi is 11811 pixels per meter.
+        assert_eq!(&bytes[33..41], b"\x00\x00\x00\x09pHYs");
+        assert_eq!(bytes[41..45], 11811u32.to_be_bytes());
+        assert!(image::load_from_memory(&bytes).is_ok());
+    }
+
+    #[test]
+    fn inches_to_pixels() {
+        assert_eq!(pixels(6.0, 300), Some(1800));
+        assert_eq!(pixels(0.001, 72), None);
+    }
+}
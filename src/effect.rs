@@ -0,0 +1,181 @@
+//! Effects applied to resized images, in the order given by `--filter-chain`.
+
+use image::{imageops, RgbaImage};
+use serde::Serialize;
+
+use crate::{
+    filter::SHARPEN_THRESHOLD,
+    levels::{self, Levels},
+};
+
+/// Names accepted in a filter chain, with the argument each takes, if any.
+pub const EFFECTS: &[&str] = &[
+    "sharpen:SIGMA",
+    "blur:SIGMA",
+    "contrast:FACTOR",
+    "brightness:DELTA",
+    "grayscale",
+    "auto-level[:CLIP%]",
+    "auto-contrast[:CLIP%]",
+];
+
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Effect {
+    /// An unsharp mask of this radius.
+    Sharpen(f32),
+    /// A Gaussian blur of this radius.
+    Blur(f32),
+    /// Scales each color channel's distance from mid-gray, e.g. 1.1 for 10% more contrast.
+    Contrast(f32),
+    /// Adds to each color channel, from -255 to 255.
+    Brightness(i32),
+    Grayscale,
+    /// Stretches the color channels to the full range, ignoring this percentage of pixels at
+    /// either end, as `--auto-level` and `--auto-contrast` do.
+    Levels(Levels, f64),
+}
+
+impl Effect {
+    /// Parses one effect, such as `sharpen:0.5` or `grayscale`.
+    pub fn parse(s: &str) -> Result<Effect, String> {
+        let (name, argument) = match s.split_once(':') {
+            Some((name, argument)) => (name.trim(), Some(argument.trim())),
+            None => (s.trim(), None),
+        };
+        let number = |check: fn(f64) -> bool| {
+            let argument = argument.ok_or_else(|| format!("'{}' needs an argument", name))?;
+            match argument.parse::<f64>() {
+                Ok(value) if value.is_finite() && check(value) => Ok(value),
+                _ => Err(format!(
+                    "'{}' is not a valid argument to '{}'",
+                    argument, name
+                )),
+            }
+        };
+        let none = |effect| match argument {
+            Some(_) => Err(format!("'{}' takes no argument", name)),
+            None => Ok(effect),
+        };
+
+        match name {
+            "sharpen" => Ok(Effect::Sharpen(number(|sigma| sigma > 0.0)? as f32)),
+            "blur" => Ok(Effect::Blur(number(|sigma| sigma > 0.0)? as f32)),
+            "contrast" => Ok(Effect::Contrast(number(|factor| factor >= 0.0)? as f32)),
+            "brightness" => {
+                let delta = number(|delta| delta.abs() <= 255.0 && delta.fract() == 0.0)?;
+                Ok(Effect::Brightness(delta as i32))
+            }
+            "grayscale" => none(Effect::Grayscale),
+            "auto-level" => Ok(Effect::Levels(Levels::Channel, clip(argument)?)),
+            "auto-contrast" => Ok(Effect::Levels(Levels::Contrast, clip(argument)?)),
+            _ => Err(format!(
+                "unknown effect '{}'; expected one of {}",
+                name,
+                EFFECTS.join(", ")
+            )),
+        }
+    }
+
+    pub fn apply(self, image: &mut RgbaImage) {
+        match self {
+            Effect::Sharpen(sigma) => *image = imageops::unsharpen(image, sigma, SHARPEN_THRESHOLD),
+            Effect::Blur(sigma) => *image = imageops::blur(image, sigma),
+            Effect::Contrast(factor) => {
+                map_colors(image, |value| (f32::from(value) - 127.5) * factor + 127.5)
+            }
+            Effect::Brightness(delta) => map_colors(image, |value| (value as i32 + delta) as f32),
+            Effect::Grayscale => {
+                for pixel in image.pixels_mut() {
+                    let [r, g, b, _] = pixel.0.map(f32::from);
+                    let luma = (0.2126 * r + 0.7152 * g + 0.0722 * b).round() as u8;
+                    pixel.0[..3].fill(luma);
+                }
+            }
+            Effect::Levels(levels, clip) => levels::stretch(image, levels, clip),
+        }
+    }
+}
+
+/// Parses a comma-separated chain of effects, in the order they are to be applied.
+pub fn parse_chain(s: &str) -> Result<Vec<Effect>, String> {
+    s.split(',').map(Effect::parse).collect()
+}
+
+/// The percentage of pixels leveling may clip, none unless given.
+fn clip(argument: Option<&str>) -> Result<f64, String> {
+    let argument = match argument {
+        Some(argument) => argument.strip_suffix('%').unwrap_or(argument),
+        None => return Ok(0.0),
+    };
+    match argument.parse::<f64>() {
+        Ok(clip) if (0.0..50.0).contains(&clip) => Ok(clip),
+        _ => Err(format!("'{}' is not a percentage below 50", argument)),
+    }
+}
+
+/// Remaps the color channels of every pixel, leaving alpha alone.
+fn map_colors(image: &mut RgbaImage, map: impl Fn(u8) -> f32) {
+    let table: Vec<u8> = (0..=255)
+        .map(|value| map(value).round().clamp(0.0, 255.0) as u8)
+        .collect();
+    for pixel in image.pixels_mut() {
+        for value in &mut pixel.0[..3] {
+            *value = table[usize::from(*value)];
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_chain, Effect};
+    use crate::levels::Levels;
+    use image::{Rgba, RgbaImage};
+
+    #[test]
+    fn parses_in_order() {
+        assert_eq!(
+            parse_chain("sharpen:0.5,contrast:1.1,grayscale"),
+            Ok(vec![
+                Effect::Sharpen(0.5),
+                Effect::Contrast(1.1),
+                Effect::Grayscale
+            ])
+        );
+        assert_eq!(
+            parse_chain("brightness:-10, auto-contrast:1%"),
+            Ok(vec![
+                Effect::Brightness(-10),
+                Effect::Levels(Levels::Contrast, 1.0)
+            ])
+        );
+    }
+
+    #[test]
+    fn rejects_bad_effects() {
+        assert!(parse_chain("sepia")
+            .unwrap_err()
+            .contains("unknown effect 'sepia'"));
+        assert!(parse_chain("sharpen").is_err());
+        assert!(parse_chain("sharpen:-1").is_err());
+        assert!(parse_chain("grayscale:2").is_err());
+        assert!(parse_chain("brightness:0.5").is_err());
+        assert!(parse_chain("grayscale,").is_err());
+    }
+
+    #[test]
+    fn order_matters() {
+        let pixel = Rgba([200, 100, 50, 128]);
+        let apply = |chain: &str| {
+            let mut image = RgbaImage::from_pixel(1, 1, pixel);
+            for effect in parse_chain(chain).unwrap() {
+                effect.apply(&mut image);
+            }
+            image.get_pixel(0, 0).0
+        };
+
+        assert_eq!(apply("grayscale"), [118, 118, 118, 128]);
+        assert_eq!(apply("contrast:2,grayscale"), [106, 106, 106, 128]);
+        assert_eq!(apply("grayscale,contrast:2"), [109, 109, 109, 128]);
+    }
+}
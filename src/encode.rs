@@ -0,0 +1,405 @@
+use std::{
+    convert::TryFrom,
+    io::{self, Cursor},
+};
+
+use image::{
+    codecs::{
+        bmp::BmpEncoder, gif::GifEncoder, ico::IcoEncoder, jpeg::JpegEncoder, png::PngEncoder,
+        tiff::TiffEncoder,
+    },
+    ColorType, DynamicImage, GenericImageView, ImageFormat,
+};
+use serde::Serialize;
+
+use crate::{
+    depth,
+    quantize::{self, Quantize},
+};
+
+/// The quality each lossy format is encoded with when none is requested, chosen per format
+/// rather than left to `image`'s generic default.
+const DEFAULT_QUALITIES: &[(ImageFormat, u8)] = &[
+    (ImageFormat::Jpeg, 82),
+    (ImageFormat::WebP, 80),
+    (ImageFormat::Avif, 50),
+];
+
+/// The quality to encode `format` with when none is requested, if it takes one at all.
+pub fn default_quality(format: ImageFormat) -> Option<u8> {
+    DEFAULT_QUALITIES
+        .iter()
+        .find(|&&(candidate, _)| candidate == format)
+        .map(|&(_, quality)| quality)
+}
+
+/// How JPEG outputs sample chroma, 4:2:0 unless told otherwise, to keep files small.
+///
+/// `image`'s encoder never subsamples, so it only writes 4:4:4 JPEGs; a subsampled JPEG is
+/// written by `jpeg-encoder` instead.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub enum Subsampling {
+    #[serde(rename = "444")]
+    S444,
+    #[serde(rename = "422")]
+    S422,
+    #[default]
+    #[serde(rename = "420")]
+    S420,
+}
+
+impl Subsampling {
+    pub const NAMES: &'static [&'static str] = &["444", "422", "420"];
+
+    pub fn from_name(name: &str) -> Option<Subsampling> {
+        match name {
+            "444" | "4:4:4" => Some(Subsampling::S444),
+            "422" | "4:2:2" => Some(Subsampling::S422),
+            "420" | "4:2:0" => Some(Subsampling::S420),
+            _ => None,
+        }
+    }
+}
+
+/// How JPEG outputs are written, which other formats ignore.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct JpegOptions {
+    pub subsampling: Subsampling,
+    /// Builds Huffman tables for each image rather than using the standard ones, which
+    /// shrinks it a few percent at no cost in quality.
+    pub optimize: bool,
+    /// The quality JPEGs are encoded with, whatever quality other formats are given.
+    pub quality: Option<u8>,
+    /// Writes a restart marker after every this many rows of MCUs, so a decoder that meets
+    /// corrupt data can pick up again at the next one. Each marker costs a few bytes.
+    pub restart_rows: Option<u16>,
+}
+
+/// How PNG outputs are written, which other formats ignore.
+#[derive(Copy, Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PngOptions {
+    /// Reduces the image to an indexed palette of at most this many colors.
+    pub quantize: Option<Quantize>,
+}
+
+/// Encodes raw pixel data in memory.
+pub fn encode(
+    data: &[u8],
+    (width, height): (u32, u32),
+    color: ColorType,
+    format: ImageFormat,
+    quality: Option<u8>,
+    jpeg: JpegOptions,
+    png: PngOptions,
+) -> io::Result<Vec<u8>> {
+    // Formats that can't hold 16 bits per channel get the nearest 8-bit values instead.
+    if !depth::holds_sixteen(format) {
+        if let Some((data, color)) = depth::narrow(data, color) {
+            return encode(&data, (width, height), color, format, quality, jpeg, png);
+        }
+    }
+
+    let mut bytes = Cursor::new(Vec::new());
+    let quality = match format {
+        ImageFormat::Jpeg => jpeg.quality.or(quality),
+        _ => quality,
+    }
+    .or_else(|| default_quality(format));
+
+    let result = match format {
+        ImageFormat::Jpeg
+            if jpeg.subsampling != Subsampling::S444
+                || jpeg.optimize
+                || jpeg.restart_rows.is_some() =>
+        {
+            let quality = quality.expect("JPEG has a default quality");
+            return encode_jpeg(data, (width, height), color, quality, jpeg);
+        }
+        ImageFormat::Jpeg => match quality {
+            Some(quality) => JpegEncoder::new_with_quality(&mut bytes, quality),
+            None => JpegEncoder::new(&mut bytes),
+        }
+        .encode(data, width, height, color),
+        ImageFormat::Png if png.quantize.is_some() => {
+            let quantize = png.quantize.expect("quantizing PNGs");
+            return quantize::encode_png(data, (width, height), color, quantize);
+        }
+        ImageFormat::Png => {
+            let data = depth::big_endian(data, color);
+            PngEncoder::new(&mut bytes).encode(&data, width, height, color)
+        }
+        ImageFormat::Gif => GifEncoder::new(&mut bytes).encode(data, width, height, color),
+        ImageFormat::Bmp => BmpEncoder::new(&mut bytes).encode(data, width, height, color),
+        ImageFormat::Ico => IcoEncoder::new(&mut bytes).encode(data, width, height, color),
+        ImageFormat::Tiff => TiffEncoder::new(&mut bytes).encode(data, width, height, color),
+        format => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("encoding {:?} images is not supported", format),
+            ))
+        }
+    };
+
+    result.map_err(io::Error::other)?;
+    Ok(bytes.into_inner())
+}
+
+/// Encodes a JPEG with `jpeg-encoder`, which can subsample, optimize and write restart
+/// markers where `image`'s encoder can't.
+fn encode_jpeg(
+    data: &[u8],
+    (width, height): (u32, u32),
+    color: ColorType,
+    quality: u8,
+    jpeg: JpegOptions,
+) -> io::Result<Vec<u8>> {
+    use jpeg_encoder::{ColorType as JpegColor, Encoder, SamplingFactor};
+
+    // Gray with alpha is written as plain gray, as `image`'s encoder writes it.
+    if color == ColorType::La8 {
+        let gray: Vec<u8> = data.chunks_exact(2).map(|pixel| pixel[0]).collect();
+        return encode_jpeg(&gray, (width, height), ColorType::L8, quality, jpeg);
+    }
+
+    let color = match color {
+        ColorType::L8 => JpegColor::Luma,
+        ColorType::Rgb8 => JpegColor::Rgb,
+        ColorType::Rgba8 => JpegColor::Rgba,
+        color => {
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                format!("encoding {:?} JPEGs is not supported", color),
+            ))
+        }
+    };
+    let edge = |edge| {
+        u16::try_from(edge).map_err(|_| {
+            io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "JPEGs may be at most 65535px on a side",
+            )
+        })
+    };
+
+    let mut bytes = Vec::new();
+    let mut encoder = Encoder::new(&mut bytes, quality);
+    encoder.set_optimized_huffman_tables(jpeg.optimize);
+    encoder.set_sampling_factor(match jpeg.subsampling {
+        Subsampling::S444 => SamplingFactor::R_4_4_4,
+        Subsampling::S422 => SamplingFactor::R_4_2_2,
+        Subsampling::S420 => SamplingFactor::R_4_2_0,
+    });
+    if let Some(rows) = jpeg.restart_rows {
+        let luma = color == JpegColor::Luma;
+        encoder.set_restart_interval(restart_interval(width, luma, jpeg.subsampling, rows)?);
+    }
+    encoder
+        .encode(data, edge(width)?, edge(height)?, color)
+        .map_err(io::Error::other)?;
+    Ok(bytes)
+}
+
+/// The number of MCUs in `rows` rows of them across a JPEG `width` wide, which is what the
+/// encoder counts restart intervals in. Subsampled chroma makes an MCU twice as wide; gray
+/// images have no chroma to subsample.
+fn restart_interval(
+    width: u32,
+    luma: bool,
+    subsampling: Subsampling,
+    rows: u16,
+) -> io::Result<u16> {
+    let mcu_width = match subsampling {
+        Subsampling::S444 => 8,
+        _ if luma => 8,
+        Subsampling::S422 | Subsampling::S420 => 16,
+    };
+    let mcus = u64::from(width.div_ceil(mcu_width)) * u64::from(rows);
+    u16::try_from(mcus).map_err(|_| {
+        io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!(
+                "a restart interval of {} rows is too long for a {}px wide JPEG",
+                rows, width
+            ),
+        )
+    })
+}
+
+/// Encodes a decoded image in memory, converting BGR layouts most encoders don't accept.
+pub fn encode_dynamic(
+    image: &DynamicImage,
+    format: ImageFormat,
+    quality: Option<u8>,
+    jpeg: JpegOptions,
+    png: PngOptions,
+) -> io::Result<Vec<u8>> {
+    match image {
+        DynamicImage::ImageBgr8(_) => {
+            let image = DynamicImage::ImageRgb8(image.to_rgb());
+            encode_dynamic(&image, format, quality, jpeg, png)
+        }
+        DynamicImage::ImageBgra8(_) => {
+            let image = DynamicImage::ImageRgba8(image.to_rgba());
+            encode_dynamic(&image, format, quality, jpeg, png)
+        }
+        _ => encode(
+            &image.to_bytes(),
+            image.dimensions(),
+            image.color(),
+            format,
+            quality,
+            jpeg,
+            png,
+        ),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{default_quality, encode, restart_interval, JpegOptions, PngOptions, Subsampling};
+    use image::{ColorType, GenericImageView, ImageFormat};
+
+    #[test]
+    fn lossy_formats_have_defaults() {
+        assert_eq!(default_quality(ImageFormat::Jpeg), Some(82));
+        assert_eq!(default_quality(ImageFormat::WebP), Some(80));
+        assert_eq!(default_quality(ImageFormat::Avif), Some(50));
+        assert_eq!(default_quality(ImageFormat::Png), None);
+    }
+
+    #[test]
+    fn subsampled_jpegs_decode() {
+        let data: Vec<u8> = (0..33 * 17 * 3).map(|n| (n * 7 % 256) as u8).collect();
+        for &name in Subsampling::NAMES {
+            let subsampling = Subsampling::from_name(name).unwrap();
+            let bytes = encode(
+                &data,
+                (33, 17),
+                ColorType::Rgb8,
+                ImageFormat::Jpeg,
+                None,
+                JpegOptions {
+                    subsampling,
+                    ..JpegOptions::default()
+                },
+                PngOptions::default(),
+            );
+            let decoded = image::load_from_memory(&bytes.unwrap()).unwrap();
+            assert_eq!(decoded.dimensions(), (33, 17), "{}", name);
+        }
+    }
+
+    #[test]
+    fn gray_jpegs_drop_alpha() {
+        let data: Vec<u8> = (0..16 * 8 * 2).map(|n| (n * 7 % 256) as u8).collect();
+        let bytes = encode(
+            &data,
+            (16, 8),
+            ColorType::La8,
+            ImageFormat::Jpeg,
+            None,
+            JpegOptions::default(),
+            PngOptions::default(),
+        );
+        let decoded = image::load_from_memory(&bytes.unwrap()).unwrap();
+        assert_eq!(decoded.color(), ColorType::L8);
+    }
+
+    #[test]
+    fn optimized_jpegs_are_smaller() {
+        let data: Vec<u8> = (0..64 * 64 * 3).map(|n| (n * 7 % 256) as u8).collect();
+        let encoded = |optimize| {
+            let jpeg = JpegOptions {
+                optimize,
+                ..JpegOptions::default()
+            };
+            encode(
+                &data,
+                (64, 64),
+                ColorType::Rgb8,
+                ImageFormat::Jpeg,
+                None,
+                jpeg,
+                PngOptions::default(),
+            )
+            .unwrap()
+        };
+        let (plain, optimized) = (encoded(false), encoded(true));
+        assert!(optimized.len() < plain.len());
+        let decoded = image::load_from_memory(&optimized).unwrap();
+        assert_eq!(decoded.dimensions(), (64, 64));
+    }
+
+    #[test]
+    fn restart_markers_follow_mcu_rows() {
+        assert_eq!(
+            restart_interval(33, false, Subsampling::S444, 2).unwrap(),
+            10
+        );
+        assert_eq!(
+            restart_interval(33, false, Subsampling::S420, 2).unwrap(),
+            6
+        );
+        assert_eq!(
+            restart_interval(33, true, Subsampling::S420, 2).unwrap(),
+            10
+        );
+        assert!(restart_interval(65535, false, Subsampling::S444, 8).is_err());
+
+        let data: Vec<u8> = (0..64 * 64 * 3).map(|n| (n * 7 % 256) as u8).collect();
+        let jpeg = JpegOptions {
+            restart_rows: Some(1),
+            ..JpegOptions::default()
+        };
+        let bytes = encode(
+            &data,
+            (64, 64),
+            ColorType::Rgb8,
+            ImageFormat::Jpeg,
+            None,
+            jpeg,
+            PngOptions::default(),
+        )
+        .unwrap();
+        // A restart interval is declared, and a marker ends each of the first three of the four
+        // 16px rows that 4:2:0 makes.
+        assert!(bytes.windows(2).any(|marker| marker == [0xff, 0xdd]));
+        let restarts = bytes
+            .windows(2)
+            .filter(|marker| marker[0] == 0xff && (0xd0..=0xd7).contains(&marker[1]))
+            .count();
+        assert_eq!(restarts, 3);
+        let decoded = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(decoded.dimensions(), (64, 64));
+    }
+
+    #[test]
+    fn jpeg_quality_overrides_the_general_one() {
+        let data: Vec<u8> = (0..64 * 64 * 3).map(|n| (n * 7 % 256) as u8).collect();
+        let encoded = |format, quality, jpeg_quality| {
+            let jpeg = JpegOptions {
+                quality: jpeg_quality,
+                ..JpegOptions::default()
+            };
+            encode(
+                &data,
+                (64, 64),
+                ColorType::Rgb8,
+                format,
+                quality,
+                jpeg,
+                PngOptions::default(),
+            )
+            .unwrap()
+        };
+        let (low, high) = (Some(20), Some(95));
+        assert_eq!(
+            encoded(ImageFormat::Jpeg, high, low),
+            encoded(ImageFormat::Jpeg, low, None)
+        );
+        assert_eq!(
+            encoded(ImageFormat::Png, high, low),
+            encoded(ImageFormat::Png, None, None)
+        );
+    }
+}
@@ -0,0 +1,130 @@
+//! Post-processing outputs with an external command, such as an optimizer.
+
+use std::{
+    io,
+    path::Path,
+    process::{Child, Command},
+};
+
+/// The placeholder replaced with the output path in each argument.
+const PLACEHOLDER: &str = "{}";
+
+/// A command line, split into words up front so no shell ever sees an output path.
+#[derive(Clone, Debug)]
+pub struct Exec {
+    program: String,
+    args: Vec<String>,
+}
+
+impl Exec {
+    /// Parses a command such as `optipng -o2 {}`. Words are split as a shell would split them,
+    /// honouring quotes and backslashes, but nothing is expanded. A command without a
+    /// placeholder gets the output path as its last argument.
+    pub fn parse(command: &str) -> Result<Exec, String> {
+        let mut words = split(command)?;
+        if words.is_empty() {
+            return Err(String::from("no command given"));
+        }
+        if !words.iter().any(|word| word.contains(PLACEHOLDER)) {
+            words.push(String::from(PLACEHOLDER));
+        }
+
+        let program = words.remove(0);
+        Ok(Exec {
+            program,
+            args: words,
+        })
+    }
+
+    pub fn program(&self) -> &str {
+        &self.program
+    }
+
+    /// Starts the command on the output at `path`.
+    pub fn spawn(&self, path: &Path) -> io::Result<Child> {
+        let path = path.to_string_lossy();
+        Command::new(self.program.replace(PLACEHOLDER, &path))
+            .args(self.args.iter().map(|arg| arg.replace(PLACEHOLDER, &path)))
+            .spawn()
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", self.program, e)))
+    }
+}
+
+fn split(command: &str) -> Result<Vec<String>, String> {
+    let mut words = Vec::new();
+    let mut word: Option<String> = None;
+    let mut chars = command.chars();
+
+    while let Some(c) = chars.next() {
+        match c {
+            c if c.is_whitespace() => words.extend(word.take()),
+            '\'' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('\'') => break,
+                        Some(c) => word.push(c),
+                        None => return Err(String::from("unterminated single quote")),
+                    }
+                }
+            }
+            '"' => {
+                let word = word.get_or_insert_with(String::new);
+                loop {
+                    match chars.next() {
+                        Some('"') => break,
+                        Some('\\') => match chars.next() {
+                            Some(c @ ('"' | '\\')) => word.push(c),
+                            Some(c) => {
+                                word.push('\\');
+                                word.push(c);
+                            }
+                            None => return Err(String::from("unterminated double quote")),
+                        },
+                        Some(c) => word.push(c),
+                        None => return Err(String::from("unterminated double quote")),
+                    }
+                }
+            }
+            '\\' => match chars.next() {
+                Some(c) => word.get_or_insert_with(String::new).push(c),
+                None => return Err(String::from("trailing backslash")),
+            },
+            c => word.get_or_insert_with(String::new).push(c),
+        }
+    }
+
+    words.extend(word);
+    Ok(words)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{split, Exec};
+
+    #[test]
+    fn splits_like_a_shell() {
+        assert_eq!(
+            split(r#"optipng  -o2 "my dir/{}" 'a "b"' c\ d """#).unwrap(),
+            vec!["optipng", "-o2", "my dir/{}", r#"a "b""#, "c d", ""]
+        );
+        assert!(split("optipng 'oops").is_err());
+        assert!(split(r"optipng \").is_err());
+    }
+
+    #[test]
+    fn nothing_is_expanded() {
+        assert_eq!(
+            split("echo $HOME; rm -rf {} && `x`").unwrap(),
+            vec!["echo", "$HOME;", "rm", "-rf", "{}", "&&", "`x`"]
+        );
+    }
+
+    #[test]
+    fn path_appended_without_placeholder() {
+        let exec = Exec::parse("jpegoptim --strip-all").unwrap();
+        assert_eq!(exec.program, "jpegoptim");
+        assert_eq!(exec.args, vec!["--strip-all", "{}"]);
+        assert!(Exec::parse("  ").is_err());
+    }
+}
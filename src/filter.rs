@@ -0,0 +1,158 @@
+//! Choosing the resampling filter.
+//!
+//! Lanczos3 is the default in both directions. When shrinking by a large factor, though,
+//! its sharpness buys little and its cost grows with the source, so below the area
+//! threshold the triangle filter is used instead. Because `image` widens a filter's support
+//! in proportion to the reduction, the triangle filter then averages over each output
+//! pixel's whole footprint, which also keeps fine detail from aliasing. A filter given
+//! explicitly is always used as is.
+
+use image::imageops::FilterType;
+use serde::{Serialize, Serializer};
+
+use crate::{alpha::AlphaThreshold, depth::BitDepth};
+
+/// Names accepted by `--filter`.
+pub const FILTERS: &[&str] = &["nearest", "triangle", "catmull-rom", "gaussian", "lanczos3"];
+
+/// Scale factors below this switch shrinking to the triangle filter, i.e. reductions
+/// beyond 8x.
+pub const DEFAULT_AREA_THRESHOLD: f64 = 0.125;
+
+pub fn parse_filter(name: &str) -> Option<FilterType> {
+    match name {
+        "nearest" => Some(FilterType::Nearest),
+        "triangle" => Some(FilterType::Triangle),
+        "catmull-rom" => Some(FilterType::CatmullRom),
+        "gaussian" => Some(FilterType::Gaussian),
+        "lanczos3" => Some(FilterType::Lanczos3),
+        _ => None,
+    }
+}
+
+/// Serializes a filter by the name `--filter` takes.
+fn filter_name<S: Serializer>(
+    filter: &Option<FilterType>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let name = filter.map(|filter| match filter {
+        FilterType::Nearest => "nearest",
+        FilterType::Triangle => "triangle",
+        FilterType::CatmullRom => "catmull-rom",
+        FilterType::Gaussian => "gaussian",
+        FilterType::Lanczos3 => "lanczos3",
+    });
+    name.serialize(serializer)
+}
+
+/// How images are resampled, as chosen on the command line.
+#[derive(Copy, Clone, Debug, Serialize)]
+pub struct Resampling {
+    #[serde(serialize_with = "filter_name")]
+    pub shrink_filter: Option<FilterType>,
+    #[serde(serialize_with = "filter_name")]
+    pub enlarge_filter: Option<FilterType>,
+    pub area_threshold: f64,
+    /// Sharpen shrunk images in proportion to how much they were reduced.
+    pub auto_sharpen: bool,
+    /// Resize each image on many threads.
+    pub parallel: bool,
+    /// Round resized dimensions down to a multiple of this, as some encoders require.
+    pub round_to: Option<u32>,
+    /// Round resized dimensions down to even numbers, as H.264 requires, after any other
+    /// rounding.
+    pub even_dimensions: bool,
+    /// Resize with this many bits per channel; resized images are otherwise 8-bit.
+    pub bit_depth: Option<BitDepth>,
+    /// Snap nearly transparent, and perhaps nearly opaque, pixels of resized images.
+    pub alpha_threshold: Option<AlphaThreshold>,
+    /// Replace transparency in resized images with this color, dropping alpha.
+    pub color_key: Option<[u8; 3]>,
+}
+
+/// Unsharp masks with differences below this are left alone, so flat areas keep their noise
+/// down.
+pub const SHARPEN_THRESHOLD: i32 = 1;
+
+/// The unsharp mask radius for an image shrunk by `scale`, if it needs sharpening at all.
+///
+/// The radius grows with each halving of the image, from 0.5px at half size to 1.5px at an
+/// eighth and beyond. Reductions of less than a quarter lose too little detail to bother.
+pub fn sharpen_sigma(scale: f64) -> Option<f32> {
+    if scale <= 0.0 || scale > 0.8 {
+        return None;
+    }
+    let halvings = (1.0 / scale).log2();
+    Some((0.5 * halvings).clamp(0.1, 1.5) as f32)
+}
+
+/// The filter for shrinking by `scale` (output over input length, so below 1).
+///
+/// The triangle filter is chosen only when `scale` is strictly below `area_threshold`.
+pub fn for_shrink(explicit: Option<FilterType>, scale: f64, area_threshold: f64) -> FilterType {
+    match explicit {
+        Some(filter) => filter,
+        None if scale < area_threshold => FilterType::Triangle,
+        None => FilterType::Lanczos3,
+    }
+}
+
+/// The filter for enlarging, which is never switched automatically.
+pub fn for_enlarge(explicit: Option<FilterType>) -> FilterType {
+    explicit.unwrap_or(FilterType::Lanczos3)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        for_enlarge, for_shrink, parse_filter, sharpen_sigma, DEFAULT_AREA_THRESHOLD, FILTERS,
+    };
+    use image::imageops::FilterType;
+
+    #[test]
+    fn area_filter_below_threshold() {
+        // 8000px down to 999px is just past an 8x reduction; 1000px is exactly 8x.
+        let threshold = DEFAULT_AREA_THRESHOLD;
+        assert_eq!(
+            for_shrink(None, 999.0 / 8000.0, threshold),
+            FilterType::Triangle
+        );
+        assert_eq!(
+            for_shrink(None, 1000.0 / 8000.0, threshold),
+            FilterType::Lanczos3
+        );
+        assert_eq!(for_shrink(None, 0.5, threshold), FilterType::Lanczos3);
+    }
+
+    #[test]
+    fn explicit_filter_wins() {
+        let filter = Some(FilterType::CatmullRom);
+        assert_eq!(for_shrink(filter, 0.01, 0.125), FilterType::CatmullRom);
+        assert_eq!(for_enlarge(filter), FilterType::CatmullRom);
+        assert_eq!(for_enlarge(None), FilterType::Lanczos3);
+    }
+
+    #[test]
+    fn zero_threshold_disables_selection() {
+        assert_eq!(for_shrink(None, 0.0001, 0.0), FilterType::Lanczos3);
+    }
+
+    #[test]
+    fn sharpening_grows_with_reduction() {
+        assert_eq!(sharpen_sigma(1.0), None);
+        assert_eq!(sharpen_sigma(0.9), None);
+        assert_eq!(sharpen_sigma(0.5), Some(0.5));
+        assert_eq!(sharpen_sigma(0.25), Some(1.0));
+        assert_eq!(sharpen_sigma(0.01), Some(1.5));
+
+        let small = sharpen_sigma(1.0 / 1.5).unwrap();
+        let large = sharpen_sigma(0.1).unwrap();
+        assert!(small < large);
+    }
+
+    #[test]
+    fn every_name_parses() {
+        assert!(FILTERS.iter().all(|name| parse_filter(name).is_some()));
+        assert!(parse_filter("box").is_none());
+    }
+}
@@ -0,0 +1,78 @@
+//! Multi-resolution ICO output.
+
+use std::io;
+
+use image::{codecs::png::PngEncoder, imageops::FilterType, DynamicImage, GenericImageView};
+
+use crate::crop::{self, Aspect};
+
+/// The largest edge an ICO directory entry can describe.
+pub const MAX_SIZE: u32 = 256;
+
+/// Center-crops `image` to a square and encodes it at each of `sizes`, packed into a single
+/// icon.
+pub fn encode_icon(image: &DynamicImage, sizes: &[u32]) -> io::Result<Vec<u8>> {
+    let (width, height) = image.dimensions();
+    let (x, y, edge, _) = crop::center_rect(width, height, Aspect::SQUARE);
+    let square = image.crop_imm(x, y, edge, edge);
+
+    let mut entries = Vec::with_capacity(sizes.len());
+    for &size in sizes {
+        let icon = square
+            .resize_exact(size, size, FilterType::Lanczos3)
+            .into_rgba();
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .encode(&icon, size, size, image::ColorType::Rgba8)
+            .map_err(io::Error::other)?;
+        entries.push((size, png));
+    }
+
+    Ok(pack(&entries))
+}
+
+/// Lays out an ICO file whose entries are PNG-encoded images, as `(size, png)` pairs.
+fn pack(entries: &[(u32, Vec<u8>)]) -> Vec<u8> {
+    const HEADER_LEN: usize = 6;
+    const ENTRY_LEN: usize = 16;
+
+    let mut ico = Vec::new();
+    ico.extend_from_slice(&0u16.to_le_bytes());
+    ico.extend_from_slice(&1u16.to_le_bytes());
+    ico.extend_from_slice(&(entries.len() as u16).to_le_bytes());
+
+    let mut offset = HEADER_LEN + ENTRY_LEN * entries.len();
+    for (size, png) in entries {
+        // Dimensions are stored in a byte, with 0 standing in for 256.
+        let edge = (*size % MAX_SIZE) as u8;
+        ico.extend_from_slice(&[edge, edge, 0, 0]);
+        ico.extend_from_slice(&1u16.to_le_bytes());
+        ico.extend_from_slice(&32u16.to_le_bytes());
+        ico.extend_from_slice(&(png.len() as u32).to_le_bytes());
+        ico.extend_from_slice(&(offset as u32).to_le_bytes());
+        offset += png.len();
+    }
+
+    for (_, png) in entries {
+        ico.extend_from_slice(png);
+    }
+
+    ico
+}
+
+#[cfg(test)]
+mod tests {
+    use super::pack;
+
+    #[test]
+    fn directory_layout() {
+        let ico = pack(&[(16, vec![1; 10]), (256, vec![2; 20])]);
+
+        assert_eq!(&ico[..6], &[0, 0, 1, 0, 2, 0]);
+        assert_eq!(ico[6], 16);
+        assert_eq!(&ico[14..22], &[10, 0, 0, 0, 38, 0, 0, 0]);
+        assert_eq!(ico[22], 0);
+        assert_eq!(&ico[30..38], &[20, 0, 0, 0, 48, 0, 0, 0]);
+        assert_eq!(ico.len(), 68);
+    }
+}
@@ -0,0 +1,124 @@
+use image::RgbaImage;
+use serde::Serialize;
+
+/// How the histogram is stretched to the full range.
+#[derive(Copy, Clone, Debug, PartialEq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Levels {
+    /// Stretch each color channel independently, which also corrects color casts.
+    Channel,
+    /// Stretch all color channels by the same amount, preserving hue.
+    Contrast,
+}
+
+/// Remaps the color channels of `image` so they span the full `0..=255` range.
+///
+/// `clip_percent` of the pixels at either end of each histogram are ignored when computing
+/// the input range, so a handful of stray pixels can't pin it open. Alpha is left untouched.
+pub fn stretch(image: &mut RgbaImage, levels: Levels, clip_percent: f64) {
+    let mut histograms = [[0u64; 256]; 3];
+    for pixel in image.pixels() {
+        for (histogram, &value) in histograms.iter_mut().zip(&pixel.0[..3]) {
+            histogram[value as usize] += 1;
+        }
+    }
+
+    let clip = (image.width() as f64 * image.height() as f64 * clip_percent / 100.0) as u64;
+    let mut ranges = histograms
+        .each_ref()
+        .map(|histogram| range_of(histogram, clip));
+
+    if let Levels::Contrast = levels {
+        let low = ranges.iter().map(|&(low, _)| low).min().unwrap_or(0);
+        let high = ranges.iter().map(|&(_, high)| high).max().unwrap_or(255);
+        ranges = [(low, high); 3];
+    }
+
+    let tables = ranges.map(|(low, high)| table(low, high));
+
+    for pixel in image.pixels_mut() {
+        for (value, table) in pixel.0[..3].iter_mut().zip(&tables) {
+            *value = table[*value as usize];
+        }
+    }
+}
+
+/// The lowest and highest values remaining after discarding `clip` samples from each end.
+fn range_of(histogram: &[u64; 256], clip: u64) -> (u8, u8) {
+    let low = cutoff(histogram, clip, 0..256).unwrap_or(0);
+    let high = cutoff(histogram, clip, (0..256).rev()).unwrap_or(255);
+    (low as u8, high as u8)
+}
+
+/// The first value, in the order given, past which more than `clip` samples have been seen.
+fn cutoff(
+    histogram: &[u64; 256],
+    clip: u64,
+    mut values: impl Iterator<Item = usize>,
+) -> Option<usize> {
+    let mut seen = 0;
+    values.find(|&value| {
+        seen += histogram[value];
+        seen > clip
+    })
+}
+
+fn table(low: u8, high: u8) -> [u8; 256] {
+    let mut table = [0; 256];
+    for (value, entry) in table.iter_mut().enumerate() {
+        *entry = if high <= low {
+            value as u8
+        } else {
+            let scaled = (value as f64 - low as f64) * 255.0 / (high - low) as f64;
+            scaled.round().clamp(0.0, 255.0) as u8
+        };
+    }
+    table
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{stretch, Levels};
+    use image::{Rgba, RgbaImage};
+
+    fn dull() -> RgbaImage {
+        RgbaImage::from_fn(100, 1, |x, _| {
+            let x = x as u8;
+            Rgba([100 + x / 2, 120 + x / 4, 80, 200])
+        })
+    }
+
+    #[test]
+    fn channel_stretch_fills_range() {
+        let mut image = dull();
+        stretch(&mut image, Levels::Channel, 0.0);
+
+        let red: Vec<_> = image.pixels().map(|p| p[0]).collect();
+        assert_eq!(red.iter().min(), Some(&0));
+        assert_eq!(red.iter().max(), Some(&255));
+        assert_eq!(image.get_pixel(0, 0)[3], 200);
+    }
+
+    #[test]
+    fn contrast_stretch_shares_range() {
+        let mut image = dull();
+        stretch(&mut image, Levels::Contrast, 0.0);
+
+        // Blue was flat at the bottom of the shared 80..=149 range.
+        assert_eq!(image.get_pixel(0, 0)[2], 0);
+        assert_eq!(image.get_pixel(99, 0)[0], 255);
+        assert!(image.get_pixel(99, 0)[1] < 255);
+    }
+
+    #[test]
+    fn clip_ignores_outliers() {
+        let mut image = RgbaImage::from_pixel(100, 1, Rgba([128, 128, 128, 255]));
+        image.put_pixel(0, 0, Rgba([0, 0, 0, 255]));
+        image.put_pixel(1, 0, Rgba([120, 120, 120, 255]));
+        image.put_pixel(99, 0, Rgba([136, 136, 136, 255]));
+        stretch(&mut image, Levels::Channel, 1.0);
+
+        assert_eq!(image.get_pixel(1, 0)[0], 0);
+        assert_eq!(image.get_pixel(2, 0)[0], 255);
+    }
+}
@@ -0,0 +1,2277 @@
+//! Resizing images, as done by the `resize` command.
+//!
+//! ```no_run
+//! use resize::{settings::Operation, ResizeOptions};
+//!
+//! let options = ResizeOptions::builder()
+//!     .operation(Operation::Shrink)
+//!     .size(1024)
+//!     .out_suffix("-small")
+//!     .build()?;
+//! resize::resize_file("photo.jpg", &options)?;
+//! # Ok::<(), Box<dyn std::error::Error>>(())
+//! ```
+
+pub mod alpha;
+pub mod canvas;
+pub mod color;
+mod compare;
+pub mod crop;
+pub mod depth;
+pub mod dpi;
+pub mod effect;
+mod encode;
+pub mod filter;
+mod ico;
+pub mod levels;
+mod lossless;
+pub mod meta;
+mod options;
+mod orient;
+pub mod output;
+mod palette;
+mod parallel;
+mod partial;
+pub mod phash;
+mod placeholder;
+pub mod profile;
+pub mod progress;
+pub mod quality;
+mod quantize;
+pub mod raw;
+pub mod settings;
+pub mod stats;
+mod thumbnail;
+mod tiles;
+pub mod timing;
+mod webp;
+
+use std::{
+    borrow::Cow,
+    cmp::Ordering,
+    collections::HashMap,
+    convert::TryFrom,
+    fs,
+    io::{self, BufRead, Cursor, Seek},
+    ops::Deref,
+    path::{Path, PathBuf},
+    time::Instant,
+};
+
+use canvas::{Anchor, Canvas};
+use crop::{AspectAction, Grid, MaxAspect};
+use depth::BitDepth;
+use effect::Effect;
+use filter::Resampling;
+use options::InputCap;
+use progress::Status;
+use settings::{Operation, Settings};
+use stats::Stats;
+use timing::Timings;
+
+use image::{
+    codecs::jpeg::JpegDecoder,
+    imageops::{self, FilterType},
+    io::Reader as ImageLoader,
+    DynamicImage, EncodableLayout, GenericImageView, ImageBuffer, ImageFormat, Pixel, RgbaImage,
+};
+
+pub use encode::{JpegOptions, PngOptions, Subsampling};
+pub use options::{ResizeOptions, ResizeOptionsBuilder};
+pub use orient::Orientation;
+pub use quantize::Quantize;
+
+/// A single image to process, along with how to process it.
+#[derive(Clone, Debug)]
+pub struct Job {
+    pub source: String,
+    /// The encoded image, for sources that aren't files, such as members of a tar stream.
+    pub data: Option<Vec<u8>>,
+    pub settings: Settings,
+    /// Where to write the output, if not derived from the source.
+    pub out: Option<PathBuf>,
+}
+
+impl Job {
+    pub fn new(source: &str, settings: &Settings) -> Job {
+        Job {
+            source: source.to_string(),
+            data: None,
+            settings: settings.clone(),
+            out: None,
+        }
+    }
+}
+
+/// An outcome for an image, along with anything still to be written for it.
+#[derive(Debug)]
+pub struct Output {
+    pub status: Status,
+    pub path: Option<PathBuf>,
+    pub dimensions: Option<(u32, u32)>,
+    /// Encoded bytes still to be written to `path`.
+    pub bytes: Option<Vec<u8>>,
+    /// The output's dominant colors, as hex, when a palette was asked for.
+    pub palette: Option<Vec<String>>,
+    /// Measurements of the source, when asked for; every output of an image shares them.
+    pub stats: Option<Stats>,
+    /// A perceptual hash of the source, when asked for; every output of an image shares it.
+    pub phash: Option<u64>,
+    /// Whether the source was turned a quarter to face the way `--orient-to` asked; every
+    /// output of an image shares it.
+    pub rotated: bool,
+    /// Whether the source was center-cropped to `--max-aspect`; every output of an image
+    /// shares it.
+    pub cropped: bool,
+    /// The format the source turned out to be, when its extension named one it failed to
+    /// decode as; every output of an image shares it.
+    pub sniffed: Option<ImageFormat>,
+    /// How long each stage of processing the image took, on its first output; the rest have
+    /// none of their own.
+    pub timings: Timings,
+}
+
+impl Output {
+    /// An output with `status` at `path`, with nothing else known about it yet.
+    pub fn new(path: Option<PathBuf>, status: Status) -> Output {
+        Output {
+            status,
+            path,
+            dimensions: None,
+            bytes: None,
+            palette: None,
+            stats: None,
+            phash: None,
+            rotated: false,
+            cropped: false,
+            sniffed: None,
+            timings: Timings::default(),
+        }
+    }
+
+    pub fn skipped(reason: String, dimensions: Option<(u32, u32)>) -> Output {
+        Output {
+            dimensions,
+            ..Output::new(None, Status::Skipped(reason))
+        }
+    }
+}
+
+/// Resizes the image at `path` as `options` describe, writing every output next to it. An
+/// output that would overwrite the image is refused unless the options allow it.
+pub fn resize_file(path: impl AsRef<Path>, options: &ResizeOptions) -> io::Result<Vec<Output>> {
+    let source = path.as_ref().to_string_lossy();
+    let job = Job::new(&source, options.settings());
+    if !options.in_place && overwrites_source(&job, options) {
+        return Err(io::Error::new(
+            io::ErrorKind::AlreadyExists,
+            format!("{} would be overwritten by its output", source),
+        ));
+    }
+    let outputs = process(&job, options)?;
+
+    for output in &outputs {
+        if let (Some(path), Some(bytes)) = (&output.path, &output.bytes) {
+            fs::write(path, bytes)?;
+        }
+    }
+    Ok(outputs)
+}
+
+/// Resizes an encoded image in memory, as `options` describe, keeping its format unless told
+/// otherwise. An image that needs no resizing comes back as given.
+///
+/// There is only one image to return, so the options may not ask for several sizes, other than
+/// for an icon, nor for a tile pyramid.
+pub fn resize_bytes(bytes: &[u8], options: &ResizeOptions) -> io::Result<Vec<u8>> {
+    if let Operation::Tiles = options.settings.operation {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "tile pyramids can only be written to files",
+        ));
+    }
+
+    let mut job = Job {
+        data: Some(bytes.to_vec()),
+        ..Job::new("", options.settings())
+    };
+    if job.settings.format.is_none() {
+        job.settings.format = Some(image::guess_format(bytes).map_err(io::Error::other)?);
+    }
+
+    let mut outputs = process(&job, options)?;
+    if outputs.len() != 1 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            "several sizes make several images; use `process` instead",
+        ));
+    }
+    Ok(outputs.remove(0).bytes.unwrap_or_else(|| bytes.to_vec()))
+}
+
+/// Like `resize_bytes`, but run on Tokio's blocking pool so as not to hold up async tasks with
+/// decoding, resizing and encoding.
+///
+/// This must be called from within a Tokio runtime.
+#[cfg(feature = "async")]
+pub async fn resize_async(bytes: Vec<u8>, options: ResizeOptions) -> io::Result<Vec<u8>> {
+    tokio::task::spawn_blocking(move || resize_bytes(&bytes, &options))
+        .await
+        .map_err(io::Error::other)?
+}
+
+/// Processes a job, using `options` for everything but the job's own settings.
+///
+/// Nothing is written except tile pyramids; other outputs are returned with their bytes.
+pub fn process(job: &Job, options: &ResizeOptions) -> io::Result<Vec<Output>> {
+    let mut timings = Timings::default();
+    let hint = decode_hint(job, options);
+    let mut sniffed = None;
+    let loaded = load(job, hint, options.input_cap, &mut timings).or_else(|e| {
+        let retried = match options.retry_sniffed {
+            true => load_sniffed(job, hint, options.input_cap, &mut timings),
+            false => None,
+        };
+        let (decoded, format) = retried.ok_or(e)?;
+        sniffed = Some(format);
+        Ok(decoded)
+    });
+    let (buffer, reduced, partial) = match loaded {
+        Ok((buffer, reduced)) => (buffer, reduced, false),
+        Err(e) => {
+            let salvaged = if options.allow_partial {
+                salvage(job)
+            } else {
+                None
+            };
+            match salvaged {
+                Some(buffer) => (buffer, false, true),
+                None if options.error_image => return placeholders(job, options, e),
+                None => return Err(e),
+            }
+        }
+    };
+
+    // Turned before anything else, so that measurements, crops and sizes all see the image
+    // the way round it will be written.
+    let started = Instant::now();
+    let turned = options
+        .orient_to
+        .and_then(|orientation| orient::turn_to(&buffer, orientation));
+    let rotated = turned.is_some();
+    let mut buffer = turned.unwrap_or(buffer);
+
+    // Elongation is judged the way round the image will be written, and before any other crop.
+    let mut cropped = false;
+    if let Some(max) = options.max_aspect {
+        let (width, height) = buffer.dimensions();
+        if let Some((x, y, w, h)) = crop::clamp_rect(width, height, max.ratio) {
+            match max.action {
+                AspectAction::Skip => {
+                    let ratio = f64::from(width.max(height)) / f64::from(width.min(height));
+                    let reason = format!("aspect {:.2} beyond {}", ratio, max.ratio);
+                    return Ok(vec![Output::skipped(reason, Some((width, height)))]);
+                }
+                AspectAction::Crop => {
+                    buffer = buffer.crop_imm(x, y, w, h);
+                    cropped = true;
+                }
+            }
+        }
+    }
+
+    let stats = options.stats.then(|| stats::measure(&buffer));
+    let phash = options.phash.then(|| phash::dhash(&buffer));
+    let mut outputs = process_image(job, buffer, reduced || cropped, options, &mut timings)?;
+    // Whatever wasn't spent encoding or writing went on resizing and the edits around it.
+    timings.resize = started
+        .elapsed()
+        .saturating_sub(timings.encode + timings.write);
+    if let Some(first) = outputs.first_mut() {
+        first.timings = timings;
+    }
+    for output in &mut outputs {
+        if partial {
+            if let Status::Resized | Status::Tiled | Status::Oriented = output.status {
+                output.status = Status::Partial;
+            }
+        }
+        output.stats = stats.clone();
+        output.phash = phash;
+        output.rotated = rotated;
+        output.cropped = cropped;
+        output.sniffed = sniffed;
+    }
+
+    // Outputs carry none of the source's metadata, so get back just what they need to display
+    // as it did.
+    if options.keep_orientation {
+        let orientation = match &job.data {
+            Some(data) => orient::read_orientation_from(&mut Cursor::new(data)),
+            None => orient::read_orientation(&job.source),
+        };
+        if let Some(orientation) = orientation.filter(|&orientation| orientation != 1) {
+            for bytes in outputs.iter_mut().flat_map(|output| &mut output.bytes) {
+                if let Ok(format) = image::guess_format(bytes) {
+                    orient::tag(bytes, format, orientation);
+                }
+            }
+        }
+    }
+    Ok(outputs)
+}
+
+/// Where a job's outputs go, along with the name their format is judged by.
+fn targets(job: &Job, settings: &Settings, options: &ResizeOptions) -> (PathBuf, PathBuf) {
+    let source = Path::new(&job.source);
+    let target = match &job.out {
+        Some(out) => out.clone(),
+        None => derived_target(source, settings, options.naming()),
+    };
+    // A custom extension says nothing of the format, which is then the one the output's name
+    // would otherwise have implied.
+    let named = match &options.naming.extension {
+        Some(_) => derived_target(source, settings, &options.naming.without_extension()),
+        None => target.clone(),
+    };
+    (target, named)
+}
+
+/// Records in an encoded output what the options ask of its metadata: its print resolution,
+/// its color space and a thumbnail of it.
+fn stamp(bytes: &mut Vec<u8>, format: ImageFormat, options: &ResizeOptions) -> io::Result<()> {
+    if let Some(dpi) = options.dpi {
+        dpi::stamp(bytes, format, dpi);
+    }
+    if let Some(space) = options.color_space {
+        color::tag(bytes, format, space);
+    }
+    if options.regenerate_thumbnail && format == ImageFormat::Jpeg {
+        thumbnail::embed(bytes)?;
+    }
+    Ok(())
+}
+
+/// Stands a placeholder in for every output of an image that failed to load, each a square of
+/// its size, so that no output goes missing. Only resizes have sizes to fill; anything else
+/// still fails with `error`.
+fn placeholders(job: &Job, options: &ResizeOptions, error: io::Error) -> io::Result<Vec<Output>> {
+    let settings = &job.settings;
+    if !settings.operation.uses_sizes() {
+        return Err(error);
+    }
+
+    let (target, named) = targets(job, settings, options);
+    let format = output_format(settings, &named)?;
+    let status = Status::Placeholder(error.to_string());
+    let placeholder = |path: PathBuf, size: u32| -> io::Result<Output> {
+        let image = DynamicImage::ImageRgb8(placeholder::broken(size));
+        let quality = settings.quality.for_size(size);
+        let mut bytes = match format {
+            ImageFormat::Ico => ico::encode_icon(&image, &settings.sizes)?,
+            _ => encode::encode_dynamic(&image, format, quality, options.jpeg, options.png)?,
+        };
+        stamp(&mut bytes, format, options)?;
+        Ok(Output {
+            bytes: Some(bytes),
+            dimensions: Some((size, size)),
+            ..Output::new(Some(path), status.clone())
+        })
+    };
+
+    let largest = settings.sizes.iter().copied().max().unwrap_or(1);
+    if format == ImageFormat::Ico {
+        return Ok(vec![placeholder(target, largest)?]);
+    }
+
+    let mut outputs = Vec::new();
+    for &size in &settings.sizes {
+        let path = if settings.sizes.len() > 1 {
+            output::sized_path(&target, size)
+        } else {
+            target.clone()
+        };
+        outputs.push(placeholder(path.clone(), size)?);
+        for &factor in &options.retina {
+            let retina = output::retina_path(&path, factor);
+            outputs.push(placeholder(retina, size.saturating_mul(factor))?);
+        }
+    }
+    Ok(outputs)
+}
+
+/// The outputs of `buffer`, which is `altered` if it no longer has the source's pixels, so
+/// that outputs needing no resizing are written all the same.
+fn process_image(
+    job: &Job,
+    mut buffer: DynamicImage,
+    altered: bool,
+    options: &ResizeOptions,
+    timings: &mut Timings,
+) -> io::Result<Vec<Output>> {
+    let image = job.source.as_str();
+
+    // Trimmed first, as the visible content is all that sizes and crops should consider.
+    let mut edited = altered;
+    if options.trim_transparent {
+        let (width, height) = buffer.dimensions();
+        match crop::visible_rect(&buffer) {
+            Some((0, 0, w, h)) if (w, h) == (width, height) => (),
+            Some((x, y, w, h)) => {
+                buffer = buffer.crop_imm(x, y, w, h);
+                edited = true;
+            }
+            None => {
+                let reason = String::from("fully transparent");
+                return Ok(vec![Output::skipped(reason, Some((width, height)))]);
+            }
+        }
+    }
+
+    if let Some(space) = options.color_space {
+        let source = match &job.data {
+            Some(data) => Cow::Borrowed(data),
+            None => Cow::Owned(fs::read(image)?),
+        };
+        let profile = color::embedded_profile(&source);
+        if let Some(converted) = color::convert(&buffer, profile.as_deref(), space) {
+            buffer = converted;
+            edited = true;
+        }
+    }
+
+    let settings = &match options.widths {
+        true => Cow::Owned(Settings {
+            sizes: (job.settings.sizes.iter())
+                .map(|&width| long_edge_for_width(buffer.dimensions(), width))
+                .collect(),
+            ..job.settings.clone()
+        }),
+        false => Cow::Borrowed(&job.settings),
+    };
+
+    if let Some(sigma) = options.denoise {
+        buffer = buffer.blur(sigma);
+    }
+
+    if let Some((levels, clip)) = options.levels {
+        let mut rgba = buffer.into_rgba();
+        levels::stretch(&mut rgba, levels, clip);
+        buffer = DynamicImage::ImageRgba8(rgba);
+    }
+
+    // Edited pixels no longer match the source's, so it can't simply be transformed losslessly.
+    edited |= options.denoise.is_some() || options.levels.is_some();
+
+    if let Some(aspect) = options.crop_aspect {
+        let (width, height) = buffer.dimensions();
+        // A crop to a nearly matching aspect would only lose a sliver of pixels.
+        if !aspect.matches(width, height, options.aspect_tolerance) {
+            let (x, y, width, height) = crop::center_rect(width, height, aspect);
+            buffer = buffer.crop_imm(x, y, width, height);
+            edited = true;
+        }
+    }
+
+    // Padded after any crop, so as to add to the region kept rather than take from it.
+    if let Some((aspect, fill)) = options.pad_aspect {
+        let (width, height) = buffer.dimensions();
+        let (width, height) = crop::padded_dimensions(width, height, aspect);
+        if (width, height) != buffer.dimensions() {
+            let canvas = Canvas {
+                width,
+                height,
+                color: fill.color(&buffer),
+                anchor: Anchor::Center,
+            };
+            buffer = DynamicImage::ImageRgba8(canvas::place(&buffer, &canvas));
+            edited = true;
+        }
+    }
+
+    let (target, named) = targets(job, settings, options);
+
+    if let Operation::Tiles = settings.operation {
+        // A pyramid is far too many files to hold back, so it is written straight away.
+        timing::time(&mut timings.write, || {
+            tiles::write_pyramid(&target, &buffer, options.tile_size)
+        })?;
+        return Ok(vec![Output {
+            dimensions: Some(buffer.dimensions()),
+            ..Output::new(Some(target), Status::Tiled)
+        }]);
+    }
+
+    if let Operation::OrientOnly = settings.operation {
+        let orientation = match &job.data {
+            Some(data) => orient::read_orientation_from(&mut Cursor::new(data)),
+            None => orient::read_orientation(image),
+        };
+        let output = match orientation.filter(|&orientation| orientation != 1) {
+            Some(orientation) => {
+                let format = output_format(settings, &named)?;
+                if format == ImageFormat::Jpeg && !edited {
+                    let source = match &job.data {
+                        Some(data) => Cow::Borrowed(data),
+                        None => Cow::Owned(fs::read(image)?),
+                    };
+                    let reoriented = timing::time(&mut timings.encode, || {
+                        lossless::reorient(&source, orientation)
+                    });
+                    if let Some((bytes, dimensions)) = reoriented {
+                        return Ok(vec![Output {
+                            bytes: Some(bytes),
+                            dimensions: Some(dimensions),
+                            ..Output::new(Some(target), Status::Oriented)
+                        }]);
+                    }
+                }
+
+                let mut oriented = orient::apply(&buffer, orientation);
+                if let Some(bit_depth) = options.resampling.bit_depth {
+                    oriented = depth::convert(&oriented, bit_depth).into_owned();
+                }
+                let mut bytes = timing::time(&mut timings.encode, || {
+                    encode::encode_dynamic(&oriented, format, None, options.jpeg, options.png)
+                })?;
+                if let Some(space) = options.color_space {
+                    color::tag(&mut bytes, format, space);
+                }
+                Output {
+                    bytes: Some(bytes),
+                    dimensions: Some(oriented.dimensions()),
+                    ..Output::new(Some(target), Status::Oriented)
+                }
+            }
+            None => {
+                let reason = String::from("already upright");
+                Output::skipped(reason, Some(buffer.dimensions()))
+            }
+        };
+        return Ok(vec![output]);
+    }
+
+    if let Operation::Canvas = settings.operation {
+        let canvas = options
+            .canvas
+            .ok_or_else(|| io::Error::new(io::ErrorKind::InvalidInput, "no canvas size given"))?;
+        let mut placed = DynamicImage::ImageRgba8(canvas::place(&buffer, &canvas));
+        if let Some(bit_depth) = options.resampling.bit_depth {
+            placed = depth::convert(&placed, bit_depth).into_owned();
+        }
+        let format = output_format(settings, &named)?;
+        let quality = settings.quality.for_size(canvas.width.max(canvas.height));
+        let bytes = timing::time(&mut timings.encode, || {
+            let mut bytes =
+                encode::encode_dynamic(&placed, format, quality, options.jpeg, options.png)?;
+            stamp(&mut bytes, format, options)?;
+            io::Result::Ok(bytes)
+        })?;
+        return Ok(vec![Output {
+            bytes: Some(bytes),
+            dimensions: Some((canvas.width, canvas.height)),
+            ..Output::new(Some(target), Status::Placed)
+        }]);
+    }
+
+    if settings.format == Some(ImageFormat::Ico) {
+        let bytes = timing::time(&mut timings.encode, || {
+            ico::encode_icon(&buffer, &settings.sizes)
+        })?;
+        return Ok(vec![Output {
+            bytes: Some(bytes),
+            ..Output::new(Some(target), Status::Resized)
+        }]);
+    }
+
+    let mut outputs = Vec::with_capacity(settings.sizes.len() * (1 + options.retina.len()));
+    for &size in &settings.sizes {
+        let path = if settings.sizes.len() > 1 {
+            output::sized_path(&target, size)
+        } else {
+            target.clone()
+        };
+        let format = output_format(settings, &named)?;
+        let quality = settings.quality.for_size(size);
+
+        // An edited image is never close enough, as leaving it be would lose the edits.
+        let near = match (settings.operation, options.skip_ratio) {
+            (Operation::Shrink, Some(ratio)) if !edited => {
+                near_size(buffer.dimensions(), size, ratio)
+            }
+            _ => false,
+        };
+        let resize = match near {
+            true => Resize::Noop,
+            false => resize_to(&buffer, size, settings.operation, options)?,
+        };
+        let resize = match resize {
+            Resize::Noop if edited => unresized(&buffer, options),
+            resize => resize,
+        };
+        if let Some(grid) = options.split {
+            outputs.extend(timing::time(&mut timings.encode, || {
+                split(&resize, &buffer, &path, (format, quality), grid, options)
+            })?);
+        } else {
+            let mut output = timing::time(&mut timings.encode, || {
+                encoded(&resize, &buffer, size, &path, (format, quality), options)
+            })?;
+            if near {
+                output.status = Status::NearSize(size);
+            }
+            let comparison = match &output.bytes {
+                Some(bytes) if options.compare => Some(timing::time(&mut timings.encode, || {
+                    compared(&buffer, bytes, &path, (format, quality), options)
+                })?),
+                _ => None,
+            };
+            outputs.push(output);
+            outputs.extend(comparison);
+        }
+
+        for &factor in &options.retina {
+            let scaled = size.saturating_mul(factor);
+            let (width, height) = buffer.dimensions();
+            let path = output::retina_path(&path, factor);
+
+            // Shrinking never invents pixels, so a variant needs a source at least its size.
+            let resize = match settings.operation {
+                Operation::Shrink if width.max(height) < scaled => {
+                    let reason = format!("too small for @{}x", factor);
+                    outputs.push(Output::skipped(reason, Some((width, height))));
+                    continue;
+                }
+                Operation::Shrink if width.max(height) == scaled => unresized(&buffer, options),
+                _ => match resize_to(&buffer, scaled, settings.operation, options)? {
+                    Resize::Noop if edited => unresized(&buffer, options),
+                    resize => resize,
+                },
+            };
+            let encoded = timing::time(&mut timings.encode, || match options.split {
+                Some(grid) => split(&resize, &buffer, &path, (format, quality), grid, options),
+                None => encoded(&resize, &buffer, scaled, &path, (format, quality), options)
+                    .map(|output| vec![output]),
+            })?;
+            outputs.extend(encoded);
+        }
+    }
+
+    Ok(outputs)
+}
+
+/// `buffer` at the size it is, for an output that needs no resizing but must be written.
+fn unresized(buffer: &DynamicImage, options: &ResizeOptions) -> Resize {
+    match options.resampling.bit_depth {
+        Some(BitDepth::Sixteen) => Resize::Resize {
+            buffer: Box::new(depth::rgba16(buffer)),
+        },
+        _ => finish_resize(
+            buffer.to_rgba(),
+            buffer.color().has_alpha(),
+            &options.effects,
+            &options.resampling,
+        ),
+    }
+}
+
+/// Whether an image of `dimensions` is larger than `size`, but by no more than `ratio`.
+fn near_size((width, height): (u32, u32), size: u32, ratio: f64) -> bool {
+    let long = width.max(height);
+    long > size && f64::from(long) <= f64::from(size) * ratio
+}
+
+fn resize_to(
+    buffer: &DynamicImage,
+    size: u32,
+    operation: Operation,
+    options: &ResizeOptions,
+) -> io::Result<Resize> {
+    Ok(match operation {
+        Operation::Enlarge => enlarge(buffer, size, &options.resampling, &options.effects)?,
+        Operation::Fit => fit(buffer, size, &options.resampling, &options.effects)?,
+        _ => shrink(
+            buffer,
+            size,
+            options.max_short_edge,
+            &options.resampling,
+            &options.effects,
+        ),
+    })
+}
+
+/// The output for resizing `source` to `size` at `path`, or a noop if it needed no resizing.
+/// An output that fails to encode is written in the fallback format instead, if there is one.
+fn encoded(
+    resize: &Resize,
+    source: &DynamicImage,
+    size: u32,
+    path: &Path,
+    (format, quality): (ImageFormat, Option<u8>),
+    options: &ResizeOptions,
+) -> io::Result<Output> {
+    let fallback = options
+        .fallback_format
+        .filter(|&fallback| fallback != format);
+    let (encoded, format, path, status) = match (
+        resize.encode(format, quality, options.jpeg, options.png),
+        fallback,
+    ) {
+        (Err(e), Some(fallback)) => {
+            let status = Status::FellBack {
+                format: fallback.extensions_str()[0],
+                error: e.to_string(),
+            };
+            let path = output::with_format(path, fallback);
+            (
+                resize.encode(fallback, quality, options.jpeg, options.png)?,
+                fallback,
+                path,
+                status,
+            )
+        }
+        (encoded, _) => (encoded?, format, path.to_path_buf(), Status::Resized),
+    };
+    Ok(match encoded {
+        Some(mut bytes) => {
+            stamp(&mut bytes, format, options)?;
+            Output {
+                dimensions: resize.dimensions(),
+                bytes: Some(bytes),
+                palette: options.palette.and_then(|count| resize.palette(count)),
+                ..Output::new(Some(path), status)
+            }
+        }
+        None => Output {
+            dimensions: Some(source.dimensions()),
+            ..Output::new(None, Status::Noop(size))
+        },
+    })
+}
+
+/// The outputs for `resize`, or for `source` if it needed no resizing, cut into the pieces of
+/// `grid`, each at `path` named for its place in the grid.
+fn split(
+    resize: &Resize,
+    source: &DynamicImage,
+    path: &Path,
+    (format, quality): (ImageFormat, Option<u8>),
+    grid: Grid,
+    options: &ResizeOptions,
+) -> io::Result<Vec<Output>> {
+    let (width, height) = resize.dimensions().unwrap_or_else(|| source.dimensions());
+    if width < grid.columns || height < grid.rows {
+        let reason = format!("too small to split {}x{}", grid.columns, grid.rows);
+        return Ok(vec![Output::skipped(reason, Some((width, height)))]);
+    }
+
+    let piece = |(row, column, (x, y, width, height))| -> io::Result<Output> {
+        let mut bytes = match resize {
+            Resize::Resize { buffer } => buffer.crop(x, y, width, height).encode(
+                format,
+                quality,
+                options.jpeg,
+                options.png,
+            )?,
+            Resize::Noop => {
+                let piece = source.crop_imm(x, y, width, height);
+                encode::encode_dynamic(&piece, format, quality, options.jpeg, options.png)?
+            }
+        };
+        stamp(&mut bytes, format, options)?;
+        Ok(Output {
+            dimensions: Some((width, height)),
+            bytes: Some(bytes),
+            ..Output::new(Some(output::piece_path(path, row, column)), Status::Resized)
+        })
+    };
+    grid.pieces(width, height).into_iter().map(piece).collect()
+}
+
+/// A side-by-side of `source` and the output encoded as `bytes` at `path`.
+fn compared(
+    source: &DynamicImage,
+    bytes: &[u8],
+    path: &Path,
+    (format, quality): (ImageFormat, Option<u8>),
+    options: &ResizeOptions,
+) -> io::Result<Output> {
+    let written = image::load_from_memory(bytes).map_err(io::Error::other)?;
+    let canvas = compare::side_by_side(source, &written);
+    Ok(Output {
+        dimensions: Some(canvas.dimensions()),
+        bytes: Some(canvas.encode(format, quality, options.jpeg, options.png)?),
+        ..Output::new(Some(output::compare_path(path)), Status::Compared)
+    })
+}
+
+/// Where the output for `source` goes when none is given, before any per-size suffix.
+pub fn derived_target(source: &Path, settings: &Settings, naming: &output::Naming) -> PathBuf {
+    let target = match settings.format {
+        Some(format) => output::with_format(source, format),
+        // Nothing can write raw files, so they become JPEGs unless told otherwise.
+        None if raw::is_raw(source) => output::with_format(source, ImageFormat::Jpeg),
+        None => source.to_path_buf(),
+    };
+    naming.apply(&target)
+}
+
+/// Whether writing `job`'s outputs would overwrite its source. Sources in memory have no file
+/// to overwrite.
+pub fn overwrites_source(job: &Job, options: &ResizeOptions) -> bool {
+    overwritten_source(std::slice::from_ref(job), options).is_some()
+}
+
+/// The first of `jobs` whose outputs would overwrite a source, its own or another job's, along
+/// with the source it would overwrite. Sources in memory have no file to overwrite.
+pub fn overwritten_source<'a>(
+    jobs: &'a [Job],
+    options: &ResizeOptions,
+) -> Option<(&'a Job, &'a str)> {
+    // Each source under its path as given and as resolved, so that either way of naming it is
+    // caught.
+    let mut sources: HashMap<PathBuf, &str> = HashMap::new();
+    for job in jobs.iter().filter(|job| job.data.is_none()) {
+        let path = Path::new(&job.source);
+        if let Ok(canonical) = fs::canonicalize(path) {
+            sources.insert(canonical, &job.source);
+        }
+        sources.insert(path.to_path_buf(), &job.source);
+    }
+    jobs.iter().find_map(|job| {
+        output_paths(job, options).into_iter().find_map(|path| {
+            let overwritten = match sources.get(&path) {
+                Some(&source) => Some(source),
+                None => fs::canonicalize(&path)
+                    .ok()
+                    .and_then(|path| sources.get(&path).copied()),
+            };
+            overwritten.map(|source| (job, source))
+        })
+    })
+}
+
+/// Every path `job` could write an output to, before any collision numbering: each size,
+/// retina variant, piece and comparison, and where a fallback would go. Sizes given as widths
+/// are named as if they were long edges, which changes no name into the source's.
+fn output_paths(job: &Job, options: &ResizeOptions) -> Vec<PathBuf> {
+    let settings = &job.settings;
+    let (target, _) = targets(job, settings, options);
+    match settings.operation {
+        // The tiles themselves go in a folder of their own beside the descriptor.
+        Operation::Tiles => return vec![target.with_extension("dzi")],
+        Operation::OrientOnly | Operation::Canvas => return vec![target],
+        _ if settings.format == Some(ImageFormat::Ico) => return vec![target],
+        _ => (),
+    }
+
+    let mut paths = Vec::new();
+    let mut add = |path: PathBuf| {
+        if let Some(fallback) = options.fallback_format {
+            paths.push(output::with_format(&path, fallback));
+        }
+        match options.split {
+            Some(grid) => {
+                for row in 0..grid.rows {
+                    for column in 0..grid.columns {
+                        paths.push(output::piece_path(&path, row, column));
+                    }
+                }
+            }
+            None if options.compare => {
+                paths.push(output::compare_path(&path));
+                paths.push(path);
+            }
+            None => paths.push(path),
+        }
+    };
+    for &size in &settings.sizes {
+        let path = match settings.sizes.len() {
+            1 => target.clone(),
+            _ => output::sized_path(&target, size),
+        };
+        for &factor in &options.retina {
+            add(output::retina_path(&path, factor));
+        }
+        add(path);
+    }
+    paths
+}
+
+/// The format to encode an output in: as requested, or else implied by its path.
+fn output_format(settings: &Settings, path: &Path) -> io::Result<ImageFormat> {
+    match settings.format {
+        Some(format) => Ok(format),
+        None => ImageFormat::from_path(path).map_err(io::Error::other),
+    }
+}
+
+/// A writable image buffer.
+trait Writable {
+    fn dimensions(&self) -> (u32, u32);
+    fn encode(
+        &self,
+        format: ImageFormat,
+        quality: Option<u8>,
+        jpeg: JpegOptions,
+        png: PngOptions,
+    ) -> io::Result<Vec<u8>>;
+    fn palette(&self, count: usize) -> Vec<String>;
+    fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Box<dyn Writable>;
+}
+
+impl<P, Container> Writable for ImageBuffer<P, Container>
+where
+    P: Pixel + 'static,
+    P::Subpixel: 'static,
+    [P::Subpixel]: EncodableLayout,
+    Container: Deref<Target = [P::Subpixel]>,
+{
+    fn dimensions(&self) -> (u32, u32) {
+        ImageBuffer::dimensions(self)
+    }
+
+    fn encode(
+        &self,
+        format: ImageFormat,
+        quality: Option<u8>,
+        jpeg: JpegOptions,
+        png: PngOptions,
+    ) -> io::Result<Vec<u8>> {
+        let dimensions = ImageBuffer::dimensions(self);
+        let color = P::COLOR_TYPE;
+        encode::encode(
+            self.as_bytes(),
+            dimensions,
+            color,
+            format,
+            quality,
+            jpeg,
+            png,
+        )
+    }
+
+    fn palette(&self, count: usize) -> Vec<String> {
+        palette::dominant(self.as_bytes(), P::COLOR_TYPE, count)
+    }
+
+    fn crop(&self, x: u32, y: u32, width: u32, height: u32) -> Box<dyn Writable> {
+        let piece: ImageBuffer<P, Vec<P::Subpixel>> =
+            ImageBuffer::from_fn(width, height, |px, py| *self.get_pixel(x + px, y + py));
+        Box::new(piece)
+    }
+}
+
+enum Resize {
+    Resize { buffer: Box<dyn Writable> },
+    Noop,
+}
+
+impl Resize {
+    fn dimensions(&self) -> Option<(u32, u32)> {
+        match self {
+            Resize::Resize { buffer } => Some(buffer.dimensions()),
+            Resize::Noop => None,
+        }
+    }
+
+    /// Encodes the resized image, if there is one.
+    fn encode(
+        &self,
+        format: ImageFormat,
+        quality: Option<u8>,
+        jpeg: JpegOptions,
+        png: PngOptions,
+    ) -> io::Result<Option<Vec<u8>>> {
+        match self {
+            Resize::Resize { buffer } => buffer.encode(format, quality, jpeg, png).map(Some),
+            Resize::Noop => Ok(None),
+        }
+    }
+
+    /// Up to `count` dominant colors of the resized image, if there is one.
+    fn palette(&self, count: usize) -> Option<Vec<String>> {
+        match self {
+            Resize::Resize { buffer } => Some(buffer.palette(count)),
+            Resize::Noop => None,
+        }
+    }
+}
+
+/// The most pixels an image may have, decoded or resized, about 4 GiB as RGBA. Anything larger
+/// is more likely a hostile header than a photo.
+const MAX_PIXELS: u64 = 1 << 30;
+
+/// The smallest longest edge the decoded image may have, if decoding may shrink it, which is
+/// the case only when every output is a shrink of the whole image.
+fn decode_hint(job: &Job, options: &ResizeOptions) -> Option<u32> {
+    let settings = &job.settings;
+    let aspect_crop = matches!(
+        options.max_aspect,
+        Some(MaxAspect {
+            action: AspectAction::Crop,
+            ..
+        })
+    );
+    // How close a source is to a size is a matter of its own size, not a scaled decode's.
+    if !options.dct_scaling
+        || options.crop_aspect.is_some()
+        || aspect_crop
+        || options.skip_ratio.is_some()
+    {
+        return None;
+    }
+    if let Operation::Shrink = settings.operation {
+        let factor = options.retina.iter().copied().max().unwrap_or(1);
+        return settings
+            .sizes
+            .iter()
+            .max()
+            .map(|&size| size.saturating_mul(factor));
+    }
+    None
+}
+
+/// Decodes a job's source, along with whether it was decoded smaller than it is.
+fn load(
+    job: &Job,
+    shrink_to: Option<u32>,
+    cap: Option<InputCap>,
+    timings: &mut Timings,
+) -> io::Result<(DynamicImage, bool)> {
+    let path = Path::new(&job.source);
+    match &job.data {
+        Some(data) if raw::is_raw(path) => {
+            let buffer = timing::time(&mut timings.decode, || raw::decode(data))?;
+            Ok((buffer, false))
+        }
+        Some(data) => decode(
+            || {
+                let mut loader = ImageLoader::new(Cursor::new(data));
+                if let Ok(format) = ImageFormat::from_path(path) {
+                    loader.set_format(format);
+                }
+                loader.with_guessed_format()
+            },
+            shrink_to,
+            cap,
+            timings,
+        ),
+        None if raw::is_raw(path) => {
+            let data = timing::time(&mut timings.open, || fs::read(path))?;
+            let buffer = timing::time(&mut timings.decode, || raw::decode(&data))?;
+            Ok((buffer, false))
+        }
+        None => decode(|| ImageLoader::open(path), shrink_to, cap, timings),
+    }
+}
+
+/// Decodes a source that failed to decode as its extension says in the format its contents
+/// say instead, along with that format, if they say another.
+fn load_sniffed(
+    job: &Job,
+    shrink_to: Option<u32>,
+    cap: Option<InputCap>,
+    timings: &mut Timings,
+) -> Option<((DynamicImage, bool), ImageFormat)> {
+    // Sources in memory are sniffed from the start.
+    let path = Path::new(&job.source);
+    if job.data.is_some() || raw::is_raw(path) {
+        return None;
+    }
+    let sniffed = || ImageLoader::open(path)?.with_guessed_format();
+    let format = sniffed().ok()?.format()?;
+    if ImageFormat::from_path(path).ok() == Some(format) {
+        return None;
+    }
+    let decoded = decode(sniffed, shrink_to, cap, timings).ok()?;
+    Some((decoded, format))
+}
+
+/// Decodes an image once its header shows it is of a sane size, shrunk to fit within `cap` if
+/// it's larger, along with whether it was decoded any smaller than it is. `loader` is called
+/// for each look at the header and once more for the image.
+fn decode<R: BufRead + Seek>(
+    loader: impl Fn() -> io::Result<ImageLoader<R>>,
+    shrink_to: Option<u32>,
+    cap: Option<InputCap>,
+    timings: &mut Timings,
+) -> io::Result<(DynamicImage, bool)> {
+    let opened = Instant::now();
+    // Only the first frame would decode, if any did, which is no resize of an animation.
+    let header = loader()?;
+    if header.format() == Some(ImageFormat::WebP) && webp::is_animated(header.into_inner())? {
+        return Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "animated WebP can't be resized",
+        ));
+    }
+
+    let (width, height) = loader()?.into_dimensions().map_err(io::Error::other)?;
+    check_pixels(width, height)?;
+
+    let capped = match cap {
+        Some(cap) if width.max(height) > cap.max_dimension => {
+            if cap.reject {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "a {}x{} image is over the {}px input cap",
+                        width, height, cap.max_dimension
+                    ),
+                ));
+            }
+            Some(cap.max_dimension)
+        }
+        _ => None,
+    };
+    // Anything over the cap only needs decoding at the cap, at most.
+    let shrink_to = match (shrink_to, capped) {
+        (Some(size), Some(edge)) => Some(size.min(edge)),
+        (size, edge) => size.or(edge),
+    };
+    let loader = loader()?;
+    timings.open += opened.elapsed();
+
+    timing::time(&mut timings.decode, || {
+        let decoded = decode_scaled(loader, (width, height), shrink_to)?;
+        let decoded = match capped {
+            Some(edge) if decoded.width().max(decoded.height()) > edge => {
+                decoded.resize(edge, edge, FilterType::Lanczos3)
+            }
+            _ => decoded,
+        };
+        let reduced = decoded.dimensions() != (width, height);
+        Ok((decoded, reduced))
+    })
+}
+
+/// Decodes an image of `width` by `height`, as small as it can be decoded without its longest
+/// edge falling short of `shrink_to`, for formats that can decode at a reduced scale.
+fn decode_scaled<R: BufRead + Seek>(
+    loader: ImageLoader<R>,
+    (width, height): (u32, u32),
+    shrink_to: Option<u32>,
+) -> io::Result<DynamicImage> {
+    let target = shrink_to
+        .and_then(|size| shrink_dimensions(width, height, size))
+        .and_then(|(width, height)| {
+            Some((u16::try_from(width).ok()?, u16::try_from(height).ok()?))
+        });
+    match (loader.format(), target) {
+        (Some(ImageFormat::Jpeg), Some((width, height))) => {
+            // The decoder scales by 1/2, 1/4 or 1/8 straight from the DCT coefficients, as far
+            // as it can without falling short of the target.
+            let mut decoder = JpegDecoder::new(loader.into_inner()).map_err(io::Error::other)?;
+            decoder.scale(width, height).map_err(io::Error::other)?;
+            DynamicImage::from_decoder(decoder).map_err(io::Error::other)
+        }
+        _ => loader.decode().map_err(io::Error::other),
+    }
+}
+
+fn check_pixels(width: u32, height: u32) -> io::Result<()> {
+    if width == 0 || height == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("a {}x{} image has no pixels", width, height),
+        ));
+    }
+    if width as u64 * height as u64 > MAX_PIXELS {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidInput,
+            format!("a {}x{} image is too large", width, height),
+        ));
+    }
+    Ok(())
+}
+
+/// Decodes what there is of a truncated image, for `--allow-partial`.
+fn salvage(job: &Job) -> Option<DynamicImage> {
+    let path = Path::new(&job.source);
+    let data = match &job.data {
+        Some(data) => Cow::Borrowed(data),
+        None => Cow::Owned(fs::read(path).ok()?),
+    };
+    let format = ImageFormat::from_path(path)
+        .or_else(|_| image::guess_format(&data))
+        .ok()?;
+    partial::decode(&data, format)
+}
+
+/// Applies `effects` to a freshly resized image, then any alpha threshold, since effects such
+/// as sharpening move alpha too. Last of all, an image that `has_alpha` trades it for any color
+/// key.
+fn finish_resize(
+    mut resized: RgbaImage,
+    has_alpha: bool,
+    effects: &[Effect],
+    resampling: &Resampling,
+) -> Resize {
+    for effect in effects {
+        effect.apply(&mut resized);
+    }
+    if let Some(threshold) = resampling.alpha_threshold {
+        threshold.apply(&mut resized);
+    }
+    let buffer: Box<dyn Writable> = match resampling.color_key {
+        Some(key) if has_alpha => Box::new(alpha::color_key(&resized, key)),
+        _ => Box::new(resized),
+    };
+    Resize::Resize { buffer }
+}
+
+fn enlarge(
+    buffer: &DynamicImage,
+    size: u32,
+    resampling: &Resampling,
+    effects: &[Effect],
+) -> io::Result<Resize> {
+    let (width, height) = buffer.dimensions();
+
+    if let Some((width, height)) = enlarge_dimensions(width, height, size) {
+        check_pixels(width, height)?;
+        let filter = filter::for_enlarge(resampling.enlarge_filter);
+        if resampling.bit_depth == Some(BitDepth::Sixteen) {
+            return Ok(resample_deep(
+                buffer, width, height, filter, None, resampling,
+            ));
+        }
+        let resized = resample(buffer, width, height, filter, resampling);
+        Ok(finish_resize(
+            resized,
+            buffer.color().has_alpha(),
+            effects,
+            resampling,
+        ))
+    } else {
+        Ok(Resize::Noop)
+    }
+}
+
+/// Shrinks the longest edge to `size`, or further if the shortest would still exceed
+/// `max_short`.
+fn shrink(
+    buffer: &DynamicImage,
+    size: u32,
+    max_short: Option<u32>,
+    resampling: &Resampling,
+    effects: &[Effect],
+) -> Resize {
+    let (width, height) = buffer.dimensions();
+    let dimensions = match max_short {
+        Some(max_short) => fit_edges(width, height, size, max_short),
+        None => shrink_dimensions(width, height, size),
+    };
+
+    if let Some((nwidth, nheight)) = dimensions {
+        let scale = nwidth.max(nheight) as f64 / width.max(height) as f64;
+        let filter = filter::for_shrink(resampling.shrink_filter, scale, resampling.area_threshold);
+        let sharpen = filter::sharpen_sigma(scale).filter(|_| resampling.auto_sharpen);
+        if resampling.bit_depth == Some(BitDepth::Sixteen) {
+            return resample_deep(buffer, nwidth, nheight, filter, sharpen, resampling);
+        }
+        let mut resized = resample(buffer, nwidth, nheight, filter, resampling);
+
+        if let Some(sigma) = sharpen {
+            resized = imageops::unsharpen(&resized, sigma, filter::SHARPEN_THRESHOLD);
+        }
+        finish_resize(resized, buffer.color().has_alpha(), effects, resampling)
+    } else {
+        Resize::Noop
+    }
+}
+
+/// Shrinks or enlarges `buffer`, whichever it takes for the longest edge to be exactly `size`.
+fn fit(
+    buffer: &DynamicImage,
+    size: u32,
+    resampling: &Resampling,
+    effects: &[Effect],
+) -> io::Result<Resize> {
+    let (width, height) = buffer.dimensions();
+    match width.max(height).cmp(&size) {
+        Ordering::Greater => Ok(shrink(buffer, size, None, resampling, effects)),
+        Ordering::Less => enlarge(buffer, size, resampling, effects),
+        Ordering::Equal => Ok(Resize::Noop),
+    }
+}
+
+fn resample(
+    buffer: &DynamicImage,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+    resampling: &Resampling,
+) -> RgbaImage {
+    let (width, height) = rounded_dimensions(width, height, resampling);
+    // Sampling a 16-bit image as RGBA keeps only the low byte of each channel, so it is
+    // narrowed properly first.
+    let buffer = depth::convert(buffer, BitDepth::Eight);
+    if resampling.parallel {
+        parallel::resize(&buffer.to_rgba(), width, height, filter)
+    } else {
+        imageops::resize(buffer.as_ref(), width, height, filter)
+    }
+}
+
+/// Resamples with 16 bits per channel, sharpening by `sharpen` if given. Effects and alpha
+/// thresholds work in 8 bits, so options never ask for them here.
+fn resample_deep(
+    buffer: &DynamicImage,
+    width: u32,
+    height: u32,
+    filter: FilterType,
+    sharpen: Option<f32>,
+    resampling: &Resampling,
+) -> Resize {
+    let (width, height) = rounded_dimensions(width, height, resampling);
+    let mut resized = imageops::resize(&depth::rgba16(buffer), width, height, filter);
+    if let Some(sigma) = sharpen {
+        // The threshold is in channel values, which run 257 times as far in 16 bits.
+        resized = imageops::unsharpen(&resized, sigma, filter::SHARPEN_THRESHOLD * 257);
+    }
+    Resize::Resize {
+        buffer: Box::new(resized),
+    }
+}
+
+/// Resized dimensions after any rounding the options ask for.
+fn rounded_dimensions(width: u32, height: u32, resampling: &Resampling) -> (u32, u32) {
+    let (width, height) = match resampling.round_to {
+        Some(multiple) => round_dimensions(width, height, multiple),
+        None => (width, height),
+    };
+    match resampling.even_dimensions {
+        true => round_dimensions(width, height, 2),
+        false => (width, height),
+    }
+}
+
+/// The longest edge that makes an image of `dimensions` `width` wide.
+fn long_edge_for_width((width, height): (u32, u32), target: u32) -> u32 {
+    if width >= height {
+        return target;
+    }
+    let long = (f64::from(target) * f64::from(height) / f64::from(width)).round();
+    long.min(f64::from(u32::MAX)) as u32
+}
+
+fn enlarge_dimensions(width: u32, height: u32, size: u32) -> Option<(u32, u32)> {
+    if width > height && width < size {
+        let nwidth = size;
+        let nheight = (size as f64 / width as f64 * height as f64).floor() as u32;
+        Some((nwidth, nheight))
+    } else if height < size {
+        let nheight = size;
+        let nwidth = (size as f64 / height as f64 * width as f64).floor() as u32;
+        Some((nwidth, nheight))
+    } else {
+        None
+    }
+}
+
+/// Shrinks to satisfy both a cap on the longest edge and one on the shortest, scaling by
+/// whichever binds; `None` if both are already satisfied.
+fn fit_edges(width: u32, height: u32, max_long: u32, max_short: u32) -> Option<(u32, u32)> {
+    let (long, short) = (width.max(height), width.min(height));
+    if long <= max_long && short <= max_short {
+        return None;
+    }
+    if max_long as f64 / long as f64 <= max_short as f64 / short as f64 {
+        return shrink_dimensions(width, height, max_long);
+    }
+
+    // The short edge binds, so it lands exactly on its cap.
+    let long = (max_short as f64 / short as f64 * long as f64).floor() as u32;
+    if width > height {
+        Some((long.max(1), max_short))
+    } else {
+        Some((max_short, long.max(1)))
+    }
+}
+
+/// Rounds each dimension down to a multiple of `multiple`, keeping at least one multiple.
+fn round_dimensions(width: u32, height: u32, multiple: u32) -> (u32, u32) {
+    let round = |edge: u32| (edge / multiple).max(1) * multiple;
+    (round(width), round(height))
+}
+
+fn shrink_dimensions(width: u32, height: u32, size: u32) -> Option<(u32, u32)> {
+    // A sliver of an image still keeps a row or column of pixels.
+    if width > height && width > size {
+        let nwidth = size;
+        let nheight = (size as f64 / width as f64 * height as f64).floor() as u32;
+        Some((nwidth, nheight.max(1)))
+    } else if height > size {
+        let nheight = size;
+        let nwidth = (size as f64 / height as f64 * width as f64).floor() as u32;
+        Some((nwidth.max(1), nheight))
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        crop::Aspect,
+        decode, enlarge_dimensions,
+        filter::{self, Resampling},
+        fit, fit_edges, long_edge_for_width, resize_bytes, round_dimensions, shrink_dimensions,
+        AspectAction, BitDepth, Grid, ImageLoader, InputCap, Orientation, ResizeOptions, Timings,
+    };
+    use image::{ColorType, DynamicImage, GenericImageView, ImageBuffer, ImageFormat};
+    use std::{io::Cursor, path::PathBuf};
+
+    fn resampling() -> Resampling {
+        Resampling {
+            shrink_filter: None,
+            enlarge_filter: None,
+            area_threshold: filter::DEFAULT_AREA_THRESHOLD,
+            auto_sharpen: false,
+            parallel: false,
+            round_to: None,
+            even_dimensions: false,
+            bit_depth: None,
+            alpha_threshold: None,
+            color_key: None,
+        }
+    }
+
+    #[test]
+    fn shrink_5000_3000() {
+        let actual = shrink_dimensions(5000, 3000, 2000);
+        let expected = Some((2000, 1200));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn shrink_3000_5000() {
+        let actual = shrink_dimensions(3000, 5000, 2000);
+        let expected = Some((1200, 2000));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn shrink_1200_1800() {
+        assert!(shrink_dimensions(1200, 1800, 2000).is_none());
+    }
+
+    #[test]
+    fn shrink_sliver() {
+        assert_eq!(shrink_dimensions(10000, 3, 100), Some((100, 1)));
+        assert_eq!(shrink_dimensions(2, 5000, 100), Some((1, 100)));
+    }
+
+    #[test]
+    fn panorama_fits_long_edge() {
+        assert_eq!(fit_edges(8000, 2000, 2048, 1080), Some((2048, 512)));
+        assert_eq!(fit_edges(2000, 8000, 2048, 1080), Some((512, 2048)));
+    }
+
+    #[test]
+    fn near_square_fits_short_edge() {
+        assert_eq!(fit_edges(3000, 2400, 2048, 1080), Some((1350, 1080)));
+        assert_eq!(fit_edges(2400, 3000, 2048, 1080), Some((1080, 1350)));
+        assert!(fit_edges(1920, 1080, 2048, 1080).is_none());
+    }
+
+    #[test]
+    fn width_sets_long_edge_of_portraits() {
+        assert_eq!(long_edge_for_width((4000, 3000), 1800), 1800);
+        assert_eq!(long_edge_for_width((3000, 4000), 1800), 2400);
+        assert_eq!(shrink_dimensions(3000, 4000, 2400), Some((1800, 2400)));
+    }
+
+    #[test]
+    fn near_sizes_are_within_ratio() {
+        use super::near_size;
+        assert!(near_size((1300, 900), 1200, 1.1));
+        assert!(near_size((900, 1320), 1200, 1.1));
+        assert!(!near_size((1400, 900), 1200, 1.1));
+        assert!(!near_size((1200, 900), 1200, 1.1));
+    }
+
+    #[test]
+    fn odd_dimensions_round_down() {
+        assert_eq!(round_dimensions(1001, 563, 8), (1000, 560));
+        assert_eq!(round_dimensions(1001, 563, 16), (992, 560));
+        assert_eq!(round_dimensions(1024, 576, 16), (1024, 576));
+    }
+
+    #[test]
+    fn rounding_keeps_a_multiple() {
+        assert_eq!(round_dimensions(1000, 5, 8), (1000, 8));
+        assert_eq!(round_dimensions(7, 15, 16), (16, 16));
+    }
+
+    #[test]
+    fn even_dimensions_for_video() {
+        let resampling = Resampling {
+            even_dimensions: true,
+            ..resampling()
+        };
+        // 300x201 shrinks to 100x67, one row too many for an even height.
+        let image = DynamicImage::new_rgb8(300, 201);
+        let resized = super::shrink(&image, 100, None, &resampling, &[]);
+        assert_eq!(resized.dimensions(), Some((100, 66)));
+
+        let sliver = DynamicImage::new_rgb8(301, 1);
+        let resized = super::shrink(&sliver, 101, None, &resampling, &[]);
+        assert_eq!(resized.dimensions(), Some((100, 2)));
+    }
+
+    #[test]
+    fn jpegs_decode_scaled_down() {
+        let mut bytes = Vec::new();
+        let image = DynamicImage::new_rgb8(1600, 1200);
+        image.write_to(&mut bytes, ImageFormat::Jpeg).unwrap();
+        let loader = || ImageLoader::new(Cursor::new(&bytes)).with_guessed_format();
+
+        assert_eq!(
+            decode(loader, Some(100), None, &mut Timings::default())
+                .unwrap()
+                .0
+                .dimensions(),
+            (200, 150)
+        );
+        assert_eq!(
+            decode(loader, Some(1000), None, &mut Timings::default())
+                .unwrap()
+                .0
+                .dimensions(),
+            (1600, 1200)
+        );
+        assert_eq!(
+            decode(loader, None, None, &mut Timings::default())
+                .unwrap()
+                .0
+                .dimensions(),
+            (1600, 1200)
+        );
+    }
+
+    #[test]
+    fn writes_jpegs_decoded_right_down_to_size() {
+        // 1/8 scale lands exactly on the size, which the scaled image is then already within.
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(1600, 200)
+            .write_to(&mut bytes, ImageFormat::Jpeg)
+            .unwrap();
+        let options = ResizeOptions::builder()
+            .size(200)
+            .downsample_before_decode(true)
+            .build()
+            .unwrap();
+        let job = super::Job {
+            data: Some(bytes),
+            ..super::Job::new("wide.jpg", options.settings())
+        };
+        let outputs = super::process(&job, &options).unwrap();
+        assert!(matches!(outputs[0].status, super::Status::Resized));
+        assert_eq!(outputs[0].dimensions, Some((200, 25)));
+    }
+
+    #[test]
+    fn writes_capped_sources_under_the_size() {
+        let options = ResizeOptions::builder()
+            .size(800)
+            .max_input_dimension(500, false)
+            .build()
+            .unwrap();
+        let job = super::Job {
+            data: Some(encoded_png(1000, 600)),
+            ..super::Job::new("large.png", options.settings())
+        };
+        let outputs = super::process(&job, &options).unwrap();
+        assert!(matches!(outputs[0].status, super::Status::Resized));
+        assert_eq!(outputs[0].dimensions, Some((500, 300)));
+    }
+
+    #[test]
+    fn judges_nearness_by_the_source_not_a_scaled_decode() {
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(1600, 200)
+            .write_to(&mut bytes, ImageFormat::Jpeg)
+            .unwrap();
+        let options = ResizeOptions::builder()
+            .size(500)
+            .skip_ratio(3.5)
+            .downsample_before_decode(true)
+            .build()
+            .unwrap();
+        let job = super::Job {
+            data: Some(bytes),
+            ..super::Job::new("wide.jpg", options.settings())
+        };
+        let outputs = super::process(&job, &options).unwrap();
+        assert!(matches!(outputs[0].status, super::Status::NearSize(500)));
+    }
+
+    #[test]
+    fn oversized_inputs_decode_within_the_cap() {
+        let cap = |reject| {
+            Some(InputCap {
+                max_dimension: 500,
+                reject,
+            })
+        };
+        for &format in &[ImageFormat::Jpeg, ImageFormat::Png] {
+            let mut bytes = Vec::new();
+            let image = DynamicImage::new_rgb8(1600, 1200);
+            image.write_to(&mut bytes, format).unwrap();
+            let loader = || ImageLoader::new(Cursor::new(&bytes)).with_guessed_format();
+
+            let (decoded, reduced) =
+                decode(loader, None, cap(false), &mut Timings::default()).unwrap();
+            assert_eq!(decoded.dimensions(), (500, 375), "{:?}", format);
+            assert!(reduced);
+            assert!(decode(loader, None, cap(true), &mut Timings::default()).is_err());
+        }
+
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(500, 300)
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        let loader = || ImageLoader::new(Cursor::new(&bytes)).with_guessed_format();
+        assert_eq!(
+            decode(loader, None, cap(true), &mut Timings::default())
+                .unwrap()
+                .0
+                .dimensions(),
+            (500, 300)
+        );
+    }
+
+    #[test]
+    fn enlarge_500_300() {
+        let actual = enlarge_dimensions(500, 300, 1000);
+        let expected = Some((1000, 600));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn enlarge_300_500() {
+        let actual = enlarge_dimensions(300, 500, 1000);
+        let expected = Some((600, 1000));
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn enlarge_800_1200() {
+        assert!(enlarge_dimensions(800, 1200, 1000).is_none());
+    }
+
+    #[test]
+    fn fit_shrinks_and_enlarges_to_the_same_size() {
+        let big = DynamicImage::new_rgb8(800, 400);
+        let small = DynamicImage::new_rgb8(100, 50);
+
+        let shrunk = fit(&big, 300, &resampling(), &[]).unwrap().dimensions();
+        let enlarged = fit(&small, 300, &resampling(), &[]).unwrap().dimensions();
+        assert_eq!(shrunk, Some((300, 150)));
+        assert_eq!(enlarged, Some((300, 150)));
+    }
+
+    #[test]
+    fn fit_leaves_exact_size_alone() {
+        let portrait = DynamicImage::new_rgb8(150, 300);
+        assert!(fit(&portrait, 300, &resampling(), &[])
+            .unwrap()
+            .dimensions()
+            .is_none());
+    }
+
+    fn encoded_png(width: u32, height: u32) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(width, height)
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        bytes
+    }
+
+    #[test]
+    fn resizes_bytes_in_their_own_format() {
+        let options = ResizeOptions::builder().size(50).build().unwrap();
+        let resized = resize_bytes(&encoded_png(200, 100), &options).unwrap();
+
+        assert_eq!(image::guess_format(&resized).unwrap(), ImageFormat::Png);
+        let resized = image::load_from_memory(&resized).unwrap();
+        assert_eq!(resized.dimensions(), (50, 25));
+    }
+
+    #[test]
+    fn faint_alpha_becomes_transparent() {
+        use crate::alpha::AlphaThreshold;
+        use image::{Rgba, RgbaImage};
+
+        // Opaque on the left, fading out towards the right.
+        let soft = RgbaImage::from_fn(200, 100, |x, _| {
+            let alpha = 255u32.saturating_sub(x.saturating_sub(100) * 255 / 100);
+            Rgba([40, 80, 120, alpha as u8])
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgba8(soft)
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+
+        let threshold = AlphaThreshold::parse("64:192").unwrap();
+        let options = ResizeOptions::builder()
+            .size(100)
+            .alpha_threshold(threshold)
+            .build()
+            .unwrap();
+        let resized = image::load_from_memory(&resize_bytes(&bytes, &options).unwrap()).unwrap();
+        let alphas: Vec<u8> = resized.to_rgba().pixels().map(|pixel| pixel.0[3]).collect();
+
+        assert!(alphas.iter().all(|&alpha| alpha == 0 || alpha >= 64));
+        assert!(alphas.iter().all(|&alpha| alpha == 255 || alpha <= 192));
+        assert!(alphas.contains(&0));
+        assert!(alphas.iter().any(|&alpha| (64..=192).contains(&alpha)));
+    }
+
+    #[test]
+    fn denoising_smooths_noise() {
+        use image::{GrayImage, Luma};
+
+        // A cheap pseudorandom generator is noise enough.
+        let mut state = 1u32;
+        let noise = GrayImage::from_fn(200, 200, |_, _| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            Luma([(state >> 24) as u8])
+        });
+        let mut bytes = Vec::new();
+        DynamicImage::ImageLuma8(noise)
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+
+        let roughness = |options: ResizeOptions| {
+            let resized = resize_bytes(&bytes, &options).unwrap();
+            let resized = image::load_from_memory(&resized).unwrap().to_luma();
+            resized
+                .as_raw()
+                .windows(2)
+                .map(|pair| (i32::from(pair[0]) - i32::from(pair[1])).abs())
+                .sum::<i32>()
+        };
+        let plain = roughness(ResizeOptions::builder().size(180).build().unwrap());
+        let denoised = roughness(
+            ResizeOptions::builder()
+                .size(180)
+                .denoise(1.0)
+                .build()
+                .unwrap(),
+        );
+        assert!(denoised < plain / 2, "{} vs {}", denoised, plain);
+    }
+
+    #[test]
+    fn small_bytes_come_back_as_given() {
+        let options = ResizeOptions::builder().size(500).build().unwrap();
+        let bytes = encoded_png(200, 100);
+        assert_eq!(resize_bytes(&bytes, &options).unwrap(), bytes);
+
+        let options = ResizeOptions::builder()
+            .sizes(vec![50, 20])
+            .build()
+            .unwrap();
+        assert!(resize_bytes(&bytes, &options).is_err());
+    }
+
+    #[test]
+    fn oriented_outputs_display_upright_without_a_tag() {
+        use image::{Rgb, RgbImage};
+
+        // Dark on the left, bright on the right; orientation 6 puts the left at the top.
+        let image = RgbImage::from_fn(48, 32, |x, _| Rgb([if x < 24 { 0 } else { 255 }; 3]));
+        let mut bytes = Vec::new();
+        DynamicImage::ImageRgb8(image)
+            .write_to(&mut bytes, ImageFormat::Jpeg)
+            .unwrap();
+        let exif = b"\xFF\xE1\x00\x22Exif\x00\x00II*\x00\x08\x00\x00\x00\x01\x00\
+            \x12\x01\x03\x00\x01\x00\x00\x00\x06\x00\x00\x00\x00\x00\x00\x00";
+        bytes.splice(2..2, exif.iter().copied());
+        let orientation =
+            |bytes: &[u8]| super::orient::read_orientation_from(&mut Cursor::new(bytes));
+        assert_eq!(orientation(&bytes), Some(6));
+
+        let options = ResizeOptions::builder()
+            .operation(super::Operation::OrientOnly)
+            .build()
+            .unwrap();
+        let job = super::Job {
+            data: Some(bytes),
+            ..super::Job::new("rotated.jpg", options.settings())
+        };
+        for edited in &[false, true] {
+            // Cropping rules out the lossless path, so both it and re-encoding are covered.
+            let options = if *edited {
+                let mut options = options.clone();
+                options.crop_aspect = Some(crate::crop::Aspect::parse("2:1").unwrap());
+                options
+            } else {
+                options.clone()
+            };
+            let outputs = super::process(&job, &options).unwrap();
+            let oriented = outputs[0].bytes.as_ref().unwrap();
+            assert_eq!(orientation(oriented), None);
+
+            let oriented = image::load_from_memory(oriented).unwrap().to_luma();
+            let (width, height) = oriented.dimensions();
+            assert!(height > width);
+            assert!(oriented.get_pixel(width / 2, 2)[0] < 64);
+            assert!(oriented.get_pixel(width / 2, height - 3)[0] > 192);
+        }
+    }
+
+    #[test]
+    fn keeps_only_the_orientation() {
+        use exif::{In, Reader, Tag};
+
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(120, 80)
+            .write_to(&mut bytes, ImageFormat::Jpeg)
+            .unwrap();
+        // An orientation of 6 and a pointer to GPS data: a latitude reference of N.
+        let exif = b"\xFF\xE1\x00\x40Exif\x00\x00II*\x00\x08\x00\x00\x00\x02\x00\
+            \x12\x01\x03\x00\x01\x00\x00\x00\x06\x00\x00\x00\
+            \x25\x88\x04\x00\x01\x00\x00\x00\x26\x00\x00\x00\x00\x00\x00\x00\
+            \x01\x00\x01\x00\x02\x00\x02\x00\x00\x00N\x00\x00\x00\x00\x00\x00\x00";
+        bytes.splice(2..2, exif.iter().copied());
+        let read = |bytes: &[u8]| Reader::new().read_from_container(&mut Cursor::new(bytes));
+        let source = read(&bytes).unwrap();
+        assert!(source.get_field(Tag::GPSLatitudeRef, In::PRIMARY).is_some());
+
+        for &format in &[ImageFormat::Jpeg, ImageFormat::Png] {
+            let options = ResizeOptions::builder()
+                .size(60)
+                .format(format)
+                .keep_orientation(true)
+                .build()
+                .unwrap();
+            let resized = resize_bytes(&bytes, &options).unwrap();
+            let exif = read(&resized).unwrap();
+            let orientation = exif.get_field(Tag::Orientation, In::PRIMARY).unwrap();
+            assert_eq!(orientation.value.get_uint(0), Some(6), "{:?}", format);
+            assert_eq!(exif.fields().count(), 1, "{:?}", format);
+            assert!(exif.get_field(Tag::GPSLatitudeRef, In::PRIMARY).is_none());
+
+            let image = image::load_from_memory(&resized).unwrap();
+            assert_eq!(image.dimensions(), (60, 40));
+        }
+    }
+
+    #[test]
+    fn undecodable_sources_get_placeholders() {
+        let options = |error_image| {
+            ResizeOptions::builder()
+                .sizes(vec![64, 32])
+                .error_image(error_image)
+                .build()
+                .unwrap()
+        };
+        let job = super::Job {
+            data: Some(b"not an image".to_vec()),
+            ..super::Job::new("broken.png", options(true).settings())
+        };
+        assert!(super::process(&job, &options(false)).is_err());
+
+        let outputs = super::process(&job, &options(true)).unwrap();
+        let sizes: Vec<_> = outputs.iter().map(|output| output.dimensions).collect();
+        assert_eq!(sizes, [Some((64, 64)), Some((32, 32))]);
+        assert_eq!(outputs[0].status.name(), "placeholder");
+        let placeholder = image::load_from_memory(outputs[1].bytes.as_ref().unwrap()).unwrap();
+        assert_eq!(placeholder.dimensions(), (32, 32));
+    }
+
+    #[test]
+    fn unencodable_outputs_fall_back() {
+        let options = |fallback: Option<ImageFormat>| {
+            let builder = ResizeOptions::builder().size(50);
+            match fallback {
+                Some(format) => builder.fallback_format(format),
+                None => builder,
+            }
+            .build()
+            .unwrap()
+        };
+        // Nothing here can write WebP, so a WebP source's output can't keep its format.
+        let job = super::Job {
+            data: Some(encoded_png(200, 100)),
+            ..super::Job::new("photo.webp", options(None).settings())
+        };
+        assert!(super::process(&job, &options(None)).is_err());
+
+        let outputs = super::process(&job, &options(Some(ImageFormat::Jpeg))).unwrap();
+        assert_eq!(outputs[0].status.name(), "fallback");
+        assert_eq!(outputs[0].path, Some(PathBuf::from("photo.jpg")));
+        let bytes = outputs[0].bytes.as_ref().unwrap();
+        assert_eq!(image::guess_format(bytes).unwrap(), ImageFormat::Jpeg);
+    }
+
+    #[test]
+    fn splits_outputs_into_pieces() {
+        let options = ResizeOptions::builder()
+            .size(100)
+            .split(Grid::parse("2x2").unwrap())
+            .build()
+            .unwrap();
+        let job = super::Job {
+            data: Some(encoded_png(300, 150)),
+            ..super::Job::new("poster.png", options.settings())
+        };
+        let outputs = super::process(&job, &options).unwrap();
+        let pieces: Vec<_> = outputs
+            .iter()
+            .map(|output| (output.path.clone().unwrap(), output.dimensions.unwrap()))
+            .collect();
+        assert_eq!(
+            pieces,
+            [
+                (PathBuf::from("poster_r0c0.png"), (50, 25)),
+                (PathBuf::from("poster_r0c1.png"), (50, 25)),
+                (PathBuf::from("poster_r1c0.png"), (50, 25)),
+                (PathBuf::from("poster_r1c1.png"), (50, 25)),
+            ]
+        );
+        let piece = image::load_from_memory(outputs[3].bytes.as_ref().unwrap()).unwrap();
+        assert_eq!(piece.dimensions(), (50, 25));
+    }
+
+    #[test]
+    fn converts_bit_depth_through_png() {
+        let deep = ImageBuffer::from_pixel(200, 100, image::Rgb([40000u16, 1000, 0]));
+        let bytes = super::encode::encode_dynamic(
+            &DynamicImage::ImageRgb16(deep),
+            ImageFormat::Png,
+            None,
+            Default::default(),
+            Default::default(),
+        )
+        .unwrap();
+        let resized = |bytes: &[u8], size, depth| {
+            let options = ResizeOptions::builder()
+                .size(size)
+                .bit_depth(depth)
+                .build()
+                .unwrap();
+            image::load_from_memory(&resize_bytes(bytes, &options).unwrap()).unwrap()
+        };
+
+        let kept = resized(&bytes, 50, BitDepth::Sixteen);
+        assert_eq!(kept.color(), ColorType::Rgba16);
+        assert_eq!(
+            kept.as_rgba16().unwrap().get_pixel(0, 0).0,
+            [40000, 1000, 0, 65535]
+        );
+
+        let shallow = resized(&bytes, 50, BitDepth::Eight);
+        assert_eq!(shallow.color(), ColorType::Rgba8);
+        assert_eq!(shallow.to_rgba().get_pixel(0, 0).0, [156, 4, 0, 255]);
+
+        let mut bytes = Vec::new();
+        shallow.write_to(&mut bytes, ImageFormat::Png).unwrap();
+        let widened = resized(&bytes, 25, BitDepth::Sixteen);
+        assert_eq!(widened.color(), ColorType::Rgba16);
+        // Shrinking again moves values a little, but they stay near 156 and 4 widened.
+        let pixel = widened.as_rgba16().unwrap().get_pixel(0, 0).0;
+        assert!((i32::from(pixel[0]) - 156 * 257).abs() < 257);
+        assert!((i32::from(pixel[1]) - 4 * 257).abs() < 257);
+    }
+
+    #[test]
+    fn narrows_sixteen_bit_input_on_either_path() {
+        let deep = ImageBuffer::from_pixel(200, 100, image::Rgb([40000u16, 1000, 0]));
+        let deep = DynamicImage::ImageRgb16(deep);
+        for parallel in [false, true] {
+            let resampling = Resampling {
+                parallel,
+                ..resampling()
+            };
+            let resized = super::resample(
+                &deep,
+                50,
+                25,
+                image::imageops::FilterType::Lanczos3,
+                &resampling,
+            );
+            assert_eq!(resized.get_pixel(0, 0).0, [156, 4, 0, 255]);
+        }
+    }
+
+    #[test]
+    fn sixteen_bit_outputs_narrow_for_jpeg() {
+        let options = ResizeOptions::builder()
+            .size(50)
+            .format(ImageFormat::Jpeg)
+            .bit_depth(BitDepth::Sixteen)
+            .build()
+            .unwrap();
+        let resized = resize_bytes(&encoded_png(200, 100), &options).unwrap();
+        let image = image::load_from_memory(&resized).unwrap();
+        assert_eq!(image.color(), ColorType::Rgb8);
+    }
+
+    #[test]
+    fn knows_which_outputs_overwrite_their_sources() {
+        let overwrites = |source: &str, out: Option<&str>, options: &ResizeOptions| {
+            let job = super::Job {
+                out: out.map(PathBuf::from),
+                ..super::Job::new(source, options.settings())
+            };
+            super::overwrites_source(&job, options)
+        };
+        let single = ResizeOptions::builder().size(64).build().unwrap();
+        assert!(overwrites("photos/a.png", None, &single));
+        assert!(overwrites("photos/a.png", Some("photos/a.png"), &single));
+        assert!(!overwrites("photos/a.png", Some("out/a.png"), &single));
+
+        let suffixed = ResizeOptions::builder()
+            .size(64)
+            .out_suffix("-resized")
+            .build()
+            .unwrap();
+        assert!(!overwrites("photos/a.png", None, &suffixed));
+
+        let several = ResizeOptions::builder()
+            .sizes(vec![64, 128])
+            .build()
+            .unwrap();
+        assert!(!overwrites("photos/a.png", None, &several));
+
+        let icon = ResizeOptions::builder()
+            .sizes(vec![16, 32])
+            .format(ImageFormat::Ico)
+            .build()
+            .unwrap();
+        assert!(overwrites("icons/x.ico", None, &icon));
+        assert!(!overwrites("icons/x.png", None, &icon));
+
+        let tiles = ResizeOptions::builder()
+            .operation(super::Operation::Tiles)
+            .build()
+            .unwrap();
+        assert!(!overwrites("photos/a.png", None, &tiles));
+
+        let split = ResizeOptions::builder()
+            .size(50)
+            .split(Grid::parse("2x1").unwrap())
+            .build()
+            .unwrap();
+        assert!(!overwrites("photos/b.png", None, &split));
+
+        // Nothing in memory is overwritten.
+        let job = super::Job {
+            data: Some(Vec::new()),
+            ..super::Job::new("photos/a.png", single.settings())
+        };
+        assert!(!super::overwrites_source(&job, &single));
+    }
+
+    #[test]
+    fn finds_outputs_overwriting_other_sources() {
+        let options = ResizeOptions::builder()
+            .size(100)
+            .format(ImageFormat::Jpeg)
+            .build()
+            .unwrap();
+        let jobs = [
+            super::Job::new("photos/a.png", options.settings()),
+            super::Job::new("photos/a.jpg", options.settings()),
+        ];
+        let (job, source) = super::overwritten_source(&jobs, &options).unwrap();
+        assert_eq!(
+            (job.source.as_str(), source),
+            ("photos/a.png", "photos/a.jpg")
+        );
+        assert!(super::overwritten_source(&jobs[..1], &options).is_none());
+    }
+
+    #[test]
+    fn resizing_files_in_place_takes_leave() {
+        let source =
+            std::env::temp_dir().join(format!("resize-in-place-{}.png", std::process::id()));
+        std::fs::write(&source, encoded_png(80, 60)).unwrap();
+        let options = ResizeOptions::builder().size(40).build().unwrap();
+        let refused = super::resize_file(&source, &options).unwrap_err();
+        assert_eq!(refused.kind(), std::io::ErrorKind::AlreadyExists);
+
+        let options = ResizeOptions::builder()
+            .size(40)
+            .in_place(true)
+            .build()
+            .unwrap();
+        super::resize_file(&source, &options).unwrap();
+        let resized = image::open(&source).unwrap();
+        std::fs::remove_file(&source).unwrap();
+        assert_eq!(resized.dimensions(), (40, 30));
+    }
+
+    #[test]
+    fn writes_crops_that_need_no_resizing() {
+        let options = ResizeOptions::builder()
+            .size(300)
+            .crop_aspect(Aspect::parse("2:1").unwrap())
+            .build()
+            .unwrap();
+        let job = super::Job {
+            data: Some(encoded_png(240, 60)),
+            ..super::Job::new("wide.png", options.settings())
+        };
+        let outputs = super::process(&job, &options).unwrap();
+        assert!(matches!(outputs[0].status, super::Status::Resized));
+        assert_eq!(outputs[0].dimensions, Some((120, 60)));
+    }
+
+    #[test]
+    fn pads_to_aspect_before_resizing() {
+        let options = ResizeOptions::builder()
+            .size(50)
+            .pad_aspect(Aspect::SQUARE, [0, 0, 255, 255])
+            .build()
+            .unwrap();
+        let resized = resize_bytes(&encoded_png(200, 100), &options).unwrap();
+        let image = image::load_from_memory(&resized).unwrap().to_rgba();
+        assert_eq!(image.dimensions(), (50, 50));
+        assert_eq!(image.get_pixel(0, 0).0, [0, 0, 255, 255]);
+        assert!(image.get_pixel(25, 25)[2] < 16);
+    }
+
+    #[test]
+    fn writes_padding_that_needs_no_resizing() {
+        let options = ResizeOptions::builder()
+            .size(9000)
+            .pad_aspect(Aspect::SQUARE, [0, 0, 255, 255])
+            .build()
+            .unwrap();
+        let job = super::Job {
+            data: Some(encoded_png(200, 100)),
+            ..super::Job::new("wide.png", options.settings())
+        };
+        let outputs = super::process(&job, &options).unwrap();
+        assert!(matches!(outputs[0].status, super::Status::Resized));
+        assert_eq!(outputs[0].dimensions, Some((200, 200)));
+    }
+
+    #[test]
+    fn clamps_elongated_sources() {
+        let crop = ResizeOptions::builder()
+            .size(50)
+            .max_aspect(1.5, AspectAction::Crop)
+            .build()
+            .unwrap();
+        let job = super::Job {
+            data: Some(encoded_png(200, 100)),
+            ..super::Job::new("wide.png", crop.settings())
+        };
+        let outputs = super::process(&job, &crop).unwrap();
+        assert_eq!(outputs[0].dimensions, Some((50, 33)));
+        assert!(outputs[0].cropped);
+
+        let skip = ResizeOptions::builder()
+            .size(50)
+            .max_aspect(1.5, AspectAction::Skip)
+            .build()
+            .unwrap();
+        let outputs = super::process(&job, &skip).unwrap();
+        assert!(matches!(outputs[0].status, super::Status::Skipped(_)));
+    }
+
+    #[test]
+    fn retries_mislabeled_sources_by_contents() {
+        let source = std::env::temp_dir().join(format!("resize-sniff-{}.jpg", std::process::id()));
+        std::fs::write(&source, encoded_png(80, 60)).unwrap();
+        let options = ResizeOptions::builder().size(40).build().unwrap();
+        let job = super::Job::new(source.to_str().unwrap(), options.settings());
+        assert!(super::process(&job, &options).is_err());
+
+        let options = ResizeOptions::builder()
+            .size(40)
+            .retry_sniffed(true)
+            .build()
+            .unwrap();
+        let outputs = super::process(&job, &options).unwrap();
+        std::fs::remove_file(&source).unwrap();
+        assert_eq!(outputs[0].dimensions, Some((40, 30)));
+        assert_eq!(outputs[0].sniffed, Some(ImageFormat::Png));
+    }
+
+    #[test]
+    fn clamps_before_scaled_decodes_and_without_resizing() {
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(1600, 200)
+            .write_to(&mut bytes, ImageFormat::Jpeg)
+            .unwrap();
+        for &(size, dimensions) in &[(200, (200, 100)), (1000, (400, 200))] {
+            let options = ResizeOptions::builder()
+                .size(size)
+                .max_aspect(2.0, AspectAction::Crop)
+                .downsample_before_decode(true)
+                .build()
+                .unwrap();
+            let job = super::Job {
+                data: Some(bytes.clone()),
+                ..super::Job::new("wide.jpg", options.settings())
+            };
+            let outputs = super::process(&job, &options).unwrap();
+            assert!(matches!(outputs[0].status, super::Status::Resized));
+            assert_eq!(outputs[0].dimensions, Some(dimensions));
+            assert!(outputs[0].cropped);
+        }
+    }
+
+    #[test]
+    fn turns_to_orientation_before_resizing() {
+        let options = ResizeOptions::builder()
+            .size(50)
+            .orient_to(Orientation::Portrait)
+            .build()
+            .unwrap();
+        let resized = resize_bytes(&encoded_png(200, 100), &options).unwrap();
+        let image = image::load_from_memory(&resized).unwrap();
+        assert_eq!(image.dimensions(), (25, 50));
+
+        let resized = resize_bytes(&encoded_png(100, 200), &options).unwrap();
+        let image = image::load_from_memory(&resized).unwrap();
+        assert_eq!(image.dimensions(), (25, 50));
+    }
+
+    #[test]
+    fn quantizes_png_outputs() {
+        let options = ResizeOptions::builder()
+            .size(50)
+            .quantize_colors(16, true)
+            .build()
+            .unwrap();
+        let resized = resize_bytes(&encoded_png(200, 100), &options).unwrap();
+        // The IHDR's color type: 3 is indexed.
+        assert_eq!(resized[25], 3);
+        let image = image::load_from_memory(&resized).unwrap();
+        assert_eq!(image.dimensions(), (50, 25));
+    }
+
+    #[cfg(feature = "async")]
+    #[test]
+    fn resizes_bytes_off_the_runtime() {
+        let runtime = tokio::runtime::Builder::new_current_thread()
+            .build()
+            .unwrap();
+        let options = ResizeOptions::builder().size(50).build().unwrap();
+        let resized = runtime
+            .block_on(super::resize_async(encoded_png(200, 100), options))
+            .unwrap();
+        assert_eq!(
+            image::load_from_memory(&resized).unwrap().dimensions(),
+            (50, 25)
+        );
+    }
+
+    #[test]
+    fn huge_enlargements_fail() {
+        let tiny = DynamicImage::new_rgb8(2, 1);
+        assert!(super::enlarge(&tiny, u32::MAX, &resampling(), &[]).is_err());
+        assert!(super::check_pixels(0, 100).is_err());
+        assert!(super::check_pixels(30_000, 30_000).is_ok());
+    }
+}
@@ -0,0 +1,850 @@
+//! Lossless JPEG transforms, which move and transpose DCT blocks as `jpegtran` does rather
+//! than decoding and re-encoding the pixels.
+//!
+//! Only sequential Huffman JPEGs with one scan of every component are handled, which is what
+//! cameras write; anything else is left to the caller to re-encode.
+
+use std::convert::TryFrom;
+
+/// The index, in natural order, of each coefficient in zigzag order.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+/// Quantized coefficients of an 8x8 block, in natural order.
+type Block = [i16; 64];
+
+#[derive(Clone, Copy)]
+struct Quant {
+    precision: u8,
+    /// In natural order.
+    values: [u16; 64],
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant: u8,
+    dc: u8,
+    ac: u8,
+    /// Blocks across and down, including any padding out to whole MCUs.
+    width: usize,
+    height: usize,
+    blocks: Vec<Block>,
+}
+
+impl Component {
+    fn trim(&mut self, width: usize, height: usize) {
+        let blocks = self.blocks.chunks(self.width).take(height);
+        self.blocks = blocks.flat_map(|row| &row[..width]).copied().collect();
+        self.width = width;
+        self.height = height;
+    }
+
+    fn transpose(&mut self) {
+        let mut blocks = Vec::with_capacity(self.blocks.len());
+        for x in 0..self.width {
+            for y in 0..self.height {
+                blocks.push(transpose(&self.blocks[y * self.width + x]));
+            }
+        }
+        self.blocks = blocks;
+        std::mem::swap(&mut self.width, &mut self.height);
+        std::mem::swap(&mut self.h, &mut self.v);
+    }
+
+    /// Mirrors left to right, which negates the coefficients of odd horizontal frequency.
+    fn flip_h(&mut self) {
+        for row in self.blocks.chunks_mut(self.width) {
+            row.reverse();
+            for block in row {
+                negate(block, |_, u| u % 2 == 1);
+            }
+        }
+    }
+
+    /// Mirrors top to bottom, which negates the coefficients of odd vertical frequency.
+    fn flip_v(&mut self) {
+        let rows = self.blocks.chunks(self.width).rev();
+        self.blocks = rows.flatten().copied().collect();
+        for block in &mut self.blocks {
+            negate(block, |v, _| v % 2 == 1);
+        }
+    }
+}
+
+fn transpose<T: Copy + Default>(block: &[T; 64]) -> [T; 64] {
+    let mut transposed = [T::default(); 64];
+    for (index, &value) in block.iter().enumerate() {
+        transposed[(index % 8) * 8 + index / 8] = value;
+    }
+    transposed
+}
+
+fn negate(block: &mut Block, odd: impl Fn(usize, usize) -> bool) {
+    for (index, value) in block.iter_mut().enumerate() {
+        if odd(index / 8, index % 8) {
+            *value = value.wrapping_neg();
+        }
+    }
+}
+
+struct Jpeg {
+    sof: u8,
+    width: u32,
+    height: u32,
+    components: Vec<Component>,
+    /// Indices into `components`, in the order the scan interleaves them.
+    scan: Vec<usize>,
+    quant: [Option<Quant>; 4],
+    /// Application and comment segments to copy through, as marker and payload.
+    segments: Vec<(u8, Vec<u8>)>,
+}
+
+impl Jpeg {
+    /// The size of an MCU, in pixels.
+    fn mcu_size(&self) -> (u32, u32) {
+        let h = self.components.iter().map(|c| c.h).max().unwrap_or(1);
+        let v = self.components.iter().map(|c| c.v).max().unwrap_or(1);
+        (8 * h as u32, 8 * v as u32)
+    }
+
+    /// MCUs across and down.
+    fn mcus(&self) -> (usize, usize) {
+        let (width, height) = self.mcu_size();
+        (
+            self.width.div_ceil(width) as usize,
+            self.height.div_ceil(height) as usize,
+        )
+    }
+
+    /// Drops any partial MCUs from the right or bottom edge, giving nothing if no whole one
+    /// is left.
+    fn trim(&mut self, right: bool, bottom: bool) -> Option<()> {
+        let (mcu_width, mcu_height) = self.mcu_size();
+        if right {
+            self.width = self.width / mcu_width * mcu_width;
+        }
+        if bottom {
+            self.height = self.height / mcu_height * mcu_height;
+        }
+        if self.width == 0 || self.height == 0 {
+            return None;
+        }
+
+        let (mcus_x, mcus_y) = self.mcus();
+        for component in &mut self.components {
+            component.trim(mcus_x * component.h, mcus_y * component.v);
+        }
+        Some(())
+    }
+
+    fn transpose(&mut self) {
+        std::mem::swap(&mut self.width, &mut self.height);
+        for component in &mut self.components {
+            component.transpose();
+        }
+        for quant in self.quant.iter_mut().flatten() {
+            quant.values = transpose(&quant.values);
+        }
+    }
+}
+
+/// Turns a JPEG upright by its EXIF `orientation`, without loss, giving the new JPEG and its
+/// dimensions.
+///
+/// As with `jpegtran -trim`, partial MCUs on an edge that a flip would move to the top or left
+/// are dropped, so an image may lose up to 15 pixels on that edge. EXIF and XMP metadata are
+/// dropped with the orientation they carry. Gives nothing for JPEGs that can't be transformed
+/// this way.
+pub fn reorient(data: &[u8], orientation: u32) -> Option<(Vec<u8>, (u32, u32))> {
+    let (transposed, flip_h, flip_v) = match orientation {
+        2 => (false, true, false),
+        3 => (false, true, true),
+        4 => (false, false, true),
+        5 => (true, false, false),
+        6 => (true, true, false),
+        7 => (true, true, true),
+        8 => (true, false, true),
+        _ => return None,
+    };
+
+    let mut jpeg = read(data)?;
+    if transposed {
+        jpeg.trim(flip_v, flip_h)?;
+        jpeg.transpose();
+    } else {
+        jpeg.trim(flip_h, flip_v)?;
+    }
+    for component in &mut jpeg.components {
+        if flip_h {
+            component.flip_h();
+        }
+        if flip_v {
+            component.flip_v();
+        }
+    }
+    Some((write(&jpeg), (jpeg.width, jpeg.height)))
+}
+
+/// A Huffman table for decoding, as laid out in Annex F of the JPEG standard.
+#[derive(Clone, Default)]
+struct Decoder {
+    mincode: [i32; 17],
+    maxcode: [i32; 17],
+    valptr: [i32; 17],
+    values: Vec<u8>,
+}
+
+impl Decoder {
+    fn new(counts: &[u8], values: Vec<u8>) -> Decoder {
+        let mut decoder = Decoder {
+            values,
+            ..Decoder::default()
+        };
+        let (mut code, mut index) = (0, 0);
+        for (length, &count) in (1..).zip(counts) {
+            let count = i32::from(count);
+            decoder.valptr[length] = index;
+            decoder.mincode[length] = code;
+            decoder.maxcode[length] = if count > 0 { code + count - 1 } else { -1 };
+            code = (code + count) << 1;
+            index += count;
+        }
+        decoder
+    }
+
+    fn decode(&self, reader: &mut BitReader) -> Option<u8> {
+        let mut code = 0;
+        for length in 1..=16 {
+            code = (code << 1) | i32::from(reader.bit()?);
+            if code <= self.maxcode[length] {
+                let index = self.valptr[length] + code - self.mincode[length];
+                return self.values.get(index as usize).copied();
+            }
+        }
+        None
+    }
+}
+
+struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+    byte: u8,
+    left: u8,
+}
+
+impl BitReader<'_> {
+    fn bit(&mut self) -> Option<u8> {
+        if self.left == 0 {
+            let byte = *self.data.get(self.position)?;
+            if byte == 0xFF {
+                // Anything but a stuffed zero is a marker, which can't come mid-block.
+                if *self.data.get(self.position + 1)? != 0 {
+                    return None;
+                }
+                self.position += 1;
+            }
+            self.position += 1;
+            self.byte = byte;
+            self.left = 8;
+        }
+        self.left -= 1;
+        Some((self.byte >> self.left) & 1)
+    }
+
+    /// Reads a `size`-bit magnitude and extends it to its signed value.
+    fn value(&mut self, size: u8) -> Option<i32> {
+        let mut value = 0;
+        for _ in 0..size {
+            value = (value << 1) | i32::from(self.bit()?);
+        }
+        if size > 0 && value < 1 << (size - 1) {
+            value -= (1 << size) - 1;
+        }
+        Some(value)
+    }
+
+    /// Skips past a restart marker, discarding what is left of the current byte.
+    fn restart(&mut self) -> Option<()> {
+        self.left = 0;
+        while self.data.get(self.position..self.position + 2)? == [0xFF, 0xFF] {
+            self.position += 1;
+        }
+        match self.data.get(self.position..self.position + 2)? {
+            [0xFF, 0xD0..=0xD7] => {
+                self.position += 2;
+                Some(())
+            }
+            _ => None,
+        }
+    }
+}
+
+fn read(data: &[u8]) -> Option<Jpeg> {
+    if data.get(..2)? != [0xFF, 0xD8] {
+        return None;
+    }
+
+    let mut position = 2;
+    let mut frame = None;
+    let mut quant = [None; 4];
+    let mut decoders = vec![Decoder::default(); 8];
+    let mut restart_interval = 0;
+    let mut segments = Vec::new();
+
+    loop {
+        if *data.get(position)? != 0xFF {
+            return None;
+        }
+        while *data.get(position)? == 0xFF {
+            position += 1;
+        }
+        let marker = data[position];
+        let length = usize::from(u16::from_be_bytes([
+            *data.get(position + 1)?,
+            *data.get(position + 2)?,
+        ]));
+        let payload = data.get(position + 3..position + 1 + length.max(2))?;
+        position += 1 + length;
+
+        match marker {
+            0xC0 | 0xC1 => frame = Some(read_frame(marker, payload)?),
+            0xC4 => read_huffman(payload, &mut decoders)?,
+            0xDB => read_quant(payload, &mut quant)?,
+            0xDD => {
+                let interval = payload.get(..2)?;
+                restart_interval = usize::from(u16::from_be_bytes([interval[0], interval[1]]));
+            }
+            0xDA => {
+                let mut jpeg = frame?;
+                jpeg.quant = quant;
+                jpeg.segments = segments;
+                read_scan(&mut jpeg, payload)?;
+                let reader = BitReader {
+                    data: &data[position..],
+                    position: 0,
+                    byte: 0,
+                    left: 0,
+                };
+                decode(&mut jpeg, reader, &decoders, restart_interval)?;
+                return Some(jpeg);
+            }
+            // EXIF and XMP, which would only reorient the image all over again.
+            0xE1 => {}
+            0xE0..=0xEF | 0xFE => segments.push((marker, payload.to_vec())),
+            // Progressive and arithmetic coding, and anything unexpected before the scan.
+            _ => return None,
+        }
+    }
+}
+
+fn read_frame(sof: u8, payload: &[u8]) -> Option<Jpeg> {
+    let &[precision, h0, h1, w0, w1, count, ref specs @ ..] = payload else {
+        return None;
+    };
+    let (width, height) = (u16::from_be_bytes([w0, w1]), u16::from_be_bytes([h0, h1]));
+    if precision != 8 || width == 0 || height == 0 || !(1..=4).contains(&count) {
+        return None;
+    }
+
+    let mut components = Vec::new();
+    for spec in specs.chunks_exact(3).take(usize::from(count)) {
+        let (h, v) = (usize::from(spec[1] >> 4), usize::from(spec[1] & 0x0F));
+        if !(1..=4).contains(&h) || !(1..=4).contains(&v) || spec[2] > 3 {
+            return None;
+        }
+        components.push(Component {
+            id: spec[0],
+            h,
+            v,
+            quant: spec[2],
+            dc: 0,
+            ac: 0,
+            width: 0,
+            height: 0,
+            blocks: Vec::new(),
+        });
+    }
+    if components.len() != usize::from(count) {
+        return None;
+    }
+    // A lone component's scan isn't interleaved, so its sampling factors mean nothing.
+    if let [component] = &mut components[..] {
+        component.h = 1;
+        component.v = 1;
+    }
+
+    Some(Jpeg {
+        sof,
+        width: u32::from(width),
+        height: u32::from(height),
+        components,
+        scan: Vec::new(),
+        quant: [None; 4],
+        segments: Vec::new(),
+    })
+}
+
+fn read_scan(jpeg: &mut Jpeg, payload: &[u8]) -> Option<()> {
+    let count = usize::from(*payload.first()?);
+    let specs = payload.get(1..1 + 2 * count)?;
+    if payload.get(1 + 2 * count..)? != [0, 63, 0] || count != jpeg.components.len() {
+        return None;
+    }
+
+    for spec in specs.chunks_exact(2) {
+        let index = jpeg.components.iter().position(|c| c.id == spec[0])?;
+        if jpeg.scan.contains(&index) || spec[1] >> 4 > 3 || spec[1] & 0x0F > 3 {
+            return None;
+        }
+        let component = &mut jpeg.components[index];
+        component.dc = spec[1] >> 4;
+        component.ac = spec[1] & 0x0F;
+        jpeg.scan.push(index);
+    }
+
+    let (mcus_x, mcus_y) = jpeg.mcus();
+    for component in &mut jpeg.components {
+        component.width = mcus_x * component.h;
+        component.height = mcus_y * component.v;
+        component.blocks = vec![[0; 64]; component.width * component.height];
+    }
+    jpeg.components
+        .iter()
+        .all(|c| jpeg.quant[usize::from(c.quant)].is_some())
+        .then_some(())
+}
+
+fn read_quant(mut payload: &[u8], quant: &mut [Option<Quant>; 4]) -> Option<()> {
+    while let [spec, rest @ ..] = payload {
+        let (precision, id) = (spec >> 4, usize::from(spec & 0x0F));
+        let size = 64 * (usize::from(precision) + 1);
+        if precision > 1 || id > 3 || rest.len() < size {
+            return None;
+        }
+        let mut values = [0; 64];
+        for (k, &index) in ZIGZAG.iter().enumerate() {
+            values[index] = match precision {
+                0 => u16::from(rest[k]),
+                _ => u16::from_be_bytes([rest[2 * k], rest[2 * k + 1]]),
+            };
+        }
+        quant[id] = Some(Quant { precision, values });
+        payload = &rest[size..];
+    }
+    Some(())
+}
+
+fn read_huffman(mut payload: &[u8], decoders: &mut [Decoder]) -> Option<()> {
+    while let [spec, rest @ ..] = payload {
+        let (class, id) = (usize::from(spec >> 4), usize::from(spec & 0x0F));
+        let counts = rest.get(..16)?;
+        let total = counts
+            .iter()
+            .map(|&count| usize::from(count))
+            .sum::<usize>();
+        let values = rest.get(16..16 + total)?;
+        if class > 1 || id > 3 {
+            return None;
+        }
+        decoders[class * 4 + id] = Decoder::new(counts, values.to_vec());
+        payload = &rest[16 + total..];
+    }
+    Some(())
+}
+
+fn decode(
+    jpeg: &mut Jpeg,
+    mut reader: BitReader,
+    decoders: &[Decoder],
+    restart_interval: usize,
+) -> Option<()> {
+    let (mcus_x, mcus_y) = jpeg.mcus();
+    let mut predictions = vec![0i32; jpeg.components.len()];
+
+    for mcu in 0..mcus_x * mcus_y {
+        if restart_interval > 0 && mcu > 0 && mcu % restart_interval == 0 {
+            reader.restart()?;
+            predictions
+                .iter_mut()
+                .for_each(|prediction| *prediction = 0);
+        }
+        for &c in &jpeg.scan {
+            let component = &mut jpeg.components[c];
+            let dc = &decoders[usize::from(component.dc)];
+            let ac = &decoders[4 + usize::from(component.ac)];
+            for y in 0..component.v {
+                for x in 0..component.h {
+                    let row = (mcu / mcus_x) * component.v + y;
+                    let column = (mcu % mcus_x) * component.h + x;
+                    let block = &mut component.blocks[row * component.width + column];
+
+                    let size = dc.decode(&mut reader)?;
+                    if size > 11 {
+                        return None;
+                    }
+                    predictions[c] += reader.value(size)?;
+                    block[0] = i16::try_from(predictions[c]).ok()?;
+
+                    let mut k = 1;
+                    while k < 64 {
+                        let symbol = ac.decode(&mut reader)?;
+                        let (run, size) = (usize::from(symbol >> 4), symbol & 0x0F);
+                        if size == 0 {
+                            if run != 15 {
+                                break;
+                            }
+                            k += 16;
+                            continue;
+                        }
+                        k += run;
+                        if k > 63 || size > 10 {
+                            return None;
+                        }
+                        block[ZIGZAG[k]] = reader.value(size)? as i16;
+                        k += 1;
+                    }
+                }
+            }
+        }
+    }
+    Some(())
+}
+
+/// Where the entropy coder's output goes: counted, to build tables, or written.
+trait Symbols {
+    /// Codes `symbol` in the table at `index`: DC tables first, then AC.
+    fn symbol(&mut self, index: usize, symbol: u8);
+    fn bits(&mut self, value: u16, size: u8);
+}
+
+struct Counter([[u32; 257]; 8]);
+
+impl Symbols for Counter {
+    fn symbol(&mut self, index: usize, symbol: u8) {
+        self.0[index][usize::from(symbol)] += 1;
+    }
+
+    fn bits(&mut self, _: u16, _: u8) {}
+}
+
+struct BitWriter {
+    codes: Vec<[(u16, u8); 256]>,
+    bytes: Vec<u8>,
+    buffer: u32,
+    count: u8,
+}
+
+impl BitWriter {
+    fn finish(mut self) -> Vec<u8> {
+        // The last byte is padded with ones.
+        if self.count > 0 {
+            let padding = 8 - self.count;
+            self.bits((1 << padding) - 1, padding);
+        }
+        self.bytes
+    }
+}
+
+impl Symbols for BitWriter {
+    fn symbol(&mut self, index: usize, symbol: u8) {
+        let (code, length) = self.codes[index][usize::from(symbol)];
+        self.bits(code, length);
+    }
+
+    fn bits(&mut self, value: u16, size: u8) {
+        self.buffer = (self.buffer << size) | (u32::from(value) & ((1 << size) - 1));
+        self.count += size;
+        while self.count >= 8 {
+            self.count -= 8;
+            let byte = (self.buffer >> self.count) as u8;
+            self.bytes.push(byte);
+            if byte == 0xFF {
+                self.bytes.push(0);
+            }
+        }
+        self.buffer &= (1 << self.count) - 1;
+    }
+}
+
+/// The size category of a coefficient, along with the bits that code it within that size.
+fn magnitude(value: i32) -> (u8, u16) {
+    let size = (32 - value.unsigned_abs().leading_zeros()) as u8;
+    let bits = if value < 0 { value - 1 } else { value };
+    (size, bits as u16)
+}
+
+fn encode(jpeg: &Jpeg, symbols: &mut impl Symbols) {
+    let (mcus_x, mcus_y) = jpeg.mcus();
+    let mut predictions = vec![0i32; jpeg.components.len()];
+
+    for mcu in 0..mcus_x * mcus_y {
+        for &c in &jpeg.scan {
+            let component = &jpeg.components[c];
+            let (dc, ac) = (usize::from(component.dc), 4 + usize::from(component.ac));
+            for y in 0..component.v {
+                for x in 0..component.h {
+                    let row = (mcu / mcus_x) * component.v + y;
+                    let column = (mcu % mcus_x) * component.h + x;
+                    let block = &component.blocks[row * component.width + column];
+
+                    let (size, bits) = magnitude(i32::from(block[0]) - predictions[c]);
+                    predictions[c] = i32::from(block[0]);
+                    symbols.symbol(dc, size);
+                    symbols.bits(bits, size);
+
+                    let mut run = 0;
+                    for &index in &ZIGZAG[1..] {
+                        if block[index] == 0 {
+                            run += 1;
+                            continue;
+                        }
+                        while run > 15 {
+                            symbols.symbol(ac, 0xF0);
+                            run -= 16;
+                        }
+                        let (size, bits) = magnitude(i32::from(block[index]));
+                        symbols.symbol(ac, (run << 4) | size);
+                        symbols.bits(bits, size);
+                        run = 0;
+                    }
+                    if run > 0 {
+                        symbols.symbol(ac, 0x00);
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Builds an optimal Huffman table for the symbols counted in `frequencies`, limited to codes
+/// of 16 bits, as in Annex K.2 of the JPEG standard. Gives the number of codes of each length
+/// and the symbols in code order.
+fn build_table(frequencies: &[u32; 257]) -> ([u8; 16], Vec<u8>) {
+    let mut frequencies = *frequencies;
+    // A reserved symbol keeps any real code from being all ones.
+    frequencies[256] = 1;
+    let mut sizes = [0usize; 257];
+    let mut others = [None; 257];
+
+    loop {
+        // The least frequent symbol, then the next; ties go to the higher symbol.
+        let least = |excluded: Option<usize>| {
+            (0..257)
+                .filter(|&symbol| frequencies[symbol] > 0 && Some(symbol) != excluded)
+                .min_by_key(|&symbol| (frequencies[symbol], std::cmp::Reverse(symbol)))
+        };
+        let v1 = least(None).expect("the reserved symbol is always there");
+        let Some(v2) = least(Some(v1)) else { break };
+
+        frequencies[v1] += frequencies[v2];
+        frequencies[v2] = 0;
+        let mut tail = v1;
+        sizes[tail] += 1;
+        while let Some(next) = others[tail] {
+            tail = next;
+            sizes[tail] += 1;
+        }
+        others[tail] = Some(v2);
+        let mut tail = v2;
+        sizes[tail] += 1;
+        while let Some(next) = others[tail] {
+            tail = next;
+            sizes[tail] += 1;
+        }
+    }
+
+    let mut counts = [0u32; 33];
+    for &size in sizes.iter().filter(|&&size| size > 0) {
+        counts[size] += 1;
+    }
+    for length in (17..=32).rev() {
+        while counts[length] > 0 {
+            let mut shorter = length - 2;
+            while counts[shorter] == 0 {
+                shorter -= 1;
+            }
+            counts[length] -= 2;
+            counts[length - 1] += 1;
+            counts[shorter + 1] += 2;
+            counts[shorter] -= 1;
+        }
+    }
+    // Give up the reserved symbol's code, which is the longest.
+    let longest = (1..=16)
+        .rev()
+        .find(|&length| counts[length] > 0)
+        .unwrap_or(1);
+    counts[longest] -= 1;
+
+    let mut symbols: Vec<u8> = (0..=255u8).filter(|&s| sizes[usize::from(s)] > 0).collect();
+    symbols.sort_by_key(|&symbol| sizes[usize::from(symbol)]);
+    let mut lengths = [0; 16];
+    for (length, &count) in lengths.iter_mut().zip(&counts[1..=16]) {
+        *length = count as u8;
+    }
+    (lengths, symbols)
+}
+
+/// The code and length of each symbol in a table built by `build_table`.
+fn codes(lengths: &[u8; 16], symbols: &[u8]) -> [(u16, u8); 256] {
+    let mut codes = [(0, 0); 256];
+    let mut symbols = symbols.iter();
+    let mut code = 0u16;
+    for (length, &count) in (1..).zip(lengths) {
+        for &symbol in symbols.by_ref().take(usize::from(count)) {
+            codes[usize::from(symbol)] = (code, length);
+            code += 1;
+        }
+        code = code.wrapping_shl(1);
+    }
+    codes
+}
+
+fn write(jpeg: &Jpeg) -> Vec<u8> {
+    let mut counter = Counter([[0; 257]; 8]);
+    encode(jpeg, &mut counter);
+
+    let mut huffman = Vec::new();
+    let mut writer = BitWriter {
+        codes: vec![[(0, 0); 256]; 8],
+        bytes: Vec::new(),
+        buffer: 0,
+        count: 0,
+    };
+    for (index, frequencies) in counter.0.iter().enumerate() {
+        if frequencies.iter().all(|&frequency| frequency == 0) {
+            continue;
+        }
+        let (lengths, symbols) = build_table(frequencies);
+        writer.codes[index] = codes(&lengths, &symbols);
+        huffman.push((((index / 4) << 4) | (index % 4)) as u8);
+        huffman.extend_from_slice(&lengths);
+        huffman.extend_from_slice(&symbols);
+    }
+    encode(jpeg, &mut writer);
+    let data = writer.finish();
+
+    let mut quant = Vec::new();
+    for (id, table) in jpeg.quant.iter().enumerate() {
+        if let Some(table) = table {
+            quant.push((table.precision << 4) | id as u8);
+            for &index in &ZIGZAG {
+                match table.precision {
+                    0 => quant.push(table.values[index] as u8),
+                    _ => quant.extend_from_slice(&table.values[index].to_be_bytes()),
+                }
+            }
+        }
+    }
+
+    let mut frame = vec![8];
+    frame.extend_from_slice(&(jpeg.height as u16).to_be_bytes());
+    frame.extend_from_slice(&(jpeg.width as u16).to_be_bytes());
+    frame.push(jpeg.components.len() as u8);
+    for component in &jpeg.components {
+        let sampling = ((component.h << 4) | component.v) as u8;
+        frame.extend_from_slice(&[component.id, sampling, component.quant]);
+    }
+
+    let mut scan = vec![jpeg.scan.len() as u8];
+    for &c in &jpeg.scan {
+        let component = &jpeg.components[c];
+        scan.extend_from_slice(&[component.id, (component.dc << 4) | component.ac]);
+    }
+    scan.extend_from_slice(&[0, 63, 0]);
+
+    let mut bytes = vec![0xFF, 0xD8];
+    let mut segment = |marker: u8, payload: &[u8]| {
+        bytes.extend_from_slice(&[0xFF, marker]);
+        bytes.extend_from_slice(&(payload.len() as u16 + 2).to_be_bytes());
+        bytes.extend_from_slice(payload);
+    };
+    for (marker, payload) in &jpeg.segments {
+        segment(*marker, payload);
+    }
+    segment(0xDB, &quant);
+    segment(jpeg.sof, &frame);
+    segment(0xC4, &huffman);
+    segment(0xDA, &scan);
+    bytes.extend_from_slice(&data);
+    bytes.extend_from_slice(&[0xFF, 0xD9]);
+    bytes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::reorient;
+    use crate::{encode, orient, Subsampling};
+    use image::{ColorType, DynamicImage, GenericImageView, ImageFormat, RgbImage};
+
+    fn jpeg(width: u32, height: u32, subsampling: Subsampling) -> Vec<u8> {
+        let image = RgbImage::from_fn(width, height, |x, y| {
+            image::Rgb([(x * 7) as u8, (y * 11) as u8, ((x + y) * 5) as u8])
+        });
+        let dimensions = (width, height);
+        let format = ImageFormat::Jpeg;
+        encode::encode(
+            image.as_raw(),
+            dimensions,
+            ColorType::Rgb8,
+            format,
+            Some(90),
+            encode::JpegOptions {
+                subsampling,
+                ..encode::JpegOptions::default()
+            },
+            encode::PngOptions::default(),
+        )
+        .unwrap()
+    }
+
+    fn decode(data: &[u8]) -> DynamicImage {
+        image::load_from_memory_with_format(data, ImageFormat::Jpeg).unwrap()
+    }
+
+    /// The largest difference in any channel between two images of the same size.
+    fn difference(a: &DynamicImage, b: &DynamicImage) -> u8 {
+        assert_eq!(a.dimensions(), b.dimensions());
+        let (a, b) = (a.to_rgb(), b.to_rgb());
+        a.iter()
+            .zip(b.iter())
+            .map(|(a, b)| a.abs_diff(*b))
+            .max()
+            .unwrap()
+    }
+
+    #[test]
+    fn matches_decoding_and_reorienting() {
+        for &subsampling in &[Subsampling::S444, Subsampling::S420] {
+            let source = jpeg(48, 32, subsampling);
+            let decoded = decode(&source);
+            for orientation in 2..=8 {
+                let (transformed, dimensions) = reorient(&source, orientation).unwrap();
+                let transformed = decode(&transformed);
+                assert_eq!(transformed.dimensions(), dimensions);
+                let expected = orient::apply(&decoded, orientation);
+                assert!(difference(&transformed, &expected) <= 2, "{}", orientation);
+            }
+        }
+    }
+
+    #[test]
+    fn trims_partial_mcus_that_would_move() {
+        let source = jpeg(40, 24, Subsampling::S420);
+        assert_eq!(reorient(&source, 2).unwrap().1, (32, 24));
+        assert_eq!(reorient(&source, 4).unwrap().1, (40, 16));
+        assert_eq!(reorient(&source, 5).unwrap().1, (24, 40));
+        assert_eq!(reorient(&source, 6).unwrap().1, (16, 40));
+    }
+
+    #[test]
+    fn leaves_other_images_alone() {
+        assert!(reorient(&jpeg(8, 8, Subsampling::S420), 2).is_none());
+        assert!(reorient(b"\x89PNG\r\n\x1a\n", 6).is_none());
+    }
+}
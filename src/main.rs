@@ -1,209 +1,2464 @@
-use std::{io, ops::Deref};
+mod archive;
+mod capture;
+mod date;
+mod exec;
+mod manifest;
+mod memory;
+mod plan;
+mod progress_file;
+mod report;
+mod s3;
+mod serve;
+mod srcset;
+mod threads;
+mod walk;
+mod watch;
 
-use image::{
-    imageops::{resize, FilterType},
-    io::Reader as ImageLoader,
-    EncodableLayout, GenericImageView, ImageBuffer, Pixel,
+use std::{
+    collections::{HashMap, HashSet, VecDeque},
+    fs,
+    io::{self, Cursor, Write},
+    panic::{self, AssertUnwindSafe},
+    path::{Path, PathBuf},
+    process::Child,
+    sync::{
+        mpsc::{self, RecvTimeoutError},
+        Mutex,
+    },
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use archive::TarWriter;
+use exec::Exec;
+use glob::Pattern;
+use manifest::{Checksum, Entry, Manifest, Resume};
+use memory::MemoryBudget;
+use progress_file::ProgressFile;
+use resize::{
+    alpha::AlphaThreshold,
+    canvas::{self, Anchor, Canvas, Fill},
+    color::ColorSpace,
+    crop::{Aspect, AspectAction, Grid},
+    depth::{self, BitDepth},
+    derived_target, dpi, effect, filter,
+    levels::Levels,
+    output::{self, Claims, Collision, Naming, Sequence},
+    phash, profile,
+    progress::{Progress, Status, Tally},
+    quality::Quality,
+    settings::Operation,
+    timing::{self, Timings},
+    Job, Orientation, Output, ResizeOptions, Subsampling,
 };
+use s3::Uploader;
+use srcset::{Srcsets, Variant};
 
 #[derive(Copy, Clone, Debug)]
-enum Operation {
-    Shrink,
-    Enlarge,
+enum SortBy {
+    Name,
+    Modified,
 }
 
 #[derive(Clone, Debug)]
 struct Opt {
     images: Vec<String>,
-    operation: Operation,
-    size: u32,
+    ignore: Vec<Pattern>,
+    recursive: bool,
+    follow_symlinks: bool,
+    /// How many directories below a walk's root, or a glob's first wildcard, to look for images.
+    max_depth: Option<usize>,
+    verbose: bool,
+    /// Only report the pixel formats of the inputs.
+    pixel_format_report: bool,
+    tar_in: bool,
+    tar_out: bool,
+    /// An `s3://bucket/prefix/` URL to upload outputs to.
+    upload: Option<String>,
+    /// Where a single image's output goes instead, `-` for stdout.
+    output: Option<String>,
+    options: ResizeOptions,
+    plan: Option<PathBuf>,
+    serve: bool,
+    /// A folder to watch, resizing images as they land in it.
+    watch: Option<PathBuf>,
+    /// Where outputs go instead of beside their sources, under the names they'd have there.
+    out_dir: Option<PathBuf>,
+    /// What to do when sources would be written to the same output.
+    on_collision: Collision,
+    since: Option<SystemTime>,
+    limit: Option<usize>,
+    sort_by: Option<SortBy>,
+    sequence: Option<Sequence>,
+    exec: Option<Exec>,
+    keep_going_on_panic: bool,
+    /// Stop at the first image that fails, rather than reporting it and carrying on.
+    fail_fast: bool,
+    /// How long reading a source may take before it is given up on.
+    read_timeout: Option<Duration>,
+    /// The percentage an output must save over its source to be written.
+    min_saving: Option<f64>,
+    /// The total bytes of output to write before skipping the rest.
+    budget: Option<u64>,
+    verify: bool,
+    /// Delete each source once every output of it is written.
+    delete_source: bool,
+    progress: Progress,
+    progress_file: Option<PathBuf>,
+    jobs: usize,
+    max_memory: Option<u64>,
+    manifest: Option<PathBuf>,
+    /// How far apart perceptual hashes may be for sources to be listed as near-duplicates, with
+    /// `--phash`.
+    phash_distance: Option<u32>,
+    /// Where to write an `<img>` tag offering each source's outputs by width.
+    srcset: Option<PathBuf>,
+    /// An earlier run's manifest, whose finished sources are left out.
+    resume: Option<PathBuf>,
+    checksum: Option<Checksum>,
+    no_op_is_error: bool,
+    /// Link outputs identical to one already written to it, rather than writing them again.
+    dedup_outputs: bool,
+    /// Report how long each stage of processing took, over the whole run.
+    timing: bool,
+    exif_date_rename: bool,
 }
 
 impl Opt {
     fn from_args() -> Opt {
-        use clap::{crate_authors, crate_description, crate_version, value_t, App, Arg, ArgGroup};
+        use clap::{
+            crate_authors, crate_description, crate_version, value_t, values_t, App, Arg, ArgGroup,
+        };
 
         let m = App::new("resize")
             .version(crate_version!())
             .author(crate_authors!())
             .about(crate_description!())
             .arg(Arg::with_name("image").takes_value(true).multiple(true))
-            .arg(Arg::with_name("up").short("u").long("up"))
-            .arg(Arg::with_name("down").short("d").long("down"))
+            .arg(
+                Arg::with_name("plan")
+                    .long("plan")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .conflicts_with("image")
+                    .help("Process the entries of a JSON plan, using other flags as defaults"),
+            )
+            .arg(
+                Arg::with_name("serve")
+                    .long("serve")
+                    .conflicts_with_all(&["image", "plan", "tar-in"])
+                    .help(
+                        "Answer JSON-lines requests such as {\"id\": 1, \"path\": \"a.png\", \
+                         \"size\": 640} from stdin, one response line each on stdout",
+                    ),
+            )
+            .arg(
+                Arg::with_name("watch")
+                    .long("watch")
+                    .takes_value(true)
+                    .value_name("DIR")
+                    .conflicts_with_all(&[
+                        "image",
+                        "plan",
+                        "serve",
+                        "tar-in",
+                        "tar-out",
+                        "output",
+                        "sequence",
+                        "limit",
+                        "sort-by",
+                        "manifest",
+                        "emit-srcset",
+                        "progress-file",
+                        "pixel-format-report",
+                    ])
+                    .requires("out-dir")
+                    .help("Keep watching this folder, resizing images as they land in it or change"),
+            )
+            .arg(
+                Arg::with_name("out-dir")
+                    .long("out-dir")
+                    .takes_value(true)
+                    .value_name("DIR")
+                    .alias("output-dir")
+                    .conflicts_with_all(&["output", "sequence", "tar-out", "upload", "exif-date-rename", "serve"])
+                    .help("Write outputs to this folder rather than beside their sources"),
+            )
+            .arg(
+                Arg::with_name("in-place")
+                    .long("in-place")
+                    .help("Let outputs overwrite their sources; without it, a run that would is refused"),
+            )
+            .arg(
+                Arg::with_name("on-collision")
+                    .long("on-collision")
+                    .takes_value(true)
+                    .possible_values(Collision::NAMES)
+                    .default_value("number")
+                    .help("When sources would be written to the same output, refuse to run, skip the later ones or number them"),
+            )
+            .arg(
+                Arg::with_name("profile")
+                    .long("profile")
+                    .takes_value(true)
+                    .value_name("NAME")
+                    .validator(|s| match profile::find(&s) {
+                        Some(_) => Ok(()),
+                        None => Err(format!("'{}' is not a profile; see --list-profiles", s)),
+                    })
+                    .help("Start from a named preset of size, format, quality and filter, which other flags override"),
+            )
+            .arg(
+                Arg::with_name("list-profiles")
+                    .long("list-profiles")
+                    .help("List the presets --profile accepts, then exit"),
+            )
+            .arg(
+                Arg::with_name("dump-config")
+                    .long("dump-config")
+                    .help("Print the options that would be used, once profiles and flags are merged, as JSON, then exit"),
+            )
+            .arg(
+                Arg::with_name("pixel-format-report")
+                    .long("pixel-format-report")
+                    .help("Count the inputs by format, color type, bit depth, alpha and ICC profile without resizing them, listing each with --verbose"),
+            )
+            .arg(
+                Arg::with_name("up")
+                    .short("u")
+                    .long("up")
+                    .help("Enlarge images smaller than the size, leaving larger ones alone"),
+            )
+            .arg(
+                Arg::with_name("down")
+                    .short("d")
+                    .long("down")
+                    .help("Shrink images larger than the size, leaving smaller ones alone (default)"),
+            )
+            .arg(
+                Arg::with_name("both")
+                    .long("both")
+                    .alias("fit-exact")
+                    .help("Shrink or enlarge every image so its longest edge is exactly the size"),
+            )
             .arg(
                 Arg::with_name("size")
                     .short("s")
                     .long("size")
-                    .required(true)
-                    .takes_value(true),
+                    .alias("max-long-edge")
+                    .required_unless_one(&[
+                        "tiles",
+                        "orient-only",
+                        "canvas",
+                        "plan",
+                        "serve",
+                        "dimensions-from",
+                        "width-inches",
+                        "profile",
+                        "list-profiles",
+                        "pixel-format-report",
+                    ])
+                    .takes_value(true)
+                    .multiple(true)
+                    .require_delimiter(true)
+                    .validator(positive_integer)
+                    .help("Target size of the longest edge; several sizes may be given, e.g. 256,1024"),
+            )
+            .arg(
+                Arg::with_name("retina")
+                    .long("retina")
+                    .takes_value(true)
+                    .value_name("FACTORS")
+                    .multiple(true)
+                    .require_delimiter(true)
+                    .conflicts_with_all(&["tiles", "orient-only"])
+                    .validator(|s| match s.parse::<u32>() {
+                        Ok(n) if n >= 2 => Ok(()),
+                        _ => Err(format!("'{}' is not a factor of 2 or more", s)),
+                    })
+                    .help(
+                        "Also write variants at these multiples of each size, e.g. 2,3 for \
+                         photo@2x.jpg and photo@3x.jpg; shrinking skips any the source is too \
+                         small for",
+                    ),
+            )
+            .arg(
+                Arg::with_name("max-short-edge")
+                    .long("max-short-edge")
+                    .takes_value(true)
+                    .value_name("N")
+                    .validator(positive_integer)
+                    .requires("size")
+                    .conflicts_with_all(&["up", "both", "tiles", "orient-only"])
+                    .help(
+                        "Also shrink until the shortest edge is at most N; the size (or \
+                         --max-long-edge) caps the longest",
+                    ),
+            )
+            .arg(
+                Arg::with_name("skip-ratio")
+                    .long("skip-ratio")
+                    .takes_value(true)
+                    .value_name("RATIO")
+                    .requires("size")
+                    .conflicts_with_all(&["up", "both", "tiles", "orient-only"])
+                    .validator(|s| match s.parse::<f64>() {
+                        Ok(n) if n.is_finite() && n >= 1.0 => Ok(()),
+                        _ => Err(format!("'{}' is not a ratio of at least 1", s)),
+                    })
+                    .help("Leave images no more than this many times the size alone, e.g. 1.1, rather than re-encode them for a small shrink"),
+            )
+            .arg(
+                Arg::with_name("round-to")
+                    .long("round-to")
+                    .takes_value(true)
+                    .value_name("N")
+                    .validator(positive_integer)
+                    .help("Round resized dimensions down to a multiple of N, e.g. 16 for video"),
+            )
+            .arg(
+                Arg::with_name("even-dimensions")
+                    .long("even-dimensions")
+                    .help("Round resized dimensions down to even numbers, as H.264 requires"),
+            )
+            .arg(
+                Arg::with_name("alpha-threshold")
+                    .long("alpha-threshold")
+                    .takes_value(true)
+                    .value_name("LOW[:HIGH]")
+                    .validator(|s| AlphaThreshold::parse(&s).map(|_| ()))
+                    .help(
+                        "Make resized pixels with alpha below LOW fully transparent, and any \
+                         above HIGH fully opaque",
+                    ),
+            )
+            .arg(
+                Arg::with_name("color-key")
+                    .long("color-key")
+                    .alias("alpha-to-color-key")
+                    .takes_value(true)
+                    .value_name("COLOR")
+                    .validator(|s| match canvas::parse_color(&s)? {
+                        [.., 255] => Ok(()),
+                        _ => Err(format!("'{}' is not an opaque color", s)),
+                    })
+                    .help("Replace transparent pixels of resized images with this color, such as magenta, and drop alpha"),
+            )
+            .arg(
+                Arg::with_name("dimensions-from")
+                    .long("dimensions-from")
+                    .takes_value(true)
+                    .value_name("IMAGE")
+                    .conflicts_with_all(&["size", "max-short-edge", "up", "both", "tiles", "orient-only"])
+                    .help("Shrink images to fit within the dimensions of this reference image"),
+            )
+            .arg(
+                Arg::with_name("width-inches")
+                    .long("width-inches")
+                    .takes_value(true)
+                    .value_name("INCHES")
+                    .requires("dpi")
+                    .conflicts_with_all(&[
+                        "size",
+                        "max-short-edge",
+                        "dimensions-from",
+                        "up",
+                        "both",
+                        "tiles",
+                        "orient-only",
+                    ])
+                    .validator(|s| match s.parse::<f64>() {
+                        Ok(n) if n.is_finite() && n > 0.0 => Ok(()),
+                        _ => Err(format!("'{}' is not a positive number of inches", s)),
+                    })
+                    .help("Resize every image to this print width at --dpi, e.g. 6 with --dpi 300"),
+            )
+            .arg(
+                Arg::with_name("dpi")
+                    .long("dpi")
+                    .takes_value(true)
+                    .value_name("DPI")
+                    .validator(|s| match s.parse::<u16>() {
+                        Ok(n) if n > 0 => Ok(()),
+                        _ => Err(format!("'{}' is not a resolution from 1 to 65535", s)),
+                    })
+                    .help("Record this print resolution in JPEG and PNG outputs"),
+            )
+            .arg(
+                Arg::with_name("format")
+                    .short("f")
+                    .long("format")
+                    .takes_value(true)
+                    .possible_values(output::FORMATS)
+                    .help("Output format; ico packs every requested size into one icon"),
+            )
+            .arg(
+                Arg::with_name("fallback-format")
+                    .long("fallback-format")
+                    .takes_value(true)
+                    .value_name("FORMAT")
+                    .possible_values(output::FORMATS)
+                    .help("Write outputs in this format, with its extension, when their own fails to encode"),
+            )
+            .arg(
+                Arg::with_name("quality")
+                    .short("q")
+                    .long("quality")
+                    .takes_value(true)
+                    .validator(|s| Quality::parse(&s).map(|_| ()))
+                    .help("JPEG quality, optionally per size, e.g. 82 or 82,256=70,1024=85 (default: 82)"),
+            )
+            .arg(
+                Arg::with_name("quality-jpeg")
+                    .long("quality-jpeg")
+                    .takes_value(true)
+                    .value_name("QUALITY")
+                    .validator(|s| match s.parse::<u8>() {
+                        Ok(n) if (1..=100).contains(&n) => Ok(()),
+                        _ => Err(format!("'{}' is not a quality between 1 and 100", s)),
+                    })
+                    .help("JPEG quality at every size, in place of --quality, e.g. for JPEG fallbacks of PNG outputs"),
+            )
+            .arg(
+                Arg::with_name("jpeg-optimize")
+                    .long("jpeg-optimize")
+                    .help("Optimize the Huffman tables of JPEG outputs, shrinking them a few percent at no cost in quality"),
+            )
+            .arg(
+                Arg::with_name("jpeg-restart-interval")
+                    .long("jpeg-restart-interval")
+                    .alias("jpeg-restart-markers")
+                    .takes_value(true)
+                    .value_name("ROWS")
+                    .validator(|s| match s.parse::<u16>() {
+                        Ok(n) if n > 0 => Ok(()),
+                        _ => Err(format!("'{}' is not a positive number of MCU rows", s)),
+                    })
+                    .help("Write a restart marker into JPEG outputs every ROWS rows of MCUs, so damaged files decode past the damage, at the cost of slightly larger files"),
+            )
+            .arg(
+                Arg::with_name("strip-keep-orientation")
+                    .long("strip-keep-orientation")
+                    .alias("strip-but-keep-orientation")
+                    .conflicts_with_all(&["orient-to", "orient-only", "regenerate-thumbnail"])
+                    .help("Tag JPEG and PNG outputs with the source's EXIF orientation and no other metadata, so they display as it did"),
+            )
+            .arg(
+                Arg::with_name("regenerate-thumbnail")
+                    .long("regenerate-thumbnail")
+                    .help("Embed a 160px thumbnail of each JPEG output in its EXIF data"),
+            )
+            .arg(
+                Arg::with_name("quantize-colors")
+                    .long("quantize-colors")
+                    .takes_value(true)
+                    .value_name("N")
+                    .validator(|s| match s.parse::<u16>() {
+                        Ok(n) if (2..=256).contains(&n) => Ok(()),
+                        _ => Err(format!("'{}' is not a color count from 2 to 256", s)),
+                    })
+                    .help("Write PNG outputs as indexed images of at most N colors, keeping translucency"),
+            )
+            .arg(
+                Arg::with_name("dither")
+                    .long("dither")
+                    .requires("quantize-colors")
+                    .help("Dither quantized PNGs, trading banding for noise"),
+            )
+            .arg(
+                Arg::with_name("bit-depth")
+                    .long("bit-depth")
+                    .takes_value(true)
+                    .possible_values(BitDepth::NAMES)
+                    .help("Bits per channel of outputs; only PNG and TIFF hold 16 (default: 8 for resized outputs)"),
+            )
+            .arg(
+                Arg::with_name("jpeg-subsampling")
+                    .long("jpeg-subsampling")
+                    .takes_value(true)
+                    .possible_values(Subsampling::NAMES)
+                    .help("Chroma subsampling of JPEG outputs; 420 and 422 trade color detail for size (default: 420)"),
+            )
+            .arg(
+                Arg::with_name("filter")
+                    .long("filter")
+                    .takes_value(true)
+                    .possible_values(filter::FILTERS)
+                    .help("Resampling filter; by default lanczos3, or triangle for extreme shrinks"),
+            )
+            .arg(
+                Arg::with_name("shrink-filter")
+                    .long("shrink-filter")
+                    .takes_value(true)
+                    .possible_values(filter::FILTERS)
+                    .help("Resampling filter for shrinking, overriding --filter"),
+            )
+            .arg(
+                Arg::with_name("enlarge-filter")
+                    .long("enlarge-filter")
+                    .takes_value(true)
+                    .possible_values(filter::FILTERS)
+                    .help("Resampling filter for enlarging, overriding --filter"),
+            )
+            .arg(
+                Arg::with_name("area-threshold")
+                    .long("area-threshold")
+                    .takes_value(true)
+                    .value_name("SCALE")
+                    .validator(|s| match s.parse::<f64>() {
+                        Ok(n) if (0.0..=1.0).contains(&n) => Ok(()),
+                        _ => Err(format!("'{}' is not a scale between 0 and 1", s)),
+                    })
+                    .help("Shrink with the triangle filter below this scale factor [default: 0.125]"),
+            )
+            .arg(
+                Arg::with_name("auto-sharpen")
+                    .long("auto-sharpen")
+                    .help("Sharpen shrunk images more the more they were reduced"),
+            )
+            .arg(
+                Arg::with_name("parallel-single")
+                    .long("parallel-single")
+                    .help("Spread the resizing of each image across all cores"),
+            )
+            .arg(
+                Arg::with_name("tiles")
+                    .long("tiles")
+                    .help("Write a Deep Zoom tile pyramid instead of resizing"),
+            )
+            .arg(
+                Arg::with_name("orient-only")
+                    .long("orient-only")
+                    .help(
+                        "Apply EXIF orientation to the pixels without resizing; JPEGs are \
+                         rotated losslessly, trimming any partial block on a moved edge",
+                    ),
+            )
+            .arg(
+                Arg::with_name("canvas")
+                    .long("canvas")
+                    .takes_value(true)
+                    .value_name("WxH")
+                    .conflicts_with_all(&[
+                        "size",
+                        "retina",
+                        "max-short-edge",
+                        "skip-ratio",
+                        "dimensions-from",
+                        "width-inches",
+                    ])
+                    .validator(|s| canvas::parse_dimensions(&s).map(|_| ()))
+                    .help("Place each image unscaled on a canvas of this size, e.g. 1000x1000, cutting off whatever doesn't fit"),
+            )
+            .arg(
+                Arg::with_name("canvas-color")
+                    .long("canvas-color")
+                    .takes_value(true)
+                    .value_name("COLOR")
+                    .requires("canvas")
+                    .validator(|s| canvas::parse_color(&s).map(|_| ()))
+                    .help("Fill the canvas with this color: white (the default), black, gray, transparent or #rrggbb[aa]"),
+            )
+            .arg(
+                Arg::with_name("canvas-anchor")
+                    .long("canvas-anchor")
+                    .takes_value(true)
+                    .value_name("ANCHOR")
+                    .requires("canvas")
+                    .possible_values(Anchor::NAMES)
+                    .help("Where on the canvas each image goes, center unless given"),
+            )
+            .arg(
+                Arg::with_name("split")
+                    .long("split")
+                    .takes_value(true)
+                    .value_name("COLUMNSxROWS")
+                    .conflicts_with_all(&["tiles", "orient-only", "canvas", "compare"])
+                    .validator(|s| Grid::parse(&s).map(|_| ()))
+                    .help("Cut each output into a grid of pieces, e.g. 3x2, named for their row and column like _r0c1"),
+            )
+            .arg(
+                Arg::with_name("overlap")
+                    .long("overlap")
+                    .takes_value(true)
+                    .value_name("PIXELS")
+                    .requires("split")
+                    .validator(|s| s.parse::<u32>().map(|_| ()).map_err(|e| e.to_string()))
+                    .help("Have each piece of a split reach this far into its neighbors, for print bleed"),
+            )
+            .arg(
+                Arg::with_name("tile-size")
+                    .long("tile-size")
+                    .takes_value(true)
+                    .default_value("256")
+                    .validator(positive_integer),
+            )
+            .arg(
+                Arg::with_name("trim-transparent")
+                    .long("trim-transparent")
+                    .help("Crop away fully transparent rows and columns at the edges first, skipping images with nothing visible"),
+            )
+            .arg(
+                Arg::with_name("color-space")
+                    .long("color-space")
+                    .takes_value(true)
+                    .value_name("SPACE")
+                    .possible_values(ColorSpace::NAMES)
+                    .help("Convert images to this color space by their embedded ICC profiles, or from sRGB if they have none, and tag outputs to match"),
+            )
+            .arg(
+                Arg::with_name("crop-aspect")
+                    .long("crop-aspect")
+                    .takes_value(true)
+                    .value_name("W:H")
+                    .validator(|s| Aspect::parse(&s).map(|_| ()))
+                    .help("Center-crop to the largest region with this aspect ratio, e.g. 16:9"),
+            )
+            .arg(
+                Arg::with_name("pad-aspect")
+                    .long("pad-aspect")
+                    .takes_value(true)
+                    .value_name("W:H")
+                    .conflicts_with_all(&["crop-aspect", "canvas"])
+                    .validator(|s| Aspect::parse(&s).map(|_| ()))
+                    .help("Pad to this aspect ratio, keeping the image centered, before resizing, e.g. 1:1"),
+            )
+            .arg(
+                Arg::with_name("pad-color")
+                    .long("pad-color")
+                    .takes_value(true)
+                    .value_name("COLOR")
+                    .requires("pad-aspect")
+                    .validator(|s| Fill::parse(&s).map(|_| ()))
+                    .help("Pad with this color: white (the default), black, gray, transparent, #rrggbb[aa], or auto for the average of the image's corners"),
+            )
+            .arg(
+                Arg::with_name("orient-to")
+                    .long("orient-to")
+                    .alias("orientation-normalize")
+                    .takes_value(true)
+                    .possible_values(Orientation::NAMES)
+                    .conflicts_with("orient-only")
+                    .help("Turn images facing the other way a quarter clockwise before resizing, leaving squares be"),
+            )
+            .arg(
+                Arg::with_name("max-aspect")
+                    .long("max-aspect")
+                    .alias("max-aspect-ratio")
+                    .takes_value(true)
+                    .value_name("RATIO")
+                    .conflicts_with("orient-only")
+                    .validator(|s| match s.parse::<f64>() {
+                        Ok(n) if n >= 1.0 && n.is_finite() => Ok(()),
+                        _ => Err(format!("'{}' is not a ratio of at least 1", s)),
+                    })
+                    .help("Skip, or with --aspect-action crop, center-crop images whose long edge is more than RATIO times their short one, e.g. 3.0, before resizing"),
+            )
+            .arg(
+                Arg::with_name("aspect-action")
+                    .long("aspect-action")
+                    .takes_value(true)
+                    .possible_values(AspectAction::NAMES)
+                    .requires("max-aspect")
+                    .help("What to do with images beyond --max-aspect: skip them (the default) or center-crop them to it"),
+            )
+            .arg(
+                Arg::with_name("aspect-tolerance")
+                    .long("aspect-tolerance")
+                    .takes_value(true)
+                    .value_name("FRACTION")
+                    .requires("crop-aspect")
+                    .validator(|s| match s.parse::<f64>() {
+                        Ok(n) if (0.0..=1.0).contains(&n) => Ok(()),
+                        _ => Err(format!("'{}' is not a fraction between 0 and 1", s)),
+                    })
+                    .help("Leave images within this fraction of the crop aspect uncropped, e.g. 0.02"),
+            )
+            .arg(
+                Arg::with_name("downsample-before-decode")
+                    .long("downsample-before-decode")
+                    .help("Decode JPEGs being shrunk at 1/2, 1/4 or 1/8 scale where that still covers the size, for speed"),
+            )
+            .arg(
+                Arg::with_name("input-max-dimension")
+                    .long("input-max-dimension")
+                    .takes_value(true)
+                    .value_name("PX")
+                    .validator(positive_integer)
+                    .help("Decode sources whose header gives a longer edge than this shrunk to fit, JPEGs at a reduced scale, guarding memory against huge images"),
+            )
+            .arg(
+                Arg::with_name("reject-oversized")
+                    .long("reject-oversized")
+                    .requires("input-max-dimension")
+                    .help("Fail sources over --input-max-dimension rather than shrinking them"),
+            )
+            .arg(
+                Arg::with_name("allow-partial")
+                    .long("allow-partial")
+                    .help("Salvage what can be decoded from truncated JPEGs and PNGs"),
+            )
+            .arg(
+                Arg::with_name("retry-different-decoder")
+                    .long("retry-different-decoder")
+                    .alias("sniff-format")
+                    .help("Retry images that fail to decode in the format their contents name"),
+            )
+            .arg(
+                Arg::with_name("error-image")
+                    .long("error-image")
+                    .conflicts_with_all(&["tiles", "orient-only"])
+                    .help("Write a crossed-out gray placeholder at each size for images that fail to decode, rather than skipping them"),
+            )
+            .arg(
+                Arg::with_name("since")
+                    .long("since")
+                    .takes_value(true)
+                    .value_name("YYYY-MM-DD")
+                    .help("Only resize files modified on or after this date")
+                    .validator(|s| date::parse_date(&s).map(|_| ())),
+            )
+            .arg(
+                Arg::with_name("denoise")
+                    .long("denoise")
+                    .takes_value(true)
+                    .value_name("SIGMA")
+                    .validator(|s| match s.parse::<f32>() {
+                        Ok(n) if n.is_finite() && n > 0.0 => Ok(()),
+                        _ => Err(format!("'{}' is not a positive blur radius", s)),
+                    })
+                    .help("Blur by SIGMA before resizing to smooth sensor noise, e.g. 0.8"),
+            )
+            .arg(
+                Arg::with_name("filter-chain")
+                    .long("filter-chain")
+                    .takes_value(true)
+                    .value_name("EFFECTS")
+                    .conflicts_with_all(&["tiles", "orient-only"])
+                    .validator(|s| effect::parse_chain(&s).map(|_| ()))
+                    .help(
+                        "Apply effects to each resized image in order, e.g. \
+                         sharpen:0.5,contrast:1.1,grayscale",
+                    ),
+            )
+            .arg(
+                Arg::with_name("auto-level")
+                    .long("auto-level")
+                    .help("Stretch each color channel to the full range before resizing"),
+            )
+            .arg(
+                Arg::with_name("auto-contrast")
+                    .long("auto-contrast")
+                    .conflicts_with("auto-level")
+                    .help("Stretch all color channels together to the full range before resizing"),
+            )
+            .arg(
+                Arg::with_name("clip-percent")
+                    .long("clip-percent")
+                    .takes_value(true)
+                    .default_value("0")
+                    .validator(|s| match s.parse::<f64>() {
+                        Ok(n) if (0.0..50.0).contains(&n) => Ok(()),
+                        _ => Err(format!("'{}' is not a percentage below 50", s)),
+                    })
+                    .help("Percentage of outlying pixels to ignore at each end when leveling"),
+            )
+            .arg(
+                Arg::with_name("progress")
+                    .long("progress")
+                    .takes_value(true)
+                    .possible_values(&["json"])
+                    .help("Stream progress events to stderr, one JSON object per line"),
+            )
+            .arg(
+                Arg::with_name("progress-file")
+                    .long("progress-file")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .help("Keep a JSON {done, total, current} status in this file, for polling"),
+            )
+            .arg(
+                Arg::with_name("recursive")
+                    .short("r")
+                    .long("recursive")
+                    .help("Resize the images in any directory given, and in its subdirectories"),
+            )
+            .arg(
+                Arg::with_name("follow-symlinks")
+                    .long("follow-symlinks")
+                    .requires("recursive")
+                    .help("Follow symlinks while walking directories, visiting each target once"),
+            )
+            .arg(
+                Arg::with_name("max-depth")
+                    .long("max-depth")
+                    .takes_value(true)
+                    .value_name("N")
+                    .validator(|s| match s.parse::<usize>() {
+                        Ok(_) => Ok(()),
+                        Err(_) => Err(format!("'{}' is not a number of directories", s)),
+                    })
+                    .help("Look no more than this many directories deep when walking a directory or expanding a ** glob"),
+            )
+            .arg(
+                Arg::with_name("verbose")
+                    .short("v")
+                    .long("verbose")
+                    .help("Report how many images each directory or glob matched at each depth"),
+            )
+            .arg(
+                Arg::with_name("ignore")
+                    .long("ignore")
+                    .takes_value(true)
+                    .multiple(true)
+                    .number_of_values(1)
+                    .value_name("GLOB")
+                    .validator(|s| Pattern::new(&s).map(|_| ()).map_err(|e| e.to_string()))
+                    .help("Skip images whose path matches this pattern; may be repeated"),
+            )
+            .arg(
+                Arg::with_name("jobs")
+                    .short("j")
+                    .long("jobs")
+                    .takes_value(true)
+                    .value_name("JOBS")
+                    .default_value("auto")
+                    .validator(|s| threads::parse_jobs(&s).map(|_| ()))
+                    .help("Number of images to process in parallel: a count, 'auto' for one per core, or a multiple of the cores, e.g. 0.5x"),
+            )
+            .arg(
+                Arg::with_name("max-memory")
+                    .long("max-memory")
+                    .takes_value(true)
+                    .value_name("BYTES")
+                    .validator(|s| memory::parse_bytes(&s).map(|_| ()))
+                    .help("Limit the estimated memory used by images decoded at once, e.g. 4G"),
+            )
+            .arg(
+                Arg::with_name("manifest")
+                    .long("manifest")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .help("Write a JSON record of every output to this file"),
+            )
+            .arg(
+                Arg::with_name("emit-srcset")
+                    .long("emit-srcset")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .help("Write an HTML <img> tag for each source to this file, its srcset listing the outputs by width"),
+            )
+            .arg(
+                Arg::with_name("resume")
+                    .long("resume")
+                    .takes_value(true)
+                    .value_name("MANIFEST")
+                    .conflicts_with("watch")
+                    .help("Leave out sources an earlier run's manifest shows as done, redoing only those it failed or never reached"),
+            )
+            .arg(
+                Arg::with_name("dedup-outputs")
+                    .long("dedup-outputs")
+                    .alias("hash-dedup-outputs")
+                    .conflicts_with_all(&["output", "tar-out", "upload"])
+                    .help("Write each distinct output once, linking identical ones to it, as recorded in the manifest"),
+            )
+            .arg(
+                Arg::with_name("timing")
+                    .long("timing")
+                    .alias("verbose-timing")
+                    .help("Report on stderr how long opening, decoding, resizing, encoding and writing took over the run"),
+            )
+            .arg(
+                Arg::with_name("checksum")
+                    .long("checksum")
+                    .takes_value(true)
+                    .possible_values(Checksum::NAMES)
+                    .requires("manifest")
+                    .help("Record a hash of each output's bytes in the manifest"),
+            )
+            .arg(
+                Arg::with_name("phash")
+                    .long("phash")
+                    .alias("detect-duplicates")
+                    .requires("manifest")
+                    .help("Record a perceptual hash of each source in the manifest, ending it with groups of near-duplicates"),
+            )
+            .arg(
+                Arg::with_name("phash-distance")
+                    .long("phash-distance")
+                    .takes_value(true)
+                    .value_name("BITS")
+                    .requires("phash")
+                    .validator(|s| match s.parse::<u32>() {
+                        Ok(bits) if bits <= 64 => Ok(()),
+                        _ => Err(format!("'{}' is not a number of bits up to 64", s)),
+                    })
+                    .help("How many bits perceptual hashes may differ by for near-duplicates [default: 10]"),
+            )
+            .arg(
+                Arg::with_name("palette")
+                    .long("palette")
+                    .takes_value(true)
+                    .value_name("N")
+                    .validator(positive_integer)
+                    .help(
+                        "Record the N dominant colors of each output, in the manifest or else \
+                         a .colors.json file beside it",
+                    ),
+            )
+            .arg(
+                Arg::with_name("stats")
+                    .long("stats")
+                    .help(
+                        "Measure each source's brightness, channel histograms, entropy and \
+                         sharpness, in the manifest or else on stderr",
+                    ),
+            )
+            .arg(
+                Arg::with_name("tar-in")
+                    .long("tar-in")
+                    .conflicts_with_all(&["image", "plan", "since"])
+                    .requires("tar-out")
+                    .help("Read images from a tar stream on stdin"),
+            )
+            .arg(
+                Arg::with_name("tar-out")
+                    .long("tar-out")
+                    .conflicts_with("tiles")
+                    .help("Write outputs as a tar stream to stdout instead of to files"),
+            )
+            .arg(
+                Arg::with_name("upload")
+                    .long("upload")
+                    .takes_value(true)
+                    .value_name("URL")
+                    .conflicts_with_all(&["tar-out", "tiles", "exec"])
+                    .validator(|s| s3::parse_url(&s).map(|_| ()))
+                    .help("Upload outputs to object storage, e.g. s3://bucket/prefix/, instead of writing files"),
+            )
+            .arg(
+                Arg::with_name("output")
+                    .short("o")
+                    .long("output")
+                    .takes_value(true)
+                    .value_name("PATH")
+                    .conflicts_with_all(&["plan", "tar-in", "tar-out", "upload", "tiles", "exec", "exif-date-rename"])
+                    .requires_if("-", "format")
+                    .help("Write the output of a single image here instead, or to stdout if -"),
+            )
+            .arg(
+                Arg::with_name("exif-date-rename")
+                    .long("exif-date-rename")
+                    .help("Name outputs by their EXIF capture time, e.g. 2024-06-01_142305.jpg"),
+            )
+            .arg(
+                Arg::with_name("exec")
+                    .long("exec")
+                    .takes_value(true)
+                    .value_name("COMMAND")
+                    .conflicts_with("tar-out")
+                    .validator(|s| Exec::parse(&s).map(|_| ()))
+                    .help("Run COMMAND on each output written, with {} replaced by its path"),
+            )
+            .arg(
+                Arg::with_name("keep-going")
+                    .long("keep-going")
+                    .conflicts_with("fail-fast")
+                    .help("Report images that fail and carry on with the rest, exiting non-zero at the end if any failed (the default)"),
+            )
+            .arg(
+                Arg::with_name("fail-fast")
+                    .long("fail-fast")
+                    .help("Stop at the first image that fails, exiting non-zero with its error"),
+            )
+            .arg(
+                Arg::with_name("keep-going-on-panic")
+                    .long("keep-going-on-panic")
+                    .help("Record an image that panics as failed and carry on with the rest"),
+            )
+            .arg(
+                Arg::with_name("read-timeout")
+                    .long("read-timeout")
+                    .takes_value(true)
+                    .value_name("SECONDS")
+                    .validator(|s| match s.parse::<f64>() {
+                        Ok(n) if n.is_finite() && n > 0.0 => Ok(()),
+                        _ => Err(format!("'{}' is not a positive number of seconds", s)),
+                    })
+                    .help("Fail any image that takes longer than this to read, e.g. from a hung network mount, and carry on"),
+            )
+            .arg(
+                Arg::with_name("min-saving")
+                    .long("min-saving")
+                    .takes_value(true)
+                    .value_name("PERCENT")
+                    .validator(|s| parse_percent(&s).map(|_| ()))
+                    .help("Keep the original unless an output is at least this much smaller, e.g. 10%"),
+            )
+            .arg(
+                Arg::with_name("budget")
+                    .long("budget")
+                    .takes_value(true)
+                    .value_name("BYTES")
+                    .validator(|s| memory::parse_bytes(&s).map(|_| ()))
+                    .help("Stop writing outputs once the next would take their total past this, e.g. 50M"),
+            )
+            .arg(
+                Arg::with_name("compare")
+                    .long("compare")
+                    .conflicts_with_all(&["tiles", "orient-only"])
+                    .help(
+                        "Also write each output beside the source scaled to match, as a \
+                         _compare image",
+                    ),
+            )
+            .arg(
+                Arg::with_name("verify")
+                    .long("verify")
+                    .conflicts_with_all(&["tar-out", "upload"])
+                    .help("Re-read each output after writing it, failing any that doesn't decode"),
+            )
+            .arg(
+                Arg::with_name("delete-source")
+                    .long("delete-source")
+                    .alias("source-delete-after-success")
+                    .requires("confirm-delete")
+                    .conflicts_with_all(&["tar-in", "tar-out", "upload", "exec"])
+                    .help(
+                        "Delete each source once all its outputs are written, and verified with \
+                         --verify; sources that fail, need no resizing or were written over are kept",
+                    ),
+            )
+            .arg(
+                Arg::with_name("confirm-delete")
+                    .long("confirm-delete")
+                    .requires("delete-source")
+                    .help("Confirm that --delete-source should really delete sources"),
+            )
+            .arg(
+                Arg::with_name("sort-by")
+                    .long("sort-by")
+                    .takes_value(true)
+                    .possible_values(&["name", "modified"])
+                    .help("Process images in order of path or of modification time"),
+            )
+            .arg(
+                Arg::with_name("sequence")
+                    .long("sequence")
+                    .takes_value(true)
+                    .value_name("PATTERN")
+                    .conflicts_with_all(&["plan", "tar-in", "output", "exif-date-rename"])
+                    .validator(|s| Sequence::parse(&s).map(|_| ()))
+                    .help("Name outputs by their place in the order from 1, e.g. frame_%04d.jpg"),
+            )
+            .arg(
+                Arg::with_name("limit")
+                    .long("limit")
+                    .takes_value(true)
+                    .value_name("N")
+                    .validator(positive_integer)
+                    .help("Process only the first N images"),
+            )
+            .arg(
+                Arg::with_name("normalize-ext")
+                    .long("normalize-ext")
+                    .help("Lowercase output extensions and spell every JPEG's the same way"),
+            )
+            .arg(
+                Arg::with_name("jpeg-ext")
+                    .long("jpeg-ext")
+                    .takes_value(true)
+                    .possible_values(&["jpg", "jpeg"])
+                    .requires("normalize-ext")
+                    .help("The extension --normalize-ext gives JPEGs [default: jpg]"),
+            )
+            .arg(
+                Arg::with_name("out-ext")
+                    .long("out-ext")
+                    .takes_value(true)
+                    .value_name("EXT")
+                    .validator(|s| match s.is_empty() || s.contains(['.', '/', '\\']) {
+                        true => Err(format!("'{}' is not a file extension", s)),
+                        false => Ok(()),
+                    })
+                    .help(
+                        "Give outputs this extension whatever their format, e.g. jpeg; the \
+                         format still comes from --format or the source",
+                    ),
+            )
+            .arg(
+                Arg::with_name("prefix")
+                    .long("prefix")
+                    .alias("output-prefix")
+                    .takes_value(true)
+                    .value_name("TEXT")
+                    .validator(file_name_part)
+                    .help("Put this before every output's name, e.g. sm_ for sm_photo.jpg"),
+            )
+            .arg(
+                Arg::with_name("suffix")
+                    .long("suffix")
+                    .alias("output-suffix")
+                    .takes_value(true)
+                    .allow_hyphen_values(true)
+                    .value_name("TEXT")
+                    .validator(file_name_part)
+                    .help("Put this after every output's name, before the extension, e.g. _v2 for photo_v2.jpg"),
+            )
+            .arg(
+                Arg::with_name("slugify")
+                    .long("slugify")
+                    .help("Reduce output names to lowercase ASCII letters, digits, - and _"),
+            )
+            .arg(
+                Arg::with_name("no-op-is-error")
+                    .long("no-op-is-error")
+                    .help("Fail the run if any image is already within the requested size"),
+            )
+            .group(
+                ArgGroup::with_name("operation")
+                    .arg("up")
+                    .arg("down")
+                    .arg("both")
+                    .arg("tiles")
+                    .arg("orient-only")
+                    .arg("canvas"),
             )
-            .group(ArgGroup::with_name("operation").arg("up").arg("down"))
             .get_matches();
 
+        if m.is_present("list-profiles") {
+            for profile in profile::PROFILES {
+                println!("{:<12}{}", profile.name, profile.description);
+            }
+            std::process::exit(0);
+        }
+
+        let operation = if m.is_present("up") {
+            Operation::Enlarge
+        } else if m.is_present("both") || m.is_present("width-inches") {
+            Operation::Fit
+        } else if m.is_present("orient-only") {
+            Operation::OrientOnly
+        } else if m.is_present("tiles") {
+            Operation::Tiles
+        } else if m.is_present("canvas") {
+            Operation::Canvas
+        } else {
+            Operation::Shrink
+        };
+
+        let mut builder = ResizeOptions::builder()
+            .operation(operation)
+            .tile_size(value_t!(m.value_of("tile-size"), u32).unwrap_or_else(|e| e.exit()))
+            .auto_sharpen(m.is_present("auto-sharpen"))
+            .parallel(m.is_present("parallel-single"))
+            .normalize_extension(
+                m.is_present("normalize-ext"),
+                m.value_of("jpeg-ext") == Some("jpeg"),
+            )
+            .slugify(m.is_present("slugify"))
+            .allow_partial(m.is_present("allow-partial"))
+            .retry_sniffed(m.is_present("retry-different-decoder"))
+            .error_image(m.is_present("error-image"))
+            .downsample_before_decode(m.is_present("downsample-before-decode"))
+            .compare(m.is_present("compare"))
+            .in_place(m.is_present("in-place"));
+        if let Some(edge) = m.value_of("input-max-dimension") {
+            let edge = edge.parse().expect("validated by clap");
+            builder = builder.max_input_dimension(edge, m.is_present("reject-oversized"));
+        }
+        if let Some(profile) = m.value_of("profile").and_then(profile::find) {
+            builder = profile.apply(builder, !m.is_present("size"));
+        }
+        if m.is_present("size") {
+            builder =
+                builder.sizes(values_t!(m.values_of("size"), u32).unwrap_or_else(|e| e.exit()));
+        }
+        if let Some(reference) = m.value_of("dimensions-from") {
+            // The reference's edges cap the long and short edges, so it fits either way round.
+            let (width, height) = image::image_dimensions(reference).unwrap_or_else(|e| {
+                let message = format!("cannot read the dimensions of {}: {}", reference, e);
+                clap::Error::with_description(&message, clap::ErrorKind::ValueValidation).exit()
+            });
+            builder = builder
+                .size(width.max(height))
+                .max_short_edge(width.min(height));
+        }
+        if m.is_present("dpi") {
+            let dpi = value_t!(m.value_of("dpi"), u16).unwrap_or_else(|e| e.exit());
+            builder = builder.dpi(dpi);
+
+            if let Some(inches) = m.value_of("width-inches") {
+                let inches = inches.parse().expect("validated by clap");
+                let width = dpi::pixels(inches, dpi).unwrap_or_else(|| {
+                    let message = format!("{} inches at {} dpi is not a usable width", inches, dpi);
+                    clap::Error::with_description(&message, clap::ErrorKind::ValueValidation).exit()
+                });
+                builder = builder.size(width).sizes_are_widths(true);
+            }
+        }
+        if let Some(prefix) = m.value_of("prefix") {
+            builder = builder.out_prefix(prefix);
+        }
+        if let Some(suffix) = m.value_of("suffix") {
+            builder = builder.out_suffix(suffix);
+        }
+        if let Some(extension) = m.value_of("out-ext") {
+            builder = builder.out_extension(extension);
+            // Only an extension naming some other format is suspect; one of its own is fine.
+            let named = output::parse_format(&extension.to_lowercase());
+            let format = m.value_of("format").and_then(output::parse_format);
+            if let (Some(named), Some(format)) = (named, format) {
+                if named != format {
+                    eprintln!(
+                        "warning: --out-ext {} names {:?} files, but outputs are {:?}",
+                        extension, named, format
+                    );
+                }
+            }
+        }
+        if let Some(quality) = m.value_of("quality-jpeg") {
+            builder = builder.jpeg_quality(quality.parse().expect("validated by clap"));
+        }
+        builder = builder.jpeg_optimize(m.is_present("jpeg-optimize"));
+        if let Some(rows) = m.value_of("jpeg-restart-interval") {
+            builder = builder.jpeg_restart_rows(rows.parse().expect("validated by clap"));
+            let format = m.value_of("format").and_then(output::parse_format);
+            if format.is_some_and(|format| format != image::ImageFormat::Jpeg) {
+                eprintln!("warning: --jpeg-restart-interval has no effect on non-JPEG outputs");
+            }
+        }
+        builder = builder.regenerate_thumbnail(m.is_present("regenerate-thumbnail"));
+        builder = builder.keep_orientation(m.is_present("strip-keep-orientation"));
+        if let Some(subsampling) = m.value_of("jpeg-subsampling") {
+            builder = builder
+                .jpeg_subsampling(Subsampling::from_name(subsampling).expect("validated by clap"));
+            let format = m.value_of("format").and_then(output::parse_format);
+            if format.is_some_and(|format| format != image::ImageFormat::Jpeg) {
+                eprintln!("warning: --jpeg-subsampling has no effect on non-JPEG outputs");
+            }
+        }
+        if let Some(colors) = m.value_of("quantize-colors") {
+            let colors = colors.parse().expect("validated by clap");
+            builder = builder.quantize_colors(colors, m.is_present("dither"));
+            let format = m.value_of("format").and_then(output::parse_format);
+            if format.is_some_and(|format| format != image::ImageFormat::Png) {
+                eprintln!("warning: --quantize-colors has no effect on non-PNG outputs");
+            }
+        }
+        if let Some(bit_depth) = m.value_of("bit-depth") {
+            let bit_depth = BitDepth::from_name(bit_depth).expect("validated by clap");
+            builder = builder.bit_depth(bit_depth);
+            let format = m.value_of("format").and_then(output::parse_format);
+            if let Some(format) = format.filter(|&format| !depth::holds_sixteen(format)) {
+                if bit_depth == BitDepth::Sixteen {
+                    eprintln!(
+                        "warning: {:?} outputs are always 8-bit, whatever --bit-depth asks",
+                        format
+                    );
+                }
+            }
+        }
+        if m.is_present("round-to") {
+            builder = builder
+                .round_to(value_t!(m.value_of("round-to"), u32).unwrap_or_else(|e| e.exit()));
+        }
+        builder = builder.even_dimensions(m.is_present("even-dimensions"));
+        if let Some(threshold) = m.value_of("alpha-threshold") {
+            builder = builder
+                .alpha_threshold(AlphaThreshold::parse(threshold).expect("validated by clap"));
+        }
+        if let Some(key) = m.value_of("color-key") {
+            let [r, g, b, _] = canvas::parse_color(key).expect("validated by clap");
+            builder = builder.color_key([r, g, b]);
+        }
+        if m.is_present("retina") {
+            builder =
+                builder.retina(values_t!(m.values_of("retina"), u32).unwrap_or_else(|e| e.exit()));
+        }
+        if m.is_present("max-short-edge") {
+            builder = builder.max_short_edge(
+                value_t!(m.value_of("max-short-edge"), u32).unwrap_or_else(|e| e.exit()),
+            );
+        }
+        if let Some(ratio) = m.value_of("skip-ratio") {
+            builder = builder.skip_ratio(ratio.parse().expect("validated by clap"));
+        }
+        if m.is_present("denoise") {
+            builder =
+                builder.denoise(value_t!(m.value_of("denoise"), f32).unwrap_or_else(|e| e.exit()));
+        }
+        if let Some(chain) = m.value_of("filter-chain") {
+            builder = builder.effects(effect::parse_chain(chain).expect("validated by clap"));
+        }
+        if m.is_present("stats") {
+            builder = builder.stats(true);
+        }
+        if m.is_present("phash") {
+            builder = builder.phash(true);
+        }
+        if m.is_present("palette") {
+            builder = builder
+                .palette(value_t!(m.value_of("palette"), usize).unwrap_or_else(|e| e.exit()));
+        }
+        if let Some(format) = m.value_of("format").and_then(output::parse_format) {
+            builder = builder.format(format);
+        }
+        if let Some(format) = m.value_of("fallback-format").and_then(output::parse_format) {
+            builder = builder.fallback_format(format);
+        }
+        if let Some(quality) = m.value_of("quality") {
+            builder = builder.quality(Quality::parse(quality).expect("validated by clap"));
+        }
+        if let Some(filter) = m
+            .value_of("shrink-filter")
+            .or_else(|| m.value_of("filter"))
+            .and_then(filter::parse_filter)
+        {
+            builder = builder.shrink_filter(filter);
+        }
+        if let Some(filter) = m
+            .value_of("enlarge-filter")
+            .or_else(|| m.value_of("filter"))
+            .and_then(filter::parse_filter)
+        {
+            builder = builder.enlarge_filter(filter);
+        }
+        if let Some(threshold) = m.value_of("area-threshold") {
+            builder = builder.area_threshold(threshold.parse().expect("validated by clap"));
+        }
+        if let Some(tolerance) = m.value_of("aspect-tolerance") {
+            builder = builder.aspect_tolerance(tolerance.parse().expect("validated by clap"));
+        }
+        builder = builder.trim_transparent(m.is_present("trim-transparent"));
+        if let Some(space) = m.value_of("color-space") {
+            builder = builder.color_space(ColorSpace::from_name(space).expect("validated by clap"));
+        }
+        if let Some(dimensions) = m.value_of("canvas") {
+            let (width, height) = canvas::parse_dimensions(dimensions).expect("validated by clap");
+            let color = m.value_of("canvas-color").unwrap_or("white");
+            let anchor = m.value_of("canvas-anchor").unwrap_or("center");
+            builder = builder.canvas(Canvas {
+                width,
+                height,
+                color: canvas::parse_color(color).expect("validated by clap"),
+                anchor: Anchor::from_name(anchor).expect("validated by clap"),
+            });
+        }
+        if let Some(grid) = m.value_of("split") {
+            let overlap = m
+                .value_of("overlap")
+                .map_or(0, |overlap| overlap.parse().expect("validated by clap"));
+            builder = builder.split(Grid {
+                overlap,
+                ..Grid::parse(grid).expect("validated by clap")
+            });
+        }
+        if let Some(aspect) = m.value_of("crop-aspect") {
+            builder = builder.crop_aspect(Aspect::parse(aspect).expect("validated by clap"));
+        }
+        if let Some(ratio) = m.value_of("max-aspect") {
+            let action = m
+                .value_of("aspect-action")
+                .map_or(AspectAction::Skip, |action| {
+                    AspectAction::from_name(action).expect("validated by clap")
+                });
+            builder = builder.max_aspect(ratio.parse().expect("validated by clap"), action);
+        }
+        if let Some(orientation) = m.value_of("orient-to") {
+            builder =
+                builder.orient_to(Orientation::from_name(orientation).expect("validated by clap"));
+        }
+        if let Some(aspect) = m.value_of("pad-aspect") {
+            let fill = m.value_of("pad-color").unwrap_or("white");
+            builder = builder.pad_aspect(
+                Aspect::parse(aspect).expect("validated by clap"),
+                Fill::parse(fill).expect("validated by clap"),
+            );
+        }
+        let levels = if m.is_present("auto-level") {
+            Some(Levels::Channel)
+        } else if m.is_present("auto-contrast") {
+            Some(Levels::Contrast)
+        } else {
+            None
+        };
+        if let Some(levels) = levels {
+            let clip = value_t!(m.value_of("clip-percent"), f64).unwrap_or_else(|e| e.exit());
+            builder = builder.levels(levels, clip);
+        }
+
+        // A plan may supply whatever the flags leave out, so its settings are validated per entry,
+        // as are those of requests served. A report resizes nothing, so needs no sizes.
+        let plan = m.value_of("plan").map(PathBuf::from);
+        let options = match &plan {
+            Some(_) => builder.build_defaults(),
+            None if m.is_present("serve") || m.is_present("pixel-format-report") => {
+                builder.build_defaults()
+            }
+            None => builder.build(),
+        }
+        .unwrap_or_else(|e| {
+            clap::Error::with_description(&e, clap::ErrorKind::ValueValidation).exit()
+        });
+
+        if m.is_present("dump-config") {
+            let json = serde_json::to_string_pretty(&options).expect("options serialize");
+            println!("{}", json);
+            std::process::exit(0);
+        }
+
+        let ignore: Vec<_> = m
+            .values_of("ignore")
+            .into_iter()
+            .flatten()
+            .map(|s| Pattern::new(s).expect("validated by clap"))
+            .collect();
+
         Opt {
-            size: value_t!(m.value_of("size"), u32).unwrap_or_else(|e| e.exit()),
+            tar_in: m.is_present("tar-in"),
+            tar_out: m.is_present("tar-out"),
+            upload: m.value_of("upload").map(String::from),
+            output: m.value_of("output").map(String::from),
+            options,
+            plan,
+            serve: m.is_present("serve"),
+            watch: m.value_of("watch").map(PathBuf::from),
+            out_dir: m.value_of("out-dir").map(PathBuf::from),
+            on_collision: m
+                .value_of("on-collision")
+                .and_then(Collision::from_name)
+                .expect("validated by clap"),
             images: m
                 .values_of("image")
                 .into_iter()
                 .flatten()
+                .filter(|x| !ignore.iter().any(|pattern| pattern.matches(x)))
                 .map(|x| x.to_string())
                 .collect(),
-            operation: if m.is_present("up") {
-                Operation::Enlarge
-            } else {
-                Operation::Shrink
+            ignore,
+            recursive: m.is_present("recursive"),
+            follow_symlinks: m.is_present("follow-symlinks"),
+            max_depth: m
+                .value_of("max-depth")
+                .map(|s| s.parse().expect("validated by clap")),
+            verbose: m.is_present("verbose"),
+            pixel_format_report: m.is_present("pixel-format-report"),
+            since: m
+                .value_of("since")
+                .map(|s| date::parse_date(s).expect("validated by clap")),
+            limit: m
+                .value_of("limit")
+                .map(|s| s.parse().expect("validated by clap")),
+            sort_by: m.value_of("sort-by").map(|s| match s {
+                "modified" => SortBy::Modified,
+                _ => SortBy::Name,
+            }),
+            sequence: m
+                .value_of("sequence")
+                .map(|s| Sequence::parse(s).expect("validated by clap")),
+            keep_going_on_panic: m.is_present("keep-going-on-panic"),
+            fail_fast: m.is_present("fail-fast"),
+            read_timeout: m
+                .value_of("read-timeout")
+                .map(|s| Duration::from_secs_f64(s.parse().expect("validated by clap"))),
+            budget: m
+                .value_of("budget")
+                .map(|s| memory::parse_bytes(s).expect("validated by clap")),
+            min_saving: m
+                .value_of("min-saving")
+                .map(|s| parse_percent(s).expect("validated by clap")),
+            verify: m.is_present("verify"),
+            delete_source: m.is_present("delete-source"),
+            exec: m
+                .value_of("exec")
+                .map(|s| Exec::parse(s).expect("validated by clap")),
+            progress: match m.value_of("progress") {
+                Some(_) => Progress::Json,
+                None => Progress::Text,
             },
+            jobs: m
+                .value_of("jobs")
+                .map(|s| threads::parse_jobs(s).expect("validated by clap"))
+                .unwrap_or(1),
+            max_memory: m
+                .value_of("max-memory")
+                .map(|s| memory::parse_bytes(s).expect("validated by clap")),
+            progress_file: m.value_of("progress-file").map(PathBuf::from),
+            manifest: m.value_of("manifest").map(PathBuf::from),
+            checksum: m.value_of("checksum").and_then(Checksum::from_name),
+            no_op_is_error: m.is_present("no-op-is-error"),
+            phash_distance: m.is_present("phash").then(|| {
+                m.value_of("phash-distance")
+                    .map_or(phash::DEFAULT_DISTANCE, |bits| {
+                        bits.parse().expect("validated by clap")
+                    })
+            }),
+            srcset: m.value_of("emit-srcset").map(PathBuf::from),
+            resume: m.value_of("resume").map(PathBuf::from),
+            dedup_outputs: m.is_present("dedup-outputs"),
+            timing: m.is_present("timing"),
+            exif_date_rename: m.is_present("exif-date-rename"),
+        }
+    }
+}
+
+impl Opt {
+    /// Whether outputs land on the filesystem, next to their sources.
+    fn writes_files(&self) -> bool {
+        !self.tar_out && self.upload.is_none() && self.output.as_deref() != Some("-")
+    }
+
+    /// Sends `job`'s outputs to `--out-dir`, if there is one, unless it already has somewhere
+    /// of its own to go.
+    fn place(&self, job: &mut Job) {
+        if let (Some(dir), None) = (&self.out_dir, &job.out) {
+            let target =
+                derived_target(Path::new(&job.source), &job.settings, self.options.naming());
+            job.out = target.file_name().map(|name| dir.join(name));
         }
     }
 }
 
+fn parse_percent(s: &str) -> Result<f64, String> {
+    match s.strip_suffix('%').unwrap_or(s).parse::<f64>() {
+        Ok(n) if (0.0..100.0).contains(&n) => Ok(n),
+        _ => Err(format!("'{}' is not a percentage below 100", s)),
+    }
+}
+
+fn file_name_part(s: String) -> Result<(), String> {
+    match s.contains(['/', '\\']) {
+        true => Err(format!("'{}' can't go in a file name", s)),
+        false => Ok(()),
+    }
+}
+
+fn positive_integer(s: String) -> Result<(), String> {
+    match s.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(()),
+        _ => Err(format!("'{}' is not a positive integer", s)),
+    }
+}
+
 fn main() -> io::Result<()> {
     let opt = Opt::from_args();
+    if opt.serve {
+        return serve::run(&opt.options);
+    }
+
+    let mut jobs = match &opt.plan {
+        Some(path) => plan::load(path, opt.options.settings())?,
+        None if opt.tar_in => {
+            archive::read_jobs(io::stdin().lock(), opt.options.settings(), &opt.ignore)?
+        }
+        None => {
+            let mut jobs = Vec::new();
+            for image in &opt.images {
+                let path = Path::new(image);
+                let walk = if path.exists() {
+                    match opt.recursive && path.is_dir() {
+                        true => Some(walk::walk(path, opt.follow_symlinks, opt.max_depth)?),
+                        false => None,
+                    }
+                } else {
+                    walk::expand(image, opt.follow_symlinks, opt.max_depth)?
+                };
+                let walk = match walk {
+                    Some(walk) => walk,
+                    None => {
+                        jobs.push(Job::new(image, opt.options.settings()));
+                        continue;
+                    }
+                };
+
+                if opt.verbose {
+                    opt.progress.matched(image, &walk.depths());
+                }
+                for link in &walk.skipped_links {
+                    let reason = String::from("symlink not followed");
+                    opt.progress
+                        .finish(&link.to_string_lossy(), &Status::Skipped(reason), None);
+                }
+                jobs.extend(
+                    walk.images
+                        .iter()
+                        .map(|path| path.to_string_lossy())
+                        .filter(|path| !opt.ignore.iter().any(|pattern| pattern.matches(path)))
+                        .map(|path| Job::new(&path, opt.options.settings())),
+                );
+            }
+            jobs
+        }
+    };
 
-    for image in opt.images {
-        match opt.operation {
-            Operation::Enlarge => enlarge(&image, opt.size)?.write()?,
-            Operation::Shrink => shrink(&image, opt.size)?.write()?,
+    if let Some(output) = &opt.output {
+        let single = match jobs.as_slice() {
+            [job] => {
+                job.settings.sizes.len() <= 1
+                    && opt.options.retina().is_empty()
+                    && (output != "-" || !opt.options.compare())
+            }
+            _ => false,
+        };
+        // Outputs sent to stdout or one path would have no way of being told apart.
+        if !single {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--output takes a single image resized to a single size",
+            ));
         }
+        // Sources only go once their outputs are safely on disk, which stdout isn't.
+        if output == "-" && opt.delete_source {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                "--delete-source needs outputs written to files, not stdout",
+            ));
+        }
+        if output != "-" {
+            jobs[0].out = Some(PathBuf::from(output));
+        }
+    }
+
+    let mut carried = Vec::new();
+    if let Some(path) = &opt.resume {
+        let mut resume = Resume::read(path)?;
+        let total = jobs.len();
+        jobs.retain(|job| match resume.take(&job.source) {
+            Some(entries) => {
+                carried.extend(entries);
+                false
+            }
+            None => true,
+        });
+        opt.progress.resumed(total - jobs.len(), total);
+    }
+
+    match opt.sort_by {
+        Some(SortBy::Name) => jobs.sort_by(|a, b| a.source.cmp(&b.source)),
+        // Anything whose time can't be read sorts first, as if it were the oldest.
+        Some(SortBy::Modified) => jobs.sort_by_cached_key(|job| {
+            fs::metadata(&job.source)
+                .and_then(|metadata| metadata.modified())
+                .ok()
+        }),
+        None => (),
+    }
+
+    if let Some(limit) = opt.limit.filter(|&limit| limit < jobs.len()) {
+        opt.progress.limited(limit, jobs.len());
+        jobs.truncate(limit);
+    }
+
+    if opt.pixel_format_report {
+        report::print(&jobs, opt.verbose);
+        return Ok(());
+    }
+
+    if let Some(sequence) = &opt.sequence {
+        for (index, job) in jobs.iter_mut().enumerate() {
+            job.out = Some(sequence.path(index + 1));
+        }
+    }
+
+    if opt.exif_date_rename {
+        rename_by_capture_time(&mut jobs, opt.options.naming(), opt.writes_files());
+    }
+
+    if let Some(dir) = &opt.out_dir {
+        fs::create_dir_all(dir)?;
+        jobs.iter_mut().for_each(|job| opt.place(job));
+    }
+    let collided = settle_collisions(&mut jobs, opt.options.naming(), opt.on_collision)?;
+    if opt.writes_files() && !opt.options.in_place() {
+        if let Some((job, source)) = resize::overwritten_source(&jobs, &opt.options) {
+            let by = match job.source == source {
+                true => String::from("its output"),
+                false => format!("the output of {}", job.source),
+            };
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidInput,
+                format!(
+                    "{} would be overwritten by {}; pass --out-dir or --suffix to write \
+                     elsewhere, or --in-place to overwrite it",
+                    source, by
+                ),
+            ));
+        }
+    }
+
+    let sink = match &opt.upload {
+        _ if opt.tar_out => Sink::Tar(TarWriter::new(io::stdout())),
+        _ if opt.output.as_deref() == Some("-") => Sink::Stdout(io::stdout()),
+        Some(url) => Sink::S3(Box::new(Uploader::from_env(url)?)),
+        None => Sink::Files,
+    };
+
+    let progress_file = match &opt.progress_file {
+        Some(path) => {
+            let sources = jobs.iter().map(|job| job.source.clone()).collect();
+            Some(ProgressFile::create(path, sources)?)
+        }
+        None => None,
+    };
+
+    let batch = Batch {
+        budget: opt.max_memory.map(MemoryBudget::new),
+        manifest: opt.manifest.as_ref().map(|_| {
+            let manifest = Manifest::carrying(carried);
+            match opt.phash_distance {
+                Some(max_distance) => manifest.with_near_duplicates(max_distance),
+                None => manifest,
+            }
+        }),
+        srcsets: opt.srcset.as_ref().map(|_| Srcsets::default()),
+        progress_file,
+        tally: Tally::default(),
+        spent: Mutex::default(),
+        noops: Mutex::default(),
+        running: Mutex::default(),
+        originals: opt.dedup_outputs.then(Mutex::default),
+        timed: opt.timing.then(Mutex::default),
+        sink,
+        opt,
+    };
+
+    for (source, first) in &collided {
+        let reason = format!("output taken by {}", first);
+        batch.finish(source, &Status::Skipped(reason), None, None);
+    }
+
+    // A watch only ends when it fails, and then with nothing left to settle or summarize.
+    if let Some(dir) = &batch.opt.watch {
+        return watch::run(dir, |path| {
+            let mut job = Job::new(&path.to_string_lossy(), batch.opt.options.settings());
+            batch.opt.place(&mut job);
+            if let Some(reason) = watch::refusal(&job, &batch.opt.options) {
+                batch.finish(&job.source, &Status::Skipped(reason), None, None);
+                return Ok(());
+            }
+            commit(&batch, &job.source, run(&batch, &job))
+        });
     }
 
+    let result = if batch.opt.jobs > 1 {
+        run_parallel(&batch, &jobs)
+    } else {
+        jobs.iter()
+            .try_for_each(|job| commit(&batch, &job.source, run(&batch, job)))
+    };
+
+    // Commands still running belong to the run, whether or not it stopped early.
+    let settled = batch.settle_all();
+    let result = result.and(settled);
+
+    // Even a failed run leaves a record of what it got through.
+    if let (Some(manifest), Some(path)) = (&batch.manifest, &batch.opt.manifest) {
+        manifest.write(path)?;
+    }
+    if let (Some(srcsets), Some(path)) = (&batch.srcsets, &batch.opt.srcset) {
+        srcsets.write(path)?;
+    }
+    batch.sink.finish()?;
+
+    if let Some(budget) = batch.opt.budget {
+        let spent = batch.spent.lock().unwrap();
+        batch.opt.progress.spent(spent.bytes, budget);
+    }
+    if let Some(timed) = &batch.timed {
+        let timed = timed.lock().unwrap();
+        batch.opt.progress.timing(&timed.timings, timed.images);
+    }
+
+    // The run's result carries the first failure, so a non-zero tally exits non-zero.
+    batch.opt.progress.summary(&batch.tally);
+    result?;
+
+    if batch.tally.panicked() > 0 {
+        return Err(io::Error::other(format!(
+            "{} image(s) panicked",
+            batch.tally.panicked()
+        )));
+    }
+    if batch.tally.timed_out() > 0 {
+        return Err(io::Error::other(format!(
+            "{} image(s) timed out",
+            batch.tally.timed_out()
+        )));
+    }
+
+    if batch.tally.failed() > 0 {
+        return Err(io::Error::other(format!(
+            "{} image(s) failed",
+            batch.tally.failed()
+        )));
+    }
+
+    let noops = batch.noops.into_inner().unwrap();
+    if batch.opt.no_op_is_error && !noops.is_empty() {
+        return Err(io::Error::other(format!(
+            "already within the requested size: {}",
+            noops.join(", ")
+        )));
+    }
     Ok(())
 }
 
-/// A writable image buffer.
-trait Writable {
-    fn write(&self, path: &str) -> io::Result<()>;
+/// Points each job's output at a name taken from its EXIF capture time, numbering any that
+/// would collide with one another or, when `check_files`, with existing files.
+///
+/// Names are settled before anything runs, so the numbering doesn't depend on `--jobs`. Jobs
+/// without a capture time, or with an output already given, keep their names.
+fn rename_by_capture_time(jobs: &mut [Job], naming: &Naming, check_files: bool) {
+    let mut claimed = HashSet::new();
+
+    for job in jobs.iter_mut().filter(|job| job.out.is_none()) {
+        let stem = match &job.data {
+            Some(data) => capture::read_stem_from(&mut Cursor::new(data)),
+            None => capture::read_stem(&job.source),
+        };
+        let stem = match stem {
+            Some(stem) => stem,
+            None => continue,
+        };
+
+        let source = Path::new(&job.source);
+        let settings = &job.settings;
+        let taken = |target: &PathBuf| {
+            claimed.contains(target)
+                || check_files
+                    && (target.exists()
+                        || settings
+                            .sizes
+                            .iter()
+                            .any(|&size| output::sized_path(target, size).exists()))
+        };
+
+        let out = (1..)
+            .map(|n| match n {
+                1 => output::with_stem(source, &stem),
+                n => output::with_stem(source, &format!("{}_{}", stem, n)),
+            })
+            .map(|renamed| derived_target(&renamed, settings, naming))
+            .find(|target| !taken(target))
+            .expect("some number is free");
+        claimed.insert(out.clone());
+        job.out = Some(out);
+    }
 }
 
-impl<P, Container> Writable for ImageBuffer<P, Container>
-where
-    P: Pixel + 'static,
-    P::Subpixel: 'static,
-    [P::Subpixel]: EncodableLayout,
-    Container: Deref<Target = [P::Subpixel]>,
-{
-    fn write(&self, path: &str) -> io::Result<()> {
-        self.save(path)
-            .map_err(|e| io::Error::new(io::ErrorKind::Other, e))
+/// Makes sure no two jobs write to the same output, nor one to another's source, numbering,
+/// dropping or refusing any that would as `collision` says. Dropped jobs come back with the source whose output they'd
+/// have overwritten.
+fn settle_collisions(
+    jobs: &mut Vec<Job>,
+    naming: &Naming,
+    collision: Collision,
+) -> io::Result<Vec<(String, String)>> {
+    let mut claims = Claims::default();
+    let mut collided = Vec::new();
+    let mut settled = Vec::with_capacity(jobs.len());
+    for job in jobs.iter().filter(|job| job.data.is_none()) {
+        claims.reserve(&job.source);
+    }
+
+    for mut job in jobs.drain(..) {
+        let target = match &job.out {
+            Some(out) => out.clone(),
+            None => derived_target(Path::new(&job.source), &job.settings, naming),
+        };
+        match claims.claim(&target, &job.source, collision) {
+            Ok(claimed) => {
+                if claimed != target {
+                    job.out = Some(claimed);
+                }
+                settled.push(job);
+            }
+            Err(first) if collision == Collision::Skip => collided.push((job.source, first)),
+            Err(first) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::InvalidInput,
+                    format!(
+                        "{} would be written to {}, which {} already takes",
+                        job.source,
+                        target.display(),
+                        first
+                    ),
+                ))
+            }
+        }
     }
+    *jobs = settled;
+    Ok(collided)
 }
 
-enum Resize<'a> {
-    Resize {
-        path: &'a str,
-        buffer: Box<dyn Writable>,
-    },
-    Noop,
+/// Processes jobs on a pool of `--jobs` threads, while a single writer commits their outputs
+/// strictly in job order, so files, logs and manifests come out the same from run to run.
+fn run_parallel(batch: &Batch, jobs: &[Job]) -> io::Result<()> {
+    use rayon::prelude::*;
+    use std::collections::BTreeMap;
+
+    let pool = rayon::ThreadPoolBuilder::new()
+        .num_threads(batch.opt.jobs)
+        .build()
+        .map_err(io::Error::other)?;
+    let (sender, receiver) = mpsc::channel();
+
+    thread::scope(|scope| {
+        let writer = scope.spawn(move || {
+            let mut pending: BTreeMap<usize, _> = BTreeMap::new();
+            let mut next = 0;
+            for (index, result) in receiver {
+                pending.insert(index, result);
+                while let Some(result) = pending.remove(&next) {
+                    commit(batch, &jobs[next].source, result)?;
+                    next += 1;
+                }
+            }
+
+            // Stopping early can leave gaps; whatever did finish is still committed, in order.
+            pending
+                .into_iter()
+                .try_for_each(|(index, result)| commit(batch, &jobs[index].source, result))
+        });
+
+        let produced = pool.install(|| {
+            jobs.par_iter()
+                .enumerate()
+                .try_for_each_with(sender, |sender, (index, job)| {
+                    let result = run(batch, job);
+                    let failed = result.is_err() && batch.opt.fail_fast;
+                    let stopped = || io::Error::other("stopped after an earlier failure");
+
+                    // The writer reports the failure itself; this only stops further jobs.
+                    sender.send((index, result)).map_err(|_| stopped())?;
+                    if failed {
+                        Err(stopped())
+                    } else {
+                        Ok(())
+                    }
+                })
+        });
+
+        let written = writer.join().expect("output writer panicked");
+        written.and(produced)
+    })
+}
+
+/// State shared by every image in a run.
+struct Batch {
+    opt: Opt,
+    budget: Option<MemoryBudget>,
+    manifest: Option<Manifest>,
+    srcsets: Option<Srcsets>,
+    progress_file: Option<ProgressFile>,
+    tally: Tally,
+    /// Bytes of output written against `--budget`.
+    spent: Mutex<Spent>,
+    /// Images that needed no resizing at one size or more, in the order committed.
+    noops: Mutex<Vec<String>>,
+    sink: Sink,
+    /// `--exec` commands not yet waited for, oldest first.
+    running: Mutex<VecDeque<Running>>,
+    /// The first output written with each hash of its bytes, with `--dedup-outputs`.
+    originals: Option<Mutex<HashMap<blake3::Hash, PathBuf>>>,
+    /// Time spent on each stage so far, with `--timing`.
+    timed: Option<Mutex<Timed>>,
+}
+
+#[derive(Default)]
+struct Timed {
+    timings: Timings,
+    /// How many images the time was spent on.
+    images: usize,
 }
 
-impl Resize<'_> {
-    fn write(&self) -> io::Result<()> {
+#[derive(Default)]
+struct Spent {
+    bytes: u64,
+    /// Whether an output has already gone over, after which nothing more is written.
+    full: bool,
+}
+
+/// Where outputs are written.
+enum Sink {
+    Files,
+    /// A tar stream on stdout, with `--tar-out`.
+    Tar(TarWriter<io::Stdout>),
+    /// Object storage, with `--upload`.
+    S3(Box<Uploader>),
+    /// The bytes of a lone output, on stdout, with `--output -`.
+    Stdout(io::Stdout),
+}
+
+impl Sink {
+    fn write(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
         match self {
-            Resize::Resize { path, buffer } => buffer.write(path),
-            Resize::Noop => Ok(()),
+            Sink::Files => fs::write(path, bytes),
+            Sink::Tar(tar) => tar.append(path, bytes),
+            Sink::S3(uploader) => uploader.put(path, bytes),
+            Sink::Stdout(stdout) => stdout.lock().write_all(bytes),
         }
     }
-}
 
-fn enlarge(image: &str, size: u32) -> io::Result<Resize> {
-    let buffer = ImageLoader::open(image)?
-        .decode()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-    let (width, height) = buffer.dimensions();
+    fn finish(self) -> io::Result<()> {
+        match self {
+            Sink::Tar(tar) => tar.finish().map(drop),
+            Sink::Stdout(mut stdout) => stdout.flush(),
+            Sink::Files | Sink::S3(_) => Ok(()),
+        }
+    }
+}
 
-    if let Some((width, height)) = enlarge_dimensions(width, height, size) {
-        Ok(Resize::Resize {
-            path: image,
-            buffer: Box::new(resize(&buffer, width, height, FilterType::Lanczos3)),
-        })
-    } else {
-        Ok(Resize::Noop)
+/// Makes `path` a link to `original`, an output with the same bytes, or a copy of it where
+/// files can't be linked. Outputs are only linked when written as files.
+fn link(path: &Path, original: &Path) -> io::Result<()> {
+    #[cfg(unix)]
+    {
+        // An absolute target stays right wherever the link is.
+        let target = fs::canonicalize(original)?;
+        match fs::remove_file(path) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => std::os::unix::fs::symlink(target, path),
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        fs::copy(original, path).map(drop)
     }
 }
 
-fn shrink(image: &str, size: u32) -> io::Result<Resize> {
-    let buffer = ImageLoader::open(image)?
-        .decode()
-        .map_err(|e| io::Error::new(io::ErrorKind::Other, e))?;
-    let (width, height) = buffer.dimensions();
+/// An output whose `--exec` command is still running, reported once it exits.
+struct Running {
+    image: String,
+    output: Output,
+    child: Child,
+}
 
-    if let Some((width, height)) = shrink_dimensions(width, height, size) {
-        Ok(Resize::Resize {
-            path: image,
-            buffer: Box::new(resize(&buffer, width, height, FilterType::Lanczos3)),
-        })
-    } else {
-        Ok(Resize::Noop)
+impl Batch {
+    /// Queues a running command, waiting on the oldest whenever more than `--jobs` are running.
+    fn queue(&self, running: Running) -> io::Result<()> {
+        let mut queue = self.running.lock().unwrap();
+        queue.push_back(running);
+        while queue.len() > self.opt.jobs {
+            let oldest = queue.pop_front().expect("queue is not empty");
+            self.settle(oldest)?;
+        }
+        Ok(())
     }
-}
 
-fn enlarge_dimensions(width: u32, height: u32, size: u32) -> Option<(u32, u32)> {
-    if width > height && width < size {
-        let nwidth = size;
-        let nheight = (size as f64 / width as f64 * height as f64).floor() as u32;
-        Some((nwidth, nheight))
-    } else if height < size {
-        let nheight = size;
-        let nwidth = (size as f64 / height as f64 * width as f64).floor() as u32;
-        Some((nwidth, nheight))
-    } else {
-        None
+    /// Waits on every queued command, returning the first failure.
+    fn settle_all(&self) -> io::Result<()> {
+        let queue = std::mem::take(&mut *self.running.lock().unwrap());
+        let mut result = Ok(());
+        for running in queue {
+            // Later commands are still waited on, so none is left behind unreported.
+            let settled = self.settle(running);
+            result = result.and(settled);
+        }
+        result
+    }
+
+    /// Waits on a command, then reports its output, which fails along with the command.
+    fn settle(&self, running: Running) -> io::Result<()> {
+        let Running {
+            image,
+            output,
+            mut child,
+        } = running;
+        let status = child.wait();
+
+        match status {
+            Ok(status) if status.success() => {
+                self.finish(
+                    &image,
+                    &output.status,
+                    output.path.as_deref(),
+                    Some(&output),
+                );
+                Ok(())
+            }
+            _ => {
+                let path = output.path.as_deref();
+                self.finish(&image, &Status::Failed, path, None);
+                let program = self.opt.exec.as_ref().map_or("", |exec| exec.program());
+                let reason = match status {
+                    Ok(status) => status.to_string(),
+                    Err(e) => e.to_string(),
+                };
+                Err(io::Error::other(format!(
+                    "{}: {} failed ({})",
+                    image, program, reason
+                )))
+            }
+        }
+    }
+
+    /// The output already written with the same bytes as those bound for `path`, if
+    /// `--dedup-outputs` is on and there is one. Otherwise `path` becomes the one to link to.
+    fn original_of(&self, path: &Path, bytes: &[u8]) -> Option<PathBuf> {
+        let mut originals = self.originals.as_ref()?.lock().unwrap();
+        let original = originals
+            .entry(blake3::hash(bytes))
+            .or_insert_with(|| path.to_path_buf());
+        Some(original.clone()).filter(|original| original != path)
+    }
+
+    /// Counts `output` against the budget, if there is one, unless that would go over it.
+    fn spend(&self, output: &Output) -> bool {
+        let (budget, bytes) = match (self.opt.budget, &output.bytes) {
+            (Some(budget), Some(bytes)) => (budget, bytes.len() as u64),
+            _ => return true,
+        };
+        let mut spent = self.spent.lock().unwrap();
+        spent.full |= spent.bytes + bytes > budget;
+        if !spent.full {
+            spent.bytes += bytes;
+        }
+        !spent.full
+    }
+
+    /// Reports an outcome for `image` at `path`, along with what was written there, if
+    /// anything was: its dimensions, bytes and colors, and the source's measurements.
+    fn finish(&self, image: &str, status: &Status, path: Option<&Path>, written: Option<&Output>) {
+        let dimensions = written.and_then(|output| output.dimensions);
+        let bytes = written.and_then(|output| output.bytes.as_deref());
+        self.opt.progress.finish(image, status, dimensions);
+        self.tally.record(status);
+
+        if let Status::Noop(_) | Status::NearSize(_) = status {
+            let mut noops = self.noops.lock().unwrap();
+            if noops.last().map(String::as_str) != Some(image) {
+                noops.push(image.to_string());
+            }
+        }
+
+        if let Some(manifest) = &self.manifest {
+            manifest.record(Entry {
+                source: image.to_string(),
+                output: path.map(|path| path.to_string_lossy().into_owned()),
+                status: status.name(),
+                width: dimensions.map(|(width, _)| width),
+                height: dimensions.map(|(_, height)| height),
+                checksum: self
+                    .opt
+                    .checksum
+                    .and_then(|checksum| bytes.map(|bytes| checksum.digest(bytes))),
+                colors: written.and_then(|output| output.palette.clone()),
+                stats: written.and_then(|output| output.stats.clone()),
+                phash: written.and_then(|output| output.phash).map(phash::to_hex),
+                alias_of: match status {
+                    Status::Duplicate(original) => Some(original.clone()),
+                    _ => None,
+                },
+            });
+        }
     }
 }
 
-fn shrink_dimensions(width: u32, height: u32, size: u32) -> Option<(u32, u32)> {
-    if width > height && width > size {
-        let nwidth = size;
-        let nheight = (size as f64 / width as f64 * height as f64).floor() as u32;
-        Some((nwidth, nheight))
-    } else if height > size {
-        let nheight = size;
-        let nwidth = (size as f64 / height as f64 * width as f64).floor() as u32;
-        Some((nwidth, nheight))
-    } else {
-        None
+fn run(batch: &Batch, job: &Job) -> io::Result<Vec<Output>> {
+    let image = &job.source;
+    batch.opt.progress.start(image);
+
+    batch
+        .budget
+        .as_ref()
+        .map(|budget| {
+            match &job.data {
+                Some(data) => memory::estimate_data(image, data),
+                None => memory::estimate(image),
+            }
+            .map(|bytes| budget.reserve(bytes))
+        })
+        .transpose()
+        .and_then(|_reservation| {
+            if !batch.opt.keep_going_on_panic {
+                return process(batch, job);
+            }
+
+            // Nothing shared is held while processing, so a panic leaves no state half-changed.
+            panic::catch_unwind(AssertUnwindSafe(|| process(batch, job))).unwrap_or_else(
+                |payload| {
+                    let message = payload
+                        .downcast_ref::<&str>()
+                        .map(|message| message.to_string())
+                        .or_else(|| payload.downcast_ref::<String>().cloned())
+                        .unwrap_or_else(|| String::from("unknown cause"));
+                    Ok(vec![Output::new(None, Status::Panicked(message))])
+                },
+            )
+        })
+}
+
+/// Reads the file at `path`, or returns `None` if that takes longer than `timeout`. A read that
+/// times out is left to finish, or not, on its own thread.
+fn read_with_timeout(path: &Path, timeout: Duration) -> io::Result<Option<Vec<u8>>> {
+    let (sender, receiver) = mpsc::channel();
+    let path = path.to_path_buf();
+    thread::spawn(move || {
+        let _ = sender.send(fs::read(path));
+    });
+    match receiver.recv_timeout(timeout) {
+        Ok(read) => read.map(Some),
+        Err(RecvTimeoutError::Timeout) => Ok(None),
+        Err(RecvTimeoutError::Disconnected) => Err(io::Error::other("reading thread died")),
     }
 }
 
-#[cfg(test)]
-mod tests {
-    use super::{enlarge_dimensions, shrink_dimensions};
+/// Processes a job as the library does, with what only a run of the command adds: `--since`,
+/// `--read-timeout` and the tar stream's passthroughs.
+fn process(batch: &Batch, job: &Job) -> io::Result<Vec<Output>> {
+    let opt = &batch.opt;
+    let image = job.source.as_str();
 
-    #[test]
-    fn shrink_5000_3000() {
-        let actual = shrink_dimensions(5000, 3000, 2000);
-        let expected = Some((2000, 1200));
-        assert_eq!(actual, expected);
+    if let Some(data) = &job.data {
+        if !archive::is_image(Path::new(image)) {
+            return Ok(vec![Output {
+                bytes: Some(data.clone()),
+                ..Output::new(
+                    Some(PathBuf::from(image)),
+                    Status::Skipped(String::from("not an image")),
+                )
+            }]);
+        }
     }
 
-    #[test]
-    fn shrink_3000_5000() {
-        let actual = shrink_dimensions(3000, 5000, 2000);
-        let expected = Some((1200, 2000));
-        assert_eq!(actual, expected);
+    if let Some(since) = opt.since {
+        if fs::metadata(image)?.modified()? < since {
+            let reason = String::from("modified before --since");
+            return Ok(vec![Output::skipped(reason, None)]);
+        }
     }
 
-    #[test]
-    fn shrink_1200_1800() {
-        assert!(shrink_dimensions(1200, 1800, 2000).is_none());
+    // The whole source is read up front, so that a read that never finishes holds up nothing
+    // but a thread of its own.
+    let read;
+    let job = match (opt.read_timeout, &job.data) {
+        (Some(timeout), None) => match read_with_timeout(Path::new(image), timeout)? {
+            Some(data) => {
+                read = Job {
+                    data: Some(data),
+                    ..job.clone()
+                };
+                &read
+            }
+            None => return Ok(vec![Output::new(None, Status::TimedOut(timeout))]),
+        },
+        _ => job,
+    };
+
+    let mut outputs = resize::process(job, &opt.options)?;
+
+    let mut kept = Vec::new();
+    if let Some(min_saving) = opt.min_saving {
+        let original = match &job.data {
+            Some(data) => data.len() as u64,
+            None => fs::metadata(image)?.len(),
+        };
+        for (index, output) in outputs.iter_mut().enumerate() {
+            let saving = match (&output.status, &output.bytes) {
+                (Status::Resized | Status::FellBack { .. }, Some(bytes)) => {
+                    100.0 * (1.0 - bytes.len() as f64 / original as f64)
+                }
+                _ => continue,
+            };
+            if saving < min_saving {
+                let reason = if saving < 0.0 {
+                    format!("kept original, {:.0}% larger", -saving)
+                } else {
+                    format!("kept original, only {:.0}% smaller", saving)
+                };
+                *output = Output::skipped(reason, output.dimensions);
+                kept.push(index);
+            }
+        }
     }
 
-    #[test]
-    fn enlarge_500_300() {
-        let actual = enlarge_dimensions(500, 300, 1000);
-        let expected = Some((1000, 600));
-        assert_eq!(actual, expected);
+    // A tar or a bucket has no original to leave in place, so an image that needed no resizing,
+    // or whose original was kept, goes in as is.
+    if !opt.writes_files() {
+        let noop = outputs
+            .iter()
+            .position(|output| matches!(output.status, Status::Noop(_) | Status::NearSize(_)))
+            .or_else(|| kept.first().copied())
+            .map(|index| &mut outputs[index]);
+        if let Some(noop) = noop {
+            noop.path = Some(PathBuf::from(image));
+            noop.bytes = Some(match &job.data {
+                Some(data) => data.clone(),
+                None => fs::read(image)?,
+            });
+        }
     }
 
-    #[test]
-    fn enlarge_300_500() {
-        let actual = enlarge_dimensions(300, 500, 1000);
-        let expected = Some((600, 1000));
-        assert_eq!(actual, expected);
+    Ok(outputs)
+}
+
+/// Checks that the output just written to `path` decodes, at `dimensions` if they're known.
+/// Outputs that never reached the disk are checked from the `sent` bytes instead.
+fn verify(path: &Path, sent: Option<&[u8]>, dimensions: Option<(u32, u32)>) -> io::Result<()> {
+    use image::GenericImageView;
+
+    let failed = |reason: String| {
+        io::Error::other(format!("{} failed to verify: {}", path.display(), reason))
+    };
+    // The whole image is decoded, since corruption needn't reach the header.
+    let decoded = match sent {
+        Some(bytes) => image::load_from_memory(bytes),
+        None => image::open(path),
+    };
+    let read = decoded.map_err(|e| failed(e.to_string()))?.dimensions();
+    match dimensions {
+        Some(expected) if read != expected => Err(failed(format!(
+            "expected {}x{}, read {}x{}",
+            expected.0, expected.1, read.0, read.1
+        ))),
+        _ => Ok(()),
+    }
+}
+
+/// Whether `a` and `b` are the same file, or might be, if either can't be resolved.
+fn same_file(a: &Path, b: &Path) -> bool {
+    match (fs::canonicalize(a), fs::canonicalize(b)) {
+        (Ok(a), Ok(b)) => a == b,
+        _ => true,
     }
+}
 
-    #[test]
-    fn enlarge_800_1200() {
-        assert!(enlarge_dimensions(800, 1200, 1000).is_none());
+/// Writes and reports the outputs of processing `image`, or its failure. A failure ends the
+/// run only with `--fail-fast`; otherwise it is reported and the run carries on.
+fn commit(batch: &Batch, image: &str, result: io::Result<Vec<Output>>) -> io::Result<()> {
+    let committed = match commit_outputs(batch, image, result) {
+        Err(e) if !batch.opt.fail_fast => {
+            batch.opt.progress.error(&e.to_string());
+            Ok(())
+        }
+        committed => committed,
+    };
+    // A failed image is as done as any other.
+    match &batch.progress_file {
+        Some(progress_file) => committed.and(progress_file.advance()),
+        None => committed,
     }
 }
+
+fn commit_outputs(batch: &Batch, image: &str, result: io::Result<Vec<Output>>) -> io::Result<()> {
+    let written = result.and_then(|outputs| {
+        // Without a manifest to hold them, measurements go to stderr, once for the image.
+        let stats = outputs.first().and_then(|output| output.stats.as_ref());
+        if let (Some(stats), None) = (stats, &batch.manifest) {
+            batch.opt.progress.stats(image, stats);
+        }
+        if outputs.first().is_some_and(|output| output.rotated) {
+            batch.tally.record_rotation();
+        }
+        if outputs.first().is_some_and(|output| output.cropped) {
+            batch.opt.progress.cropped(image);
+        }
+        if let Some(format) = outputs.first().and_then(|output| output.sniffed) {
+            batch
+                .opt
+                .progress
+                .sniffed(image, format.extensions_str()[0]);
+        }
+        let mut timings = outputs
+            .first()
+            .map_or_else(Timings::default, |output| output.timings);
+
+        // The source only goes once every output of it is safely in a file elsewhere, and
+        // never when an output stands in for a source that couldn't be read in full.
+        let mut deletable = batch.opt.delete_source
+            && matches!(batch.sink, Sink::Files)
+            && !outputs.is_empty()
+            && outputs.iter().all(|output| {
+                output.path.is_some()
+                    && output.bytes.is_some()
+                    && !matches!(output.status, Status::Placeholder(_) | Status::Partial)
+            });
+
+        let mut spawned = Vec::new();
+        let mut variants = Vec::new();
+        for mut output in outputs {
+            if output.path.is_some() && !batch.spend(&output) {
+                let skipped = Output::skipped(String::from("over budget"), output.dimensions);
+                batch.finish(image, &skipped.status, None, Some(&skipped));
+                deletable = false;
+                continue;
+            }
+            if let (Some(path), Some(bytes)) = (&output.path, &output.bytes) {
+                let original = batch.original_of(path, bytes);
+                timing::time(&mut timings.write, || match &original {
+                    Some(original) => link(path, original),
+                    None => batch.sink.write(path, bytes),
+                })?;
+                if let Some(original) = original {
+                    output.status = Status::Duplicate(original.to_string_lossy().into_owned());
+                }
+                if batch.opt.verify {
+                    let sent = match batch.sink {
+                        Sink::Files => None,
+                        _ => Some(bytes.as_slice()),
+                    };
+                    verify(path, sent, output.dimensions)?;
+                }
+                if let (Some((width, height)), Some(_)) = (output.dimensions, &batch.srcsets) {
+                    if !matches!(output.status, Status::Compared | Status::Placeholder(_)) {
+                        let path = path.to_string_lossy().into_owned();
+                        variants.push(Variant {
+                            path,
+                            width,
+                            height,
+                        });
+                    }
+                }
+                if deletable {
+                    deletable = !same_file(path, Path::new(image));
+                }
+
+                // Without a manifest to hold them, colors go in a sidecar next to the output, as
+                // long as there is somewhere beside it.
+                let beside = !matches!(batch.sink, Sink::Stdout(_));
+                if let (Some(palette), None, true) = (&output.palette, &batch.manifest, beside) {
+                    let sidecar = PathBuf::from(format!("{}.colors.json", path.display()));
+                    let json = serde_json::to_vec_pretty(palette).map_err(io::Error::other)?;
+                    batch.sink.write(&sidecar, &json)?;
+                }
+
+                // Outputs run through a command are reported once it exits.
+                if let Some(exec) = &batch.opt.exec {
+                    spawned.push((exec.spawn(path)?, output));
+                    continue;
+                }
+            }
+            batch.finish(image, &output.status, output.path.as_deref(), Some(&output));
+        }
+
+        if let Some(srcsets) = &batch.srcsets {
+            srcsets.record(image, variants);
+        }
+        if deletable {
+            fs::remove_file(image)?;
+            batch.opt.progress.deleted(image);
+        }
+        if let Some(timed) = &batch.timed {
+            let mut timed = timed.lock().unwrap();
+            timed.timings += timings;
+            timed.images += 1;
+        }
+        Ok(spawned)
+    });
+
+    let spawned = written.map_err(|e| {
+        batch.finish(image, &Status::Failed, None, None);
+        io::Error::new(e.kind(), format!("{}: {}", image, e))
+    })?;
+
+    spawned.into_iter().try_for_each(|(child, output)| {
+        batch.queue(Running {
+            image: image.to_string(),
+            output,
+            child,
+        })
+    })
+}
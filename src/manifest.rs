@@ -0,0 +1,239 @@
+//! A machine-readable record of every output written in a run.
+
+use std::{
+    collections::HashMap,
+    fs::File,
+    io::{self, BufReader},
+    path::Path,
+    sync::Mutex,
+};
+
+use resize::{phash, stats::Stats};
+use serde::Serialize;
+use serde_json::Value;
+use sha2::{Digest, Sha256};
+
+#[derive(Debug, Serialize)]
+pub struct Entry {
+    pub source: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub output: Option<String>,
+    pub status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub checksum: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub colors: Option<Vec<String>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub stats: Option<Stats>,
+    /// A perceptual hash of the source, in hex.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub phash: Option<String>,
+    /// The output this one is a link to, having the same bytes.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub alias_of: Option<String>,
+}
+
+/// Entries collected from every job, written out as a JSON array once the run ends.
+#[derive(Debug, Default)]
+pub struct Manifest {
+    /// Entries of sources an earlier run finished, written first and as they were.
+    carried: Vec<Value>,
+    entries: Mutex<Vec<Entry>>,
+    /// How far apart perceptual hashes may be for their sources to be listed as
+    /// near-duplicates, if they are to be.
+    near_duplicates: Option<u32>,
+}
+
+/// A manifest with its near-duplicates listed after the entries.
+#[derive(Serialize)]
+struct Grouped<'a> {
+    entries: &'a [Value],
+    near_duplicates: Vec<Vec<String>>,
+}
+
+impl Manifest {
+    /// A manifest that starts with the entries of an earlier run's, so resuming doesn't lose
+    /// them.
+    pub fn carrying(carried: Vec<Value>) -> Manifest {
+        Manifest {
+            carried,
+            entries: Mutex::default(),
+            near_duplicates: None,
+        }
+    }
+
+    /// Ends the manifest with groups of sources whose perceptual hashes are within
+    /// `max_distance` bits of one another.
+    pub fn with_near_duplicates(self, max_distance: u32) -> Manifest {
+        Manifest {
+            near_duplicates: Some(max_distance),
+            ..self
+        }
+    }
+
+    pub fn record(&self, entry: Entry) {
+        self.entries.lock().unwrap().push(entry);
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let entries = self.entries.lock().unwrap();
+        let mut all = self.carried.clone();
+        for entry in entries.iter() {
+            all.push(serde_json::to_value(entry).map_err(io::Error::other)?);
+        }
+        let file = File::create(path)?;
+        match self.near_duplicates {
+            Some(max_distance) => {
+                let hashes: Vec<(String, u64)> = all.iter().filter_map(hashed).collect();
+                let grouped = Grouped {
+                    entries: &all,
+                    near_duplicates: phash::groups(&hashes, max_distance),
+                };
+                serde_json::to_writer_pretty(file, &grouped)
+            }
+            None => serde_json::to_writer_pretty(file, &all),
+        }
+        .map_err(io::Error::other)
+    }
+}
+
+/// An entry's source and perceptual hash, if it has one.
+fn hashed(entry: &Value) -> Option<(String, u64)> {
+    let source = entry.get("source")?.as_str()?;
+    let hash = u64::from_str_radix(entry.get("phash")?.as_str()?, 16).ok()?;
+    Some((source.to_string(), hash))
+}
+
+/// The sources an earlier run's manifest shows as done, with their entries, for `--resume`.
+///
+/// A source is done once it has an output written and nothing of it failed. Sources that were
+/// only skipped are cheap to look at again, so they aren't counted.
+#[derive(Debug, Default)]
+pub struct Resume {
+    done: HashMap<String, Vec<Value>>,
+}
+
+impl Resume {
+    pub fn read(path: &Path) -> io::Result<Resume> {
+        let file = BufReader::new(File::open(path)?);
+        let invalid = |e: String| {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("{} is not a manifest: {}", path.display(), e),
+            )
+        };
+        let manifest: Value = serde_json::from_reader(file).map_err(|e| invalid(e.to_string()))?;
+        // One that lists near-duplicates keeps its entries under a key of their own.
+        let entries = match manifest {
+            Value::Array(entries) => entries,
+            Value::Object(mut manifest) => match manifest.remove("entries") {
+                Some(Value::Array(entries)) => entries,
+                _ => return Err(invalid(String::from("no entries"))),
+            },
+            _ => return Err(invalid(String::from("not an array of entries"))),
+        };
+        Ok(Resume::from_entries(entries))
+    }
+
+    fn from_entries(entries: Vec<Value>) -> Resume {
+        fn status(entry: &Value) -> &str {
+            entry.get("status").and_then(Value::as_str).unwrap_or("")
+        }
+
+        let mut sources: HashMap<String, Vec<Value>> = HashMap::new();
+        for entry in entries {
+            if let Some(source) = entry.get("source").and_then(Value::as_str) {
+                sources.entry(source.to_string()).or_default().push(entry);
+            }
+        }
+
+        sources.retain(|_, entries| {
+            let failed = entries.iter().any(|entry| {
+                matches!(
+                    status(entry),
+                    "failed" | "panicked" | "timed-out" | "placeholder" | ""
+                )
+            });
+            !failed && entries.iter().any(|entry| status(entry) != "skipped")
+        });
+        Resume { done: sources }
+    }
+
+    /// The recorded entries of `source`, if it was done, which it no longer needs to be.
+    pub fn take(&mut self, source: &str) -> Option<Vec<Value>> {
+        self.done.remove(source)
+    }
+}
+
+#[derive(Copy, Clone, Debug)]
+pub enum Checksum {
+    Sha256,
+    Blake3,
+}
+
+impl Checksum {
+    pub const NAMES: &'static [&'static str] = &["sha256", "blake3"];
+
+    pub fn from_name(name: &str) -> Option<Checksum> {
+        match name {
+            "sha256" => Some(Checksum::Sha256),
+            "blake3" => Some(Checksum::Blake3),
+            _ => None,
+        }
+    }
+
+    /// A self-describing digest of `bytes`, e.g. `sha256:9f86d0...`.
+    pub fn digest(self, bytes: &[u8]) -> String {
+        match self {
+            Checksum::Sha256 => format!("sha256:{}", hex(&Sha256::digest(bytes))),
+            Checksum::Blake3 => format!("blake3:{}", blake3::hash(bytes).to_hex()),
+        }
+    }
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Checksum, Resume};
+    use serde_json::json;
+
+    #[test]
+    fn sha256() {
+        assert_eq!(
+            Checksum::Sha256.digest(b"test"),
+            "sha256:9f86d081884c7d659a2feaa0c55ad015a3bf4f1b2b0b822cd15d6c15b0f00a08"
+        );
+    }
+
+    #[test]
+    fn blake3() {
+        assert_eq!(
+            Checksum::Blake3.digest(b"test"),
+            "blake3:4878ca0425c739fa427f7eda20fe845f6b2e46ba5fe2a14df5b1e32f50603215"
+        );
+    }
+
+    #[test]
+    fn resumes_past_finished_sources() {
+        let mut resume = Resume::from_entries(vec![
+            json!({"source": "a.jpg", "output": "a-64.jpg", "status": "resized"}),
+            json!({"source": "a.jpg", "status": "skipped"}),
+            json!({"source": "b.jpg", "output": "b-64.jpg", "status": "resized"}),
+            json!({"source": "b.jpg", "status": "failed"}),
+            json!({"source": "c.jpg", "status": "skipped"}),
+            json!({"source": "d.jpg", "status": "panicked"}),
+        ]);
+        assert_eq!(resume.take("a.jpg").map(|entries| entries.len()), Some(2));
+        assert_eq!(resume.take("a.jpg"), None);
+        for source in &["b.jpg", "c.jpg", "d.jpg", "e.jpg"] {
+            assert_eq!(resume.take(source), None);
+        }
+    }
+}
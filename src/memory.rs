@@ -0,0 +1,143 @@
+//! Throttling concurrent decodes by their estimated memory use.
+
+use std::{
+    fs,
+    io::{self, BufRead, Cursor, Seek},
+    path::Path,
+    sync::{Condvar, Mutex},
+};
+
+use image::{
+    codecs::{jpeg::JpegDecoder, png::PngDecoder},
+    io::Reader as ImageLoader,
+    ImageDecoder, ImageFormat,
+};
+
+use resize::raw;
+
+/// A pool of bytes shared by concurrent jobs.
+///
+/// Each job reserves its estimated decode size before starting and waits while the pool is
+/// exhausted, so many small images can run side by side while a few huge ones serialize.
+pub struct MemoryBudget {
+    limit: u64,
+    used: Mutex<u64>,
+    released: Condvar,
+}
+
+pub struct Reservation<'a> {
+    budget: &'a MemoryBudget,
+    bytes: u64,
+}
+
+impl MemoryBudget {
+    pub fn new(limit: u64) -> MemoryBudget {
+        MemoryBudget {
+            limit,
+            used: Mutex::new(0),
+            released: Condvar::new(),
+        }
+    }
+
+    /// Blocks until `bytes` can be reserved.
+    ///
+    /// Reservations larger than the whole budget are clamped to it, so that an oversized image
+    /// waits for the pool to empty and then runs alone rather than waiting forever.
+    pub fn reserve(&self, bytes: u64) -> Reservation<'_> {
+        let bytes = bytes.min(self.limit);
+        let mut used = self.used.lock().unwrap();
+        while *used + bytes > self.limit {
+            used = self.released.wait(used).unwrap();
+        }
+        *used += bytes;
+
+        Reservation {
+            budget: self,
+            bytes,
+        }
+    }
+}
+
+impl Drop for Reservation<'_> {
+    fn drop(&mut self) {
+        *self.budget.used.lock().unwrap() -= self.bytes;
+        self.budget.released.notify_all();
+    }
+}
+
+/// Estimates the size of an image's decoded buffer from its header alone.
+pub fn estimate(path: &str) -> io::Result<u64> {
+    // Raw headers can't be read cheaply; sensor data decodes to roughly three times its size
+    // on disk, counting the demosaiced copy.
+    if raw::is_raw(Path::new(path)) {
+        return Ok(fs::metadata(path)?.len() * 3);
+    }
+    estimate_from(ImageLoader::open(path)?)
+}
+
+/// Like `estimate`, for an image already in memory under the name `path`.
+pub fn estimate_data(path: &str, data: &[u8]) -> io::Result<u64> {
+    if raw::is_raw(Path::new(path)) {
+        return Ok(data.len() as u64 * 3);
+    }
+    estimate_from(ImageLoader::new(Cursor::new(data)).with_guessed_format()?)
+}
+
+fn estimate_from<R: BufRead + Seek>(loader: ImageLoader<R>) -> io::Result<u64> {
+    let header = match loader.format() {
+        Some(ImageFormat::Jpeg) => JpegDecoder::new(loader.into_inner()).map(|d| d.total_bytes()),
+        Some(ImageFormat::Png) => PngDecoder::new(loader.into_inner()).map(|d| d.total_bytes()),
+        _ => loader
+            .into_dimensions()
+            .map(|(width, height)| width as u64 * height as u64 * 4),
+    };
+
+    header.map_err(io::Error::other)
+}
+
+/// Parses a byte count with an optional binary suffix, e.g. `4G` or `512M`.
+pub fn parse_bytes(s: &str) -> Result<u64, String> {
+    let invalid = || format!("'{}' is not a byte count (e.g. 512M or 4G)", s);
+
+    let upper = s.trim().to_ascii_uppercase();
+    let digits = upper.trim_end_matches('B');
+    let (digits, multiplier) = match digits.chars().last() {
+        Some('K') => (&digits[..digits.len() - 1], 1 << 10),
+        Some('M') => (&digits[..digits.len() - 1], 1 << 20),
+        Some('G') => (&digits[..digits.len() - 1], 1 << 30),
+        Some('T') => (&digits[..digits.len() - 1], 1 << 40),
+        _ => (digits, 1),
+    };
+
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(multiplier))
+        .filter(|&n| n > 0)
+        .ok_or_else(invalid)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse_bytes, MemoryBudget};
+
+    #[test]
+    fn byte_counts() {
+        assert_eq!(parse_bytes("1024"), Ok(1024));
+        assert_eq!(parse_bytes("512M"), Ok(512 << 20));
+        assert_eq!(parse_bytes("4G"), Ok(4 << 30));
+        assert_eq!(parse_bytes("4gb"), Ok(4 << 30));
+        assert!(parse_bytes("0").is_err());
+        assert!(parse_bytes("G").is_err());
+        assert!(parse_bytes("lots").is_err());
+    }
+
+    #[test]
+    fn oversized_reservation_is_clamped() {
+        let budget = MemoryBudget::new(100);
+        let reservation = budget.reserve(1_000);
+        assert_eq!(reservation.bytes, 100);
+        drop(reservation);
+        assert_eq!(*budget.used.lock().unwrap(), 0);
+    }
+}
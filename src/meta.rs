@@ -0,0 +1,117 @@
+//! What an image's header says about it, read without decoding its pixels.
+
+use std::{
+    fs::File,
+    io::{self, BufRead, BufReader, Seek},
+    path::Path,
+};
+
+use image::{
+    codecs::{
+        bmp::BmpDecoder, gif::GifDecoder, ico::IcoDecoder, jpeg::JpegDecoder, png::PngDecoder,
+        tga::TgaDecoder, tiff::TiffDecoder, webp::WebPDecoder,
+    },
+    io::Reader as ImageLoader,
+    ColorType, GenericImageView, ImageDecoder, ImageFormat,
+};
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub struct ImageMeta {
+    pub format: ImageFormat,
+    pub dimensions: (u32, u32),
+    pub color: ColorType,
+}
+
+impl ImageMeta {
+    /// Bits per channel.
+    pub fn depth(&self) -> u16 {
+        self.color.bits_per_pixel() / u16::from(self.color.channel_count())
+    }
+}
+
+/// Reads the header of the image at `path`, reading no further into the file than it takes.
+pub fn read_metadata(path: &Path) -> io::Result<ImageMeta> {
+    read_metadata_from(BufReader::new(File::open(path)?), path)
+}
+
+/// Like `read_metadata`, from `reader`, with `path` to judge the format by when its contents
+/// don't say. Formats without a decoder that can stop at the header are decoded in full.
+pub fn read_metadata_from<R: BufRead + Seek>(reader: R, path: &Path) -> io::Result<ImageMeta> {
+    let loader = ImageLoader::new(reader).with_guessed_format()?;
+    let format = match loader.format() {
+        Some(format) => format,
+        None => ImageFormat::from_path(path).map_err(io::Error::other)?,
+    };
+    let reader = loader.into_inner();
+    let header = match format {
+        ImageFormat::Png => PngDecoder::new(reader).map(header),
+        ImageFormat::Jpeg => JpegDecoder::new(reader).map(header),
+        ImageFormat::Gif => GifDecoder::new(reader).map(header),
+        ImageFormat::Bmp => BmpDecoder::new(reader).map(header),
+        ImageFormat::Tiff => TiffDecoder::new(reader).map(header),
+        ImageFormat::WebP => WebPDecoder::new(reader).map(header),
+        ImageFormat::Ico => IcoDecoder::new(reader).map(header),
+        ImageFormat::Tga => TgaDecoder::new(reader).map(header),
+        format => {
+            let mut loader = ImageLoader::new(reader);
+            loader.set_format(format);
+            loader
+                .decode()
+                .map(|image| (image.dimensions(), image.color()))
+        }
+    };
+    let (dimensions, color) = header.map_err(io::Error::other)?;
+    Ok(ImageMeta {
+        format,
+        dimensions,
+        color,
+    })
+}
+
+fn header<'a>(decoder: impl ImageDecoder<'a>) -> ((u32, u32), ColorType) {
+    (decoder.dimensions(), decoder.color_type())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{read_metadata_from, ImageMeta};
+    use image::{
+        pnm::{PNMSubtype, SampleEncoding},
+        ColorType, DynamicImage, ImageFormat, ImageOutputFormat,
+    };
+    use std::{io::Cursor, path::Path};
+
+    #[test]
+    fn reads_headers_without_decoding() {
+        let mut bytes = Vec::new();
+        DynamicImage::new_luma_a16(40, 30)
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        // Cut off where the pixels start, the header is all there is to read.
+        let pixels = bytes.windows(4).position(|tag| tag == b"IDAT").unwrap();
+        bytes.truncate(pixels + 4);
+        let meta = read_metadata_from(Cursor::new(&bytes), Path::new("a.png")).unwrap();
+        assert_eq!(
+            meta,
+            ImageMeta {
+                format: ImageFormat::Png,
+                dimensions: (40, 30),
+                color: ColorType::La16,
+            }
+        );
+        assert_eq!(meta.depth(), 16);
+    }
+
+    #[test]
+    fn decodes_formats_without_header_decoders() {
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(5, 4)
+            .write_to(
+                &mut bytes,
+                ImageOutputFormat::Pnm(PNMSubtype::Pixmap(SampleEncoding::Binary)),
+            )
+            .unwrap();
+        let meta = read_metadata_from(Cursor::new(&bytes), Path::new("a.ppm")).unwrap();
+        assert_eq!((meta.dimensions, meta.color), ((5, 4), ColorType::Rgb8));
+    }
+}
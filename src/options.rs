@@ -0,0 +1,733 @@
+use image::{imageops::FilterType, ImageFormat};
+use serde::Serialize;
+
+use crate::{
+    alpha::AlphaThreshold,
+    canvas::{Canvas, Fill},
+    color::ColorSpace,
+    crop::{Aspect, AspectAction, Grid, MaxAspect},
+    depth::BitDepth,
+    effect::Effect,
+    encode::{JpegOptions, PngOptions, Subsampling},
+    filter::{self, Resampling},
+    levels::Levels,
+    orient::Orientation,
+    output::Naming,
+    quality::Quality,
+    quantize::Quantize,
+    settings::{self, Operation, Settings},
+};
+
+/// Everything that decides how an image is processed, validated as a whole.
+#[derive(Clone, Debug, Serialize)]
+pub struct ResizeOptions {
+    pub(crate) settings: Settings,
+    pub(crate) resampling: Resampling,
+    pub(crate) tile_size: u32,
+    pub(crate) levels: Option<(Levels, f64)>,
+    pub(crate) trim_transparent: bool,
+    pub(crate) color_space: Option<ColorSpace>,
+    pub(crate) crop_aspect: Option<Aspect>,
+    pub(crate) max_aspect: Option<MaxAspect>,
+    pub(crate) pad_aspect: Option<(Aspect, Fill)>,
+    pub(crate) orient_to: Option<Orientation>,
+    pub(crate) canvas: Option<Canvas>,
+    pub(crate) split: Option<Grid>,
+    pub(crate) aspect_tolerance: f64,
+    pub(crate) naming: Naming,
+    pub(crate) allow_partial: bool,
+    pub(crate) retry_sniffed: bool,
+    pub(crate) error_image: bool,
+    pub(crate) palette: Option<usize>,
+    pub(crate) stats: bool,
+    pub(crate) phash: bool,
+    pub(crate) compare: bool,
+    pub(crate) in_place: bool,
+    pub(crate) denoise: Option<f32>,
+    pub(crate) effects: Vec<Effect>,
+    pub(crate) max_short_edge: Option<u32>,
+    pub(crate) skip_ratio: Option<f64>,
+    pub(crate) widths: bool,
+    pub(crate) dpi: Option<u16>,
+    pub(crate) retina: Vec<u32>,
+    pub(crate) jpeg: JpegOptions,
+    pub(crate) png: PngOptions,
+    pub(crate) regenerate_thumbnail: bool,
+    pub(crate) keep_orientation: bool,
+    #[serde(serialize_with = "settings::format_name")]
+    pub(crate) fallback_format: Option<ImageFormat>,
+    pub(crate) dct_scaling: bool,
+    pub(crate) input_cap: Option<InputCap>,
+}
+
+/// The longest edge a source may have, going by its header, before it is decoded.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub(crate) struct InputCap {
+    pub(crate) max_dimension: u32,
+    /// Fails larger sources rather than decoding them shrunk to fit.
+    pub(crate) reject: bool,
+}
+
+impl ResizeOptions {
+    pub fn builder() -> ResizeOptionsBuilder {
+        ResizeOptionsBuilder {
+            options: ResizeOptions {
+                settings: Settings {
+                    operation: Operation::Shrink,
+                    sizes: Vec::new(),
+                    format: None,
+                    quality: Quality::default(),
+                },
+                resampling: Resampling {
+                    shrink_filter: None,
+                    enlarge_filter: None,
+                    area_threshold: filter::DEFAULT_AREA_THRESHOLD,
+                    auto_sharpen: false,
+                    parallel: false,
+                    round_to: None,
+                    even_dimensions: false,
+                    bit_depth: None,
+                    alpha_threshold: None,
+                    color_key: None,
+                },
+                tile_size: 256,
+                levels: None,
+                trim_transparent: false,
+                color_space: None,
+                crop_aspect: None,
+                max_aspect: None,
+                pad_aspect: None,
+                orient_to: None,
+                canvas: None,
+                split: None,
+                aspect_tolerance: 0.0,
+                naming: Naming::default(),
+                allow_partial: false,
+                retry_sniffed: false,
+                error_image: false,
+                palette: None,
+                stats: false,
+                phash: false,
+                compare: false,
+                in_place: false,
+                denoise: None,
+                effects: Vec::new(),
+                max_short_edge: None,
+                skip_ratio: None,
+                widths: false,
+                dpi: None,
+                retina: Vec::new(),
+                jpeg: JpegOptions::default(),
+                png: PngOptions::default(),
+                regenerate_thumbnail: false,
+                keep_orientation: false,
+                fallback_format: None,
+                dct_scaling: false,
+                input_cap: None,
+            },
+        }
+    }
+
+    /// The settings images get unless a job gives its own.
+    pub fn settings(&self) -> &Settings {
+        &self.settings
+    }
+
+    pub fn naming(&self) -> &Naming {
+        &self.naming
+    }
+
+    pub fn retina(&self) -> &[u32] {
+        &self.retina
+    }
+
+    pub fn compare(&self) -> bool {
+        self.compare
+    }
+
+    pub fn in_place(&self) -> bool {
+        self.in_place
+    }
+}
+
+/// Builds `ResizeOptions`, starting from a shrink with Lanczos3 and no sizes.
+#[derive(Clone, Debug)]
+pub struct ResizeOptionsBuilder {
+    options: ResizeOptions,
+}
+
+impl ResizeOptionsBuilder {
+    pub fn operation(mut self, operation: Operation) -> Self {
+        self.options.settings.operation = operation;
+        self
+    }
+
+    /// Adds a target size for the longest edge; each size makes its own output.
+    pub fn size(mut self, size: u32) -> Self {
+        if !self.options.settings.sizes.contains(&size) {
+            self.options.settings.sizes.push(size);
+        }
+        self
+    }
+
+    pub fn sizes(self, sizes: impl IntoIterator<Item = u32>) -> Self {
+        sizes
+            .into_iter()
+            .fold(self, |builder, size| builder.size(size))
+    }
+
+    pub fn format(mut self, format: ImageFormat) -> Self {
+        self.options.settings.format = Some(format);
+        self
+    }
+
+    pub fn quality(mut self, quality: Quality) -> Self {
+        self.options.settings.quality = quality;
+        self
+    }
+
+    /// Sets the filter for both shrinking and enlarging.
+    pub fn filter(self, filter: FilterType) -> Self {
+        self.shrink_filter(filter).enlarge_filter(filter)
+    }
+
+    pub fn shrink_filter(mut self, filter: FilterType) -> Self {
+        self.options.resampling.shrink_filter = Some(filter);
+        self
+    }
+
+    pub fn enlarge_filter(mut self, filter: FilterType) -> Self {
+        self.options.resampling.enlarge_filter = Some(filter);
+        self
+    }
+
+    /// Shrinks past this scale factor use the triangle filter, unless a filter is set.
+    pub fn area_threshold(mut self, threshold: f64) -> Self {
+        self.options.resampling.area_threshold = threshold;
+        self
+    }
+
+    pub fn auto_sharpen(mut self, auto_sharpen: bool) -> Self {
+        self.options.resampling.auto_sharpen = auto_sharpen;
+        self
+    }
+
+    /// Resizes each image on many threads.
+    pub fn parallel(mut self, parallel: bool) -> Self {
+        self.options.resampling.parallel = parallel;
+        self
+    }
+
+    /// Rounds resized dimensions down to a multiple of `multiple`, but never below it.
+    pub fn round_to(mut self, multiple: u32) -> Self {
+        self.options.resampling.round_to = Some(multiple);
+        self
+    }
+
+    /// Rounds resized dimensions down to even numbers, but never below 2.
+    pub fn even_dimensions(mut self, even: bool) -> Self {
+        self.options.resampling.even_dimensions = even;
+        self
+    }
+
+    /// Writes outputs with `depth` bits per channel, where their format allows it.
+    pub fn bit_depth(mut self, depth: BitDepth) -> Self {
+        self.options.resampling.bit_depth = Some(depth);
+        self
+    }
+
+    /// Makes resized pixels with alpha below the threshold fully transparent, and, given a high
+    /// threshold, those above it fully opaque.
+    pub fn alpha_threshold(mut self, threshold: AlphaThreshold) -> Self {
+        self.options.resampling.alpha_threshold = Some(threshold);
+        self
+    }
+
+    /// Replaces resized pixels less than half opaque with `key`, and drops alpha, for images
+    /// that have it.
+    pub fn color_key(mut self, key: [u8; 3]) -> Self {
+        self.options.resampling.color_key = Some(key);
+        self
+    }
+
+    /// How JPEG outputs sample chroma; other formats ignore it.
+    pub fn jpeg_subsampling(mut self, subsampling: Subsampling) -> Self {
+        self.options.jpeg.subsampling = subsampling;
+        self
+    }
+
+    /// Encodes JPEG outputs at `quality`, from 1 to 100, in place of whatever `quality` gives
+    /// other formats.
+    pub fn jpeg_quality(mut self, quality: u8) -> Self {
+        self.options.jpeg.quality = Some(quality);
+        self
+    }
+
+    /// Writes a restart marker into JPEG outputs after every `rows` rows of MCUs, from 1 up, so
+    /// one damaged in transit can be decoded past the damage. Files grow slightly.
+    pub fn jpeg_restart_rows(mut self, rows: u16) -> Self {
+        self.options.jpeg.restart_rows = Some(rows);
+        self
+    }
+
+    /// Optimizes the Huffman tables of JPEG outputs, for smaller files at the same quality.
+    pub fn jpeg_optimize(mut self, optimize: bool) -> Self {
+        self.options.jpeg.optimize = optimize;
+        self
+    }
+
+    /// Writes PNG outputs as indexed images of at most `colors` colors, from 2 to 256,
+    /// dithering them if asked. Translucency is kept in the palette.
+    pub fn quantize_colors(mut self, colors: u16, dither: bool) -> Self {
+        self.options.png.quantize = Some(Quantize { colors, dither });
+        self
+    }
+
+    /// Embeds a thumbnail of each JPEG output in its EXIF data, for viewers that show those
+    /// rather than decode the whole image.
+    pub fn regenerate_thumbnail(mut self, regenerate: bool) -> Self {
+        self.options.regenerate_thumbnail = regenerate;
+        self
+    }
+
+    /// Tags JPEG and PNG outputs with the source's EXIF orientation, and nothing else of its
+    /// metadata, so they display as it did without carrying its camera or GPS data.
+    pub fn keep_orientation(mut self, keep: bool) -> Self {
+        self.options.keep_orientation = keep;
+        self
+    }
+
+    /// Writes outputs in `format` instead, with its extension, when their own format fails to
+    /// encode.
+    pub fn fallback_format(mut self, format: ImageFormat) -> Self {
+        self.options.fallback_format = Some(format);
+        self
+    }
+
+    pub fn tile_size(mut self, tile_size: u32) -> Self {
+        self.options.tile_size = tile_size;
+        self
+    }
+
+    /// Blurs by `sigma` before resizing, smoothing sensor noise at some cost in detail.
+    pub fn denoise(mut self, sigma: f32) -> Self {
+        self.options.denoise = Some(sigma);
+        self
+    }
+
+    /// Applies `effects` to each resized image, in order.
+    pub fn effects(mut self, effects: Vec<Effect>) -> Self {
+        self.options.effects = effects;
+        self
+    }
+
+    /// Stretches levels before resizing, ignoring `clip_percent` of pixels at each end.
+    pub fn levels(mut self, levels: Levels, clip_percent: f64) -> Self {
+        self.options.levels = Some((levels, clip_percent));
+        self
+    }
+
+    /// When shrinking, also caps the shortest edge, shrinking further wherever it binds first.
+    pub fn max_short_edge(mut self, edge: u32) -> Self {
+        self.options.max_short_edge = Some(edge);
+        self
+    }
+
+    /// When shrinking, leaves images whose longest edge is within `ratio` of the size alone,
+    /// e.g. 1.1 for anything up to 10% larger, as resizing them would only cost a re-encode.
+    pub fn skip_ratio(mut self, ratio: f64) -> Self {
+        self.options.skip_ratio = Some(ratio);
+        self
+    }
+
+    /// Treats sizes as output widths rather than longest edges, the height following from
+    /// each image's aspect.
+    pub fn sizes_are_widths(mut self, widths: bool) -> Self {
+        self.options.widths = widths;
+        self
+    }
+
+    /// Records this print resolution in JPEG and PNG outputs.
+    pub fn dpi(mut self, dpi: u16) -> Self {
+        self.options.dpi = Some(dpi);
+        self
+    }
+
+    /// Alongside each size, also writes `@2x`-style variants at these multiples of it.
+    pub fn retina(mut self, factors: impl IntoIterator<Item = u32>) -> Self {
+        for factor in factors {
+            if !self.options.retina.contains(&factor) {
+                self.options.retina.push(factor);
+            }
+        }
+        self
+    }
+
+    /// Crops away fully transparent rows and columns at the edges before anything else,
+    /// skipping images with nothing visible.
+    pub fn trim_transparent(mut self, trim: bool) -> Self {
+        self.options.trim_transparent = trim;
+        self
+    }
+
+    /// Converts images to `space` by their embedded profiles, or from sRGB if they have none,
+    /// before anything else, tagging outputs with the profile of their space.
+    pub fn color_space(mut self, space: ColorSpace) -> Self {
+        self.options.color_space = Some(space);
+        self
+    }
+
+    /// The canvas images are placed on by `Operation::Canvas`.
+    pub fn canvas(mut self, canvas: Canvas) -> Self {
+        self.options.canvas = Some(canvas);
+        self
+    }
+
+    /// Cuts each output into the pieces of `grid`, written in its place.
+    pub fn split(mut self, grid: Grid) -> Self {
+        self.options.split = Some(grid);
+        self
+    }
+
+    pub fn crop_aspect(mut self, aspect: Aspect) -> Self {
+        self.options.crop_aspect = Some(aspect);
+        self
+    }
+
+    /// Crops or skips images whose long edge is more than `ratio` times their short one, after
+    /// any turn to `orient_to` and before anything else.
+    pub fn max_aspect(mut self, ratio: f64, action: AspectAction) -> Self {
+        self.options.max_aspect = Some(MaxAspect { ratio, action });
+        self
+    }
+
+    /// Pads each image out to `aspect` with `fill`, a color or the image's own corners',
+    /// keeping it centered, before it's resized.
+    pub fn pad_aspect(mut self, aspect: Aspect, fill: impl Into<Fill>) -> Self {
+        self.options.pad_aspect = Some((aspect, fill.into()));
+        self
+    }
+
+    /// Turns each image a quarter clockwise, before anything else is done to it, if it faces
+    /// the other way from `orientation`. Square images are left as they are.
+    pub fn orient_to(mut self, orientation: Orientation) -> Self {
+        self.options.orient_to = Some(orientation);
+        self
+    }
+
+    /// Leaves images within `tolerance` of the crop aspect, as a fraction of it, uncropped.
+    pub fn aspect_tolerance(mut self, tolerance: f64) -> Self {
+        self.options.aspect_tolerance = tolerance;
+        self
+    }
+
+    /// Lowercases output extensions, spelling JPEG's `jpg`, or `jpeg` if `long_jpeg`.
+    pub fn normalize_extension(mut self, normalize: bool, long_jpeg: bool) -> Self {
+        self.options.naming.normalize_extension = normalize;
+        self.options.naming.long_jpeg_extension = long_jpeg;
+        self
+    }
+
+    /// Gives outputs this extension, without a leading dot, whatever their format.
+    pub fn out_extension(mut self, extension: &str) -> Self {
+        self.options.naming.extension = Some(extension.to_string());
+        self
+    }
+
+    /// Puts `prefix` before every output's stem, e.g. `sm_` for `sm_photo.jpg`.
+    pub fn out_prefix(mut self, prefix: &str) -> Self {
+        self.options.naming.prefix = prefix.to_string();
+        self
+    }
+
+    /// Puts `suffix` after every output's stem, before its extension, e.g. `_v2` for
+    /// `photo_v2.jpg`.
+    pub fn out_suffix(mut self, suffix: &str) -> Self {
+        self.options.naming.suffix = suffix.to_string();
+        self
+    }
+
+    /// Reduces output stems to lowercase ASCII letters, digits, `-` and `_`.
+    pub fn slugify(mut self, slugify: bool) -> Self {
+        self.options.naming.slugify = slugify;
+        self
+    }
+
+    /// Lets JPEGs being shrunk decode at 1/2, 1/4 or 1/8 scale, which is far faster than
+    /// decoding them whole.
+    pub fn downsample_before_decode(mut self, downsample: bool) -> Self {
+        self.options.dct_scaling = downsample;
+        self
+    }
+
+    /// Shrinks sources whose header gives an edge longer than `max_dimension` to fit within it
+    /// as they're decoded, or fails them if `reject`. JPEGs are decoded at a reduced scale, so
+    /// never take the memory their full size would; other formats are shrunk once decoded.
+    pub fn max_input_dimension(mut self, max_dimension: u32, reject: bool) -> Self {
+        self.options.input_cap = Some(InputCap {
+            max_dimension,
+            reject,
+        });
+        self
+    }
+
+    /// Salvages what can be decoded from truncated images instead of failing them.
+    pub fn allow_partial(mut self, allow_partial: bool) -> Self {
+        self.options.allow_partial = allow_partial;
+        self
+    }
+
+    /// Retries images that fail to decode as the format their extension names in the format
+    /// their contents name instead, noting it on their outputs.
+    pub fn retry_sniffed(mut self, retry_sniffed: bool) -> Self {
+        self.options.retry_sniffed = retry_sniffed;
+        self
+    }
+
+    /// Writes a placeholder at each target size for images that fail to load, rather than
+    /// failing them.
+    pub fn error_image(mut self, error_image: bool) -> Self {
+        self.options.error_image = error_image;
+        self
+    }
+
+    /// Records up to `count` dominant colors of each resized output.
+    pub fn palette(mut self, count: usize) -> Self {
+        self.options.palette = Some(count);
+        self
+    }
+
+    /// Measures each source's brightness, histograms and sharpness, on every output of it.
+    pub fn stats(mut self, stats: bool) -> Self {
+        self.options.stats = stats;
+        self
+    }
+
+    /// Hashes each source perceptually, on every output of it, to find near-duplicates by.
+    pub fn phash(mut self, phash: bool) -> Self {
+        self.options.phash = phash;
+        self
+    }
+
+    /// Lets outputs be written over sources, which writing files refuses otherwise.
+    pub fn in_place(mut self, in_place: bool) -> Self {
+        self.options.in_place = in_place;
+        self
+    }
+
+    /// Also writes each resized output beside the source, scaled to match, as `_compare`.
+    pub fn compare(mut self, compare: bool) -> Self {
+        self.options.compare = compare;
+        self
+    }
+
+    pub fn build(self) -> Result<ResizeOptions, String> {
+        self.options.settings.validate()?;
+        self.build_defaults()
+    }
+
+    /// Builds options whose settings may be incomplete, to be completed per job, as a plan
+    /// does. Everything else is still validated.
+    pub fn build_defaults(self) -> Result<ResizeOptions, String> {
+        let options = self.options;
+
+        if let (Operation::Canvas, None) = (options.settings.operation, options.canvas) {
+            return Err(String::from("no canvas size given"));
+        }
+        if let (Operation::OrientOnly, Some(_)) = (options.settings.operation, options.orient_to) {
+            return Err(String::from(
+                "images can't be turned while only being oriented",
+            ));
+        }
+        if options.keep_orientation
+            && (options.orient_to.is_some()
+                || options.regenerate_thumbnail
+                || matches!(options.settings.operation, Operation::OrientOnly))
+        {
+            return Err(String::from(
+                "orientation can't be kept for turned or reoriented images, or beside a thumbnail",
+            ));
+        }
+        if options.input_cap.is_some_and(|cap| cap.max_dimension == 0) {
+            return Err(String::from("input dimension cap must be positive"));
+        }
+        if options.tile_size == 0 {
+            return Err(String::from("tile size must be positive"));
+        }
+        if !(0.0..=1.0).contains(&options.resampling.area_threshold) {
+            return Err(String::from("area threshold must be between 0 and 1"));
+        }
+        if options
+            .max_aspect
+            .is_some_and(|max| !(max.ratio >= 1.0 && max.ratio.is_finite()))
+        {
+            return Err(String::from(
+                "the maximum aspect must be a ratio of at least 1",
+            ));
+        }
+        if !(0.0..=1.0).contains(&options.aspect_tolerance) {
+            return Err(String::from("aspect tolerance must be between 0 and 1"));
+        }
+        if options.resampling.round_to == Some(0) {
+            return Err(String::from("dimensions must round to a positive multiple"));
+        }
+        if options
+            .jpeg
+            .quality
+            .is_some_and(|quality| !(1..=100).contains(&quality))
+        {
+            return Err(String::from("JPEG quality must be between 1 and 100"));
+        }
+        if options.jpeg.restart_rows == Some(0) {
+            return Err(String::from(
+                "JPEG restart markers must be at least one MCU row apart",
+            ));
+        }
+        if let Some(quantize) = options.png.quantize {
+            if !(2..=256).contains(&quantize.colors) {
+                return Err(String::from("PNGs must be quantized to 2 to 256 colors"));
+            }
+        }
+        if options.resampling.bit_depth == Some(BitDepth::Sixteen)
+            && (!options.effects.is_empty()
+                || options.resampling.alpha_threshold.is_some()
+                || options.resampling.color_key.is_some())
+        {
+            return Err(String::from(
+                "effects, alpha thresholds and color keys can't be applied to 16-bit outputs",
+            ));
+        }
+        if options.retina.iter().any(|&factor| factor < 2) {
+            return Err(String::from("retina factors must be at least 2"));
+        }
+        for affix in &[&options.naming.prefix, &options.naming.suffix] {
+            if affix.contains(['/', '\\']) {
+                return Err(format!("'{}' can't go in a file name", affix));
+            }
+        }
+        if let Some(extension) = &options.naming.extension {
+            if extension.is_empty() || extension.contains(['.', '/', '\\']) {
+                return Err(format!("'{}' is not a file extension", extension));
+            }
+        }
+        if options.dpi == Some(0) {
+            return Err(String::from("dpi must be positive"));
+        }
+        if options.max_short_edge == Some(0) {
+            return Err(String::from("short edge cap must be positive"));
+        }
+        if options
+            .skip_ratio
+            .is_some_and(|ratio| !(ratio.is_finite() && ratio >= 1.0))
+        {
+            return Err(String::from("skip ratio must be at least 1"));
+        }
+        if options.palette == Some(0) {
+            return Err(String::from("palette must have at least one color"));
+        }
+        if options
+            .denoise
+            .is_some_and(|sigma| !(sigma.is_finite() && sigma > 0.0))
+        {
+            return Err(String::from("denoise sigma must be positive"));
+        }
+        if let Some((_, clip)) = options.levels {
+            if !(0.0..50.0).contains(&clip) {
+                return Err(String::from("clip percentage must be below 50"));
+            }
+        }
+
+        Ok(options)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ResizeOptions;
+    use crate::{
+        depth::BitDepth, effect::Effect, levels::Levels, quality::Quality, settings::Operation,
+    };
+    use image::imageops::FilterType;
+
+    #[test]
+    fn builds_valid_options() {
+        let options = ResizeOptions::builder()
+            .operation(Operation::Enlarge)
+            .sizes(vec![512, 256, 512])
+            .filter(FilterType::CatmullRom)
+            .quality(Quality::parse("90").unwrap())
+            .build()
+            .unwrap();
+
+        assert_eq!(options.settings().sizes, vec![512, 256]);
+        assert_eq!(
+            options.resampling.shrink_filter,
+            Some(FilterType::CatmullRom)
+        );
+        assert_eq!(
+            options.resampling.enlarge_filter,
+            Some(FilterType::CatmullRom)
+        );
+    }
+
+    #[test]
+    fn rejects_invalid_options() {
+        assert!(ResizeOptions::builder().build().is_err());
+        assert!(ResizeOptions::builder()
+            .size(0)
+            .tile_size(0)
+            .build()
+            .is_err());
+        assert!(ResizeOptions::builder()
+            .size(100)
+            .levels(Levels::Channel, 60.0)
+            .build()
+            .is_err());
+        assert!(ResizeOptions::builder()
+            .size(100)
+            .denoise(0.0)
+            .build()
+            .is_err());
+        assert!(ResizeOptions::builder()
+            .size(100)
+            .quality(Quality::parse("256=70").unwrap())
+            .build()
+            .is_err());
+        assert!(ResizeOptions::builder()
+            .size(100)
+            .quantize_colors(1, false)
+            .build()
+            .is_err());
+        assert!(ResizeOptions::builder()
+            .size(100)
+            .bit_depth(BitDepth::Sixteen)
+            .effects(vec![Effect::Grayscale])
+            .build()
+            .is_err());
+    }
+
+    #[test]
+    fn defaults_may_leave_out_sizes() {
+        assert!(ResizeOptions::builder().build_defaults().is_ok());
+        assert!(ResizeOptions::builder()
+            .area_threshold(2.0)
+            .build_defaults()
+            .is_err());
+    }
+
+    #[test]
+    fn serializes_by_name() {
+        let options = ResizeOptions::builder()
+            .operation(Operation::Fit)
+            .size(640)
+            .format(image::ImageFormat::Jpeg)
+            .shrink_filter(FilterType::CatmullRom)
+            .build()
+            .unwrap();
+        let json = serde_json::to_value(&options).unwrap();
+        assert_eq!(json["settings"]["operation"], "fit");
+        assert_eq!(json["settings"]["format"], "jpeg");
+        assert_eq!(json["resampling"]["shrink_filter"], "catmull-rom");
+        assert_eq!(json["jpeg"]["subsampling"], "420");
+    }
+}
@@ -0,0 +1,170 @@
+//! EXIF orientation, and turning images to face a given way.
+
+use std::{
+    fs::File,
+    io::{BufRead, BufReader, Seek},
+};
+
+use exif::{In, Reader, Tag};
+use image::{DynamicImage, GenericImageView, ImageFormat};
+use serde::Serialize;
+
+use crate::dpi;
+
+/// Which way round an image is, as `--orient-to` turns them.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Orientation {
+    Landscape,
+    Portrait,
+}
+
+impl Orientation {
+    pub const NAMES: &'static [&'static str] = &["landscape", "portrait"];
+
+    pub fn from_name(name: &str) -> Option<Orientation> {
+        match name {
+            "landscape" => Some(Orientation::Landscape),
+            "portrait" => Some(Orientation::Portrait),
+            _ => None,
+        }
+    }
+}
+
+/// Reads the EXIF orientation of the image at `path`, if it has one.
+///
+/// Images without EXIF data, or with unreadable EXIF data, are treated as unoriented.
+pub fn read_orientation(path: &str) -> Option<u32> {
+    read_orientation_from(&mut BufReader::new(File::open(path).ok()?))
+}
+
+/// Reads the EXIF orientation of an encoded image.
+pub fn read_orientation_from<R: BufRead + Seek>(reader: &mut R) -> Option<u32> {
+    let exif = Reader::new().read_from_container(reader).ok()?;
+    exif.get_field(Tag::Orientation, In::PRIMARY)?
+        .value
+        .get_uint(0)
+        .filter(|orientation| (1..=8).contains(orientation))
+}
+
+/// Transforms `image` so that it displays upright without its EXIF orientation.
+pub fn apply(image: &DynamicImage, orientation: u32) -> DynamicImage {
+    match orientation {
+        2 => image.fliph(),
+        3 => image.rotate180(),
+        4 => image.flipv(),
+        5 => image.rotate90().fliph(),
+        6 => image.rotate90(),
+        7 => image.rotate270().fliph(),
+        8 => image.rotate270(),
+        _ => image.clone(),
+    }
+}
+
+/// Tags an encoded JPEG or PNG with EXIF data holding nothing but `orientation`, so that it
+/// displays as its source did. Other formats are left as they are.
+pub fn tag(bytes: &mut Vec<u8>, format: ImageFormat, orientation: u32) {
+    // A big-endian TIFF header, then one IFD of one entry: the orientation, a short, which
+    // is left-justified in the value.
+    let mut exif = b"MM\0\x2A".to_vec();
+    exif.extend_from_slice(&8u32.to_be_bytes());
+    exif.extend_from_slice(&1u16.to_be_bytes());
+    exif.extend_from_slice(&[0x01, 0x12, 0, 3, 0, 0, 0, 1]);
+    exif.extend_from_slice(&(orientation as u16).to_be_bytes());
+    exif.extend_from_slice(&[0, 0]);
+    exif.extend_from_slice(&0u32.to_be_bytes());
+
+    match format {
+        ImageFormat::Jpeg => tag_jpeg(bytes, &exif),
+        ImageFormat::Png => tag_png(bytes, &exif),
+        _ => (),
+    }
+}
+
+/// Adds the EXIF data in an `APP1` segment, after the JFIF header if there is one.
+fn tag_jpeg(bytes: &mut Vec<u8>, exif: &[u8]) {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return;
+    }
+    let at = match bytes.get(2..6) {
+        Some(&[0xFF, 0xE0, high, low]) => 4 + usize::from(u16::from_be_bytes([high, low])),
+        _ => 2,
+    };
+
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&(2 + 6 + exif.len() as u16).to_be_bytes());
+    segment.extend_from_slice(b"Exif\0\0");
+    segment.extend_from_slice(exif);
+    bytes.splice(at..at, segment);
+}
+
+/// Adds an `eXIf` chunk after the header.
+fn tag_png(bytes: &mut Vec<u8>, exif: &[u8]) {
+    dpi::insert_png_chunk(bytes, b"eXIf", exif);
+}
+
+/// `image` turned a quarter clockwise, if it faces the other way from `orientation`. Squares
+/// face both ways, so are never turned.
+pub fn turn_to(image: &DynamicImage, orientation: Orientation) -> Option<DynamicImage> {
+    let (width, height) = image.dimensions();
+    let opposed = match orientation {
+        Orientation::Landscape => height > width,
+        Orientation::Portrait => width > height,
+    };
+    opposed.then(|| image.rotate90())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{apply, turn_to, Orientation};
+    use image::{DynamicImage, GenericImageView, Luma, RgbaImage};
+
+    /// A 3x2 image whose pixels are numbered in reading order.
+    fn numbered() -> DynamicImage {
+        let image = image::ImageBuffer::from_fn(3, 2, |x, y| Luma([(y * 3 + x) as u8]));
+        DynamicImage::ImageLuma8(image)
+    }
+
+    fn rows(image: &DynamicImage) -> Vec<Vec<u8>> {
+        let luma = image.to_luma();
+        (0..luma.height())
+            .map(|y| (0..luma.width()).map(|x| luma.get_pixel(x, y)[0]).collect())
+            .collect()
+    }
+
+    #[test]
+    fn rotate_90() {
+        let oriented = apply(&numbered(), 6);
+        assert_eq!(oriented.dimensions(), (2, 3));
+        assert_eq!(rows(&oriented), vec![vec![3, 0], vec![4, 1], vec![5, 2]]);
+    }
+
+    #[test]
+    fn transpose() {
+        let oriented = apply(&numbered(), 5);
+        assert_eq!(rows(&oriented), vec![vec![0, 3], vec![1, 4], vec![2, 5]]);
+    }
+
+    #[test]
+    fn transverse() {
+        let oriented = apply(&numbered(), 7);
+        assert_eq!(rows(&oriented), vec![vec![5, 2], vec![4, 1], vec![3, 0]]);
+    }
+
+    #[test]
+    fn upright_is_unchanged() {
+        let image = DynamicImage::ImageRgba8(RgbaImage::new(4, 2));
+        assert_eq!(apply(&image, 1).dimensions(), (4, 2));
+    }
+
+    #[test]
+    fn turns_only_images_facing_the_other_way() {
+        let turned = turn_to(&numbered(), Orientation::Portrait).unwrap();
+        assert_eq!(rows(&turned), vec![vec![3, 0], vec![4, 1], vec![5, 2]]);
+        assert!(turn_to(&numbered(), Orientation::Landscape).is_none());
+
+        let square = DynamicImage::ImageRgba8(RgbaImage::new(2, 2));
+        assert!(turn_to(&square, Orientation::Landscape).is_none());
+        assert!(turn_to(&square, Orientation::Portrait).is_none());
+    }
+}
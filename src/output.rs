@@ -0,0 +1,490 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+};
+
+use image::ImageFormat;
+use serde::Serialize;
+
+/// Output formats selectable by name.
+pub const FORMATS: &[&str] = &["jpeg", "jpg", "png", "gif", "bmp", "ico", "tiff"];
+
+pub fn parse_format(s: &str) -> Option<ImageFormat> {
+    match s {
+        "jpeg" | "jpg" => Some(ImageFormat::Jpeg),
+        "png" => Some(ImageFormat::Png),
+        "gif" => Some(ImageFormat::Gif),
+        "bmp" => Some(ImageFormat::Bmp),
+        "ico" => Some(ImageFormat::Ico),
+        "tiff" => Some(ImageFormat::Tiff),
+        _ => None,
+    }
+}
+
+/// The path an output in the given format is written to, e.g. `photo.png` becomes
+/// `photo.jpg`.
+pub fn with_format(path: &Path, format: ImageFormat) -> PathBuf {
+    path.with_extension(format.extensions_str()[0])
+}
+
+/// The path with its file stem replaced, e.g. `photos/cat.jpg` becomes `photos/dog.jpg`.
+pub fn with_stem(path: &Path, stem: &str) -> PathBuf {
+    match path.extension() {
+        Some(extension) => path.with_file_name(format!("{}.{}", stem, extension.to_string_lossy())),
+        None => path.with_file_name(stem),
+    }
+}
+
+/// How output file names are tidied, beyond what their format implies. Only names change,
+/// never what is encoded.
+#[derive(Clone, Debug, Default, Serialize)]
+pub struct Naming {
+    /// Lowercases extensions and spells every JPEG's the same way.
+    pub normalize_extension: bool,
+    /// Spells normalized JPEG extensions `jpeg` rather than `jpg`.
+    pub long_jpeg_extension: bool,
+    /// Reduces stems to lowercase ASCII letters, digits, `-` and `_`.
+    pub slugify: bool,
+    /// Put before every stem, after any slugifying.
+    pub prefix: String,
+    /// Put after every stem, before the extension.
+    pub suffix: String,
+    /// Replaces every extension with this one, whatever the format.
+    pub extension: Option<String>,
+}
+
+impl Naming {
+    pub fn apply(&self, path: &Path) -> PathBuf {
+        let mut path = path.to_path_buf();
+
+        if self.slugify {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            path = with_stem(&path, &slug(&stem));
+        }
+
+        if !self.prefix.is_empty() || !self.suffix.is_empty() {
+            let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+            path = with_stem(&path, &format!("{}{}{}", self.prefix, stem, self.suffix));
+        }
+
+        if self.normalize_extension {
+            if let Some(extension) = path.extension() {
+                let extension = extension.to_string_lossy().to_lowercase();
+                let extension = match extension.as_str() {
+                    "jpg" | "jpeg" if self.long_jpeg_extension => "jpeg",
+                    "jpg" | "jpeg" => "jpg",
+                    other => other,
+                };
+                path = path.with_extension(extension);
+            }
+        }
+
+        if let Some(extension) = &self.extension {
+            path = path.with_extension(extension);
+        }
+
+        path
+    }
+
+    /// The same naming, but keeping the extension each format implies.
+    pub fn without_extension(&self) -> Naming {
+        Naming {
+            extension: None,
+            ..self.clone()
+        }
+    }
+}
+
+/// A URL-safe version of a file stem, e.g. `Café au Lait (2)` becomes `caf-au-lait-2`.
+fn slug(stem: &str) -> String {
+    let mut slug = String::with_capacity(stem.len());
+    for c in stem.chars().filter(char::is_ascii) {
+        if c.is_ascii_alphanumeric() || c == '_' {
+            slug.push(c.to_ascii_lowercase());
+        } else if !slug.is_empty() && !slug.ends_with('-') {
+            slug.push('-');
+        }
+    }
+
+    let slug = slug.trim_end_matches('-');
+    if slug.is_empty() {
+        String::from("image")
+    } else {
+        slug.to_string()
+    }
+}
+
+/// The path an output of the given size is written to when several sizes are requested, e.g.
+/// `photo.jpg` becomes `photo-256.jpg`.
+pub fn sized_path(path: &Path, size: u32) -> PathBuf {
+    suffixed(path, &format!("-{}", size))
+}
+
+/// The path of a high-density variant, e.g. `photo@2x.jpg`.
+pub fn retina_path(path: &Path, factor: u32) -> PathBuf {
+    suffixed(path, &format!("@{}x", factor))
+}
+
+/// The path of one piece of a split image, e.g. `photo_r0c1.jpg`.
+pub fn piece_path(path: &Path, row: u32, column: u32) -> PathBuf {
+    suffixed(path, &format!("_r{}c{}", row, column))
+}
+
+/// The path of a before-and-after comparison, e.g. `photo_compare.jpg`.
+pub fn compare_path(path: &Path) -> PathBuf {
+    suffixed(path, "_compare")
+}
+
+/// What to do when a source would be written where another's output already goes, for
+/// `--on-collision`.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Collision {
+    /// Refuses to run at all.
+    Error,
+    /// Leaves the later source out.
+    Skip,
+    /// Numbers the later outputs from 1, e.g. `photo_1.jpg`.
+    Number,
+}
+
+impl Collision {
+    pub const NAMES: &'static [&'static str] = &["error", "skip", "number"];
+
+    pub fn from_name(name: &str) -> Option<Collision> {
+        match name {
+            "error" => Some(Collision::Error),
+            "skip" => Some(Collision::Skip),
+            "number" => Some(Collision::Number),
+            _ => None,
+        }
+    }
+}
+
+/// The output paths sources have claimed so far, and which source claimed each, along with
+/// the sources themselves, which no other source's output may take.
+#[derive(Debug, Default)]
+pub struct Claims {
+    claimed: HashMap<PathBuf, String>,
+    sources: HashMap<PathBuf, String>,
+}
+
+impl Claims {
+    /// Keeps other sources' outputs off `source`. Every source should be reserved before any
+    /// output is claimed.
+    pub fn reserve(&mut self, source: &str) {
+        self.sources
+            .insert(PathBuf::from(source), source.to_string());
+    }
+
+    /// Claims `target` for `source`. If another source has it, or it is another source, a
+    /// numbered path that's free is claimed instead under [`Collision::Number`]; otherwise the
+    /// source that has it is the error.
+    pub fn claim(
+        &mut self,
+        target: &Path,
+        source: &str,
+        collision: Collision,
+    ) -> Result<PathBuf, String> {
+        let taken = |claims: &Claims, path: &Path| {
+            let input = claims.sources.get(path).filter(|&input| input != source);
+            claims.claimed.get(path).or(input).cloned()
+        };
+        let target = match taken(self, target) {
+            None => target.to_path_buf(),
+            Some(first) if collision != Collision::Number => return Err(first),
+            Some(_) => {
+                let stem = target.file_stem().unwrap_or_default().to_string_lossy();
+                (1..)
+                    .map(|n| with_stem(target, &format!("{}_{}", stem, n)))
+                    .find(|numbered| taken(self, numbered).is_none())
+                    .expect("some number is free")
+            }
+        };
+        self.claimed.insert(target.clone(), source.to_string());
+        Ok(target)
+    }
+}
+
+/// Numbered output paths, from a printf-style pattern such as `frame_%04d.jpg`.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Sequence {
+    prefix: String,
+    width: usize,
+    zero_pad: bool,
+    suffix: String,
+}
+
+impl Sequence {
+    /// Parses a pattern with exactly one `%d`, optionally with a width such as `%4d` or `%04d`;
+    /// `%%` is a literal `%`.
+    pub fn parse(pattern: &str) -> Result<Sequence, String> {
+        let mut parts = (String::new(), String::new());
+        let mut placeholder = None;
+        let mut chars = pattern.chars().peekable();
+
+        while let Some(c) = chars.next() {
+            let part = match placeholder {
+                Some(_) => &mut parts.1,
+                None => &mut parts.0,
+            };
+            if c != '%' {
+                part.push(c);
+                continue;
+            }
+            if chars.peek() == Some(&'%') {
+                chars.next();
+                part.push('%');
+                continue;
+            }
+
+            let mut spec = String::new();
+            while let Some(&digit) = chars.peek().filter(|c| c.is_ascii_digit()) {
+                spec.push(digit);
+                chars.next();
+            }
+            if chars.next() != Some('d') {
+                return Err(format!("'{}' has a placeholder other than %d", pattern));
+            }
+            if placeholder.is_some() {
+                return Err(format!("'{}' has more than one placeholder", pattern));
+            }
+            placeholder = Some(spec);
+        }
+
+        let spec = placeholder.ok_or_else(|| format!("'{}' has no %d placeholder", pattern))?;
+        let (prefix, suffix) = parts;
+        Ok(Sequence {
+            prefix,
+            width: spec.parse().unwrap_or(0),
+            zero_pad: spec.starts_with('0'),
+            suffix,
+        })
+    }
+
+    pub fn path(&self, index: usize) -> PathBuf {
+        let number = if self.zero_pad {
+            format!("{:0width$}", index, width = self.width)
+        } else {
+            format!("{:width$}", index, width = self.width)
+        };
+        PathBuf::from(format!("{}{}{}", self.prefix, number, self.suffix))
+    }
+}
+
+fn suffixed(path: &Path, suffix: &str) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    let name = match path.extension() {
+        Some(extension) => format!("{}{}.{}", stem, suffix, extension.to_string_lossy()),
+        None => format!("{}{}", stem, suffix),
+    };
+    path.with_file_name(name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        compare_path, piece_path, retina_path, sized_path, with_format, with_stem, Claims,
+        Collision, Naming, Sequence,
+    };
+    use image::ImageFormat;
+    use std::path::{Path, PathBuf};
+
+    #[test]
+    fn formatted() {
+        let actual = with_format(Path::new("photos/cat.png"), ImageFormat::Jpeg);
+        assert_eq!(actual, PathBuf::from("photos/cat.jpg"));
+    }
+
+    #[test]
+    fn restemmed() {
+        let actual = with_stem(Path::new("photos/cat.final.png"), "2024-06-01_142305");
+        assert_eq!(actual, PathBuf::from("photos/2024-06-01_142305.png"));
+    }
+
+    #[test]
+    fn affixed_stems() {
+        let naming = Naming {
+            prefix: String::from("sm_"),
+            suffix: String::from("_v2"),
+            ..Naming::default()
+        };
+        assert_eq!(
+            naming.apply(Path::new("photo.jpg")),
+            PathBuf::from("sm_photo_v2.jpg")
+        );
+        assert_eq!(
+            naming.apply(Path::new("a/b.c/photo.final.jpg")),
+            PathBuf::from("a/b.c/sm_photo.final_v2.jpg")
+        );
+        assert_eq!(
+            naming.apply(Path::new("a/README")),
+            PathBuf::from("a/sm_README_v2")
+        );
+
+        let naming = Naming {
+            slugify: true,
+            suffix: String::from("@Home"),
+            ..Naming::default()
+        };
+        assert_eq!(
+            naming.apply(Path::new("My Photo.png")),
+            PathBuf::from("my-photo@Home.png")
+        );
+    }
+
+    #[test]
+    fn normalized_extensions() {
+        let naming = Naming {
+            normalize_extension: true,
+            ..Naming::default()
+        };
+        assert_eq!(
+            naming.apply(Path::new("a/Cat.JPEG")),
+            PathBuf::from("a/Cat.jpg")
+        );
+        assert_eq!(naming.apply(Path::new("Cat.PNG")), PathBuf::from("Cat.png"));
+
+        let naming = Naming {
+            long_jpeg_extension: true,
+            ..naming
+        };
+        assert_eq!(
+            naming.apply(Path::new("Cat.JPG")),
+            PathBuf::from("Cat.jpeg")
+        );
+    }
+
+    #[test]
+    fn extension_replaced() {
+        let naming = Naming {
+            normalize_extension: true,
+            extension: Some(String::from("img")),
+            ..Naming::default()
+        };
+        assert_eq!(
+            naming.apply(Path::new("a/Cat.JPG")),
+            PathBuf::from("a/Cat.img")
+        );
+        assert_eq!(
+            naming.without_extension().apply(Path::new("a/Cat.JPG")),
+            PathBuf::from("a/Cat.jpg")
+        );
+    }
+
+    #[test]
+    fn slugified() {
+        let naming = Naming {
+            slugify: true,
+            ..Naming::default()
+        };
+        assert_eq!(
+            naming.apply(Path::new("a b/Café au Lait (2).JPG")),
+            PathBuf::from("a b/caf-au-lait-2.JPG")
+        );
+        assert_eq!(
+            naming.apply(Path::new("写真.png")),
+            PathBuf::from("image.png")
+        );
+    }
+
+    #[test]
+    fn sized() {
+        let actual = sized_path(Path::new("photos/cat.jpg"), 256);
+        assert_eq!(actual, PathBuf::from("photos/cat-256.jpg"));
+    }
+
+    #[test]
+    fn sized_multiple_dots() {
+        let actual = sized_path(Path::new("cat.final.png"), 1024);
+        assert_eq!(actual, PathBuf::from("cat.final-1024.png"));
+    }
+
+    #[test]
+    fn sized_no_extension() {
+        let actual = sized_path(Path::new("cat"), 64);
+        assert_eq!(actual, PathBuf::from("cat-64"));
+    }
+
+    #[test]
+    fn sequence() {
+        let sequence = Sequence::parse("out/frame_%04d.jpg").unwrap();
+        assert_eq!(sequence.path(7), PathBuf::from("out/frame_0007.jpg"));
+        assert_eq!(sequence.path(12345), PathBuf::from("out/frame_12345.jpg"));
+        assert_eq!(
+            Sequence::parse("100%%-%d.png").unwrap().path(3),
+            PathBuf::from("100%-3.png")
+        );
+        assert_eq!(
+            Sequence::parse("%3d.png").unwrap().path(3),
+            PathBuf::from("  3.png")
+        );
+    }
+
+    #[test]
+    fn sequence_needs_one_number() {
+        assert!(Sequence::parse("frame.jpg").is_err());
+        assert!(Sequence::parse("%d_%d.jpg").is_err());
+        assert!(Sequence::parse("%s.jpg").is_err());
+        assert!(Sequence::parse("frame_%04").is_err());
+    }
+
+    #[test]
+    fn compare() {
+        let actual = compare_path(&sized_path(Path::new("a/cat.jpg"), 64));
+        assert_eq!(actual, PathBuf::from("a/cat-64_compare.jpg"));
+    }
+
+    #[test]
+    fn retina() {
+        let actual = retina_path(&sized_path(Path::new("a/cat.jpg"), 64), 2);
+        assert_eq!(actual, PathBuf::from("a/cat-64@2x.jpg"));
+    }
+
+    #[test]
+    fn piece() {
+        let actual = piece_path(&sized_path(Path::new("a/cat.jpg"), 64), 1, 2);
+        assert_eq!(actual, PathBuf::from("a/cat-64_r1c2.jpg"));
+    }
+
+    #[test]
+    fn collisions() {
+        let target = Path::new("out/img.jpg");
+        let mut claims = Claims::default();
+        assert_eq!(
+            claims.claim(target, "a/img.jpg", Collision::Error),
+            Ok(PathBuf::from("out/img.jpg"))
+        );
+        assert_eq!(
+            claims.claim(target, "b/img.jpg", Collision::Skip),
+            Err(String::from("a/img.jpg"))
+        );
+        assert_eq!(
+            claims.claim(target, "b/img.jpg", Collision::Number),
+            Ok(PathBuf::from("out/img_1.jpg"))
+        );
+        assert_eq!(
+            claims.claim(target, "c/img.jpg", Collision::Number),
+            Ok(PathBuf::from("out/img_2.jpg"))
+        );
+    }
+
+    #[test]
+    fn outputs_keep_off_other_sources() {
+        let mut claims = Claims::default();
+        claims.reserve("a.png");
+        claims.reserve("a.jpg");
+        claims.reserve("b.jpg");
+        assert_eq!(
+            claims.claim(Path::new("a.jpg"), "a.png", Collision::Number),
+            Ok(PathBuf::from("a_1.jpg"))
+        );
+        assert_eq!(
+            claims.claim(Path::new("a.jpg"), "a.jpg", Collision::Error),
+            Ok(PathBuf::from("a.jpg"))
+        );
+        assert_eq!(
+            claims.claim(Path::new("b.jpg"), "b.png", Collision::Error),
+            Err(String::from("b.jpg"))
+        );
+    }
+}
@@ -0,0 +1,129 @@
+//! Dominant colors, found by median cut.
+
+use image::ColorType;
+
+/// At most this many pixels are sampled; more barely change the result.
+const MAX_SAMPLES: usize = 1 << 16;
+
+/// Up to `count` dominant colors of an 8-bit image's raw `bytes`, most common first, as hex
+/// such as `#1a2b3c`. Fully transparent pixels don't count. Other sample types have no palette.
+pub fn dominant(bytes: &[u8], color: ColorType, count: usize) -> Vec<String> {
+    let channels = match color {
+        ColorType::L8 | ColorType::La8 | ColorType::Rgb8 | ColorType::Rgba8 => {
+            color.channel_count() as usize
+        }
+        _ => return Vec::new(),
+    };
+
+    let pixels = bytes.len() / channels;
+    let step = (pixels / MAX_SAMPLES).max(1);
+    let samples: Vec<[u8; 3]> = bytes
+        .chunks_exact(channels)
+        .step_by(step)
+        .filter(|pixel| !color.has_alpha() || pixel[channels - 1] != 0)
+        .map(|pixel| match channels {
+            1 | 2 => [pixel[0]; 3],
+            _ => [pixel[0], pixel[1], pixel[2]],
+        })
+        .collect();
+
+    let mut boxes = vec![samples];
+    while boxes.len() < count {
+        // Split the box spread widest along any channel, at its median.
+        let widest = boxes
+            .iter()
+            .enumerate()
+            .map(|(index, pixels)| (index, widest_channel(pixels)))
+            .max_by_key(|&(_, (_, range))| range);
+        let (index, channel) = match widest {
+            Some((index, (channel, range))) if range > 0 => (index, channel),
+            _ => break,
+        };
+
+        let mut pixels = boxes.swap_remove(index);
+        pixels.sort_unstable_by_key(|pixel| pixel[channel]);
+        // Pixels sharing the median value stay together, so no color is split between boxes.
+        let median = pixels[pixels.len() / 2][channel];
+        let below = pixels.partition_point(|pixel| pixel[channel] < median);
+        let split = match below {
+            0 => pixels.partition_point(|pixel| pixel[channel] <= median),
+            below => below,
+        };
+        let upper = pixels.split_off(split);
+        boxes.push(pixels);
+        boxes.push(upper);
+    }
+
+    boxes.retain(|pixels| !pixels.is_empty());
+    boxes.sort_by_key(|pixels| std::cmp::Reverse(pixels.len()));
+    boxes
+        .iter()
+        .map(|pixels| {
+            let [r, g, b] = mean(pixels);
+            format!("#{:02x}{:02x}{:02x}", r, g, b)
+        })
+        .collect()
+}
+
+/// The channel whose values spread widest, and how widely.
+fn widest_channel(pixels: &[[u8; 3]]) -> (usize, u8) {
+    (0..3)
+        .map(|channel| {
+            let values = pixels.iter().map(|pixel| pixel[channel]);
+            let range = values.clone().max().unwrap_or(0) - values.min().unwrap_or(0);
+            (channel, range)
+        })
+        .max_by_key(|&(_, range)| range)
+        .unwrap_or((0, 0))
+}
+
+fn mean(pixels: &[[u8; 3]]) -> [u8; 3] {
+    let mut sums = [0u64; 3];
+    for pixel in pixels {
+        for (sum, &value) in sums.iter_mut().zip(pixel) {
+            *sum += u64::from(value);
+        }
+    }
+    let count = pixels.len() as u64;
+    sums.map(|sum| ((sum + count / 2) / count) as u8)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::dominant;
+    use image::{ColorType, Rgba, RgbaImage};
+
+    /// Three quarters red, one quarter blue.
+    fn halves() -> RgbaImage {
+        RgbaImage::from_fn(40, 10, |x, _| {
+            if x < 30 {
+                Rgba([255, 0, 0, 255])
+            } else {
+                Rgba([0, 0, 255, 255])
+            }
+        })
+    }
+
+    #[test]
+    fn most_common_first() {
+        let image = halves();
+        assert_eq!(
+            dominant(&image, ColorType::Rgba8, 2),
+            vec!["#ff0000", "#0000ff"]
+        );
+        // No more colors are made up than the image has.
+        assert_eq!(dominant(&image, ColorType::Rgba8, 5).len(), 2);
+        assert_eq!(dominant(&image, ColorType::Rgba8, 1), vec!["#bf0040"]);
+    }
+
+    #[test]
+    fn transparent_pixels_ignored() {
+        let mut image = halves();
+        for x in 30..40 {
+            for y in 0..10 {
+                image.put_pixel(x, y, Rgba([0, 0, 255, 0]));
+            }
+        }
+        assert_eq!(dominant(&image, ColorType::Rgba8, 2), vec!["#ff0000"]);
+    }
+}
@@ -0,0 +1,203 @@
+//! Resizing one image on many threads.
+//!
+//! `image` resizes on a single thread, which leaves most cores idle for a lone huge image. This
+//! resampler uses the same filters, applied separably: a vertical pass over the columns, then a
+//! horizontal pass over the rows. Each output row of either pass reads only the pass's input,
+//! never a neighbouring output row, so rows are shared out across threads without strips that
+//! overlap and without seams where they meet. The intermediate image is kept as `f32` rather than
+//! truncated to bytes, so the result may differ from `image`'s by a rounding step.
+
+use std::f32::consts::PI;
+
+use image::{imageops::FilterType, RgbaImage};
+use rayon::prelude::*;
+
+const CHANNELS: usize = 4;
+
+struct Kernel {
+    support: f32,
+    weight: fn(f32) -> f32,
+}
+
+fn kernel(filter: FilterType) -> Kernel {
+    match filter {
+        FilterType::Nearest => Kernel {
+            support: 0.0,
+            weight: |_| 1.0,
+        },
+        FilterType::Triangle => Kernel {
+            support: 1.0,
+            weight: |x| (1.0 - x.abs()).max(0.0),
+        },
+        FilterType::CatmullRom => Kernel {
+            support: 2.0,
+            weight: catmull_rom,
+        },
+        FilterType::Gaussian => Kernel {
+            support: 3.0,
+            weight: |x| (-2.0 * x * x).exp() / (0.5 * (2.0 * PI).sqrt()),
+        },
+        FilterType::Lanczos3 => Kernel {
+            support: 3.0,
+            weight: |x| {
+                if x.abs() < 3.0 {
+                    sinc(x) * sinc(x / 3.0)
+                } else {
+                    0.0
+                }
+            },
+        },
+    }
+}
+
+fn sinc(x: f32) -> f32 {
+    if x == 0.0 {
+        1.0
+    } else {
+        (x * PI).sin() / (x * PI)
+    }
+}
+
+fn catmull_rom(x: f32) -> f32 {
+    let a = x.abs();
+    if a < 1.0 {
+        (9.0 * a.powi(3) - 15.0 * a.powi(2) + 6.0) / 6.0
+    } else if a < 2.0 {
+        (-3.0 * a.powi(3) + 15.0 * a.powi(2) - 24.0 * a + 12.0) / 6.0
+    } else {
+        0.0
+    }
+}
+
+/// Normalized filter weights for each output position along one axis, along with the index
+/// of the first input they apply to. Positions are mapped as `image` maps them.
+fn weights(input: u32, output: u32, kernel: &Kernel) -> Vec<(usize, Vec<f32>)> {
+    let ratio = input as f32 / output as f32;
+    let scale = ratio.max(1.0);
+    let support = kernel.support * scale;
+
+    (0..output)
+        .map(|out| {
+            let center = (out as f32 + 0.5) * ratio;
+            let left = ((center - support).floor() as i64).clamp(0, input as i64 - 1);
+            let right = ((center + support).ceil() as i64).clamp(left + 1, input as i64);
+
+            let mut weights: Vec<f32> = (left..right)
+                .map(|i| (kernel.weight)((i as f32 - center + 0.5) / scale))
+                .collect();
+            let sum: f32 = weights.iter().sum();
+            if sum != 0.0 {
+                weights.iter_mut().for_each(|weight| *weight /= sum);
+            }
+            (left as usize, weights)
+        })
+        .collect()
+}
+
+pub fn resize(image: &RgbaImage, width: u32, height: u32, filter: FilterType) -> RgbaImage {
+    let kernel = kernel(filter);
+    let (input_width, input_height) = image.dimensions();
+    let input = image.as_raw();
+    let stride = input_width as usize * CHANNELS;
+
+    let rows = weights(input_height, height, &kernel);
+    let mut columns = vec![0f32; stride * height as usize];
+    columns
+        .par_chunks_mut(stride)
+        .zip(rows.par_iter())
+        .for_each(|(row, (first, weights))| {
+            for (offset, weight) in weights.iter().enumerate() {
+                let source = &input[(first + offset) * stride..][..stride];
+                for (value, &sample) in row.iter_mut().zip(source) {
+                    *value += sample as f32 * weight;
+                }
+            }
+            // Overshoot is clipped between passes, as `image` clips it.
+            row.iter_mut()
+                .for_each(|value| *value = value.clamp(0.0, 255.0));
+        });
+
+    let cols = weights(input_width, width, &kernel);
+    let output_stride = width as usize * CHANNELS;
+    let mut output = vec![0u8; output_stride * height as usize];
+    output
+        .par_chunks_mut(output_stride)
+        .zip(columns.par_chunks(stride))
+        .for_each(|(row, source)| {
+            for (pixel, (first, weights)) in row.chunks_exact_mut(CHANNELS).zip(&cols) {
+                let mut sums = [0f32; CHANNELS];
+                for (offset, weight) in weights.iter().enumerate() {
+                    let sample = &source[(first + offset) * CHANNELS..][..CHANNELS];
+                    for (sum, value) in sums.iter_mut().zip(sample) {
+                        *sum += value * weight;
+                    }
+                }
+                for (channel, sum) in pixel.iter_mut().zip(&sums) {
+                    *channel = sum.round().clamp(0.0, 255.0) as u8;
+                }
+            }
+        });
+
+    RgbaImage::from_raw(width, height, output).expect("buffer sized to fit")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resize;
+    use image::{imageops, imageops::FilterType, Rgba, RgbaImage};
+
+    fn gradient(width: u32, height: u32) -> RgbaImage {
+        RgbaImage::from_fn(width, height, |x, y| {
+            Rgba([
+                (x * 255 / width) as u8,
+                (y * 255 / height) as u8,
+                ((x + y) % 256) as u8,
+                255,
+            ])
+        })
+    }
+
+    #[test]
+    fn matches_image_resize() {
+        let image = gradient(301, 207);
+        let filters = [
+            FilterType::Nearest,
+            FilterType::Triangle,
+            FilterType::CatmullRom,
+            FilterType::Gaussian,
+            FilterType::Lanczos3,
+        ];
+
+        for &(width, height) in &[(97, 63), (420, 290)] {
+            for &filter in &filters {
+                let ours = resize(&image, width, height, filter);
+                let theirs = imageops::resize(&image, width, height, filter);
+                let worst = ours
+                    .as_raw()
+                    .iter()
+                    .zip(theirs.as_raw())
+                    .map(|(&a, &b)| (a as i32 - b as i32).abs())
+                    .max()
+                    .unwrap();
+
+                assert!(
+                    worst <= 3,
+                    "{:?} to {}x{}: off by {}",
+                    filter,
+                    width,
+                    height,
+                    worst
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn flat_image_stays_flat() {
+        let image = RgbaImage::from_pixel(1000, 700, Rgba([40, 120, 200, 255]));
+        let resized = resize(&image, 123, 86, FilterType::Lanczos3);
+        assert!(resized
+            .pixels()
+            .all(|&pixel| pixel == Rgba([40, 120, 200, 255])));
+    }
+}
@@ -0,0 +1,140 @@
+//! Salvaging what can be decoded from truncated images.
+
+use std::io::{Cursor, Read};
+
+use image::{
+    codecs::{jpeg::JpegDecoder, png::PngDecoder},
+    ColorType, DynamicImage, ImageBuffer, ImageDecoder, ImageFormat,
+};
+
+/// How much of a progressive decode is read at a time; anything decoded before the data runs
+/// out is kept, at this granularity.
+const CHUNK: usize = 4096;
+
+/// Decodes as much of a truncated image as there is, leaving the rest zeroed: black, or
+/// transparent where there is alpha. Only JPEG and PNG can be salvaged.
+pub fn decode(data: &[u8], format: ImageFormat) -> Option<DynamicImage> {
+    match format {
+        ImageFormat::Jpeg => {
+            // An end-of-image marker makes the decoder stop where the data does, filling in
+            // what's missing, rather than fail.
+            let mut data = data.to_vec();
+            data.extend_from_slice(&[0xFF, 0xD9]);
+            DynamicImage::from_decoder(JpegDecoder::new(Cursor::new(data)).ok()?).ok()
+        }
+        ImageFormat::Png => salvage(PngDecoder::new(Cursor::new(data)).ok()?),
+        _ => None,
+    }
+}
+
+/// Reads a decoder's rows until they run out.
+fn salvage<'a>(decoder: impl ImageDecoder<'a>) -> Option<DynamicImage> {
+    let (width, height) = decoder.dimensions();
+    let color = decoder.color_type();
+    let mut bytes = vec![0; decoder.total_bytes() as usize];
+
+    let mut reader = decoder.into_reader().ok()?;
+    let mut filled = 0;
+    while filled < bytes.len() {
+        let end = (filled + CHUNK).min(bytes.len());
+        match reader.read(&mut bytes[filled..end]) {
+            Ok(0) | Err(_) => break,
+            Ok(read) => filled += read,
+        }
+    }
+
+    // Without so much as a row there is nothing worth keeping.
+    if filled == 0 {
+        return None;
+    }
+    from_bytes(color, width, height, bytes)
+}
+
+fn from_bytes(color: ColorType, width: u32, height: u32, bytes: Vec<u8>) -> Option<DynamicImage> {
+    // Decoders produce 16-bit samples in native byte order.
+    let words = || -> Vec<u16> {
+        bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_ne_bytes([pair[0], pair[1]]))
+            .collect()
+    };
+
+    match color {
+        ColorType::L8 => ImageBuffer::from_raw(width, height, bytes).map(DynamicImage::ImageLuma8),
+        ColorType::La8 => {
+            ImageBuffer::from_raw(width, height, bytes).map(DynamicImage::ImageLumaA8)
+        }
+        ColorType::Rgb8 => ImageBuffer::from_raw(width, height, bytes).map(DynamicImage::ImageRgb8),
+        ColorType::Rgba8 => {
+            ImageBuffer::from_raw(width, height, bytes).map(DynamicImage::ImageRgba8)
+        }
+        ColorType::L16 => {
+            ImageBuffer::from_raw(width, height, words()).map(DynamicImage::ImageLuma16)
+        }
+        ColorType::La16 => {
+            ImageBuffer::from_raw(width, height, words()).map(DynamicImage::ImageLumaA16)
+        }
+        ColorType::Rgb16 => {
+            ImageBuffer::from_raw(width, height, words()).map(DynamicImage::ImageRgb16)
+        }
+        ColorType::Rgba16 => {
+            ImageBuffer::from_raw(width, height, words()).map(DynamicImage::ImageRgba16)
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::decode;
+    use image::{DynamicImage, GenericImageView, ImageFormat, Rgba, RgbaImage};
+
+    /// Noise, so the encoded data isn't small enough for decoders to buffer all at once.
+    fn noise() -> DynamicImage {
+        let mut state = 1u32;
+        let image = RgbaImage::from_fn(400, 300, |_, _| {
+            state = state.wrapping_mul(1_103_515_245).wrapping_add(12345);
+            let [r, g, b, _] = state.to_le_bytes();
+            Rgba([r, g, b, 255])
+        });
+        DynamicImage::ImageRgb8(DynamicImage::ImageRgba8(image).to_rgb())
+    }
+
+    fn encoded(image: &DynamicImage, format: ImageFormat) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        image.write_to(&mut bytes, format).unwrap();
+        bytes
+    }
+
+    #[test]
+    fn salvages_truncated_jpeg() {
+        let bytes = encoded(&noise(), ImageFormat::Jpeg);
+        let truncated = &bytes[..bytes.len() / 2];
+        assert!(image::load_from_memory(truncated).is_err());
+
+        let salvaged = decode(truncated, ImageFormat::Jpeg).unwrap();
+        assert_eq!(salvaged.dimensions(), (400, 300));
+    }
+
+    #[test]
+    fn salvages_truncated_png() {
+        let original = noise();
+        let bytes = encoded(&original, ImageFormat::Png);
+        let truncated = &bytes[..bytes.len() / 2];
+        assert!(image::load_from_memory(truncated).is_err());
+
+        let salvaged = decode(truncated, ImageFormat::Png).unwrap().to_rgb();
+        assert_eq!(salvaged.dimensions(), (400, 300));
+        assert_eq!(
+            salvaged.get_pixel(10, 1),
+            original.to_rgb().get_pixel(10, 1)
+        );
+        assert_eq!(salvaged.get_pixel(10, 299).0, [0, 0, 0]);
+    }
+
+    #[test]
+    fn header_alone_is_not_enough() {
+        let bytes = encoded(&noise(), ImageFormat::Png);
+        assert!(decode(&bytes[..40], ImageFormat::Png).is_none());
+    }
+}
@@ -0,0 +1,113 @@
+//! Perceptual hashes of decoded images, for spotting near-duplicates with `--phash`.
+//!
+//! The hash is a dHash: the image shrunk to 9x8 in gray, with one bit for each pair of
+//! neighbors saying whether brightness rises from left to right. Rescaling, recompressing and
+//! small edits flip few bits, so near-duplicates lie a short Hamming distance apart.
+
+use image::{imageops::FilterType, DynamicImage};
+
+/// How many bits two hashes may differ by and still be taken for the same picture, by default.
+pub const DEFAULT_DISTANCE: u32 = 10;
+
+pub fn dhash(image: &DynamicImage) -> u64 {
+    let small = image.resize_exact(9, 8, FilterType::Triangle).to_luma();
+    let mut hash = 0;
+    for y in 0..8 {
+        for x in 0..8 {
+            let brighter = small.get_pixel(x + 1, y).0[0] > small.get_pixel(x, y).0[0];
+            hash = hash << 1 | u64::from(brighter);
+        }
+    }
+    hash
+}
+
+/// How many bits `a` and `b` differ by.
+pub fn distance(a: u64, b: u64) -> u32 {
+    (a ^ b).count_ones()
+}
+
+/// Groups of two or more names whose hashes lie within `max_distance` of one another, or of
+/// another in the group, in the order each group's first name appears. A name given more than
+/// once counts once.
+pub fn groups(hashes: &[(String, u64)], max_distance: u32) -> Vec<Vec<String>> {
+    let mut names: Vec<&str> = Vec::new();
+    let mut unique: Vec<u64> = Vec::new();
+    for (name, hash) in hashes {
+        if !names.contains(&name.as_str()) {
+            names.push(name);
+            unique.push(*hash);
+        }
+    }
+
+    // Each name points towards the first of its group, so joining two groups is one pointer.
+    let mut parents: Vec<usize> = (0..names.len()).collect();
+    for i in 0..unique.len() {
+        for j in i + 1..unique.len() {
+            if distance(unique[i], unique[j]) <= max_distance {
+                let (a, b) = (root(&mut parents, i), root(&mut parents, j));
+                parents[a.max(b)] = a.min(b);
+            }
+        }
+    }
+
+    let mut groups: Vec<Vec<String>> = vec![Vec::new(); names.len()];
+    for (index, name) in names.iter().enumerate() {
+        let group = root(&mut parents, index);
+        groups[group].push(name.to_string());
+    }
+    groups.retain(|group| group.len() > 1);
+    groups
+}
+
+/// The first of the group `index` is in, shortening the way there as it goes.
+fn root(parents: &mut [usize], mut index: usize) -> usize {
+    while parents[index] != index {
+        parents[index] = parents[parents[index]];
+        index = parents[index];
+    }
+    index
+}
+
+/// A hash as the 16 hex digits it's recorded with.
+pub fn to_hex(hash: u64) -> String {
+    format!("{:016x}", hash)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{dhash, distance, groups};
+    use image::{DynamicImage, ImageBuffer, Luma};
+
+    fn gradient(width: u32, height: u32, flip: bool) -> DynamicImage {
+        DynamicImage::ImageLuma8(ImageBuffer::from_fn(width, height, |x, y| {
+            let value = (x * 255 / width + y * 40 / height) as u8;
+            Luma([if flip { 255 - value } else { value }])
+        }))
+    }
+
+    #[test]
+    fn rescaled_images_hash_alike() {
+        let large = dhash(&gradient(400, 300, false));
+        let small = dhash(&gradient(120, 90, false));
+        let flipped = dhash(&gradient(400, 300, true));
+        assert!(distance(large, small) <= 4);
+        assert!(distance(large, flipped) > 32);
+    }
+
+    #[test]
+    fn groups_near_hashes() {
+        let hashes = [
+            (String::from("a.jpg"), 0b0000),
+            (String::from("b.jpg"), u64::MAX),
+            (String::from("c.jpg"), 0b0111),
+            (String::from("a.jpg"), 0b0000),
+            (String::from("d.jpg"), 0b1111_0000),
+            (String::from("e.jpg"), u64::MAX - 1),
+        ];
+        assert_eq!(
+            groups(&hashes, 3),
+            [vec!["a.jpg", "c.jpg"], vec!["b.jpg", "e.jpg"]]
+        );
+        assert_eq!(groups(&hashes, 0), Vec::<Vec<String>>::new());
+    }
+}
@@ -0,0 +1,37 @@
+//! Stand-ins for images that fail to decode, for `--error-image`.
+
+use image::{Rgb, RgbImage};
+
+const BACKGROUND: Rgb<u8> = Rgb([0xd0, 0xd0, 0xd0]);
+const CROSS: Rgb<u8> = Rgb([0x90, 0x90, 0x90]);
+
+/// A light gray square `size` on a side, crossed out corner to corner.
+pub fn broken(size: u32) -> RgbImage {
+    // The cross stays visible at thumbnail sizes without swamping large ones.
+    let thickness = i64::from((size / 32).max(1));
+    let last = i64::from(size) - 1;
+    RgbImage::from_fn(size, size, |x, y| {
+        let (x, y) = (i64::from(x), i64::from(y));
+        if (x - y).abs() < thickness || (x + y - last).abs() < thickness {
+            CROSS
+        } else {
+            BACKGROUND
+        }
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{broken, BACKGROUND, CROSS};
+
+    #[test]
+    fn crossed_out_square() {
+        let image = broken(64);
+        assert_eq!(image.dimensions(), (64, 64));
+        for &(x, y) in &[(0, 0), (63, 0), (0, 63), (63, 63), (32, 31)] {
+            assert_eq!(*image.get_pixel(x, y), CROSS);
+        }
+        assert_eq!(*image.get_pixel(32, 0), BACKGROUND);
+        assert_eq!(*image.get_pixel(0, 32), BACKGROUND);
+    }
+}
@@ -0,0 +1,202 @@
+//! Declarative batches, where each entry may override the settings given by flags.
+//!
+//! A plan is a JSON array of entries such as
+//!
+//! ```json
+//! [
+//!     { "source": "hero.png", "op": "shrink", "size": [640, 1280], "format": "jpeg" },
+//!     { "source": "logo.png", "size": 48, "format": "ico", "out": "favicon.ico" },
+//!     { "source": "scan.tiff", "op": "enlarge", "size": 4000, "quality": "90" }
+//! ]
+//! ```
+//!
+//! Only `source` is required; anything left out falls back to the corresponding flag.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::Deserialize;
+
+use resize::{
+    output,
+    quality::Quality,
+    settings::{Operation, Settings},
+    Job,
+};
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Entry {
+    #[serde(alias = "path")]
+    source: String,
+    op: Option<String>,
+    size: Option<Sizes>,
+    format: Option<String>,
+    quality: Option<QualitySpec>,
+    out: Option<PathBuf>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum Sizes {
+    One(u32),
+    Many(Vec<u32>),
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+enum QualitySpec {
+    Value(u8),
+    Spec(String),
+}
+
+/// Reads the plan at `path`, filling unspecified fields from `defaults`.
+///
+/// Every entry is validated before anything is processed, and all invalid entries are
+/// reported together.
+pub fn load(path: &Path, defaults: &Settings) -> io::Result<Vec<Job>> {
+    let text = fs::read_to_string(path)?;
+    let entries: Vec<Entry> = serde_json::from_str(&text).map_err(|e| {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("{}: {}", path.display(), e),
+        )
+    })?;
+
+    let mut jobs = Vec::with_capacity(entries.len());
+    let mut errors = Vec::new();
+    for (index, entry) in entries.into_iter().enumerate() {
+        let source = entry.source.clone();
+        match job(entry, defaults) {
+            Ok(job) => jobs.push(job),
+            Err(e) => errors.push(format!("  entry {} ({}): {}", index, source, e)),
+        }
+    }
+
+    if errors.is_empty() {
+        Ok(jobs)
+    } else {
+        Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("invalid plan {}:\n{}", path.display(), errors.join("\n")),
+        ))
+    }
+}
+
+/// The job for a single entry given as JSON, filling unspecified fields from `defaults`.
+pub fn entry_job(entry: serde_json::Value, defaults: &Settings) -> Result<Job, String> {
+    job(
+        serde_json::from_value(entry).map_err(|e| e.to_string())?,
+        defaults,
+    )
+}
+
+fn job(entry: Entry, defaults: &Settings) -> Result<Job, String> {
+    let mut settings = defaults.clone();
+
+    if let Some(op) = entry.op {
+        settings.operation =
+            Operation::from_name(&op).ok_or_else(|| format!("unknown op '{}'", op))?;
+    }
+
+    match entry.size {
+        Some(Sizes::One(size)) => settings.sizes = vec![size],
+        Some(Sizes::Many(sizes)) => settings.sizes = sizes,
+        None => (),
+    }
+    if settings.sizes.contains(&0) {
+        return Err(String::from("sizes must be positive"));
+    }
+
+    if let Some(format) = entry.format {
+        settings.format = Some(
+            output::parse_format(&format).ok_or_else(|| format!("unknown format '{}'", format))?,
+        );
+    }
+
+    match entry.quality {
+        Some(QualitySpec::Value(quality)) => {
+            settings.quality = Quality::parse(&quality.to_string())?
+        }
+        Some(QualitySpec::Spec(spec)) => settings.quality = Quality::parse(&spec)?,
+        None => (),
+    }
+
+    settings.validate()?;
+    Ok(Job {
+        out: entry.out,
+        ..Job::new(&entry.source, &settings)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{job, Entry};
+    use image::ImageFormat;
+    use resize::{
+        quality::Quality,
+        settings::{Operation, Settings},
+    };
+
+    fn defaults() -> Settings {
+        Settings {
+            operation: Operation::Shrink,
+            sizes: vec![1024],
+            format: None,
+            quality: Quality::parse("80").unwrap(),
+        }
+    }
+
+    fn entry(json: &str) -> Entry {
+        serde_json::from_str(json).unwrap()
+    }
+
+    #[test]
+    fn defaults_fill_unspecified_fields() {
+        let job = job(entry(r#"{ "source": "a.png" }"#), &defaults()).unwrap();
+        assert_eq!(job.settings.sizes, vec![1024]);
+        assert_eq!(job.settings.quality.for_size(1024), Some(80));
+        assert!(job.out.is_none());
+    }
+
+    #[test]
+    fn entry_overrides_defaults() {
+        let json = r#"{
+            "source": "a.png",
+            "op": "enlarge",
+            "size": [256, 512],
+            "format": "jpeg",
+            "quality": 90,
+            "out": "b.jpg"
+        }"#;
+        let job = job(entry(json), &defaults()).unwrap();
+
+        assert!(matches!(job.settings.operation, Operation::Enlarge));
+        assert_eq!(job.settings.sizes, vec![256, 512]);
+        assert_eq!(job.settings.format, Some(ImageFormat::Jpeg));
+        assert_eq!(job.settings.quality.for_size(256), Some(90));
+        assert_eq!(job.out.unwrap().to_str(), Some("b.jpg"));
+    }
+
+    #[test]
+    fn invalid_entries() {
+        let invalid = [
+            r#"{ "source": "a.png", "op": "explode" }"#,
+            r#"{ "source": "a.png", "format": "psd" }"#,
+            r#"{ "source": "a.png", "size": 0 }"#,
+            r#"{ "source": "a.png", "quality": "256=70" }"#,
+        ];
+
+        for json in &invalid {
+            assert!(job(entry(json), &defaults()).is_err(), "{}", json);
+        }
+    }
+
+    #[test]
+    fn unknown_fields_are_rejected() {
+        let result: Result<Entry, _> = serde_json::from_str(r#"{ "source": "a", "sise": 1 }"#);
+        assert!(result.is_err());
+    }
+}
@@ -0,0 +1,93 @@
+//! Named presets for recurring recipes, which explicit options override.
+
+use image::{imageops::FilterType, ImageFormat};
+
+use crate::{quality::Quality, ResizeOptionsBuilder};
+
+/// A partial set of options, applied before anything given explicitly.
+#[derive(Copy, Clone, Debug)]
+pub struct Profile {
+    pub name: &'static str,
+    pub description: &'static str,
+    sizes: &'static [u32],
+    format: ImageFormat,
+    quality: Option<u8>,
+    filter: FilterType,
+    auto_sharpen: bool,
+}
+
+pub const PROFILES: &[Profile] = &[
+    Profile {
+        name: "web",
+        description: "1600px JPEGs at quality 82, sharpened after shrinking",
+        sizes: &[1600],
+        format: ImageFormat::Jpeg,
+        quality: Some(82),
+        filter: FilterType::Lanczos3,
+        auto_sharpen: true,
+    },
+    Profile {
+        name: "thumbnail",
+        description: "256px JPEGs at quality 75, sharpened after shrinking",
+        sizes: &[256],
+        format: ImageFormat::Jpeg,
+        quality: Some(75),
+        filter: FilterType::CatmullRom,
+        auto_sharpen: true,
+    },
+    Profile {
+        name: "archive",
+        description: "4096px lossless PNGs",
+        sizes: &[4096],
+        format: ImageFormat::Png,
+        quality: None,
+        filter: FilterType::Lanczos3,
+        auto_sharpen: false,
+    },
+];
+
+pub fn find(name: &str) -> Option<&'static Profile> {
+    PROFILES.iter().find(|profile| profile.name == name)
+}
+
+impl Profile {
+    /// Applies the profile to `builder`, including its sizes only if `with_sizes`, since sizes
+    /// given explicitly add to rather than replace them.
+    pub fn apply(&self, builder: ResizeOptionsBuilder, with_sizes: bool) -> ResizeOptionsBuilder {
+        let mut builder = builder
+            .format(self.format)
+            .filter(self.filter)
+            .auto_sharpen(self.auto_sharpen);
+        if let Some(quality) = self.quality {
+            let quality = Quality::parse(&quality.to_string()).expect("profile quality is valid");
+            builder = builder.quality(quality);
+        }
+        if with_sizes {
+            builder = builder.sizes(self.sizes.iter().copied());
+        }
+        builder
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{find, PROFILES};
+    use crate::ResizeOptions;
+
+    #[test]
+    fn profiles_are_complete() {
+        for profile in PROFILES {
+            let options = profile.apply(ResizeOptions::builder(), true).build();
+            assert!(options.is_ok(), "{}", profile.name);
+        }
+        assert!(find("web").is_some());
+        assert!(find("poster").is_none());
+    }
+
+    #[test]
+    fn explicit_sizes_replace_the_profile_sizes() {
+        let builder = ResizeOptions::builder().size(640);
+        let options = find("web").unwrap().apply(builder, false).build().unwrap();
+        assert_eq!(options.settings().sizes, vec![640]);
+    }
+}
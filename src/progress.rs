@@ -0,0 +1,567 @@
+//! Progress reporting, either as notes for a person or as events for an external UI.
+
+use std::{
+    collections::BTreeMap,
+    fmt,
+    io::{self, Write},
+    sync::atomic::{AtomicUsize, Ordering},
+    time::Duration,
+};
+
+use serde::Serialize;
+
+use crate::{stats::Stats, timing::Timings};
+
+#[derive(Copy, Clone, Debug)]
+pub enum Progress {
+    /// Human-readable notes on stderr for anything out of the ordinary.
+    Text,
+    /// One JSON object per line on stderr.
+    Json,
+}
+
+#[derive(Clone, Debug)]
+pub enum Status {
+    Resized,
+    Tiled,
+    Oriented,
+    /// Placed unscaled on a canvas.
+    Placed,
+    /// A side-by-side of the source and an output, for judging the resize.
+    Compared,
+    /// Written from what could be salvaged of a truncated image.
+    Partial,
+    /// Written in the fallback format, by its extension, after the requested one failed with
+    /// the error given.
+    FellBack {
+        format: &'static str,
+        error: String,
+    },
+    /// A placeholder for a source that failed to load, with the error.
+    Placeholder(String),
+    /// Identical to an output already written, at the path given, so linked to it instead.
+    Duplicate(String),
+    /// Left untouched, for the reason given.
+    Skipped(String),
+    /// Left untouched because it already fits within the given size.
+    Noop(u32),
+    /// Left untouched because it is already within `--skip-ratio` of the given size.
+    NearSize(u32),
+    Failed,
+    /// Failed by panicking, with the panic's message.
+    Panicked(String),
+    /// Failed because reading the source took longer than this.
+    TimedOut(Duration),
+}
+
+impl Status {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Status::Resized => "resized",
+            Status::Tiled => "tiled",
+            Status::Oriented => "oriented",
+            Status::Placed => "placed",
+            Status::Compared => "compared",
+            Status::Partial => "partial",
+            Status::FellBack { .. } => "fallback",
+            Status::Placeholder(_) => "placeholder",
+            Status::Duplicate(_) => "duplicate",
+            Status::Skipped(_) | Status::Noop(_) | Status::NearSize(_) => "skipped",
+            Status::Failed => "failed",
+            Status::Panicked(_) => "panicked",
+            Status::TimedOut(_) => "timed-out",
+        }
+    }
+}
+
+/// Counts of the outcomes reported during a run, one per output or skip.
+#[derive(Debug, Default)]
+pub struct Tally {
+    ok: AtomicUsize,
+    skipped: AtomicUsize,
+    failed: AtomicUsize,
+    /// Of those failed, how many panicked.
+    panicked: AtomicUsize,
+    /// Of those failed, how many took too long to read.
+    timed_out: AtomicUsize,
+    /// Of those ok, how many were written in the fallback format.
+    fell_back: AtomicUsize,
+    /// Of those ok, how many were linked to an identical output.
+    duplicates: AtomicUsize,
+    /// How many images were turned to face the way `--orient-to` asked.
+    rotated: AtomicUsize,
+}
+
+impl Tally {
+    pub fn record(&self, status: &Status) {
+        let count = match status {
+            Status::Resized
+            | Status::Tiled
+            | Status::Oriented
+            | Status::Placed
+            | Status::Compared
+            | Status::Partial
+            | Status::FellBack { .. }
+            | Status::Placeholder(_)
+            | Status::Duplicate(_) => &self.ok,
+            Status::Skipped(_) | Status::Noop(_) | Status::NearSize(_) => &self.skipped,
+            Status::Failed | Status::Panicked(_) | Status::TimedOut(_) => &self.failed,
+        };
+        count.fetch_add(1, Ordering::Relaxed);
+
+        match status {
+            Status::Panicked(_) => self.panicked.fetch_add(1, Ordering::Relaxed),
+            Status::TimedOut(_) => self.timed_out.fetch_add(1, Ordering::Relaxed),
+            Status::FellBack { .. } => self.fell_back.fetch_add(1, Ordering::Relaxed),
+            Status::Duplicate(_) => self.duplicates.fetch_add(1, Ordering::Relaxed),
+            _ => 0,
+        };
+    }
+
+    /// Counts an image turned to face the way asked, however many outputs it has.
+    pub fn record_rotation(&self) {
+        self.rotated.fetch_add(1, Ordering::Relaxed);
+    }
+
+    pub fn panicked(&self) -> usize {
+        self.panicked.load(Ordering::Relaxed)
+    }
+
+    pub fn timed_out(&self) -> usize {
+        self.timed_out.load(Ordering::Relaxed)
+    }
+
+    pub fn failed(&self) -> usize {
+        self.failed.load(Ordering::Relaxed)
+    }
+
+    pub fn fell_back(&self) -> usize {
+        self.fell_back.load(Ordering::Relaxed)
+    }
+
+    pub fn duplicates(&self) -> usize {
+        self.duplicates.load(Ordering::Relaxed)
+    }
+
+    pub fn rotated(&self) -> usize {
+        self.rotated.load(Ordering::Relaxed)
+    }
+
+    fn counts(&self) -> (usize, usize, usize) {
+        (
+            self.ok.load(Ordering::Relaxed),
+            self.skipped.load(Ordering::Relaxed),
+            self.failed.load(Ordering::Relaxed),
+        )
+    }
+}
+
+impl fmt::Display for Tally {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let (ok, skipped, failed) = self.counts();
+        write!(f, "ok={} skipped={} failed={}", ok, skipped, failed)?;
+        if self.panicked() > 0 {
+            write!(f, " panicked={}", self.panicked())?;
+        }
+        if self.timed_out() > 0 {
+            write!(f, " timed_out={}", self.timed_out())?;
+        }
+        if self.fell_back() > 0 {
+            write!(f, " fell_back={}", self.fell_back())?;
+        }
+        if self.duplicates() > 0 {
+            write!(f, " duplicates={}", self.duplicates())?;
+        }
+        if self.rotated() > 0 {
+            write!(f, " rotated={}", self.rotated())?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Serialize)]
+struct Summary {
+    event: &'static str,
+    ok: usize,
+    skipped: usize,
+    failed: usize,
+    panicked: usize,
+    timed_out: usize,
+    fell_back: usize,
+    duplicates: usize,
+    rotated: usize,
+}
+
+#[derive(Serialize)]
+struct Failure<'a> {
+    event: &'static str,
+    message: &'a str,
+}
+
+#[derive(Serialize)]
+struct Budget {
+    event: &'static str,
+    spent: u64,
+    budget: u64,
+}
+
+#[derive(Serialize)]
+struct Timing {
+    event: &'static str,
+    images: usize,
+    /// Milliseconds spent in each stage, summed over the images.
+    stages: BTreeMap<&'static str, f64>,
+}
+
+#[derive(Serialize)]
+struct Matched<'a> {
+    event: &'static str,
+    pattern: &'a str,
+    depths: &'a BTreeMap<usize, usize>,
+}
+
+#[derive(Serialize)]
+struct Limit {
+    event: &'static str,
+    kept: usize,
+    total: usize,
+}
+
+#[derive(Serialize)]
+struct Resumed {
+    event: &'static str,
+    done: usize,
+    total: usize,
+}
+
+#[derive(Serialize)]
+struct Event<'a> {
+    event: &'a str,
+    path: &'a str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    status: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    w: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    h: Option<u32>,
+}
+
+#[derive(Serialize)]
+struct Measured<'a> {
+    event: &'static str,
+    path: &'a str,
+    #[serde(flatten)]
+    stats: &'a Stats,
+}
+
+impl Progress {
+    pub fn start(self, path: &str) {
+        if let Progress::Json = self {
+            emit(&Event {
+                event: "start",
+                path,
+                status: None,
+                w: None,
+                h: None,
+            });
+        }
+    }
+
+    /// Reports an outcome for `path`, with the output dimensions where known.
+    pub fn finish(self, path: &str, status: &Status, dimensions: Option<(u32, u32)>) {
+        match self {
+            Progress::Text => match status {
+                Status::Skipped(reason) => eprintln!("skipped ({}): {}", reason, path),
+                Status::Panicked(message) => eprintln!("panicked ({}): {}", message, path),
+                Status::TimedOut(limit) => {
+                    eprintln!("timed out (after {:?}): {}", limit, path)
+                }
+                Status::Partial => eprintln!("partial (truncated source): {}", path),
+                Status::FellBack { format, error } => {
+                    eprintln!("fell back to {} ({}): {}", format, error, path)
+                }
+                Status::Placeholder(error) => eprintln!("placeholder ({}): {}", error, path),
+                Status::Duplicate(original) => {
+                    eprintln!("duplicate (linked to {}): {}", original, path)
+                }
+                Status::Noop(size) => {
+                    eprintln!("skipped (already within {}px): {}", size, path)
+                }
+                Status::NearSize(size) => {
+                    eprintln!("skipped (close enough to {}px): {}", size, path)
+                }
+                _ => (),
+            },
+            Progress::Json => emit(&Event {
+                event: "finish",
+                path,
+                status: Some(status.name()),
+                w: dimensions.map(|(w, _)| w),
+                h: dimensions.map(|(_, h)| h),
+            }),
+        }
+    }
+
+    /// Reports that the source at `path` was center-cropped to the maximum aspect.
+    pub fn cropped(self, path: &str) {
+        match self {
+            Progress::Text => eprintln!("cropped to aspect: {}", path),
+            Progress::Json => emit(&Event {
+                event: "cropped",
+                path,
+                status: None,
+                w: None,
+                h: None,
+            }),
+        }
+    }
+
+    /// Reports that the source at `path` was decoded as `format`, not as its extension says.
+    pub fn sniffed(self, path: &str, format: &str) {
+        match self {
+            Progress::Text => eprintln!("decoded as {} despite its extension: {}", format, path),
+            Progress::Json => emit(&Event {
+                event: "sniffed",
+                path,
+                status: Some(format),
+                w: None,
+                h: None,
+            }),
+        }
+    }
+
+    /// Reports that the source at `path` was deleted, its outputs all written.
+    pub fn deleted(self, path: &str) {
+        match self {
+            Progress::Text => eprintln!("deleted source: {}", path),
+            Progress::Json => emit(&Event {
+                event: "deleted",
+                path,
+                status: None,
+                w: None,
+                h: None,
+            }),
+        }
+    }
+
+    /// Reports measurements of the source at `path`, in full for a UI or as the headline
+    /// numbers for a person.
+    pub fn stats(self, path: &str, stats: &Stats) {
+        match self {
+            Progress::Text => eprintln!(
+                "stats (brightness {:.1}, entropy {:.2}, sharpness {:.1}, blown {:.1}%, \
+                 crushed {:.1}%): {}",
+                stats.brightness,
+                stats.entropy,
+                stats.sharpness,
+                100.0 * stats.blown(),
+                100.0 * stats.crushed(),
+                path
+            ),
+            Progress::Json => emit(&Measured {
+                event: "stats",
+                path,
+                stats,
+            }),
+        }
+    }
+
+    /// Reports how many images a directory or glob matched at each depth below it.
+    pub fn matched(self, pattern: &str, depths: &BTreeMap<usize, usize>) {
+        match self {
+            Progress::Text if depths.is_empty() => eprintln!("matched nothing: {}", pattern),
+            Progress::Text => {
+                let counts: Vec<_> = depths
+                    .iter()
+                    .map(|(depth, count)| format!("{} at depth {}", count, depth))
+                    .collect();
+                eprintln!("matched {}: {}", counts.join(", "), pattern)
+            }
+            Progress::Json => emit(&Matched {
+                event: "matched",
+                pattern,
+                depths,
+            }),
+        }
+    }
+
+    /// Reports that only the first `kept` of `total` images will be processed.
+    pub fn limited(self, kept: usize, total: usize) {
+        match self {
+            Progress::Text => eprintln!("limited to the first {} of {} images", kept, total),
+            Progress::Json => emit(&Limit {
+                event: "limit",
+                kept,
+                total,
+            }),
+        }
+    }
+
+    /// Reports that `done` of `total` images were finished by an earlier run, so are left out.
+    pub fn resumed(self, done: usize, total: usize) {
+        match self {
+            Progress::Text => eprintln!("resuming: {} of {} images already done", done, total),
+            Progress::Json => emit(&Resumed {
+                event: "resume",
+                done,
+                total,
+            }),
+        }
+    }
+
+    /// Reports how many bytes of output were written against a budget.
+    pub fn spent(self, spent: u64, budget: u64) {
+        match self {
+            Progress::Text => eprintln!(
+                "wrote {} bytes of a {} byte budget ({:.0}%)",
+                spent,
+                budget,
+                100.0 * spent as f64 / budget as f64
+            ),
+            Progress::Json => emit(&Budget {
+                event: "budget",
+                spent,
+                budget,
+            }),
+        }
+    }
+
+    /// Reports how long each stage took over `images` images, in a table for a person.
+    pub fn timing(self, timings: &Timings, images: usize) {
+        let millis = |time: Duration| time.as_secs_f64() * 1000.0;
+        match self {
+            Progress::Text => {
+                let total = millis(timings.total());
+                eprintln!(
+                    "{:<8}{:>12}{:>12}{:>8}",
+                    "stage", "total ms", "per image", "share"
+                );
+                for (stage, time) in timings.stages() {
+                    eprintln!(
+                        "{:<8}{:>12.1}{:>12.1}{:>7.0}%",
+                        stage,
+                        millis(time),
+                        millis(time) / images.max(1) as f64,
+                        100.0 * millis(time) / total.max(f64::MIN_POSITIVE)
+                    );
+                }
+            }
+            Progress::Json => emit(&Timing {
+                event: "timing",
+                images,
+                stages: timings
+                    .stages()
+                    .iter()
+                    .map(|&(stage, time)| (stage, millis(time)))
+                    .collect(),
+            }),
+        }
+    }
+
+    /// Reports why an image failed, when the run carries on past it.
+    pub fn error(self, message: &str) {
+        match self {
+            Progress::Text => eprintln!("error: {}", message),
+            Progress::Json => emit(&Failure {
+                event: "error",
+                message,
+            }),
+        }
+    }
+
+    /// Reports the totals for the run, as the last line on stderr.
+    pub fn summary(self, tally: &Tally) {
+        match self {
+            Progress::Text => eprintln!("{}", tally),
+            Progress::Json => {
+                let (ok, skipped, failed) = tally.counts();
+                emit(&Summary {
+                    event: "summary",
+                    ok,
+                    skipped,
+                    failed,
+                    panicked: tally.panicked(),
+                    timed_out: tally.timed_out(),
+                    fell_back: tally.fell_back(),
+                    duplicates: tally.duplicates(),
+                    rotated: tally.rotated(),
+                })
+            }
+        }
+    }
+}
+
+fn emit(event: &impl Serialize) {
+    // Progress is best effort; a closed stderr shouldn't fail the batch.
+    let mut stderr = io::stderr().lock();
+    if let Ok(line) = serde_json::to_string(event) {
+        let _ = writeln!(stderr, "{}", line);
+        let _ = stderr.flush();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Status, Tally};
+    use std::time::Duration;
+
+    #[test]
+    fn tally_counts_panics_as_failures() {
+        let tally = Tally::default();
+        tally.record(&Status::Resized);
+        tally.record(&Status::Panicked(String::from("index out of bounds")));
+        assert_eq!(tally.to_string(), "ok=1 skipped=0 failed=1 panicked=1");
+    }
+
+    #[test]
+    fn tally_counts_timeouts_as_failures() {
+        let tally = Tally::default();
+        tally.record(&Status::TimedOut(Duration::from_secs(5)));
+        tally.record(&Status::Failed);
+        assert_eq!(tally.to_string(), "ok=0 skipped=0 failed=2 timed_out=1");
+    }
+
+    #[test]
+    fn tally_counts_fallbacks_as_ok() {
+        let tally = Tally::default();
+        tally.record(&Status::FellBack {
+            format: "jpg",
+            error: String::from("encoding WebP images is not supported"),
+        });
+        tally.record(&Status::Resized);
+        assert_eq!(tally.to_string(), "ok=2 skipped=0 failed=0 fell_back=1");
+    }
+
+    #[test]
+    fn tally_counts_duplicates_as_ok() {
+        let tally = Tally::default();
+        tally.record(&Status::Resized);
+        tally.record(&Status::Duplicate(String::from("a_64.png")));
+        assert_eq!(tally.to_string(), "ok=2 skipped=0 failed=0 duplicates=1");
+    }
+
+    #[test]
+    fn tally_counts_rotations_once_per_image() {
+        let tally = Tally::default();
+        tally.record(&Status::Resized);
+        tally.record(&Status::Resized);
+        tally.record_rotation();
+        assert_eq!(tally.to_string(), "ok=2 skipped=0 failed=0 rotated=1");
+    }
+
+    #[test]
+    fn tally_counts_by_outcome() {
+        let tally = Tally::default();
+        for status in &[
+            Status::Resized,
+            Status::Tiled,
+            Status::Skipped(String::from("small")),
+            Status::Noop(256),
+            Status::Failed,
+            Status::Oriented,
+        ] {
+            tally.record(status);
+        }
+        assert_eq!(tally.to_string(), "ok=3 skipped=2 failed=1");
+    }
+}
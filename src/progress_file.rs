@@ -0,0 +1,82 @@
+//! A status file rewritten as each image completes, for UIs that poll rather than stream.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+    sync::atomic::{AtomicUsize, Ordering},
+};
+
+use serde::Serialize;
+
+#[derive(Debug, Serialize)]
+struct Snapshot<'a> {
+    done: usize,
+    total: usize,
+    /// The image being processed, or none once all are done.
+    current: Option<&'a str>,
+}
+
+/// Tracks a run's images in the order they complete.
+#[derive(Debug)]
+pub struct ProgressFile {
+    path: PathBuf,
+    sources: Vec<String>,
+    done: AtomicUsize,
+}
+
+impl ProgressFile {
+    /// Writes the first snapshot, with nothing yet done.
+    pub fn create(path: &Path, sources: Vec<String>) -> io::Result<ProgressFile> {
+        let file = ProgressFile {
+            path: path.to_path_buf(),
+            sources,
+            done: AtomicUsize::new(0),
+        };
+        file.write(0)?;
+        Ok(file)
+    }
+
+    /// Records that one more image is done, moving on to the next.
+    pub fn advance(&self) -> io::Result<()> {
+        let done = self.done.fetch_add(1, Ordering::Relaxed) + 1;
+        self.write(done)
+    }
+
+    /// Replaces the file by renaming a complete copy over it, so a reader never sees half of
+    /// one.
+    fn write(&self, done: usize) -> io::Result<()> {
+        let snapshot = Snapshot {
+            done,
+            total: self.sources.len(),
+            current: self.sources.get(done).map(String::as_str),
+        };
+        let mut temporary = self.path.clone().into_os_string();
+        temporary.push(".tmp");
+
+        let json = serde_json::to_vec(&snapshot).map_err(io::Error::other)?;
+        fs::write(&temporary, json)?;
+        fs::rename(&temporary, &self.path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ProgressFile;
+    use serde_json::{json, Value};
+    use std::fs;
+
+    #[test]
+    fn rewritten_as_images_complete() {
+        let path = std::env::temp_dir().join(format!("resize-{}.progress", std::process::id()));
+        let read = || serde_json::from_slice::<Value>(&fs::read(&path).unwrap()).unwrap();
+        let sources = vec![String::from("a.jpg"), String::from("b.jpg")];
+
+        let file = ProgressFile::create(&path, sources).unwrap();
+        assert_eq!(read(), json!({ "done": 0, "total": 2, "current": "a.jpg" }));
+        file.advance().unwrap();
+        assert_eq!(read(), json!({ "done": 1, "total": 2, "current": "b.jpg" }));
+        file.advance().unwrap();
+        assert_eq!(read(), json!({ "done": 2, "total": 2, "current": null }));
+        fs::remove_file(path).unwrap();
+    }
+}
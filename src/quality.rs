@@ -0,0 +1,95 @@
+use serde::Serialize;
+
+/// Encoder quality, optionally varying with output size.
+///
+/// Parsed from a comma-separated list in which a bare number sets the default and `size=q`
+/// entries override it for a particular output size, e.g. `82,256=70,1024=85`.
+#[derive(Clone, Debug, Default, PartialEq, Serialize)]
+pub struct Quality {
+    default: Option<u8>,
+    by_size: Vec<(u32, u8)>,
+}
+
+impl Quality {
+    pub fn parse(s: &str) -> Result<Quality, String> {
+        let mut quality = Quality::default();
+
+        for entry in s.split(',').map(str::trim) {
+            match entry.split_once('=') {
+                Some((size, value)) => {
+                    let size = size
+                        .parse::<u32>()
+                        .ok()
+                        .filter(|&size| size > 0)
+                        .ok_or_else(|| format!("'{}' is not a valid size", size))?;
+                    if quality.sizes().any(|existing| existing == size) {
+                        return Err(format!("quality for size {} given more than once", size));
+                    }
+                    quality.by_size.push((size, parse_value(value)?));
+                }
+                None if quality.default.is_none() => quality.default = Some(parse_value(entry)?),
+                None => return Err(String::from("default quality given more than once")),
+            }
+        }
+
+        Ok(quality)
+    }
+
+    /// Sizes with an explicit quality mapping.
+    pub fn sizes(&self) -> impl Iterator<Item = u32> + '_ {
+        self.by_size.iter().map(|&(size, _)| size)
+    }
+
+    /// The quality to encode an output of the given size with, if any was requested.
+    pub fn for_size(&self, size: u32) -> Option<u8> {
+        self.by_size
+            .iter()
+            .find(|&&(candidate, _)| candidate == size)
+            .map(|&(_, quality)| quality)
+            .or(self.default)
+    }
+}
+
+fn parse_value(s: &str) -> Result<u8, String> {
+    s.parse::<u8>()
+        .ok()
+        .filter(|quality| (1..=100).contains(quality))
+        .ok_or_else(|| format!("'{}' is not a quality between 1 and 100", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Quality;
+
+    #[test]
+    fn default_only() {
+        let quality = Quality::parse("85").unwrap();
+        assert_eq!(quality.for_size(256), Some(85));
+        assert_eq!(quality.for_size(1024), Some(85));
+    }
+
+    #[test]
+    fn each_size_gets_its_quality() {
+        let quality = Quality::parse("256=70,1024=85").unwrap();
+        assert_eq!(quality.for_size(256), Some(70));
+        assert_eq!(quality.for_size(1024), Some(85));
+        assert_eq!(quality.for_size(512), None);
+    }
+
+    #[test]
+    fn unmapped_sizes_use_default() {
+        let quality = Quality::parse("256=70, 90").unwrap();
+        assert_eq!(quality.for_size(256), Some(70));
+        assert_eq!(quality.for_size(2048), Some(90));
+    }
+
+    #[test]
+    fn invalid() {
+        assert!(Quality::parse("0").is_err());
+        assert!(Quality::parse("101").is_err());
+        assert!(Quality::parse("256=").is_err());
+        assert!(Quality::parse("x=80").is_err());
+        assert!(Quality::parse("80,90").is_err());
+        assert!(Quality::parse("256=70,256=80").is_err());
+    }
+}
@@ -0,0 +1,223 @@
+//! Reducing PNG outputs to a palette of indexed colors, for `--quantize-colors`.
+
+use std::{collections::HashMap, io};
+
+use color_quant::NeuQuant;
+use image::{ColorType, DynamicImage, ImageBuffer, RgbaImage};
+use serde::Serialize;
+
+/// How NeuQuant samples pixels while learning a palette: 1 looks at every pixel and 30 is
+/// fastest. 10 is the usual balance.
+const SAMPLE_FACTOR: i32 = 10;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct Quantize {
+    /// The most colors the palette may have, from 2 to 256.
+    pub colors: u16,
+    /// Diffuses the error of each pixel's nearest palette color into its neighbors, trading
+    /// banding for noise.
+    pub dither: bool,
+}
+
+/// A palette, with the index of each pixel's color in it.
+struct Indexed {
+    palette: Vec<[u8; 4]>,
+    indices: Vec<u8>,
+}
+
+/// Encodes `data` as an indexed PNG of at most `quantize.colors` colors, keeping any alpha in
+/// the palette. An image with no more colors than that keeps them exactly.
+pub fn encode_png(
+    data: &[u8],
+    (width, height): (u32, u32),
+    color: ColorType,
+    quantize: Quantize,
+) -> io::Result<Vec<u8>> {
+    let rgba = to_rgba(data, (width, height), color).ok_or_else(|| {
+        io::Error::new(
+            io::ErrorKind::Unsupported,
+            format!("quantizing {:?} images is not supported", color),
+        )
+    })?;
+    let colors = usize::from(quantize.colors);
+    let indexed = exact(&rgba, colors).unwrap_or_else(|| learned(&rgba, colors, quantize.dither));
+
+    let mut bytes = Vec::new();
+    let mut encoder = png::Encoder::new(&mut bytes, width, height);
+    encoder.set_color(png::ColorType::Indexed);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder.set_palette(
+        indexed
+            .palette
+            .iter()
+            .flat_map(|color| color[..3].to_vec())
+            .collect(),
+    );
+    // Entries past the last translucent one are opaque, so needn't be listed.
+    let alpha: Vec<u8> = indexed.palette.iter().map(|color| color[3]).collect();
+    if let Some(last) = alpha.iter().rposition(|&alpha| alpha < 255) {
+        encoder.set_trns(alpha[..=last].to_vec());
+    }
+    let mut writer = encoder.write_header().map_err(io::Error::other)?;
+    writer
+        .write_image_data(&indexed.indices)
+        .map_err(io::Error::other)?;
+    drop(writer);
+    Ok(bytes)
+}
+
+fn to_rgba(data: &[u8], (width, height): (u32, u32), color: ColorType) -> Option<RgbaImage> {
+    let bytes = data.to_vec();
+    let words = || -> Vec<u16> {
+        data.chunks_exact(2)
+            .map(|word| u16::from_ne_bytes([word[0], word[1]]))
+            .collect()
+    };
+    let image = match color {
+        ColorType::L8 => DynamicImage::ImageLuma8(ImageBuffer::from_raw(width, height, bytes)?),
+        ColorType::La8 => DynamicImage::ImageLumaA8(ImageBuffer::from_raw(width, height, bytes)?),
+        ColorType::Rgb8 => DynamicImage::ImageRgb8(ImageBuffer::from_raw(width, height, bytes)?),
+        ColorType::Rgba8 => return ImageBuffer::from_raw(width, height, bytes),
+        ColorType::Bgr8 => DynamicImage::ImageBgr8(ImageBuffer::from_raw(width, height, bytes)?),
+        ColorType::Bgra8 => DynamicImage::ImageBgra8(ImageBuffer::from_raw(width, height, bytes)?),
+        ColorType::L16 => DynamicImage::ImageLuma16(ImageBuffer::from_raw(width, height, words())?),
+        ColorType::La16 => {
+            DynamicImage::ImageLumaA16(ImageBuffer::from_raw(width, height, words())?)
+        }
+        ColorType::Rgb16 => {
+            DynamicImage::ImageRgb16(ImageBuffer::from_raw(width, height, words())?)
+        }
+        ColorType::Rgba16 => {
+            DynamicImage::ImageRgba16(ImageBuffer::from_raw(width, height, words())?)
+        }
+        _ => return None,
+    };
+    Some(image.to_rgba())
+}
+
+/// The image's own colors as the palette, if there are no more than `colors` of them.
+fn exact(image: &RgbaImage, colors: usize) -> Option<Indexed> {
+    let mut palette = Vec::new();
+    let mut index_of = HashMap::new();
+    let mut indices = Vec::with_capacity(image.len() / 4);
+    for pixel in image.pixels() {
+        let index = match index_of.get(&pixel.0) {
+            Some(&index) => index,
+            None if palette.len() == colors => return None,
+            None => {
+                let index = palette.len() as u8;
+                palette.push(pixel.0);
+                index_of.insert(pixel.0, index);
+                index
+            }
+        };
+        indices.push(index);
+    }
+    Some(Indexed { palette, indices })
+}
+
+/// A palette learned from the image by NeuQuant, with each pixel mapped to its nearest color,
+/// dithered if asked.
+fn learned(image: &RgbaImage, colors: usize, dither: bool) -> Indexed {
+    let quantizer = NeuQuant::new(SAMPLE_FACTOR, colors, image.as_raw());
+    let palette: Vec<[u8; 4]> = quantizer
+        .color_map_rgba()
+        .chunks_exact(4)
+        .map(|color| [color[0], color[1], color[2], color[3]])
+        .collect();
+
+    if !dither {
+        let indices = image
+            .pixels()
+            .map(|pixel| quantizer.index_of(&pixel.0) as u8)
+            .collect();
+        return Indexed { palette, indices };
+    }
+
+    // Floyd-Steinberg: each pixel's error goes 7/16 right, then 3/16, 5/16 and 1/16 to the
+    // pixels below left, below and below right.
+    let (width, height) = (image.width() as usize, image.height() as usize);
+    let mut errors = vec![[0.0f32; 4]; width * 2];
+    let mut indices = Vec::with_capacity(width * height);
+    for y in 0..height {
+        let (current, next) = errors.split_at_mut(width);
+        for x in 0..width {
+            let pixel = image.get_pixel(x as u32, y as u32).0;
+            let mut wanted = [0u8; 4];
+            for channel in 0..4 {
+                let value = f32::from(pixel[channel]) + current[x][channel];
+                wanted[channel] = value.round().clamp(0.0, 255.0) as u8;
+            }
+            let index = quantizer.index_of(&wanted);
+            indices.push(index as u8);
+
+            let chosen = palette[index];
+            for channel in 0..4 {
+                let error = f32::from(wanted[channel]) - f32::from(chosen[channel]);
+                if x + 1 < width {
+                    current[x + 1][channel] += error * 7.0 / 16.0;
+                    next[x + 1][channel] += error / 16.0;
+                }
+                if x > 0 {
+                    next[x - 1][channel] += error * 3.0 / 16.0;
+                }
+                next[x][channel] += error * 5.0 / 16.0;
+            }
+        }
+        // The next row's errors become the current ones, and the row after starts clean.
+        errors.rotate_left(width);
+        errors[width..].fill([0.0; 4]);
+    }
+    Indexed { palette, indices }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_png, Quantize};
+    use image::{ColorType, GenericImageView, Rgba, RgbaImage};
+
+    fn decoded_colors(bytes: &[u8]) -> usize {
+        let image = image::load_from_memory(bytes).unwrap().to_rgba();
+        let mut colors: Vec<_> = image.pixels().map(|pixel| pixel.0).collect();
+        colors.sort_unstable();
+        colors.dedup();
+        colors.len()
+    }
+
+    fn indexed(bytes: &[u8]) -> bool {
+        // The IHDR's color type byte: 3 is indexed.
+        bytes[25] == 3
+    }
+
+    #[test]
+    fn few_colors_are_kept_exactly() {
+        let image = RgbaImage::from_fn(16, 16, |x, _| match x % 3 {
+            0 => Rgba([255, 0, 0, 255]),
+            1 => Rgba([0, 0, 255, 128]),
+            _ => Rgba([0, 0, 0, 0]),
+        });
+        let quantize = Quantize {
+            colors: 4,
+            dither: false,
+        };
+        let bytes = encode_png(image.as_raw(), (16, 16), ColorType::Rgba8, quantize).unwrap();
+        assert!(indexed(&bytes));
+        let decoded = image::load_from_memory(&bytes).unwrap().to_rgba();
+        assert_eq!(decoded, image);
+    }
+
+    #[test]
+    fn many_colors_are_reduced() {
+        let image = RgbaImage::from_fn(64, 64, |x, y| {
+            Rgba([(x * 4) as u8, (y * 4) as u8, 128, 255])
+        });
+        for &dither in &[false, true] {
+            let quantize = Quantize { colors: 16, dither };
+            let bytes = encode_png(image.as_raw(), (64, 64), ColorType::Rgba8, quantize).unwrap();
+            assert!(indexed(&bytes));
+            assert!(decoded_colors(&bytes) <= 16);
+            let decoded = image::load_from_memory(&bytes).unwrap();
+            assert_eq!(decoded.dimensions(), (64, 64));
+        }
+    }
+}
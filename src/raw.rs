@@ -0,0 +1,171 @@
+//! Camera raw files, decoded with `rawloader` when built with the `raw` feature.
+//!
+//! Demosaicing is deliberately basic: each 2x2 block of a Bayer sensor becomes one output
+//! pixel, so the decoded image is half the sensor's resolution. That is plenty for anything this
+//! tool produces, which is nearly always smaller still.
+
+use std::{io, path::Path};
+
+use image::DynamicImage;
+
+/// Extensions of the raw formats `rawloader` understands.
+const EXTENSIONS: &[&str] = &[
+    "3fr", "arw", "cr2", "crw", "dcr", "dng", "erf", "kdc", "mef", "mos", "mrw", "nef", "nrw",
+    "orf", "pef", "raf", "rw2", "sr2", "srf", "srw",
+];
+
+pub fn is_raw(path: &Path) -> bool {
+    path.extension()
+        .and_then(|ext| ext.to_str())
+        .is_some_and(|ext| EXTENSIONS.iter().any(|raw| raw.eq_ignore_ascii_case(ext)))
+}
+
+#[cfg(not(feature = "raw"))]
+pub fn decode(_data: &[u8]) -> io::Result<DynamicImage> {
+    Err(io::Error::new(
+        io::ErrorKind::Unsupported,
+        "camera raw files require building with the `raw` feature",
+    ))
+}
+
+#[cfg(feature = "raw")]
+pub fn decode(data: &[u8]) -> io::Result<DynamicImage> {
+    use image::{ImageBuffer, Rgb};
+    use rawloader::RawImageData;
+
+    let raw = rawloader::decode(&mut io::Cursor::new(data))
+        .map_err(|e| io::Error::other(e.to_string()))?;
+    let data: Vec<f32> = match &raw.data {
+        RawImageData::Integer(data) => data.iter().map(|&value| value as f32).collect(),
+        RawImageData::Float(data) => data.clone(),
+    };
+
+    let [top, right, bottom, left] = raw.crops;
+    let width = raw.width.saturating_sub(left + right);
+    let height = raw.height.saturating_sub(top + bottom);
+    let sample = |row: usize, col: usize, channel: usize| {
+        data[((top + row) * raw.width + left + col) * raw.cpp + channel]
+    };
+
+    let levels = Levels::new(&raw);
+    let cfa = raw.cropped_cfa();
+    let pixel = |x: usize, y: usize| -> [u8; 3] {
+        if raw.cpp == 3 {
+            return [0, 1, 2].map(|c| encode_srgb(levels.normalize(c, sample(y, x, c))));
+        }
+
+        // Average each color over the block; a second green counts as green.
+        let mut sums = [0f32; 3];
+        let mut counts = [0f32; 3];
+        for (dy, dx) in [(0, 0), (0, 1), (1, 0), (1, 1)] {
+            let (row, col) = (y * 2 + dy, x * 2 + dx);
+            let color = cfa.color_at(row, col);
+            let channel = if color == 3 { 1 } else { color };
+            sums[channel] += levels.normalize(color, sample(row, col, 0));
+            counts[channel] += 1.0;
+        }
+
+        let mut rgb = [0u8; 3];
+        for channel in 0..3 {
+            let value = if counts[channel] > 0.0 {
+                sums[channel] / counts[channel]
+            } else {
+                // Monochrome sensors report a single color; use it for everything.
+                sums.iter().sum::<f32>() / counts.iter().sum::<f32>()
+            };
+            rgb[channel] = encode_srgb(value);
+        }
+        rgb
+    };
+
+    let (out_width, out_height) = if raw.cpp == 3 {
+        (width, height)
+    } else {
+        (width / 2, height / 2)
+    };
+    if out_width == 0 || out_height == 0 {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            "raw image is too small to demosaic",
+        ));
+    }
+
+    let image = ImageBuffer::from_fn(out_width as u32, out_height as u32, |x, y| {
+        Rgb(pixel(x as usize, y as usize))
+    });
+    Ok(DynamicImage::ImageRgb8(image))
+}
+
+/// Black and white points and white balance, per sensor color.
+#[cfg(feature = "raw")]
+struct Levels {
+    black: [f32; 4],
+    range: [f32; 4],
+    balance: [f32; 4],
+}
+
+#[cfg(feature = "raw")]
+impl Levels {
+    fn new(raw: &rawloader::RawImage) -> Levels {
+        let black = raw.blacklevels.map(|level| level as f32);
+        let mut range = [1f32; 4];
+        for (index, range) in range.iter_mut().enumerate() {
+            *range = (raw.whitelevels[index] as f32 - black[index]).max(1.0);
+        }
+
+        // Coefficients are relative to green, and missing ones show up as NaN.
+        let green = raw.wb_coeffs[1];
+        let balance = raw.wb_coeffs.map(|coeff| {
+            let coeff = coeff / green;
+            if coeff.is_finite() && coeff > 0.0 {
+                coeff
+            } else {
+                1.0
+            }
+        });
+
+        Levels {
+            black,
+            range,
+            balance,
+        }
+    }
+
+    /// Maps a sensor value for `color` to linear light in `0.0..=1.0`.
+    fn normalize(&self, color: usize, value: f32) -> f32 {
+        let value = (value - self.black[color]) / self.range[color] * self.balance[color];
+        value.clamp(0.0, 1.0)
+    }
+}
+
+/// Converts linear light to an 8-bit sRGB value.
+#[cfg_attr(not(feature = "raw"), allow(dead_code))]
+fn encode_srgb(linear: f32) -> u8 {
+    let encoded = if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round().clamp(0.0, 255.0) as u8
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{encode_srgb, is_raw};
+    use std::path::Path;
+
+    #[test]
+    fn raw_extensions() {
+        assert!(is_raw(Path::new("IMG_0001.CR2")));
+        assert!(is_raw(Path::new("dsc/0001.nef")));
+        assert!(!is_raw(Path::new("photo.jpg")));
+        assert!(!is_raw(Path::new("nef")));
+    }
+
+    #[test]
+    fn srgb_endpoints() {
+        assert_eq!(encode_srgb(0.0), 0);
+        assert_eq!(encode_srgb(1.0), 255);
+        assert_eq!(encode_srgb(0.5), 188);
+    }
+}
@@ -0,0 +1,146 @@
+//! Surveying the pixel formats of inputs without resizing them, for `--pixel-format-report`.
+
+use std::{
+    collections::BTreeMap,
+    fs,
+    io::{self, Cursor},
+    path::Path,
+};
+
+use resize::{color, meta, Job};
+
+/// How an image stores its pixels, as far as its header says.
+#[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub struct PixelFormat {
+    pub format: String,
+    pub channels: &'static str,
+    pub depth: u16,
+    pub alpha: bool,
+    pub icc: bool,
+}
+
+impl PixelFormat {
+    /// Reads the pixel format of an encoded image from its header, decoding it in full only
+    /// for formats without a header decoder.
+    pub fn read(bytes: &[u8], path: &Path) -> io::Result<PixelFormat> {
+        let meta = meta::read_metadata_from(Cursor::new(bytes), path)?;
+        let channels = match meta.color.channel_count() {
+            1 => "gray",
+            2 => "gray+alpha",
+            3 => "rgb",
+            _ => "rgba",
+        };
+        Ok(PixelFormat {
+            format: format!("{:?}", meta.format).to_lowercase(),
+            channels,
+            depth: meta.depth(),
+            alpha: meta.color.has_alpha(),
+            icc: color::embedded_profile(bytes).is_some(),
+        })
+    }
+}
+
+impl std::fmt::Display for PixelFormat {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{:<8}{:<12}{:<7}{:<7}{:<5}",
+            self.format,
+            self.channels,
+            self.depth,
+            yes_no(self.alpha),
+            yes_no(self.icc)
+        )
+    }
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+/// Prints how many of `jobs` have each pixel format, and each job's own if `verbose`.
+pub fn print(jobs: &[Job], verbose: bool) {
+    let mut counts = BTreeMap::new();
+    let mut unreadable = 0;
+    for job in jobs {
+        let path = Path::new(&job.source);
+        let read = match &job.data {
+            Some(data) => PixelFormat::read(data, path),
+            None => fs::read(path).and_then(|bytes| PixelFormat::read(&bytes, path)),
+        };
+        match read {
+            Ok(format) => {
+                if verbose {
+                    println!("{}{}", format, job.source);
+                }
+                *counts.entry(format).or_insert(0) += 1;
+            }
+            Err(e) => {
+                if verbose {
+                    println!("unreadable ({}): {}", e, job.source);
+                }
+                unreadable += 1;
+            }
+        }
+    }
+
+    if verbose {
+        println!();
+    }
+    println!(
+        "{:<8}{:<12}{:<7}{:<7}{:<5}count",
+        "format", "color", "depth", "alpha", "icc"
+    );
+    for (format, count) in &counts {
+        println!("{}{}", format, count);
+    }
+    if unreadable > 0 {
+        println!("{} unreadable", unreadable);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::PixelFormat;
+    use image::{DynamicImage, ImageBuffer, ImageFormat, Rgba};
+    use resize::color::{self, ColorSpace};
+    use std::path::Path;
+
+    #[test]
+    fn reads_headers() {
+        let mut bytes = Vec::new();
+        let deep = ImageBuffer::from_pixel(4, 3, Rgba([0u16, 0, 0, 65535]));
+        DynamicImage::ImageRgba16(deep)
+            .write_to(&mut bytes, ImageFormat::Png)
+            .unwrap();
+        let format = PixelFormat::read(&bytes, Path::new("deep.png")).unwrap();
+        assert_eq!(
+            format,
+            PixelFormat {
+                format: String::from("png"),
+                channels: "rgba",
+                depth: 16,
+                alpha: true,
+                icc: false,
+            }
+        );
+
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(4, 3)
+            .write_to(&mut bytes, ImageFormat::Jpeg)
+            .unwrap();
+        color::tag(&mut bytes, ImageFormat::Jpeg, ColorSpace::DisplayP3);
+        let format = PixelFormat::read(&bytes, Path::new("wide.jpg")).unwrap();
+        assert_eq!((format.channels, format.depth), ("rgb", 8));
+        assert!(!format.alpha && format.icc);
+    }
+
+    #[test]
+    fn unreadable_inputs_fail() {
+        assert!(PixelFormat::read(b"not an image", Path::new("a.txt")).is_err());
+    }
+}
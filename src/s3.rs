@@ -0,0 +1,145 @@
+//! Uploading outputs to S3 or compatible object storage, instead of writing files.
+//!
+//! Credentials come from `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and, optionally,
+//! `AWS_SESSION_TOKEN`; the region from `AWS_REGION` or `AWS_DEFAULT_REGION`. Setting
+//! `AWS_ENDPOINT_URL` points uploads at another S3-compatible service, using path-style URLs.
+
+use std::{
+    io,
+    path::{Component, Path},
+};
+
+/// Splits an `s3://bucket/prefix/` URL into its bucket and key prefix.
+pub fn parse_url(url: &str) -> Result<(String, String), String> {
+    let invalid = || format!("'{}' is not a URL such as s3://bucket/prefix/", url);
+    let rest = url.strip_prefix("s3://").ok_or_else(invalid)?;
+    let (bucket, prefix) = rest.split_once('/').unwrap_or((rest, ""));
+
+    if bucket.is_empty() {
+        return Err(invalid());
+    }
+    Ok((bucket.to_string(), prefix.to_string()))
+}
+
+/// The object key an output is uploaded under: its path, made relative, after the prefix.
+#[cfg_attr(not(feature = "s3"), allow(dead_code))]
+fn object_key(prefix: &str, path: &Path) -> String {
+    let mut key = prefix.to_string();
+    if !key.is_empty() && !key.ends_with('/') {
+        key.push('/');
+    }
+
+    let parts: Vec<_> = path
+        .components()
+        .filter_map(|component| match component {
+            Component::Normal(part) => Some(part.to_string_lossy()),
+            _ => None,
+        })
+        .collect();
+    key.push_str(&parts.join("/"));
+    key
+}
+
+#[cfg(not(feature = "s3"))]
+pub struct Uploader;
+
+#[cfg(not(feature = "s3"))]
+impl Uploader {
+    pub fn from_env(_url: &str) -> io::Result<Uploader> {
+        Err(io::Error::new(
+            io::ErrorKind::Unsupported,
+            "uploading to S3 requires building with the `s3` feature",
+        ))
+    }
+
+    pub fn put(&self, _path: &Path, _bytes: &[u8]) -> io::Result<()> {
+        unreachable!("an uploader is never built without the `s3` feature")
+    }
+}
+
+#[cfg(feature = "s3")]
+pub struct Uploader {
+    bucket: rusty_s3::Bucket,
+    credentials: rusty_s3::Credentials,
+    prefix: String,
+}
+
+#[cfg(feature = "s3")]
+impl Uploader {
+    /// An uploader for `url`, configured from the standard AWS environment variables.
+    pub fn from_env(url: &str) -> io::Result<Uploader> {
+        use rusty_s3::{Bucket, Credentials, UrlStyle};
+        use std::env;
+
+        let (bucket, prefix) = parse_url(url).map_err(io::Error::other)?;
+        let credentials = Credentials::from_env().ok_or_else(|| {
+            io::Error::other("AWS_ACCESS_KEY_ID and AWS_SECRET_ACCESS_KEY must be set")
+        })?;
+        let region = env::var("AWS_REGION")
+            .or_else(|_| env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| String::from("us-east-1"));
+        let (endpoint, style) = match env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => (endpoint, UrlStyle::Path),
+            Err(_) => (
+                format!("https://s3.{}.amazonaws.com", region),
+                UrlStyle::VirtualHost,
+            ),
+        };
+
+        let endpoint = endpoint.parse().map_err(io::Error::other)?;
+        Ok(Uploader {
+            bucket: Bucket::new(endpoint, style, bucket, region).map_err(io::Error::other)?,
+            credentials,
+            prefix,
+        })
+    }
+
+    /// Uploads `bytes` as the output at `path`.
+    pub fn put(&self, path: &Path, bytes: &[u8]) -> io::Result<()> {
+        use rusty_s3::S3Action;
+        use std::time::Duration;
+
+        let key = object_key(&self.prefix, path);
+        let url = self
+            .bucket
+            .put_object(Some(&self.credentials), &key)
+            .sign(Duration::from_secs(60 * 60));
+        ureq::put(url.as_str())
+            .send(bytes)
+            .map_err(|e| io::Error::other(format!("uploading {}: {}", key, e)))?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{object_key, parse_url};
+    use std::path::Path;
+
+    #[test]
+    fn parse_s3_url() {
+        assert_eq!(
+            parse_url("s3://assets/thumbs/"),
+            Ok((String::from("assets"), String::from("thumbs/")))
+        );
+        assert_eq!(
+            parse_url("s3://assets"),
+            Ok((String::from("assets"), String::new()))
+        );
+        assert!(parse_url("https://assets/thumbs").is_err());
+        assert!(parse_url("s3:///thumbs").is_err());
+    }
+
+    #[test]
+    fn keys_follow_the_prefix() {
+        assert_eq!(
+            object_key("thumbs", Path::new("./a/cat.jpg")),
+            "thumbs/a/cat.jpg"
+        );
+        assert_eq!(
+            object_key("thumbs/", Path::new("/cat.jpg")),
+            "thumbs/cat.jpg"
+        );
+        assert_eq!(object_key("", Path::new("../cat.jpg")), "cat.jpg");
+    }
+}
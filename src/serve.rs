@@ -0,0 +1,169 @@
+//! A long-running mode for servers, answering JSON-lines requests on stdin with JSON-lines
+//! responses on stdout, so one warm process can resize image after image.
+//!
+//! A request is a plan entry, with `path` for its source and an `id` echoed in the response:
+//!
+//! ```json
+//! { "id": 7, "path": "hero.png", "op": "shrink", "size": 640 }
+//! ```
+
+use std::{
+    fs,
+    io::{self, BufRead, Write},
+};
+
+use serde::Serialize;
+use serde_json::Value;
+
+use resize::{self, Job, ResizeOptions};
+
+use crate::plan;
+
+#[derive(Debug, Serialize)]
+struct Response {
+    id: Value,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    outputs: Option<Vec<Written>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    error: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+struct Written {
+    status: &'static str,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    height: Option<u32>,
+}
+
+/// Answers requests until stdin closes. Only failing to read or answer ends it early; a bad
+/// request gets an error response, as does one whose output would overwrite its source,
+/// unless the options allow writing in place.
+pub fn run(options: &ResizeOptions) -> io::Result<()> {
+    let mut stdout = io::stdout().lock();
+    for line in io::stdin().lock().lines() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let response = respond(&line, options);
+        serde_json::to_writer(&mut stdout, &response).map_err(io::Error::other)?;
+        writeln!(stdout)?;
+        stdout.flush()?;
+    }
+    Ok(())
+}
+
+fn respond(line: &str, options: &ResizeOptions) -> Response {
+    // The id is taken first, so even a request that fails later can be answered by it.
+    let mut request: Value = match serde_json::from_str(line) {
+        Ok(request) => request,
+        Err(e) => return failure(Value::Null, e.to_string()),
+    };
+    let id = match request.as_object_mut() {
+        Some(fields) => fields.remove("id").unwrap_or(Value::Null),
+        None => return failure(Value::Null, String::from("a request must be an object")),
+    };
+
+    let job = match plan::entry_job(request, options.settings()) {
+        Ok(job) => job,
+        Err(e) => return failure(id, e),
+    };
+    if !options.in_place() && resize::overwrites_source(&job, options) {
+        let error = format!("{} would be overwritten by its output", job.source);
+        return failure(id, error);
+    }
+    match process(&job, options) {
+        Ok(outputs) => Response {
+            id,
+            outputs: Some(outputs),
+            error: None,
+        },
+        Err(e) => failure(id, format!("{}: {}", job.source, e)),
+    }
+}
+
+fn process(job: &Job, options: &ResizeOptions) -> io::Result<Vec<Written>> {
+    let mut written = Vec::new();
+    for output in resize::process(job, options)? {
+        if let (Some(path), Some(bytes)) = (&output.path, &output.bytes) {
+            fs::write(path, bytes)?;
+        }
+        written.push(Written {
+            status: output.status.name(),
+            path: output.path.map(|path| path.to_string_lossy().into_owned()),
+            width: output.dimensions.map(|(width, _)| width),
+            height: output.dimensions.map(|(_, height)| height),
+        });
+    }
+    Ok(written)
+}
+
+fn failure(id: Value, error: String) -> Response {
+    Response {
+        id,
+        outputs: None,
+        error: Some(error),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::respond;
+    use resize::ResizeOptions;
+    use serde_json::Value;
+
+    fn options() -> ResizeOptions {
+        ResizeOptions::builder().build_defaults().unwrap()
+    }
+
+    fn in_place() -> ResizeOptions {
+        ResizeOptions::builder()
+            .in_place(true)
+            .build_defaults()
+            .unwrap()
+    }
+
+    #[test]
+    fn malformed_requests_are_answered() {
+        let response = respond("{ not json", &options());
+        assert_eq!(response.id, Value::Null);
+        assert!(response.error.is_some());
+
+        let response = respond(
+            r#"{ "id": "a", "path": "x.png", "op": "explode" }"#,
+            &options(),
+        );
+        assert_eq!(response.id, Value::from("a"));
+        assert!(response.error.unwrap().contains("explode"));
+    }
+
+    #[test]
+    fn failures_keep_their_id() {
+        let response = respond(
+            r#"{ "id": 3, "path": "/no/such.png", "size": 9 }"#,
+            &in_place(),
+        );
+        assert_eq!(response.id, Value::from(3));
+        assert!(response.outputs.is_none());
+        assert!(response.error.unwrap().starts_with("/no/such.png"));
+    }
+
+    #[test]
+    fn refuses_to_overwrite_sources() {
+        let request = r#"{ "id": 4, "path": "/no/such.png", "size": 9 }"#;
+        let response = respond(request, &options());
+        assert_eq!(
+            response.error.unwrap(),
+            "/no/such.png would be overwritten by its output"
+        );
+
+        let request = r#"{ "id": 5, "path": "/no/such.png", "size": 9, "format": "jpeg" }"#;
+        let response = respond(request, &options());
+        assert!(!response.error.unwrap().contains("overwritten"));
+    }
+}
@@ -0,0 +1,80 @@
+use image::ImageFormat;
+use serde::{Serialize, Serializer};
+
+use crate::{ico, quality::Quality};
+
+#[derive(Copy, Clone, Debug, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum Operation {
+    Shrink,
+    Enlarge,
+    /// Shrinks or enlarges, whichever brings the longest edge to exactly the size.
+    Fit,
+    Tiles,
+    OrientOnly,
+    /// Places the image unscaled on a canvas of a fixed size.
+    Canvas,
+}
+
+impl Operation {
+    pub fn from_name(name: &str) -> Option<Operation> {
+        match name {
+            "shrink" | "down" => Some(Operation::Shrink),
+            "enlarge" | "up" => Some(Operation::Enlarge),
+            "fit" | "both" => Some(Operation::Fit),
+            "tiles" => Some(Operation::Tiles),
+            "orient-only" => Some(Operation::OrientOnly),
+            "canvas" => Some(Operation::Canvas),
+            _ => None,
+        }
+    }
+
+    pub(crate) fn uses_sizes(self) -> bool {
+        matches!(
+            self,
+            Operation::Shrink | Operation::Enlarge | Operation::Fit
+        )
+    }
+}
+
+/// What to do with a single image.
+#[derive(Clone, Debug, Serialize)]
+pub struct Settings {
+    pub operation: Operation,
+    pub sizes: Vec<u32>,
+    #[serde(serialize_with = "format_name")]
+    pub format: Option<ImageFormat>,
+    pub quality: Quality,
+}
+
+/// Serializes a format by name, e.g. `jpeg`.
+pub(crate) fn format_name<S: Serializer>(
+    format: &Option<ImageFormat>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    let name = format.map(|format| format!("{:?}", format).to_lowercase());
+    name.serialize(serializer)
+}
+
+impl Settings {
+    pub fn validate(&self) -> Result<(), String> {
+        if self.operation.uses_sizes() && self.sizes.is_empty() {
+            return Err(String::from("no size given"));
+        }
+
+        if self.format == Some(ImageFormat::Ico)
+            && self.sizes.iter().any(|&size| size > ico::MAX_SIZE)
+        {
+            return Err(format!("icon sizes may not exceed {}px", ico::MAX_SIZE));
+        }
+
+        if let Some(size) = self.quality.sizes().find(|size| !self.sizes.contains(size)) {
+            return Err(format!(
+                "quality maps size {}, which is not a requested size",
+                size
+            ));
+        }
+
+        Ok(())
+    }
+}
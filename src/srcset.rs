@@ -0,0 +1,105 @@
+//! HTML `<img>` tags offering each source's outputs by width, for `--emit-srcset`.
+
+use std::{fs, io, path::Path, sync::Mutex};
+
+/// One output of a source, as a browser would choose among them.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Variant {
+    pub path: String,
+    pub width: u32,
+    pub height: u32,
+}
+
+/// A tag for every source with outputs, in the order they were committed, written out as one
+/// HTML file once the run ends.
+#[derive(Debug, Default)]
+pub struct Srcsets {
+    tags: Mutex<Vec<String>>,
+}
+
+impl Srcsets {
+    pub fn record(&self, source: &str, variants: Vec<Variant>) {
+        if let Some(tag) = tag(source, variants) {
+            self.tags.lock().unwrap().push(tag);
+        }
+    }
+
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let tags = self.tags.lock().unwrap();
+        fs::write(path, tags.concat())
+    }
+}
+
+/// A commented `<img>` tag for `source`, its `src` the widest variant and its `srcset` every
+/// width once, narrowest first. Paths are given as they were written.
+fn tag(source: &str, mut variants: Vec<Variant>) -> Option<String> {
+    variants.sort_by_key(|variant| variant.width);
+    variants.dedup_by_key(|variant| variant.width);
+    let widest = variants.last()?;
+
+    let srcset: Vec<String> = variants
+        .iter()
+        .map(|variant| format!("{} {}w", url(&variant.path), variant.width))
+        .collect();
+    Some(format!(
+        "<!-- {} -->\n<img src=\"{}\" srcset=\"{}\" width=\"{}\" height=\"{}\" alt=\"\">\n",
+        source.replace("--", "-&#45;"),
+        escape(&url(&widest.path)),
+        escape(&srcset.join(", ")),
+        widest.width,
+        widest.height,
+    ))
+}
+
+/// A path as a relative URL: forward slashes, with the spaces and commas that would split a
+/// `srcset` entry escaped.
+fn url(path: &str) -> String {
+    path.replace('\\', "/")
+        .replace('%', "%25")
+        .replace(' ', "%20")
+        .replace(',', "%2C")
+}
+
+/// Text safe to put in a double-quoted attribute.
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('"', "&quot;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{tag, Variant};
+
+    fn variant(path: &str, width: u32) -> Variant {
+        Variant {
+            path: path.to_string(),
+            width,
+            height: width * 3 / 4,
+        }
+    }
+
+    #[test]
+    fn offers_each_width_narrowest_first() {
+        let variants = vec![
+            variant("out/photo-512.jpg", 512),
+            variant("out/photo-256.jpg", 256),
+            variant("out/photo@2x.jpg", 512),
+        ];
+        assert_eq!(
+            tag("photo.jpg", variants).unwrap(),
+            "<!-- photo.jpg -->\n<img src=\"out/photo-512.jpg\" \
+             srcset=\"out/photo-256.jpg 256w, out/photo-512.jpg 512w\" \
+             width=\"512\" height=\"384\" alt=\"\">\n"
+        );
+        assert_eq!(tag("photo.jpg", Vec::new()), None);
+    }
+
+    #[test]
+    fn escapes_paths() {
+        let tag = tag("a.jpg", vec![variant("my photos/a, b&c.jpg", 64)]).unwrap();
+        assert!(tag.contains("src=\"my%20photos/a%2C%20b&amp;c.jpg\""));
+        assert!(tag.contains("srcset=\"my%20photos/a%2C%20b&amp;c.jpg 64w\""));
+    }
+}
@@ -0,0 +1,173 @@
+//! Measurements of a decoded image, for spotting blurry or badly exposed ones in a large set.
+
+use image::{DynamicImage, GrayImage};
+use serde::Serialize;
+
+#[derive(Clone, Debug, Serialize)]
+pub struct Stats {
+    /// Mean luma, from 0 to 255.
+    pub brightness: f64,
+    /// Shannon entropy of the luma histogram, in bits, from 0 for a flat image to 8.
+    pub entropy: f64,
+    /// Variance of the luma's Laplacian; the blurrier the image, the lower.
+    pub sharpness: f64,
+    /// Red, green and blue, or luma alone for a grayscale image.
+    pub channels: Vec<Channel>,
+}
+
+/// A summary of one channel's histogram.
+#[derive(Clone, Debug, Serialize)]
+pub struct Channel {
+    pub name: &'static str,
+    pub mean: f64,
+    pub median: u8,
+    /// The fraction of pixels at 0, where shadows are crushed.
+    pub clipped_low: f64,
+    /// The fraction of pixels at 255, where highlights are blown.
+    pub clipped_high: f64,
+}
+
+impl Stats {
+    /// The larger fraction of any channel's pixels at 255.
+    pub fn blown(&self) -> f64 {
+        self.channels
+            .iter()
+            .map(|channel| channel.clipped_high)
+            .fold(0.0, f64::max)
+    }
+
+    /// The larger fraction of any channel's pixels at 0.
+    pub fn crushed(&self) -> f64 {
+        self.channels
+            .iter()
+            .map(|channel| channel.clipped_low)
+            .fold(0.0, f64::max)
+    }
+}
+
+/// Measures `image`. Images of more than 8 bits are measured at 8.
+pub fn measure(image: &DynamicImage) -> Stats {
+    let luma = image.to_luma();
+    let luma_histogram = histogram(luma.as_raw(), 1, 0);
+
+    let channels = if image.color().channel_count() < 3 {
+        vec![channel("luma", &luma_histogram)]
+    } else {
+        let rgb = image.to_rgb();
+        ["red", "green", "blue"]
+            .iter()
+            .enumerate()
+            .map(|(index, name)| channel(name, &histogram(rgb.as_raw(), 3, index)))
+            .collect()
+    };
+
+    Stats {
+        brightness: mean(&luma_histogram),
+        entropy: entropy(&luma_histogram),
+        sharpness: sharpness(&luma),
+        channels,
+    }
+}
+
+fn histogram(bytes: &[u8], stride: usize, offset: usize) -> [u64; 256] {
+    let mut counts = [0; 256];
+    for &value in bytes.iter().skip(offset).step_by(stride) {
+        counts[usize::from(value)] += 1;
+    }
+    counts
+}
+
+fn channel(name: &'static str, histogram: &[u64; 256]) -> Channel {
+    let total = histogram.iter().sum::<u64>().max(1);
+    let mut seen = 0;
+    let median = histogram
+        .iter()
+        .position(|&count| {
+            seen += count;
+            2 * seen >= total
+        })
+        .unwrap_or(0) as u8;
+
+    Channel {
+        name,
+        mean: mean(histogram),
+        median,
+        clipped_low: histogram[0] as f64 / total as f64,
+        clipped_high: histogram[255] as f64 / total as f64,
+    }
+}
+
+fn mean(histogram: &[u64; 256]) -> f64 {
+    let total = histogram.iter().sum::<u64>().max(1);
+    let sum: u64 = (0..)
+        .zip(histogram)
+        .map(|(value, count)| value * count)
+        .sum();
+    sum as f64 / total as f64
+}
+
+fn entropy(histogram: &[u64; 256]) -> f64 {
+    let total = histogram.iter().sum::<u64>().max(1) as f64;
+    histogram
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / total;
+            p * (1.0 / p).log2()
+        })
+        .sum()
+}
+
+/// The variance of the 4-neighbour Laplacian over every pixel with all four neighbours.
+fn sharpness(luma: &GrayImage) -> f64 {
+    let (width, height) = luma.dimensions();
+    if width < 3 || height < 3 {
+        return 0.0;
+    }
+
+    let at = |x: u32, y: u32| f64::from(luma.get_pixel(x, y)[0]);
+    let (mut sum, mut squares) = (0.0, 0.0);
+    for y in 1..height - 1 {
+        for x in 1..width - 1 {
+            let laplacian =
+                4.0 * at(x, y) - at(x - 1, y) - at(x + 1, y) - at(x, y - 1) - at(x, y + 1);
+            sum += laplacian;
+            squares += laplacian * laplacian;
+        }
+    }
+    let count = f64::from((width - 2) * (height - 2));
+    let mean = sum / count;
+    squares / count - mean * mean
+}
+
+#[cfg(test)]
+mod tests {
+    use super::measure;
+    use image::{DynamicImage, GrayImage, Luma, Rgb, RgbImage};
+
+    #[test]
+    fn flat_image() {
+        let image = RgbImage::from_pixel(8, 8, Rgb([255, 128, 0]));
+        let stats = measure(&DynamicImage::ImageRgb8(image));
+        assert_eq!(stats.entropy, 0.0);
+        assert_eq!(stats.sharpness, 0.0);
+        assert_eq!(stats.channels.len(), 3);
+        assert_eq!(stats.channels[1].median, 128);
+        assert_eq!(stats.blown(), 1.0);
+        assert_eq!(stats.crushed(), 1.0);
+    }
+
+    #[test]
+    fn checkerboard_is_sharper_than_gradient() {
+        let checkerboard = GrayImage::from_fn(16, 16, |x, y| Luma([((x + y) % 2 * 255) as u8]));
+        let gradient = GrayImage::from_fn(16, 16, |x, _| Luma([(x * 16) as u8]));
+        let checkerboard = measure(&DynamicImage::ImageLuma8(checkerboard));
+        let gradient = measure(&DynamicImage::ImageLuma8(gradient));
+
+        assert_eq!(checkerboard.channels.len(), 1);
+        assert_eq!(checkerboard.entropy, 1.0);
+        assert_eq!(checkerboard.brightness, 127.5);
+        assert_eq!(gradient.entropy, 4.0);
+        assert!(checkerboard.sharpness > gradient.sharpness);
+    }
+}
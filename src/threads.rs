@@ -0,0 +1,56 @@
+//! Turning a `--jobs` spec into a number of threads.
+
+use std::thread;
+
+/// The number of threads `spec` asks for: a count, `auto` for one per core, or a multiple of
+/// the cores such as `0.5x` or `2x`. Multiples round down, but never below one thread.
+pub fn parse_jobs(spec: &str) -> Result<usize, String> {
+    let cores = thread::available_parallelism().map_or(1, |cores| cores.get());
+    jobs_for(spec, cores)
+}
+
+fn jobs_for(spec: &str, cores: usize) -> Result<usize, String> {
+    let invalid = || {
+        format!(
+            "'{}' is not a number of jobs, 'auto', or a multiple of the cores like '2x'",
+            spec
+        )
+    };
+
+    if spec == "auto" {
+        return Ok(cores);
+    }
+    if let Some(multiple) = spec.strip_suffix('x') {
+        return match multiple.parse::<f64>() {
+            Ok(multiple) if multiple.is_finite() && multiple > 0.0 => {
+                Ok(((cores as f64 * multiple) as usize).max(1))
+            }
+            _ => Err(invalid()),
+        };
+    }
+    match spec.parse::<usize>() {
+        Ok(jobs) if jobs > 0 => Ok(jobs),
+        _ => Err(invalid()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::jobs_for;
+
+    #[test]
+    fn counts_and_multiples() {
+        assert_eq!(jobs_for("3", 8), Ok(3));
+        assert_eq!(jobs_for("auto", 8), Ok(8));
+        assert_eq!(jobs_for("0.5x", 8), Ok(4));
+        assert_eq!(jobs_for("2x", 8), Ok(16));
+        assert_eq!(jobs_for("0.1x", 4), Ok(1));
+    }
+
+    #[test]
+    fn rejects_nonsense() {
+        for spec in &["0", "-1", "x", "0x", "-2x", "NaNx", "half"] {
+            assert!(jobs_for(spec, 8).is_err(), "{}", spec);
+        }
+    }
+}
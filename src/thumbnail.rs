@@ -0,0 +1,115 @@
+//! Embedding an EXIF thumbnail of a JPEG output, for `--regenerate-thumbnail`.
+
+use std::{convert::TryFrom, io};
+
+use image::ImageFormat;
+
+use crate::encode::{self, JpegOptions, PngOptions};
+
+/// The longest edge of an embedded thumbnail, as cameras commonly write them.
+pub const SIZE: u32 = 160;
+
+/// The quality thumbnails are encoded with; they only need to be recognizable.
+const QUALITY: u8 = 75;
+
+/// Adds an EXIF segment holding a thumbnail of the encoded JPEG in `bytes`, after the JFIF
+/// header if there is one. Other data leaves `bytes` as it is.
+pub fn embed(bytes: &mut Vec<u8>) -> io::Result<()> {
+    if !bytes.starts_with(&[0xFF, 0xD8]) {
+        return Ok(());
+    }
+    let image = image::load_from_memory_with_format(bytes, ImageFormat::Jpeg)
+        .map_err(io::Error::other)?
+        .thumbnail(SIZE, SIZE);
+    let thumbnail = encode::encode_dynamic(
+        &image,
+        ImageFormat::Jpeg,
+        Some(QUALITY),
+        JpegOptions::default(),
+        PngOptions::default(),
+    )?;
+
+    let exif = exif_with_thumbnail(&thumbnail);
+    // The segment's length counts itself but not its marker, and must fit in 16 bits.
+    let length = match u16::try_from(exif.len() + 2) {
+        Ok(length) => length,
+        Err(_) => return Ok(()),
+    };
+    let mut segment = vec![0xFF, 0xE1];
+    segment.extend_from_slice(&length.to_be_bytes());
+    segment.extend_from_slice(&exif);
+
+    let at = match bytes.get(2..6) {
+        Some(&[0xFF, 0xE0, high, low]) => 4 + usize::from(u16::from_be_bytes([high, low])),
+        _ => 2,
+    };
+    bytes.splice(at..at, segment);
+    Ok(())
+}
+
+/// EXIF data with an empty first IFD, for the image itself, and a second, for the thumbnail,
+/// pointing at `thumbnail` right after it.
+fn exif_with_thumbnail(thumbnail: &[u8]) -> Vec<u8> {
+    // The TIFF header, then the first IFD's entry count and the offset of the next.
+    const IFD1: u32 = 8 + 2 + 4;
+    const ENTRIES: u16 = 3;
+    let data = IFD1 + 2 + u32::from(ENTRIES) * 12 + 4;
+
+    let mut exif = b"Exif\0\0MM\0\x2A".to_vec();
+    exif.extend_from_slice(&8u32.to_be_bytes());
+    exif.extend_from_slice(&0u16.to_be_bytes());
+    exif.extend_from_slice(&IFD1.to_be_bytes());
+
+    exif.extend_from_slice(&ENTRIES.to_be_bytes());
+    // Compression, a short, of 6 for JPEG; shorts are left-justified in the value.
+    exif.extend_from_slice(&[0x01, 0x03, 0, 3, 0, 0, 0, 1, 0, 6, 0, 0]);
+    // Where the thumbnail starts, and its length, both longs.
+    exif.extend_from_slice(&[0x02, 0x01, 0, 4, 0, 0, 0, 1]);
+    exif.extend_from_slice(&data.to_be_bytes());
+    exif.extend_from_slice(&[0x02, 0x02, 0, 4, 0, 0, 0, 1]);
+    exif.extend_from_slice(&(thumbnail.len() as u32).to_be_bytes());
+    exif.extend_from_slice(&0u32.to_be_bytes());
+
+    exif.extend_from_slice(thumbnail);
+    exif
+}
+
+#[cfg(test)]
+mod tests {
+    use super::embed;
+    use exif::{In, Reader, Tag};
+    use image::{DynamicImage, GenericImageView, ImageFormat};
+    use std::io::Cursor;
+
+    #[test]
+    fn embeds_a_thumbnail_of_the_image() {
+        let mut bytes = Vec::new();
+        DynamicImage::new_rgb8(400, 300)
+            .write_to(&mut bytes, ImageFormat::Jpeg)
+            .unwrap();
+        embed(&mut bytes).unwrap();
+
+        let exif = Reader::new()
+            .read_from_container(&mut Cursor::new(&bytes))
+            .unwrap();
+        let field = |tag| {
+            exif.get_field(tag, In::THUMBNAIL)
+                .and_then(|field| field.value.get_uint(0))
+                .unwrap() as usize
+        };
+        let start = field(Tag::JPEGInterchangeFormat);
+        let length = field(Tag::JPEGInterchangeFormatLength);
+        let thumbnail = image::load_from_memory(&exif.buf()[start..start + length]).unwrap();
+        assert_eq!(thumbnail.dimensions(), (160, 120));
+
+        let image = image::load_from_memory(&bytes).unwrap();
+        assert_eq!(image.dimensions(), (400, 300));
+    }
+
+    #[test]
+    fn leaves_other_formats_alone() {
+        let mut bytes = b"\x89PNG".to_vec();
+        embed(&mut bytes).unwrap();
+        assert_eq!(bytes, b"\x89PNG");
+    }
+}
@@ -0,0 +1,118 @@
+//! Deep Zoom tile pyramids.
+//!
+//! For a source `photo.png`, tiles are written next to it as
+//! `photo_files/<level>/<col>_<row>.jpg`, along with a `photo.dzi` descriptor. The highest
+//! level holds the image at full resolution; each level below it halves both dimensions
+//! (rounding up) until level 0, which is a single pixel. Tiles are `tile_size` pixels square,
+//! except along the right and bottom edges, and do not overlap.
+
+use std::{
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use image::{imageops::FilterType, DynamicImage, GenericImageView};
+
+pub fn write_pyramid(path: &Path, image: &DynamicImage, tile_size: u32) -> io::Result<()> {
+    let (width, height) = image.dimensions();
+    let root = files_dir(path);
+    let levels = level_dimensions(width, height);
+
+    let mut level_image = image.clone();
+    for (level, &(level_width, level_height)) in levels.iter().enumerate().rev() {
+        if level_image.dimensions() != (level_width, level_height) {
+            level_image = level_image.resize_exact(level_width, level_height, FilterType::Lanczos3);
+        }
+
+        let dir = root.join(level.to_string());
+        fs::create_dir_all(&dir)?;
+
+        for (col, row, x, y, w, h) in tiles(level_width, level_height, tile_size) {
+            level_image
+                .crop_imm(x, y, w, h)
+                .save(dir.join(format!("{}_{}.jpg", col, row)))
+                .map_err(io::Error::other)?;
+        }
+    }
+
+    fs::write(
+        path.with_extension("dzi"),
+        descriptor(width, height, tile_size),
+    )
+}
+
+fn files_dir(path: &Path) -> PathBuf {
+    let stem = path.file_stem().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!("{}_files", stem))
+}
+
+fn descriptor(width: u32, height: u32, tile_size: u32) -> String {
+    format!(
+        concat!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n",
+            "<Image xmlns=\"http://schemas.microsoft.com/deepzoom/2008\" ",
+            "Format=\"jpg\" Overlap=\"0\" TileSize=\"{}\">\n",
+            "  <Size Width=\"{}\" Height=\"{}\"/>\n",
+            "</Image>\n",
+        ),
+        tile_size, width, height
+    )
+}
+
+/// Dimensions of each pyramid level, indexed by level.
+fn level_dimensions(width: u32, height: u32) -> Vec<(u32, u32)> {
+    let mut levels = vec![(width, height)];
+    let (mut width, mut height) = (width, height);
+
+    while width > 1 || height > 1 {
+        width = width.div_ceil(2);
+        height = height.div_ceil(2);
+        levels.push((width, height));
+    }
+
+    levels.reverse();
+    levels
+}
+
+/// Tiles covering a level, as `(col, row, x, y, width, height)`.
+fn tiles(
+    width: u32,
+    height: u32,
+    tile_size: u32,
+) -> impl Iterator<Item = (u32, u32, u32, u32, u32, u32)> {
+    let cols = width.div_ceil(tile_size);
+    let rows = height.div_ceil(tile_size);
+
+    (0..cols).flat_map(move |col| {
+        (0..rows).map(move |row| {
+            let x = col * tile_size;
+            let y = row * tile_size;
+            let w = tile_size.min(width - x);
+            let h = tile_size.min(height - y);
+            (col, row, x, y, w, h)
+        })
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{level_dimensions, tiles};
+
+    #[test]
+    fn levels_600_300() {
+        let levels = level_dimensions(600, 300);
+        assert_eq!(levels.len(), 11);
+        assert_eq!(levels[0], (1, 1));
+        assert_eq!(levels[8], (150, 75));
+        assert_eq!(levels[10], (600, 300));
+    }
+
+    #[test]
+    fn edge_tiles_are_clipped() {
+        let tiles: Vec<_> = tiles(600, 300, 256).collect();
+        assert_eq!(tiles.len(), 6);
+        assert_eq!(tiles[0], (0, 0, 0, 0, 256, 256));
+        assert_eq!(tiles[1], (0, 1, 0, 256, 256, 44));
+        assert_eq!(tiles[5], (2, 1, 512, 256, 88, 44));
+    }
+}
@@ -0,0 +1,78 @@
+//! How long each stage of processing takes, for `--timing`.
+
+use std::{
+    ops::AddAssign,
+    time::{Duration, Instant},
+};
+
+/// Time spent in each stage of processing, for one image or summed over many.
+#[derive(Copy, Clone, Debug, Default, PartialEq)]
+pub struct Timings {
+    /// Opening the source and reading its header.
+    pub open: Duration,
+    pub decode: Duration,
+    pub resize: Duration,
+    pub encode: Duration,
+    /// Writing outputs, which is left to the caller but for tile pyramids.
+    pub write: Duration,
+}
+
+impl Timings {
+    /// Each stage by name, in the order an image goes through them.
+    pub fn stages(&self) -> [(&'static str, Duration); 5] {
+        [
+            ("open", self.open),
+            ("decode", self.decode),
+            ("resize", self.resize),
+            ("encode", self.encode),
+            ("write", self.write),
+        ]
+    }
+
+    pub fn total(&self) -> Duration {
+        self.stages().iter().map(|&(_, time)| time).sum()
+    }
+}
+
+impl AddAssign for Timings {
+    fn add_assign(&mut self, other: Timings) {
+        self.open += other.open;
+        self.decode += other.decode;
+        self.resize += other.resize;
+        self.encode += other.encode;
+        self.write += other.write;
+    }
+}
+
+/// Runs `f`, adding the time it takes to `stage`.
+pub fn time<T>(stage: &mut Duration, f: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let result = f();
+    *stage += started.elapsed();
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{time, Timings};
+    use std::time::Duration;
+
+    #[test]
+    fn sums_stages() {
+        let mut timings = Timings {
+            decode: Duration::from_millis(30),
+            ..Timings::default()
+        };
+        timings += Timings {
+            decode: Duration::from_millis(10),
+            write: Duration::from_millis(5),
+            ..Timings::default()
+        };
+        assert_eq!(timings.decode, Duration::from_millis(40));
+        assert_eq!(timings.total(), Duration::from_millis(45));
+
+        let doubled = time(&mut timings.encode, || 21 * 2);
+        assert_eq!(doubled, 42);
+        assert!(timings.total() >= Duration::from_millis(45));
+    }
+}
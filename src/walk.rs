@@ -0,0 +1,230 @@
+//! Finding the images under a directory, for `--recursive` and for globs.
+
+use std::{
+    collections::{BTreeMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use glob::{MatchOptions, Pattern};
+use image::ImageFormat;
+use resize::raw;
+
+/// Images found by a walk, and the symlinks it passed over.
+#[derive(Debug, Default)]
+pub struct Walk {
+    /// Where the walk started.
+    pub root: PathBuf,
+    pub images: Vec<PathBuf>,
+    pub skipped_links: Vec<PathBuf>,
+}
+
+impl Walk {
+    /// How many images were found at each depth, counting those directly in the root as 0.
+    pub fn depths(&self) -> BTreeMap<usize, usize> {
+        let mut depths = BTreeMap::new();
+        for path in &self.images {
+            let relative = path.strip_prefix(&self.root).unwrap_or(path);
+            let depth = relative.components().count().saturating_sub(1);
+            *depths.entry(depth).or_insert(0) += 1;
+        }
+        depths
+    }
+}
+
+/// Walks `root` for files with image extensions, in sorted order, going no more than
+/// `max_depth` directories below it, if given.
+///
+/// Symlinks are passed over unless `follow`, in which case each directory and file is visited
+/// once, however many links lead to it, so a link back up the tree can't loop forever.
+pub fn walk(root: &Path, follow: bool, max_depth: Option<usize>) -> io::Result<Walk> {
+    let mut walk = Walk {
+        root: root.to_path_buf(),
+        ..Walk::default()
+    };
+    let mut visited = HashSet::new();
+    visited.insert(fs::canonicalize(root)?);
+    let limits = Limits { follow, max_depth };
+    descend(root, 0, limits, &mut visited, &mut walk)?;
+    Ok(walk)
+}
+
+#[derive(Copy, Clone)]
+struct Limits {
+    follow: bool,
+    max_depth: Option<usize>,
+}
+
+/// Expands `pattern` into the images it matches, or returns `None` if it isn't a glob. Paths
+/// that exist should be taken as they are instead, whatever characters they contain.
+///
+/// `*` stays within a directory while `**` crosses any number of them, though no more than
+/// `max_depth` below the pattern's first wildcard.
+pub fn expand(pattern: &str, follow: bool, max_depth: Option<usize>) -> io::Result<Option<Walk>> {
+    if !is_glob(pattern) {
+        return Ok(None);
+    }
+    let compiled = Pattern::new(pattern).map_err(|e| io::Error::other(e.to_string()))?;
+
+    // The literal directories leading up to the first wildcard are where the walk starts.
+    let components: Vec<_> = pattern.split('/').collect();
+    let literal = components.iter().take_while(|c| !is_glob(c)).count();
+    let base = components[..literal].join("/");
+    let wildcards = &components[literal..];
+    let depth = match wildcards.contains(&"**") {
+        true => max_depth,
+        false => Some(wildcards.len() - 1),
+    };
+    let depth = depth.map(|depth| max_depth.map_or(depth, |max| depth.min(max)));
+
+    let (root, relative) = match base.as_str() {
+        "" if pattern.starts_with('/') => ("/", false),
+        "" => (".", true),
+        base => (base, false),
+    };
+    if !Path::new(root).is_dir() {
+        return Ok(Some(Walk::default()));
+    }
+
+    let mut walk = walk(Path::new(root), follow, depth)?;
+    if relative {
+        // A walk from the working directory finds `./a.jpg`, which the pattern calls `a.jpg`.
+        walk.root = PathBuf::new();
+        for path in walk.images.iter_mut().chain(&mut walk.skipped_links) {
+            if let Ok(stripped) = path.strip_prefix(".") {
+                *path = stripped.to_path_buf();
+            }
+        }
+    }
+
+    let options = MatchOptions {
+        require_literal_separator: true,
+        ..MatchOptions::new()
+    };
+    let matches = |path: &PathBuf| compiled.matches_path_with(path, options);
+    walk.images.retain(matches);
+    walk.skipped_links.retain(matches);
+    Ok(Some(walk))
+}
+
+fn is_glob(s: &str) -> bool {
+    s.contains(['*', '?', '['])
+}
+
+fn descend(
+    dir: &Path,
+    depth: usize,
+    limits: Limits,
+    visited: &mut HashSet<PathBuf>,
+    walk: &mut Walk,
+) -> io::Result<()> {
+    let follow = limits.follow;
+    let mut entries = fs::read_dir(dir)?.collect::<io::Result<Vec<_>>>()?;
+    entries.sort_by_key(|entry| entry.file_name());
+
+    for entry in entries {
+        let path = entry.path();
+        let mut file_type = entry.file_type()?;
+
+        if file_type.is_symlink() {
+            if !follow {
+                walk.skipped_links.push(path);
+                continue;
+            }
+            // A dangling link leads nowhere, and is no more an image than a missing file.
+            file_type = match fs::metadata(&path) {
+                Ok(metadata) => metadata.file_type(),
+                Err(_) => continue,
+            };
+            if !visited.insert(fs::canonicalize(&path)?) {
+                continue;
+            }
+        } else if follow && !visited.insert(fs::canonicalize(&path)?) {
+            continue;
+        }
+
+        if file_type.is_dir() {
+            if limits.max_depth.is_none_or(|max| depth < max) {
+                descend(&path, depth + 1, limits, visited, walk)?;
+            }
+        } else if is_image(&path) {
+            walk.images.push(path);
+        }
+    }
+    Ok(())
+}
+
+fn is_image(path: &Path) -> bool {
+    ImageFormat::from_path(path).is_ok() || raw::is_raw(path)
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::{expand, walk};
+    use std::{fs, os::unix::fs::symlink, path::PathBuf};
+
+    fn tree(name: &str) -> PathBuf {
+        let root =
+            std::env::temp_dir().join(format!("resize-walk-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&root);
+        fs::create_dir_all(root.join("a/b")).unwrap();
+        fs::write(root.join("a/one.jpg"), b"").unwrap();
+        fs::write(root.join("a/b/two.PNG"), b"").unwrap();
+        fs::write(root.join("a/notes.txt"), b"").unwrap();
+        symlink(root.join("a"), root.join("a/b/up")).unwrap();
+        symlink(root.join("a/one.jpg"), root.join("same.jpg")).unwrap();
+        root
+    }
+
+    #[test]
+    fn symlinks_skipped_by_default() {
+        let root = tree("skip");
+        let walk = walk(&root, false, None).unwrap();
+        assert_eq!(
+            walk.images,
+            vec![root.join("a/b/two.PNG"), root.join("a/one.jpg")]
+        );
+        assert_eq!(
+            walk.skipped_links,
+            vec![root.join("a/b/up"), root.join("same.jpg")]
+        );
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn followed_symlinks_visit_once() {
+        let root = tree("follow");
+        let walk = walk(&root, true, None).unwrap();
+        assert_eq!(
+            walk.images,
+            vec![root.join("a/b/two.PNG"), root.join("a/one.jpg")]
+        );
+        assert!(walk.skipped_links.is_empty());
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn depth_limits_the_walk() {
+        let root = tree("depth");
+        let walk = walk(&root, false, Some(1)).unwrap();
+        assert_eq!(walk.images, vec![root.join("a/one.jpg")]);
+        assert_eq!(walk.depths().into_iter().collect::<Vec<_>>(), [(1, 1)]);
+        fs::remove_dir_all(root).unwrap();
+    }
+
+    #[test]
+    fn globs_expand_within_depth() {
+        let root = tree("glob");
+        let glob = |pattern: &str, max_depth| {
+            let pattern = format!("{}/{}", root.display(), pattern);
+            expand(&pattern, false, max_depth).unwrap().unwrap().images
+        };
+        assert_eq!(glob("a/*.jpg", None), vec![root.join("a/one.jpg")]);
+        assert_eq!(glob("*/*.png", None), Vec::<PathBuf>::new());
+        assert_eq!(glob("**/*.jpg", None), vec![root.join("a/one.jpg")]);
+        assert_eq!(glob("a/**/*", Some(0)), vec![root.join("a/one.jpg")]);
+        assert_eq!(glob("a/**/*", None).len(), 2);
+        assert!(expand("a/one.jpg", false, None).unwrap().is_none());
+        fs::remove_dir_all(root).unwrap();
+    }
+}
@@ -0,0 +1,180 @@
+//! Watching a drop folder and resizing images as they land in it, for `--watch`.
+//!
+//! The folder is polled rather than subscribed to, so the same code serves every platform and
+//! network mounts that raise no events. Polling also debounces for free: a file is only taken
+//! once two polls in a row find it unchanged, so one still being copied in is left until it's
+//! whole.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    thread,
+    time::{Duration, SystemTime},
+};
+
+use resize::{Job, ResizeOptions};
+
+use crate::archive;
+
+/// How long to wait between looks at the folder.
+pub const INTERVAL: Duration = Duration::from_millis(500);
+
+/// What a file looked like: its length and when it was last modified.
+type Stamp = (u64, SystemTime);
+
+#[derive(Debug)]
+struct Seen {
+    /// How the file looked at the last poll.
+    last: Stamp,
+    /// How it looked when it was last taken, if it has been.
+    taken: Option<Stamp>,
+}
+
+/// The images directly in a folder, and how each looked when last polled.
+#[derive(Debug)]
+pub struct Watch {
+    dir: PathBuf,
+    seen: HashMap<PathBuf, Seen>,
+}
+
+impl Watch {
+    /// Starts watching `dir`. Images already there are left alone; only those that land or
+    /// change later are taken.
+    pub fn new(dir: &Path) -> io::Result<Watch> {
+        let mut watch = Watch {
+            dir: dir.to_path_buf(),
+            seen: HashMap::new(),
+        };
+        for (path, stamp) in watch.scan()? {
+            let seen = Seen {
+                last: stamp,
+                taken: Some(stamp),
+            };
+            watch.seen.insert(path, seen);
+        }
+        Ok(watch)
+    }
+
+    /// The images that have settled since they were last taken, in sorted order. A file
+    /// settles once it looks the same as it did at the previous poll.
+    pub fn poll(&mut self) -> io::Result<Vec<PathBuf>> {
+        let scanned = self.scan()?;
+        // Anything gone is forgotten, so a file dropped again under the same name is taken.
+        self.seen
+            .retain(|path, _| scanned.iter().any(|(scanned, _)| scanned == path));
+
+        let mut ready = Vec::new();
+        for (path, stamp) in scanned {
+            match self.seen.get_mut(&path) {
+                Some(seen) if seen.last == stamp && seen.taken != Some(stamp) => {
+                    seen.taken = Some(stamp);
+                    ready.push(path);
+                }
+                Some(seen) => seen.last = stamp,
+                None => {
+                    let seen = Seen {
+                        last: stamp,
+                        taken: None,
+                    };
+                    self.seen.insert(path, seen);
+                }
+            }
+        }
+        Ok(ready)
+    }
+
+    fn scan(&self) -> io::Result<Vec<(PathBuf, Stamp)>> {
+        let mut scanned = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let entry = entry?;
+            let path = entry.path();
+            // A file removed between listing and reading it is simply no longer there.
+            let metadata = match entry.metadata() {
+                Ok(metadata) => metadata,
+                Err(_) => continue,
+            };
+            if metadata.is_file() && archive::is_image(&path) {
+                scanned.push((path, (metadata.len(), metadata.modified()?)));
+            }
+        }
+        scanned.sort();
+        Ok(scanned)
+    }
+}
+
+/// Why a file that settled is left alone, if it is: its output would overwrite it, which only
+/// writing in place allows.
+pub fn refusal(job: &Job, options: &ResizeOptions) -> Option<String> {
+    match options.in_place() || !resize::overwrites_source(job, options) {
+        true => None,
+        false => Some(String::from("output would overwrite it")),
+    }
+}
+
+/// Polls `dir` until `take` fails, handing it each image as it settles.
+pub fn run(dir: &Path, mut take: impl FnMut(&Path) -> io::Result<()>) -> io::Result<()> {
+    let mut watch = Watch::new(dir)?;
+    loop {
+        for path in watch.poll()? {
+            take(&path)?;
+        }
+        thread::sleep(INTERVAL);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{refusal, Watch};
+    use resize::{Job, ResizeOptions};
+    use std::{fs, path::PathBuf};
+
+    fn folder(name: &str) -> PathBuf {
+        let dir =
+            std::env::temp_dir().join(format!("resize-watch-{}-{}", name, std::process::id()));
+        let _ = fs::remove_dir_all(&dir);
+        fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn takes_images_once_they_settle() {
+        let dir = folder("settle");
+        fs::write(dir.join("old.png"), b"old").unwrap();
+        let mut watch = Watch::new(&dir).unwrap();
+        assert!(watch.poll().unwrap().is_empty());
+
+        fs::write(dir.join("new.png"), b"partial").unwrap();
+        fs::write(dir.join("notes.txt"), b"not an image").unwrap();
+        assert!(watch.poll().unwrap().is_empty());
+        fs::write(dir.join("new.png"), b"partial, then whole").unwrap();
+        assert!(watch.poll().unwrap().is_empty());
+        assert_eq!(watch.poll().unwrap(), [dir.join("new.png")]);
+        assert!(watch.poll().unwrap().is_empty());
+
+        // Changing a file takes it again.
+        fs::write(dir.join("old.png"), b"replaced").unwrap();
+        assert!(watch.poll().unwrap().is_empty());
+        assert_eq!(watch.poll().unwrap(), [dir.join("old.png")]);
+        fs::remove_dir_all(&dir).unwrap();
+    }
+
+    #[test]
+    fn leaves_files_their_outputs_would_overwrite() {
+        let options = ResizeOptions::builder().size(64).build().unwrap();
+        let landed = Job::new("incoming/photo.png", options.settings());
+        assert!(refusal(&landed, &options).is_some());
+        let in_place = ResizeOptions::builder()
+            .size(64)
+            .in_place(true)
+            .build()
+            .unwrap();
+        assert!(refusal(&landed, &in_place).is_none());
+
+        let placed = Job {
+            out: Some(PathBuf::from("processed/photo.png")),
+            ..landed
+        };
+        assert!(refusal(&placed, &options).is_none());
+    }
+}
@@ -0,0 +1,43 @@
+//! Recognizing WebP features the decoder can't handle.
+
+use std::io::{self, Read};
+
+/// The animation bit of the flags in a `VP8X` extended header.
+const ANIMATION: u8 = 0x02;
+
+/// Whether a WebP stream is animated, as its extended header says. Anything that isn't a
+/// WebP with such a header isn't.
+pub fn is_animated(mut reader: impl Read) -> io::Result<bool> {
+    // RIFF size WEBP, then the first chunk's tag and size, then its first byte.
+    let mut header = [0u8; 21];
+    match reader.read_exact(&mut header) {
+        Err(e) if e.kind() == io::ErrorKind::UnexpectedEof => return Ok(false),
+        result => result?,
+    }
+    Ok(&header[..4] == b"RIFF"
+        && &header[8..12] == b"WEBP"
+        && &header[12..16] == b"VP8X"
+        && header[20] & ANIMATION != 0)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::is_animated;
+
+    fn extended(flags: u8) -> Vec<u8> {
+        let mut data = b"RIFF\x1a\x00\x00\x00WEBPVP8X\x0a\x00\x00\x00".to_vec();
+        data.extend_from_slice(&[flags, 0, 0, 0, 99, 0, 0, 99, 0, 0]);
+        data
+    }
+
+    #[test]
+    fn animation_flag() {
+        assert!(is_animated(&extended(0x12)[..]).unwrap());
+        assert!(!is_animated(&extended(0x10)[..]).unwrap());
+        assert!(!is_animated(&b"RIFF\x00\x00\x00\x00WEBPVP8 "[..]).unwrap());
+        assert!(
+            !is_animated(&b"\x89PNG\r\n\x1a\n\x00\x00\x00\x0dIHDR\x00\x00\x00\x01\x00"[..])
+                .unwrap()
+        );
+    }
+}